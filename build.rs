@@ -1,18 +1,233 @@
 use anyhow::*;
 use fs_extra::copy_items;
 use fs_extra::dir::CopyOptions;
+use glob::glob;
 use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Only part of the preprocessor and the texture helpers are needed here.
+#[allow(dead_code)]
+#[path = "src/atlas.rs"]
+mod atlas;
+#[allow(dead_code)]
+#[path = "src/ktx2.rs"]
+mod ktx2;
+#[allow(dead_code)]
+#[path = "src/mip_chain.rs"]
+mod mip_chain;
+#[allow(dead_code)]
+#[path = "src/rendering/preprocessor.rs"]
+mod preprocessor;
+
+use ktx2::Ktx2;
+use mip_chain::DownsampleFilter;
+use preprocessor::{Preprocessed, Preprocessor};
+
+/// Directory with the GLSL sources, relative to the crate root.
+const SHADER_DIR: &str = "src/shaders";
+
+/// Shaders that are compiled once more with extra defines, as
+/// `(source, output, defines)`.
+const VARIANTS: &[(&str, &str, &[(&str, &str)])] = &[
+    (
+        "shader.frag",
+        "shader.layers.frag.spv",
+        &[("TEXTURE_LAYERS", "")],
+    ),
+    (
+        "debug_line.vert",
+        "debug_line.screen.vert.spv",
+        &[("SCREEN_SPACE", "")],
+    ),
+    (
+        "sky.frag",
+        "sky.cubemap.frag.spv",
+        &[("SKY_CUBEMAP", "")],
+    ),
+    (
+        "sky.frag",
+        "sky.equirect.frag.spv",
+        &[("SKY_EQUIRECT", "")],
+    ),
+    (
+        "skinned.vert",
+        "skinned.gbuffer.vert.spv",
+        &[("JOINT_SET", "1")],
+    ),
+    (
+        "post/blur.frag",
+        "post/blur.vertical.frag.spv",
+        &[("BLUR_VERTICAL", "")],
+    ),
+];
 
 fn main() -> Result<()> {
-    // This tells cargo to rerun this script if something in /res/ changes.
-    println!("cargo:rerun-if-changed=res/*");
+    // Cargo looks at every file in a folder, so this reruns the script when
+    // anything in res/ changes. The sources included above are part of the
+    // script, changing them rebuilds and reruns it anyway.
+    println!("cargo:rerun-if-changed=res");
 
     let out_dir = env::var("OUT_DIR")?;
     let mut copy_options = CopyOptions::new();
     copy_options.overwrite = true;
     let mut paths_to_copy = Vec::new();
     paths_to_copy.push("res/");
-    copy_items(&paths_to_copy, out_dir, &copy_options)?;
+    copy_items(&paths_to_copy, &out_dir, &copy_options)?;
+
+    pack_block_atlas(&Path::new(&out_dir).join("res"))?;
+    compile_shaders(&Path::new(&out_dir).join("shaders"))?;
 
     Ok(())
 }
+
+/// Writes `atlas.png`, its manifest and `atlas.ktx2` with the mip levels to
+/// `out_dir`, unless they're newer than every block texture. Development
+/// mode packs the atlas itself when the program starts.
+fn pack_block_atlas(out_dir: &Path) -> Result<()> {
+    const BLOCK_DIR: &str = "res/blocks";
+    println!("cargo:rerun-if-changed={}", BLOCK_DIR);
+
+    // The folder changes when a texture is removed.
+    let mut inputs = vec![PathBuf::from(BLOCK_DIR)];
+    for entry in fs::read_dir(BLOCK_DIR)? {
+        inputs.push(entry?.path());
+    }
+    let png = out_dir.join("atlas.png");
+    let ktx2 = out_dir.join("atlas.ktx2");
+    let outputs = [png.with_extension("txt"), png.clone(), ktx2.clone()];
+    if outputs.iter().all(|output| is_newer(output, &inputs)) {
+        return Ok(());
+    }
+
+    let block_atlas = atlas::block_atlas(BLOCK_DIR)?;
+    block_atlas.save(&png)?;
+    // The mip levels are stored along with the atlas, so they don't have to
+    // be generated on every start. Only a box filter stays within the gutter
+    // for all of the atlas' clean levels.
+    let mut levels = mip_chain::mip_chain(&block_atlas.image, DownsampleFilter::Box, true);
+    // The smaller levels would mix neighbouring textures.
+    levels.truncate(block_atlas.manifest.mip_levels as usize);
+    Ktx2 {
+        vk_format: ktx2::VK_FORMAT_R8G8B8A8_SRGB,
+        width: block_atlas.manifest.width,
+        height: block_atlas.manifest.height,
+        layer_count: 1,
+        levels: levels.into_iter().map(|l| l.into_raw()).collect(),
+    }
+    .save(&ktx2)
+}
+
+/// Compiles every shader under `src/shaders/` into `out_dir`, keeping the
+/// folder structure, e.g. `blit/blit.vert` -> `blit/blit.vert.spv`.
+fn compile_shaders(out_dir: &Path) -> Result<()> {
+    // Cargo reruns the script when any file in the folder changes.
+    println!("cargo:rerun-if-changed={}", SHADER_DIR);
+
+    let mut jobs = Vec::new();
+    for extension in &["vert", "frag", "comp"] {
+        for source in glob(&format!("{}/**/*.{}", SHADER_DIR, extension))? {
+            let source = source?;
+            let output = spirv_path(&source, out_dir)?;
+            jobs.push((source, output, Preprocessor::new()));
+        }
+    }
+    for (source, output, defines) in VARIANTS {
+        let preprocessor = defines
+            .iter()
+            .fold(Preprocessor::new(), |p, (name, value)| {
+                p.define(name, value)
+            });
+        jobs.push((
+            Path::new(SHADER_DIR).join(source),
+            out_dir.join(output),
+            preprocessor,
+        ));
+    }
+
+    let mut compiler = shaderc::Compiler::new().context("Failed to create shader compiler")?;
+    let mut errors = Vec::new();
+    for (source, output, preprocessor) in jobs {
+        let code = match preprocessor.process_file(&source) {
+            Ok(code) => code,
+            Err(e) => {
+                errors.push(e.to_string());
+                continue;
+            }
+        };
+        if is_up_to_date(&code, &output) {
+            continue;
+        }
+        if let Err(e) = compile_shader(&mut compiler, &source, &code, &output) {
+            errors.push(format!("{:?}", e));
+        }
+    }
+
+    if !errors.is_empty() {
+        bail!("Failed to compile shaders:\n\n{}", errors.join("\n\n"));
+    }
+    Ok(())
+}
+
+fn compile_shader(
+    compiler: &mut shaderc::Compiler,
+    source: &Path,
+    code: &Preprocessed,
+    output: &Path,
+) -> Result<()> {
+    let kind = match source.extension().and_then(|e| e.to_str()) {
+        Some("vert") => shaderc::ShaderKind::Vertex,
+        Some("frag") => shaderc::ShaderKind::Fragment,
+        Some("comp") => shaderc::ShaderKind::Compute,
+        _ => bail!("Unsupported shader {:?}", source),
+    };
+    let name = source.to_string_lossy();
+    let artifact = match compiler.compile_into_spirv(&code.source, kind, &name, "main", None) {
+        Ok(artifact) => artifact,
+        Err(shaderc::Error::CompilationError(_, message)) => {
+            bail!("{}", code.remap_diagnostics(&name, &message))
+        }
+        Err(e) => return Err(e).with_context(|| format!("{:?} doesn't compile", source)),
+    };
+    if artifact.get_num_warnings() > 0 {
+        let warnings = code.remap_diagnostics(&name, &artifact.get_warning_messages());
+        for line in warnings.lines() {
+            println!("cargo:warning={}", line);
+        }
+    }
+
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(output, artifact.as_binary_u8())?;
+    Ok(())
+}
+
+fn spirv_path(source: &Path, out_dir: &Path) -> Result<PathBuf> {
+    let relative = source.strip_prefix(SHADER_DIR)?;
+    let mut file_name = relative.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".spv");
+    Ok(out_dir.join(relative).with_file_name(file_name))
+}
+
+/// Only shaders where the shader or one of its includes changed since it was
+/// last compiled are compiled again.
+fn is_up_to_date(code: &Preprocessed, output: &Path) -> bool {
+    is_newer(output, &code.files)
+}
+
+/// Whether `output` was written after the last change to any of `inputs` and
+/// to the build script itself, which is rebuilt when the code that writes
+/// the outputs changes.
+fn is_newer(output: &Path, inputs: &[PathBuf]) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    let output = match modified(output) {
+        Some(output) => output,
+        None => return false,
+    };
+    let script = env::current_exe().ok();
+    inputs
+        .iter()
+        .chain(script.iter())
+        .all(|input| modified(input).map_or(false, |m| m <= output))
+}
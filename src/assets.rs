@@ -0,0 +1,341 @@
+use crate::mipmap::{MipFilter, MipmapGenerator};
+use crate::modeling::model::{Material, Model};
+use crate::texture::Texture;
+use crate::texture_file::TextureFile;
+use anyhow::*;
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+
+/// Shared reference to a loaded asset. The asset, and with it any GPU
+/// resources it owns, is dropped once the last handle is gone.
+pub struct Handle<T>(Rc<T>);
+
+impl<T> Handle<T> {
+    /// Wraps an asset that isn't tracked by any [`AssetStorage`].
+    pub fn new(asset: T) -> Self {
+        Self(Rc::new(asset))
+    }
+
+    /// Number of handles currently pointing to this asset.
+    pub fn ref_count(&self) -> usize {
+        Rc::strong_count(&self.0)
+    }
+
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self(Rc::clone(&self.0))
+    }
+}
+
+impl<T> Deref for Handle<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+/// Cache of assets of one type keyed by the path they were loaded from.
+/// The storage doesn't keep assets alive by itself, it only hands out
+/// another handle while someone still holds one.
+pub struct AssetStorage<T> {
+    assets: HashMap<PathBuf, Weak<T>>,
+}
+
+impl<T> AssetStorage<T> {
+    pub fn new() -> Self {
+        Self {
+            assets: HashMap::new(),
+        }
+    }
+
+    pub fn get<P: AsRef<Path>>(&self, path: P) -> Option<Handle<T>> {
+        self.assets
+            .get(&cache_key(path.as_ref()))
+            .and_then(Weak::upgrade)
+            .map(Handle)
+    }
+
+    /// Returns the cached asset for `path`, or loads and caches it.
+    pub fn get_or_load<P, F>(&mut self, path: P, load: F) -> Result<Handle<T>>
+    where
+        P: AsRef<Path>,
+        F: FnOnce(&Path) -> Result<T>,
+    {
+        if let Some(handle) = self.get(path.as_ref()) {
+            return Ok(handle);
+        }
+        let asset = load(path.as_ref())?;
+        Ok(self.insert(path, asset))
+    }
+
+    /// Caches `asset` under `path`, replacing whatever was stored there.
+    /// Existing handles to the old asset stay valid.
+    pub fn insert<P: AsRef<Path>>(&mut self, path: P, asset: T) -> Handle<T> {
+        let handle = Handle::new(asset);
+        self.assets
+            .insert(cache_key(path.as_ref()), Rc::downgrade(&handle.0));
+        handle
+    }
+
+    /// Number of live handles to the asset loaded from `path`.
+    pub fn ref_count<P: AsRef<Path>>(&self, path: P) -> usize {
+        self.assets
+            .get(&cache_key(path.as_ref()))
+            .map_or(0, Weak::strong_count)
+    }
+
+    /// Forgets entries whose asset has already been dropped. Returns how many
+    /// entries were removed.
+    pub fn collect_garbage(&mut self) -> usize {
+        let before = self.assets.len();
+        self.assets.retain(|_, asset| asset.strong_count() > 0);
+        before - self.assets.len()
+    }
+
+    /// Live assets together with the path they were loaded from.
+    pub fn iter(&self) -> impl Iterator<Item = (&Path, Handle<T>)> {
+        self.assets
+            .iter()
+            .filter_map(|(path, asset)| Some((path.as_path(), Handle(asset.upgrade()?))))
+    }
+
+    /// Number of assets that are still alive.
+    pub fn len(&self) -> usize {
+        self.assets
+            .values()
+            .filter(|asset| asset.strong_count() > 0)
+            .count()
+    }
+}
+
+impl<T> Default for AssetStorage<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn cache_key(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Central place for loading textures, materials and models. Every asset is
+/// loaded once per path and shared between everything that uses it.
+pub struct AssetManager {
+    pub textures: AssetStorage<Texture>,
+    pub materials: AssetStorage<Material>,
+    pub models: AssetStorage<Model>,
+    pub mipmaps: MipmapGenerator,
+    material_layout: wgpu::BindGroupLayout,
+}
+
+impl AssetManager {
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            textures: AssetStorage::new(),
+            materials: AssetStorage::new(),
+            models: AssetStorage::new(),
+            mipmaps: MipmapGenerator::new(device),
+            material_layout: Material::bind_group_layout(device),
+        }
+    }
+
+    /// Layout of every material bind group created by the manager.
+    pub fn material_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.material_layout
+    }
+
+    pub fn load_texture<P: AsRef<Path>>(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: P,
+    ) -> Result<Handle<Texture>> {
+        let mipmaps = &mut self.mipmaps;
+        self.textures
+            .get_or_load(path, |path| load_texture(device, queue, mipmaps, path))
+    }
+
+    pub fn load_material<P: AsRef<Path>>(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: P,
+    ) -> Result<Handle<Material>> {
+        if let Some(material) = self.materials.get(path.as_ref()) {
+            return Ok(material);
+        }
+        let texture = self.load_texture(device, queue, path.as_ref())?;
+        let material = Material::from_texture(
+            device,
+            queue,
+            &self.material_layout,
+            texture,
+            None,
+            path.as_ref().to_str().unwrap_or("material"),
+        )?;
+        Ok(self.materials.insert(path, material))
+    }
+
+    pub fn load_model<P: AsRef<Path>>(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: P,
+    ) -> Result<Handle<Model>> {
+        if let Some(model) = self.models.get(path.as_ref()) {
+            return Ok(model);
+        }
+        let cached = self.textures.iter().map(|(_, t)| t).collect::<Vec<_>>();
+        let model = Model::load_cached(
+            device,
+            queue,
+            &self.material_layout,
+            path.as_ref(),
+            &mut self.textures,
+        )?;
+        generate_model_mipmaps(device, queue, &mut self.mipmaps, &model, &cached);
+        Ok(self.models.insert(path, model))
+    }
+
+    /// Loads everything that came from `path`, or uses a file that changed
+    /// with it, again. Handles that are already out there keep the old asset,
+    /// so their owners have to fetch new ones when this returns `true`.
+    pub fn reload<P: AsRef<Path>>(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: P,
+    ) -> Result<bool> {
+        let path = cache_key(path.as_ref());
+        let mut models = Vec::new();
+        // Keeps the new texture alive until the models using it are rebuilt.
+        let mut texture = None;
+
+        if let Some(old) = self.textures.get(&path) {
+            let new = self.textures.insert(
+                &path,
+                load_texture(device, queue, &mut self.mipmaps, &path)?,
+            );
+            if self.materials.get(&path).is_some() {
+                let material = Material::from_texture(
+                    device,
+                    queue,
+                    &self.material_layout,
+                    new.clone(),
+                    None,
+                    path.to_str().unwrap_or("material"),
+                )?;
+                self.materials.insert(&path, material);
+            }
+            // Models get rebuilt so that their materials use the new texture.
+            models.extend(
+                self.models
+                    .iter()
+                    .filter(|(_, model)| model.material.iter().any(|m| m.texture.ptr_eq(&old)))
+                    .map(|(model_path, _)| model_path.to_path_buf()),
+            );
+            texture = Some(new);
+        }
+
+        let is_mtl = path.extension().map_or(false, |e| e == "mtl");
+        models.extend(
+            self.models
+                .iter()
+                .map(|(model_path, _)| model_path)
+                // Material libraries are referenced by name from models in the
+                // same folder, so every one of them might be using it.
+                .filter(|model_path| {
+                    *model_path == path || (is_mtl && model_path.parent() == path.parent())
+                })
+                .map(Path::to_path_buf),
+        );
+        models.sort();
+        models.dedup();
+
+        let reloaded = texture.is_some() || !models.is_empty();
+        for model_path in models {
+            let cached = self.textures.iter().map(|(_, t)| t).collect::<Vec<_>>();
+            let model = Model::load_cached(
+                device,
+                queue,
+                &self.material_layout,
+                &model_path,
+                &mut self.textures,
+            )?;
+            generate_model_mipmaps(device, queue, &mut self.mipmaps, &model, &cached);
+            self.models.insert(&model_path, model);
+        }
+        Ok(reloaded)
+    }
+
+    /// Drops cache entries of assets that no longer have any handles.
+    pub fn collect_garbage(&mut self) {
+        let models = self.models.collect_garbage();
+        let materials = self.materials.collect_garbage();
+        let textures = self.textures.collect_garbage();
+        if models + materials + textures > 0 {
+            log::debug!(
+                "Freed {} models, {} materials and {} textures",
+                models,
+                materials,
+                textures
+            );
+        }
+    }
+}
+
+fn load_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    mipmaps: &mut MipmapGenerator,
+    path: &Path,
+) -> Result<Texture> {
+    let texture = Texture::load(device, queue, path)?;
+    // KTX2 and DDS files come with their mip levels.
+    if !TextureFile::is_texture_file(path) {
+        mipmaps.generate_all(device, queue, &[texture.mipmap_target(MipFilter::Linear)]);
+    }
+    Ok(texture)
+}
+
+/// Fills the mip levels of the textures `model` uploaded in one submission.
+/// The ones that were already `cached` before it was loaded have theirs.
+fn generate_model_mipmaps(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    mipmaps: &mut MipmapGenerator,
+    model: &Model,
+    cached: &[Handle<Texture>],
+) {
+    let mut textures: Vec<&Handle<Texture>> = Vec::new();
+    for material in model.material.iter() {
+        // Materials of the model can share a texture too.
+        let texture = &material.texture;
+        if !cached
+            .iter()
+            .chain(textures.iter().copied())
+            .any(|t| t.ptr_eq(texture))
+        {
+            textures.push(texture);
+        }
+    }
+    let targets = textures
+        .iter()
+        .map(|t| t.mipmap_target(MipFilter::Linear))
+        .chain(
+            model
+                .material
+                .iter()
+                .map(|m| m.normal_texture.mipmap_target(MipFilter::NormalMap)),
+        )
+        .collect::<Vec<_>>();
+    mipmaps.generate_all(device, queue, &targets);
+}
@@ -0,0 +1,453 @@
+//! Packs block textures into a single atlas image. It's used by `build.rs`
+//! and the `atlas` binary as well, so it must only depend on `std`, `image`
+//! and `anyhow`.
+//!
+//! The atlas comes with a manifest, a text file with one line per texture:
+//!
+//! ```text
+//! size 64 64
+//! mip_levels 3
+//! grass_top 4 4 16 16
+//! ```
+//!
+//! A rect is the texture itself, without its gutter.
+
+use anyhow::{bail, Context, Result};
+use image::RgbaImage;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::Path;
+
+/// Where a texture ended up in the atlas, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AtlasRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl AtlasRect {
+    /// Texture coordinates of the top left and bottom right corner, as
+    /// `[u_min, v_min, u_max, v_max]`.
+    pub fn uv(&self, atlas_width: u32, atlas_height: u32) -> [f32; 4] {
+        let (w, h) = (atlas_width as f32, atlas_height as f32);
+        [
+            self.x as f32 / w,
+            self.y as f32 / h,
+            (self.x + self.width) as f32 / w,
+            (self.y + self.height) as f32 / h,
+        ]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AtlasManifest {
+    pub width: u32,
+    pub height: u32,
+    /// Number of mip levels that don't bleed between textures.
+    pub mip_levels: u32,
+    pub rects: BTreeMap<String, AtlasRect>,
+}
+
+impl AtlasManifest {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read atlas manifest {:?}", path))?;
+        Self::parse(&text).with_context(|| format!("Invalid atlas manifest {:?}", path))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut size = None;
+        let mut mip_levels = 1;
+        let mut rects = BTreeMap::new();
+        for (i, line) in text.lines().enumerate() {
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            let number = |word: &str| {
+                word.parse::<u32>()
+                    .with_context(|| format!("line {}: {:?} isn't a number", i + 1, word))
+            };
+            match words.as_slice() {
+                [] => {}
+                [comment, ..] if comment.starts_with('#') => {}
+                ["size", width, height] => size = Some((number(width)?, number(height)?)),
+                ["mip_levels", levels] => mip_levels = number(levels)?,
+                [name, x, y, width, height] => {
+                    let rect = AtlasRect {
+                        x: number(x)?,
+                        y: number(y)?,
+                        width: number(width)?,
+                        height: number(height)?,
+                    };
+                    if rects.insert(String::from(*name), rect).is_some() {
+                        bail!("line {}: {:?} is listed twice", i + 1, name);
+                    }
+                }
+                _ => bail!("line {}: expected `name x y width height`", i + 1),
+            }
+        }
+        let (width, height) = size.context("the `size` line is missing")?;
+        Ok(Self {
+            width,
+            height,
+            mip_levels,
+            rects,
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_string())
+            .with_context(|| format!("Failed to write atlas manifest {:?}", path))
+    }
+
+    pub fn rect(&self, name: &str) -> Option<AtlasRect> {
+        self.rects.get(name).copied()
+    }
+
+    /// Texture coordinates of `name`, see [`AtlasRect::uv`].
+    pub fn uv(&self, name: &str) -> Option<[f32; 4]> {
+        self.rect(name).map(|r| r.uv(self.width, self.height))
+    }
+}
+
+impl fmt::Display for AtlasManifest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "size {} {}", self.width, self.height)?;
+        writeln!(f, "mip_levels {}", self.mip_levels)?;
+        for (name, r) in self.rects.iter() {
+            writeln!(f, "{} {} {} {} {}", name, r.x, r.y, r.width, r.height)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct Atlas {
+    pub image: RgbaImage,
+    pub manifest: AtlasManifest,
+}
+
+impl Atlas {
+    /// Loads an atlas written by [`Atlas::save`].
+    pub fn load<P: AsRef<Path>>(image_path: P) -> Result<Self> {
+        let image_path = image_path.as_ref();
+        let image = image::open(image_path)
+            .with_context(|| format!("Failed to load atlas {:?}", image_path))?
+            .to_rgba8();
+        let manifest = AtlasManifest::load(image_path.with_extension("txt"))?;
+        Ok(Self { image, manifest })
+    }
+
+    /// Writes the image and its manifest next to each other, e.g.
+    /// `atlas.png` and `atlas.txt`.
+    pub fn save<P: AsRef<Path>>(&self, image_path: P) -> Result<()> {
+        let image_path = image_path.as_ref();
+        self.image
+            .save(image_path)
+            .with_context(|| format!("Failed to write atlas {:?}", image_path))?;
+        self.manifest.save(image_path.with_extension("txt"))
+    }
+}
+
+/// Packs the block textures in `dir`. `build.rs` and development mode must
+/// agree on the settings.
+pub fn block_atlas<P: AsRef<Path>>(dir: P) -> Result<Atlas> {
+    AtlasBuilder::new().gutter(4).add_dir(dir)?.build()
+}
+
+pub struct AtlasBuilder {
+    textures: Vec<(String, RgbaImage)>,
+    padding: u32,
+    gutter: u32,
+    max_size: u32,
+}
+
+impl AtlasBuilder {
+    pub fn new() -> Self {
+        Self {
+            textures: Vec::new(),
+            padding: 0,
+            gutter: 4,
+            max_size: 4096,
+        }
+    }
+
+    /// Empty pixels between the gutters of two textures.
+    pub fn padding(mut self, padding: u32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Pixels around every texture that repeat its edge, so filtering and
+    /// smaller mip levels don't pick up the neighbours. A gutter of `2^n`
+    /// keeps `n + 1` mip levels clean.
+    pub fn gutter(mut self, gutter: u32) -> Self {
+        self.gutter = gutter;
+        self
+    }
+
+    pub fn max_size(mut self, max_size: u32) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    pub fn add(mut self, name: &str, image: RgbaImage) -> Self {
+        self.textures.push((String::from(name), image));
+        self
+    }
+
+    /// Adds every PNG in `dir`, named after the file without its extension.
+    pub fn add_dir<P: AsRef<Path>>(mut self, dir: P) -> Result<Self> {
+        let dir = dir.as_ref();
+        let mut paths = std::fs::read_dir(dir)
+            .with_context(|| format!("Failed to read {:?}", dir))?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        paths.retain(|p| p.extension().map_or(false, |e| e == "png"));
+        // Keep the layout the same no matter the order of the directory.
+        paths.sort();
+        for path in paths {
+            let name = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .with_context(|| format!("{:?} isn't a valid texture name", path))?;
+            let image = image::open(&path)
+                .with_context(|| format!("Failed to load {:?}", path))?
+                .to_rgba8();
+            self = self.add(name, image);
+        }
+        Ok(self)
+    }
+
+    pub fn build(self) -> Result<Atlas> {
+        let mut names = BTreeSet::new();
+        for (name, _) in self.textures.iter() {
+            if !names.insert(name.as_str()) {
+                bail!("Texture {:?} was added twice", name);
+            }
+        }
+
+        let mip_levels = 1 + log2(self.gutter);
+        // Slots start and end on multiples of the block a texel of the
+        // smallest clean mip level covers.
+        let align = 1 << (mip_levels - 1);
+        let slot_size = |image: &RgbaImage| {
+            let (w, h) = image.dimensions();
+            let extra = 2 * self.gutter + self.padding;
+            (round_up(w + extra, align), round_up(h + extra, align))
+        };
+
+        // Shelf packing, tallest textures first.
+        let mut order = (0..self.textures.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| {
+            let (w, h) = slot_size(&self.textures[i].1);
+            (std::cmp::Reverse(h), std::cmp::Reverse(w), i)
+        });
+        let area = order
+            .iter()
+            .map(|&i| {
+                let (w, h) = slot_size(&self.textures[i].1);
+                w as u64 * h as u64
+            })
+            .sum::<u64>();
+        let mut size = ((area as f64).sqrt().ceil() as u32)
+            .max(1)
+            .next_power_of_two();
+        let positions = loop {
+            if size > self.max_size {
+                bail!(
+                    "{} textures don't fit into a {1}x{1} atlas",
+                    self.textures.len(),
+                    self.max_size
+                );
+            }
+            if let Some(positions) = pack(&order, size, |i| slot_size(&self.textures[i].1)) {
+                break positions;
+            }
+            size *= 2;
+        };
+
+        let mut image = RgbaImage::new(size, size);
+        let mut rects = BTreeMap::new();
+        for (i, (x, y)) in positions {
+            let (name, texture) = &self.textures[i];
+            let rect = AtlasRect {
+                x: x + self.gutter,
+                y: y + self.gutter,
+                width: texture.width(),
+                height: texture.height(),
+            };
+            blit_with_gutter(&mut image, texture, rect, self.gutter);
+            rects.insert(name.clone(), rect);
+        }
+        Ok(Atlas {
+            image,
+            manifest: AtlasManifest {
+                width: size,
+                height: size,
+                mip_levels,
+                rects,
+            },
+        })
+    }
+}
+
+/// Places the slots in `order` on shelves of a `size` square. Returns the top
+/// left corner of every slot, or `None` if they don't fit.
+fn pack(
+    order: &[usize],
+    size: u32,
+    slot_size: impl Fn(usize) -> (u32, u32),
+) -> Option<Vec<(usize, (u32, u32))>> {
+    let mut positions = Vec::with_capacity(order.len());
+    let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+    for &i in order {
+        let (w, h) = slot_size(i);
+        if x + w > size {
+            x = 0;
+            y += shelf_height;
+            shelf_height = 0;
+        }
+        if x + w > size || y + h > size {
+            return None;
+        }
+        positions.push((i, (x, y)));
+        x += w;
+        shelf_height = shelf_height.max(h);
+    }
+    Some(positions)
+}
+
+/// Copies `texture` to `rect` and repeats its outermost pixels `gutter` times
+/// in every direction.
+fn blit_with_gutter(atlas: &mut RgbaImage, texture: &RgbaImage, rect: AtlasRect, gutter: u32) {
+    let g = gutter as i64;
+    for y in -g..rect.height as i64 + g {
+        for x in -g..rect.width as i64 + g {
+            let sx = x.max(0).min(rect.width as i64 - 1) as u32;
+            let sy = y.max(0).min(rect.height as i64 - 1) as u32;
+            atlas.put_pixel(
+                (rect.x as i64 + x) as u32,
+                (rect.y as i64 + y) as u32,
+                *texture.get_pixel(sx, sy),
+            );
+        }
+    }
+}
+
+fn log2(n: u32) -> u32 {
+    if n == 0 {
+        0
+    } else {
+        31 - n.leading_zeros()
+    }
+}
+
+fn round_up(n: u32, multiple: u32) -> u32 {
+    n + (multiple - n % multiple) % multiple
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A texture where every pixel is different.
+    fn texture(width: u32, height: u32, seed: u8) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            image::Rgba([x as u8, y as u8, seed, 255])
+        })
+    }
+
+    fn build(gutter: u32, padding: u32) -> (Atlas, Vec<(String, RgbaImage)>) {
+        let textures = [(16, 16), (8, 8), (5, 3), (16, 8), (1, 1), (7, 12)]
+            .iter()
+            .enumerate()
+            .map(|(i, &(w, h))| (format!("texture_{}", i), texture(w, h, i as u8)))
+            .collect::<Vec<_>>();
+        let builder = textures.iter().fold(
+            AtlasBuilder::new().gutter(gutter).padding(padding),
+            |b, (name, image)| b.add(name, image.clone()),
+        );
+        (builder.build().unwrap(), textures)
+    }
+
+    /// The rect with its gutter.
+    fn slot(rect: AtlasRect, gutter: u32) -> (u32, u32, u32, u32) {
+        (
+            rect.x - gutter,
+            rect.y - gutter,
+            rect.x + rect.width + gutter,
+            rect.y + rect.height + gutter,
+        )
+    }
+
+    #[test]
+    fn textures_dont_overlap() {
+        for &(gutter, padding) in [(0, 0), (4, 0), (4, 1), (8, 3)].iter() {
+            let (atlas, _) = build(gutter, padding);
+            let manifest = &atlas.manifest;
+            assert_eq!(manifest.rects.len(), 6);
+            let slots = manifest
+                .rects
+                .values()
+                .map(|&r| slot(r, gutter))
+                .collect::<Vec<_>>();
+            for (i, a) in slots.iter().enumerate() {
+                assert!(a.2 <= manifest.width && a.3 <= manifest.height);
+                for b in slots[i + 1..].iter() {
+                    let apart = a.2 <= b.0 || b.2 <= a.0 || a.3 <= b.1 || b.3 <= a.1;
+                    assert!(apart, "{:?} and {:?} overlap", a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn gutters_repeat_the_edges() {
+        let gutter = 4;
+        let (atlas, textures) = build(gutter, 1);
+        for (name, texture) in textures.iter() {
+            let rect = atlas.manifest.rect(name).unwrap();
+            let (x0, y0, x1, y1) = slot(rect, gutter);
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    let sx = x.max(rect.x).min(rect.x + rect.width - 1) - rect.x;
+                    let sy = y.max(rect.y).min(rect.y + rect.height - 1) - rect.y;
+                    assert_eq!(
+                        atlas.image.get_pixel(x, y),
+                        texture.get_pixel(sx, sy),
+                        "{} at {} {}",
+                        name,
+                        x,
+                        y
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn slots_are_aligned_to_the_smallest_clean_level() {
+        for &(gutter, mip_levels) in [(0, 1), (1, 1), (2, 2), (4, 3), (8, 4)].iter() {
+            let (atlas, _) = build(gutter, 1);
+            assert_eq!(atlas.manifest.mip_levels, mip_levels);
+            let align = 1 << (mip_levels - 1);
+            for &rect in atlas.manifest.rects.values() {
+                let (x0, y0, _, _) = slot(rect, gutter);
+                assert_eq!((x0 % align, y0 % align), (0, 0), "{:?}", rect);
+            }
+            assert_eq!(atlas.manifest.width % align, 0);
+        }
+    }
+
+    #[test]
+    fn manifest_round_trip() {
+        let (atlas, _) = build(4, 0);
+        let text = atlas.manifest.to_string();
+        assert!(text.starts_with("size 64 64\nmip_levels 3\n"), "{}", text);
+        assert_eq!(AtlasManifest::parse(&text).unwrap(), atlas.manifest);
+        assert!(AtlasManifest::parse("mip_levels 3\n").is_err());
+        assert!(AtlasManifest::parse("size 4 4\na 0 0 1 1\na 1 1 1 1\n").is_err());
+    }
+}
@@ -0,0 +1,90 @@
+//! Packs textures into an atlas, e.g.
+//! `cargo run --bin atlas -- res/blocks out/atlas.png --gutter 4`.
+//! Writes the image and a manifest next to it, see `src/atlas.rs`. With a
+//! `.ktx2` output the mip levels are built as well and stored with the image.
+
+#[allow(dead_code)]
+#[path = "../atlas.rs"]
+mod atlas;
+#[allow(dead_code)]
+#[path = "../ktx2.rs"]
+mod ktx2;
+#[allow(dead_code)]
+#[path = "../mip_chain.rs"]
+mod mip_chain;
+
+use anyhow::*;
+use atlas::AtlasBuilder;
+use ktx2::Ktx2;
+use mip_chain::DownsampleFilter;
+use std::path::Path;
+
+const USAGE: &str = "Usage: atlas <texture dir> <output.png|output.ktx2> [--gutter N] \
+                     [--padding N] [--max-size N] [--mip-filter box|kaiser|lanczos]";
+
+fn main() -> Result<()> {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let (input, output) = match args.as_slice() {
+        [input, output, ..] if !input.starts_with("--") && !output.starts_with("--") => {
+            (input, Path::new(output))
+        }
+        _ => bail!(USAGE),
+    };
+
+    let mut builder = AtlasBuilder::new();
+    let mut mip_filter = DownsampleFilter::Box;
+    let mut options = args[2..].iter();
+    while let Some(option) = options.next() {
+        let value = options
+            .next()
+            .with_context(|| format!("{} needs a value\n{}", option, USAGE))?;
+        let number = || {
+            value
+                .parse::<u32>()
+                .with_context(|| format!("{} needs a number\n{}", option, USAGE))
+        };
+        match option.as_str() {
+            "--gutter" => builder = builder.gutter(number()?),
+            "--padding" => builder = builder.padding(number()?),
+            "--max-size" => builder = builder.max_size(number()?),
+            "--mip-filter" => mip_filter = value.parse().map_err(|e| anyhow!("{}", e))?,
+            _ => bail!("Unknown option {}\n{}", option, USAGE),
+        }
+    }
+
+    let mut atlas = builder.add_dir(input)?.build()?;
+    if output.extension().map_or(false, |e| e == "ktx2") {
+        // The gutter keeps that many box filtered levels clean, filters that
+        // reach further need a wider one.
+        let clean = mip_filter.clean_levels(1 << (atlas.manifest.mip_levels - 1));
+        if clean < atlas.manifest.mip_levels {
+            println!(
+                "The gutter only keeps {} levels clean with that filter",
+                clean
+            );
+            atlas.manifest.mip_levels = clean;
+        }
+        let mut levels = mip_chain::mip_chain(&atlas.image, mip_filter, true);
+        // The smaller levels would mix neighbouring textures.
+        levels.truncate(atlas.manifest.mip_levels as usize);
+        println!("Built {} mip levels", levels.len());
+        Ktx2 {
+            vk_format: ktx2::VK_FORMAT_R8G8B8A8_SRGB,
+            width: atlas.manifest.width,
+            height: atlas.manifest.height,
+            layer_count: 1,
+            levels: levels.into_iter().map(|l| l.into_raw()).collect(),
+        }
+        .save(output)?;
+        atlas.manifest.save(output.with_extension("txt"))?;
+    } else {
+        atlas.save(output)?;
+    }
+    println!(
+        "Packed {} textures into a {}x{} atlas",
+        atlas.manifest.rects.len(),
+        atlas.manifest.width,
+        atlas.manifest.height
+    );
+    Ok(())
+}
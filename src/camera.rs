@@ -14,6 +14,7 @@ pub struct Camera {
     target: Point3<f32>,
     up: Vector3<f32>,
     pub aspect: f32,
+    /// Vertical field of view in degrees.
     fov: f32,
     near: f32,
     far: f32,
@@ -64,10 +65,21 @@ impl Camera {
             Translation3::new(self.radius.x, self.radius.y, self.radius.z).to_homogeneous();
         let view = Matrix4::look_at_rh(&self.eye, &target, &self.up);
         let model = radius_from_center * rot * translation;
-        let proj = Matrix4::new_perspective(self.aspect, self.fov, self.near, self.far);
+        let proj =
+            Matrix4::new_perspective(self.aspect, self.fov.to_radians(), self.near, self.far);
         let result = OPENGL_TO_WGPU_MATRIX * proj * view * model.try_inverse().unwrap();
         return result;
     }
+
+    /// Sets the vertical field of view, in degrees.
+    pub fn set_fov(&mut self, fov: f32) {
+        self.fov = fov;
+    }
+
+    /// Unit vector in the direction the camera looks.
+    pub fn direction(&self) -> Vector3<f32> {
+        self.target.coords.normalize()
+    }
 }
 
 pub struct CameraController {
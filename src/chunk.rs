@@ -1,7 +1,8 @@
-use crate::cube::{Cube, CubeType};
+use crate::cube::{BlockTextures, Cube, CubeType};
 use crate::modeling::custom_models::quad;
 use crate::modeling::vertex_index::Vertex;
 use crate::quad::Quad;
+use nalgebra::{Point3, Vector3};
 use wgpu::util::DeviceExt;
 use crate::rendering::graphics::Graphics;
 
@@ -16,19 +17,94 @@ const CHUNK_HEIGHT: usize = 16;
 
 const CHUNK_SIZE: usize = CHUNK_WIDTH * CHUNK_LENGTH * CHUNK_HEIGHT;
 
+/// First and last x and z of the pool of water in the top layer.
+const POOL: (usize, usize) = (5, 10);
+
 impl Chunk {
-    pub fn new(graphics: &Graphics) -> Chunk {
-        let default = Cube::default();
-        let mut voxels: [Cube; CHUNK_SIZE] = [default; CHUNK_SIZE];
+    pub fn new(graphics: &Graphics, textures: &BlockTextures) -> Chunk {
+        let mut voxels = Chunk::generate();
 
-        let faces = Chunk::filter_unseen_quads(&mut voxels);
+        let faces = Chunk::filter_unseen_quads(&mut voxels, textures);
 
         let chunk_mesh = ChunkMesh::new(&graphics, quad::VERTICES, quad::INDICES, faces);
 
         Chunk { voxels, chunk_mesh }
     }
 
-    fn filter_unseen_quads(voxels: &mut [Cube; CHUNK_SIZE]) -> Vec<Quad> {
+    /// Grass, with a pool of water in the middle of the top layer.
+    fn generate() -> [Cube; CHUNK_SIZE] {
+        let mut voxels = [Cube::default(); CHUNK_SIZE];
+        let y = CHUNK_HEIGHT - 1;
+        for z in POOL.0..=POOL.1 {
+            for x in POOL.0..=POOL.1 {
+                voxels[x + 16 * z + 16 * 16 * y].cube_type = CubeType::WATER;
+            }
+        }
+        voxels
+    }
+
+    /// Corners of the box around the blocks. Blocks are centered on whole
+    /// coordinates, so it starts half a block before the first one.
+    pub fn bounds(&self) -> (Point3<f32>, Point3<f32>) {
+        (
+            Point3::new(-0.5, -0.5, -0.5),
+            Point3::new(
+                CHUNK_WIDTH as f32 - 0.5,
+                CHUNK_HEIGHT as f32 - 0.5,
+                CHUNK_LENGTH as f32 - 0.5,
+            ),
+        )
+    }
+
+    /// The first block a ray hits within `max_distance`, as `[x, y, z]`.
+    /// `direction` has to be a unit vector.
+    pub fn raycast(
+        &self,
+        origin: Point3<f32>,
+        direction: Vector3<f32>,
+        max_distance: f32,
+    ) -> Option<[usize; 3]> {
+        // Walks the cells the ray passes through one at a time. The cells are
+        // shifted by half a block, so they start at whole coordinates.
+        let start = origin + Vector3::repeat(0.5);
+        let size = [CHUNK_WIDTH, CHUNK_HEIGHT, CHUNK_LENGTH];
+        let mut cell = [0i64; 3];
+        let mut step = [0i64; 3];
+        // Distance along the ray to the next cell border on each axis, and
+        // between two borders.
+        let mut next = [f32::INFINITY; 3];
+        let mut delta = [f32::INFINITY; 3];
+        for i in 0..3 {
+            cell[i] = start[i].floor() as i64;
+            if direction[i] > 0. {
+                step[i] = 1;
+                next[i] = (cell[i] as f32 + 1. - start[i]) / direction[i];
+                delta[i] = 1. / direction[i];
+            } else if direction[i] < 0. {
+                step[i] = -1;
+                next[i] = (cell[i] as f32 - start[i]) / direction[i];
+                delta[i] = -1. / direction[i];
+            }
+        }
+        let mut distance = 0.;
+        while distance <= max_distance {
+            if (0..3).all(|i| cell[i] >= 0 && (cell[i] as usize) < size[i]) {
+                let (x, y, z) = (cell[0] as usize, cell[1] as usize, cell[2] as usize);
+                if self.voxels[x + CHUNK_WIDTH * z + CHUNK_WIDTH * CHUNK_LENGTH * y].is_active {
+                    return Some([x, y, z]);
+                }
+            }
+            let axis = (0..3)
+                .min_by(|&a, &b| next[a].partial_cmp(&next[b]).unwrap())
+                .unwrap();
+            distance = next[axis];
+            cell[axis] += step[axis];
+            next[axis] += delta[axis];
+        }
+        None
+    }
+
+    fn filter_unseen_quads(voxels: &mut [Cube; CHUNK_SIZE], textures: &BlockTextures) -> Vec<Quad> {
         let mut faces: Vec<Quad> = Vec::new();
         for y in 0..16 {
             for z in 0..16 {
@@ -67,11 +143,11 @@ impl Chunk {
                         right_face,
                         back_face,
                         front_face,
-                        CubeType::GRASS,
+                        voxels[x + 16 * z + 16 * 16 * y].cube_type,
                     );
                     faces.append(
                         &mut voxels[x + 16 * z + 16 * 16 * y]
-                            .get_faces([x as f32, y as f32, z as f32]),
+                            .get_faces([x as f32, y as f32, z as f32], textures),
                     );
                 }
             }
@@ -82,6 +158,22 @@ impl Chunk {
     pub fn get_block(&self, x: usize, y: usize, z: usize) -> &Cube {
         &self.voxels[x + 16 * z + 16 * 16 * y]
     }
+
+    /// The active block around `point`, if there is one.
+    pub fn block_at(&self, point: Point3<f32>) -> Option<&Cube> {
+        block_at(&self.voxels, point)
+    }
+}
+
+fn block_at(voxels: &[Cube; CHUNK_SIZE], point: Point3<f32>) -> Option<&Cube> {
+    // Blocks are centered on whole coordinates.
+    let cell = point.map(|c| c.round());
+    let size = [CHUNK_WIDTH, CHUNK_HEIGHT, CHUNK_LENGTH];
+    if (0..3).any(|i| cell[i] < 0. || cell[i] >= size[i] as f32) {
+        return None;
+    }
+    let (x, y, z) = (cell.x as usize, cell.y as usize, cell.z as usize);
+    Some(&voxels[x + 16 * z + 16 * 16 * y]).filter(|b| b.is_active)
 }
 
 pub trait DrawChunk<'a> {
@@ -91,6 +183,14 @@ pub trait DrawChunk<'a> {
         light_bind_group: &'a wgpu::BindGroup,
         matrix_bind_group: &'a wgpu::BindGroup,
     );
+
+    /// Draws the chunk with only the matrices bound, for pipelines that don't
+    /// shade it.
+    fn draw_chunk_geometry(
+        &mut self,
+        chunk_mesh: &'a ChunkMesh,
+        matrix_bind_group: &'a wgpu::BindGroup,
+    );
 }
 
 impl<'a> DrawChunk<'a> for wgpu::RenderPass<'a> {
@@ -99,11 +199,19 @@ impl<'a> DrawChunk<'a> for wgpu::RenderPass<'a> {
         chunk_mesh: &'a ChunkMesh,
         light_bind_group: &'a wgpu::BindGroup,
         matrix_bind_group: &'a wgpu::BindGroup,
+    ) {
+        self.set_bind_group(2, light_bind_group, &[]);
+        self.draw_chunk_geometry(chunk_mesh, matrix_bind_group);
+    }
+
+    fn draw_chunk_geometry(
+        &mut self,
+        chunk_mesh: &'a ChunkMesh,
+        matrix_bind_group: &'a wgpu::BindGroup,
     ) {
         self.set_vertex_buffer(0, chunk_mesh.vertex_buffer.slice(..));
         self.set_vertex_buffer(1, chunk_mesh.instance_buffer.slice(..));
         self.set_bind_group(0, matrix_bind_group, &[]);
-        self.set_bind_group(2, light_bind_group, &[]);
         self.set_index_buffer(chunk_mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
 
         self.draw_indexed(
@@ -120,6 +228,7 @@ pub struct ChunkMesh {
     instance_buffer: wgpu::Buffer,
     indices_len: usize,
     instances_len: usize,
+    buffer_bytes: u64,
 }
 
 impl ChunkMesh {
@@ -147,12 +256,47 @@ impl ChunkMesh {
         });
         let indices_len = indices.len();
         let instances_len = instances.len();
+        let buffer_bytes = (std::mem::size_of_val(vertices)
+            + std::mem::size_of_val(indices)
+            + std::mem::size_of_val(instance_data.as_slice())) as u64;
         Self {
             vertex_buffer,
             index_buffer,
             instance_buffer,
             indices_len,
             instances_len,
+            buffer_bytes,
         }
     }
+
+    /// Faces of the chunk, one instance each.
+    pub fn quad_count(&self) -> usize {
+        self.instances_len
+    }
+
+    /// Size of the vertex, index and instance buffers.
+    pub fn gpu_memory(&self) -> u64 {
+        self.buffer_bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_pool_is_fluid() {
+        let voxels = Chunk::generate();
+        let top = (CHUNK_HEIGHT - 1) as f32;
+        let is_fluid = |x: f32, y: f32, z: f32| {
+            block_at(&voxels, Point3::new(x, y, z)).map(|b| b.cube_type.is_fluid())
+        };
+        assert_eq!(is_fluid(POOL.0 as f32, top, POOL.1 as f32), Some(true));
+        // Points are rounded to the closest block.
+        assert_eq!(is_fluid(7.6, top - 0.4, 8.2), Some(true));
+        assert_eq!(is_fluid(POOL.0 as f32 - 1., top, 8.), Some(false));
+        assert_eq!(is_fluid(8., top - 1., 8.), Some(false));
+        assert_eq!(is_fluid(8., top + 1., 8.), None);
+        assert_eq!(is_fluid(-1., 0., 0.), None);
+    }
 }
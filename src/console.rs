@@ -0,0 +1,513 @@
+//! The developer console, opened with the key below Escape. Commands are
+//! registered in a [`CommandRegistry`] with typed arguments, e.g. `tp x y z`
+//! takes three `f32`s, and run against the state the registry was made for.
+
+use anyhow::Result;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+use winit::event::VirtualKeyCode;
+
+/// Lines of output the console keeps.
+const OUTPUT_LINES: usize = 12;
+
+#[derive(Debug, PartialEq)]
+pub enum CommandError {
+    Unknown(String),
+    ArgumentCount {
+        usage: String,
+        expected: usize,
+        got: usize,
+    },
+    Argument {
+        usage: String,
+        index: usize,
+        value: String,
+        message: String,
+    },
+    UnclosedQuote,
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::Unknown(name) => {
+                write!(f, "Unknown command {:?}, try \"help\"", name)
+            }
+            CommandError::ArgumentCount {
+                usage,
+                expected,
+                got,
+            } => write!(
+                f,
+                "Expected {} arguments, got {}. Usage: {}",
+                expected, got, usage
+            ),
+            CommandError::Argument {
+                usage,
+                index,
+                value,
+                message,
+            } => write!(
+                f,
+                "Argument {} ({:?}) is invalid: {}. Usage: {}",
+                index + 1,
+                value,
+                message,
+                usage
+            ),
+            CommandError::UnclosedQuote => write!(f, "A quote isn't closed"),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+/// Arguments a command can take, parsed from the words after its name.
+/// Implemented for tuples of up to four [`FromStr`] types.
+pub trait CommandArgs: Sized {
+    const COUNT: usize;
+
+    /// Parses exactly `COUNT` words. On failure returns the index of the
+    /// word and what's wrong with it.
+    fn parse(words: &[&str]) -> Result<Self, (usize, String)>;
+}
+
+macro_rules! impl_command_args {
+    ($count:expr; $($ty:ident $index:tt),*) => {
+        impl<$($ty),*> CommandArgs for ($($ty,)*)
+        where
+            $($ty: FromStr, $ty::Err: fmt::Display,)*
+        {
+            const COUNT: usize = $count;
+
+            #[allow(unused_variables)]
+            fn parse(words: &[&str]) -> Result<Self, (usize, String)> {
+                Ok(($(
+                    words[$index]
+                        .parse::<$ty>()
+                        .map_err(|e| ($index, e.to_string()))?,
+                )*))
+            }
+        }
+    };
+}
+
+impl_command_args!(0;);
+impl_command_args!(1; A 0);
+impl_command_args!(2; A 0, B 1);
+impl_command_args!(3; A 0, B 1, C 2);
+impl_command_args!(4; A 0, B 1, C 2, D 3);
+
+type Handler<T> = Box<dyn Fn(&mut T, &[&str]) -> Result<String>>;
+
+struct Command<T> {
+    usage: String,
+    help: String,
+    handler: Handler<T>,
+}
+
+/// Commands that run against a `T`. Names can be several words long, like
+/// `set fov`, the longest registered name that a line starts with is run.
+pub struct CommandRegistry<T> {
+    commands: BTreeMap<String, Command<T>>,
+}
+
+impl<T> CommandRegistry<T> {
+    pub fn new() -> Self {
+        Self {
+            commands: BTreeMap::new(),
+        }
+    }
+
+    /// Registers `name`, replacing a command with the same name. `params`
+    /// names the arguments for the usage text, e.g. `"<x> <y> <z>"`. The
+    /// message `handler` returns is printed to the console.
+    pub fn register<A, F>(&mut self, name: &str, params: &str, help: &str, handler: F)
+    where
+        A: CommandArgs,
+        F: Fn(&mut T, A) -> Result<String> + 'static,
+    {
+        let usage = if params.is_empty() {
+            name.to_string()
+        } else {
+            format!("{} {}", name, params)
+        };
+        let handler_usage = usage.clone();
+        let handler = move |target: &mut T, words: &[&str]| {
+            if words.len() != A::COUNT {
+                return Err(CommandError::ArgumentCount {
+                    usage: handler_usage.clone(),
+                    expected: A::COUNT,
+                    got: words.len(),
+                }
+                .into());
+            }
+            let args = A::parse(words).map_err(|(index, message)| CommandError::Argument {
+                usage: handler_usage.clone(),
+                index,
+                value: words[index].to_string(),
+                message,
+            })?;
+            handler(target, args)
+        };
+        self.commands.insert(
+            name.to_string(),
+            Command {
+                usage,
+                help: help.to_string(),
+                handler: Box::new(handler),
+            },
+        );
+    }
+
+    /// Parses `line` and runs the command it names.
+    pub fn execute(&self, target: &mut T, line: &str) -> Result<String> {
+        let words = split_words(line)?;
+        let words = words.iter().map(String::as_str).collect::<Vec<_>>();
+        if words.is_empty() {
+            return Ok(String::new());
+        }
+        let (name_len, command) = (1..=words.len())
+            .rev()
+            .find_map(|len| {
+                self.commands
+                    .get(&words[..len].join(" "))
+                    .map(|command| (len, command))
+            })
+            .ok_or_else(|| CommandError::Unknown(words[0].to_string()))?;
+        (command.handler)(target, &words[name_len..])
+    }
+
+    /// Names of the commands that start with `prefix`, in order.
+    pub fn complete(&self, prefix: &str) -> Vec<&str> {
+        self.commands
+            .keys()
+            .filter(|name| name.starts_with(prefix))
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// The usage and help of every command, one per line.
+    pub fn help(&self) -> String {
+        self.commands
+            .values()
+            .map(|c| format!("{} - {}", c.usage, c.help))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Splits at whitespace, except within double quotes.
+fn split_words(line: &str) -> Result<Vec<String>, CommandError> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_word = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if quoted {
+        return Err(CommandError::UnclosedQuote);
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+/// What a key press in the console asks for.
+#[derive(Debug, PartialEq)]
+pub enum ConsoleAction {
+    /// Run this line.
+    Submit(String),
+    /// Complete the input.
+    Complete,
+    Close,
+    None,
+}
+
+/// The input line, history and output of the console. Drawing it is up to
+/// [`DebugInfo`](crate::debug_info::DebugInfo).
+#[derive(Debug, Default)]
+pub struct Console {
+    pub open: bool,
+    pub input: String,
+    history: Vec<String>,
+    /// Position in `history` while going through it with the arrow keys.
+    history_index: Option<usize>,
+    output: VecDeque<String>,
+}
+
+impl Console {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+    }
+
+    /// Oldest first.
+    pub fn output(&self) -> impl Iterator<Item = &str> {
+        self.output.iter().map(String::as_str)
+    }
+
+    /// Adds `text` to the output, a line at a time.
+    pub fn print(&mut self, text: &str) {
+        for line in text.lines() {
+            if self.output.len() == OUTPUT_LINES {
+                self.output.pop_front();
+            }
+            self.output.push_back(line.to_string());
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.output.clear();
+    }
+
+    /// Typed text. Control characters and the toggle key are left to
+    /// [`key`](Self::key).
+    pub fn char(&mut self, c: char) {
+        if !c.is_control() && c != '`' {
+            self.input.push(c);
+        }
+    }
+
+    pub fn key(&mut self, key: VirtualKeyCode) -> ConsoleAction {
+        match key {
+            VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => {
+                let line = std::mem::take(&mut self.input);
+                self.history_index = None;
+                if line.trim().is_empty() {
+                    return ConsoleAction::None;
+                }
+                if self.history.last() != Some(&line) {
+                    self.history.push(line.clone());
+                }
+                self.print(&format!("> {}", line));
+                ConsoleAction::Submit(line)
+            }
+            VirtualKeyCode::Back => {
+                self.input.pop();
+                ConsoleAction::None
+            }
+            VirtualKeyCode::Up => {
+                let index = match self.history_index {
+                    Some(index) => index.saturating_sub(1),
+                    None if self.history.is_empty() => return ConsoleAction::None,
+                    None => self.history.len() - 1,
+                };
+                self.history_index = Some(index);
+                self.input = self.history[index].clone();
+                ConsoleAction::None
+            }
+            VirtualKeyCode::Down => {
+                match self.history_index {
+                    Some(index) if index + 1 < self.history.len() => {
+                        self.history_index = Some(index + 1);
+                        self.input = self.history[index + 1].clone();
+                    }
+                    Some(_) => {
+                        self.history_index = None;
+                        self.input.clear();
+                    }
+                    None => {}
+                }
+                ConsoleAction::None
+            }
+            VirtualKeyCode::Tab => ConsoleAction::Complete,
+            VirtualKeyCode::Escape | VirtualKeyCode::Grave => ConsoleAction::Close,
+            _ => ConsoleAction::None,
+        }
+    }
+
+    /// Completes the input to the command names of `registry` it's the start
+    /// of. With several candidates it's completed as far as they agree and
+    /// they're printed.
+    pub fn complete<T>(&mut self, registry: &CommandRegistry<T>) {
+        let candidates = registry.complete(&self.input);
+        match candidates.as_slice() {
+            [] => {}
+            [name] => self.input = format!("{} ", name),
+            [first, rest @ ..] => {
+                let common = rest.iter().fold(first.len(), |len, name| {
+                    first
+                        .bytes()
+                        .zip(name.bytes())
+                        .take(len)
+                        .take_while(|(a, b)| a == b)
+                        .count()
+                });
+                self.input = first[..common].to_string();
+                let list = candidates.join("  ");
+                self.print(&list);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct State {
+        position: (f32, f32, f32),
+        fov: f32,
+        regenerated: bool,
+    }
+
+    fn registry() -> CommandRegistry<State> {
+        let mut registry = CommandRegistry::new();
+        registry.register(
+            "tp",
+            "<x> <y> <z>",
+            "Teleport",
+            |s: &mut State, (x, y, z)| {
+                s.position = (x, y, z);
+                Ok(format!("{} {} {}", x, y, z))
+            },
+        );
+        registry.register(
+            "set fov",
+            "<degrees>",
+            "Field of view",
+            |s: &mut State, (fov,)| {
+                s.fov = fov;
+                Ok(String::new())
+            },
+        );
+        registry.register(
+            "set",
+            "<name> <value>",
+            "Anything else",
+            |_: &mut State, (name, value): (String, i32)| Ok(format!("{} = {}", name, value)),
+        );
+        registry.register("regen", "", "Regenerate", |s: &mut State, ()| {
+            s.regenerated = true;
+            Ok(String::new())
+        });
+        registry
+    }
+
+    #[test]
+    fn splits_words_and_quotes() {
+        assert_eq!(
+            split_words("  say \"hello world\" x ").unwrap(),
+            vec!["say", "hello world", "x"]
+        );
+        assert_eq!(split_words("a \"\" b").unwrap(), vec!["a", "", "b"]);
+        assert_eq!(split_words("say \"oops"), Err(CommandError::UnclosedQuote));
+    }
+
+    #[test]
+    fn dispatches_typed_arguments() {
+        let registry = registry();
+        let mut state = State::default();
+        let message = registry.execute(&mut state, "tp 1 -2.5 3").unwrap();
+        assert_eq!(state.position, (1., -2.5, 3.));
+        assert_eq!(message, "1 -2.5 3");
+        registry.execute(&mut state, "regen").unwrap();
+        assert!(state.regenerated);
+        assert_eq!(registry.execute(&mut state, "   ").unwrap(), "");
+    }
+
+    #[test]
+    fn prefers_the_longest_name() {
+        let registry = registry();
+        let mut state = State::default();
+        registry.execute(&mut state, "set fov 70").unwrap();
+        assert_eq!(state.fov, 70.);
+        assert_eq!(
+            registry.execute(&mut state, "set speed 3").unwrap(),
+            "speed = 3"
+        );
+    }
+
+    #[test]
+    fn reports_errors() {
+        let registry = registry();
+        let mut state = State::default();
+        let error = |line: &str| {
+            registry
+                .execute(&mut State::default(), line)
+                .unwrap_err()
+                .downcast::<CommandError>()
+                .unwrap()
+        };
+        assert_eq!(error("fly"), CommandError::Unknown("fly".to_string()));
+        assert_eq!(
+            error("tp 1 2"),
+            CommandError::ArgumentCount {
+                usage: "tp <x> <y> <z>".to_string(),
+                expected: 3,
+                got: 2,
+            }
+        );
+        match error("tp 1 up 3") {
+            CommandError::Argument { index, value, .. } => {
+                assert_eq!(index, 1);
+                assert_eq!(value, "up");
+            }
+            e => panic!("unexpected error {:?}", e),
+        }
+        assert!(registry.execute(&mut state, "regen now").is_err());
+        assert!(!state.regenerated);
+    }
+
+    #[test]
+    fn completes_names() {
+        let registry = registry();
+        assert_eq!(registry.complete("se"), vec!["set", "set fov"]);
+        assert_eq!(registry.complete("x"), Vec::<&str>::new());
+
+        let mut console = Console::new();
+        console.input = "r".to_string();
+        console.complete(&registry);
+        assert_eq!(console.input, "regen ");
+
+        console.input = "s".to_string();
+        console.complete(&registry);
+        assert_eq!(console.input, "set");
+        assert_eq!(console.output().last(), Some("set  set fov"));
+    }
+
+    #[test]
+    fn keeps_history() {
+        let mut console = Console::new();
+        for line in ["tp 0 0 0", "regen"].iter() {
+            line.chars().for_each(|c| console.char(c));
+            assert_eq!(
+                console.key(VirtualKeyCode::Return),
+                ConsoleAction::Submit(line.to_string())
+            );
+        }
+        assert_eq!(console.input, "");
+        console.key(VirtualKeyCode::Up);
+        assert_eq!(console.input, "regen");
+        console.key(VirtualKeyCode::Up);
+        console.key(VirtualKeyCode::Up);
+        assert_eq!(console.input, "tp 0 0 0");
+        console.key(VirtualKeyCode::Down);
+        assert_eq!(console.input, "regen");
+        console.key(VirtualKeyCode::Down);
+        assert_eq!(console.input, "");
+    }
+}
@@ -1,8 +1,7 @@
+use crate::atlas::AtlasManifest;
 use crate::quad::{Quad, QuadDirection};
+use anyhow::{Context, Result};
 use nalgebra::Rotation3;
-use rand::Rng;
-
-const CUBE_TYPE_OFFSETS: &[(f32, f32)] = &[(1., 1.)];
 
 #[derive(Copy, Clone)]
 pub struct Cube {
@@ -51,15 +50,14 @@ impl Cube {
         }
     }
 
-    pub fn get_faces(&self, position: [f32; 3]) -> Vec<Quad> {
+    pub fn get_faces(&self, position: [f32; 3], textures: &BlockTextures) -> Vec<Quad> {
         let mut quads = Vec::new();
-        let mut rng = rand::thread_rng();
-        let offset = rng.gen_range(0..2) as u32;
+        let uv = |direction: QuadDirection| textures.uv(self.cube_type, direction);
         if self.back_face {
             quads.push(Quad::new(
                 position,
                 Rotation3::new([0., 0., 0.].into()),
-                offset,
+                uv(QuadDirection::SIDE),
                 QuadDirection::SIDE,
             ));
         }
@@ -68,7 +66,7 @@ impl Cube {
             quads.push(Quad::new(
                 position,
                 Rotation3::from_euler_angles(0., pitch.to_radians(), 0.),
-                offset,
+                uv(QuadDirection::SIDE),
                 QuadDirection::SIDE,
             ))
         }
@@ -77,7 +75,7 @@ impl Cube {
             quads.push(Quad::new(
                 position,
                 Rotation3::from_euler_angles(0., pitch.to_radians(), 0.),
-                offset,
+                uv(QuadDirection::SIDE),
                 QuadDirection::SIDE,
             ))
         }
@@ -86,7 +84,7 @@ impl Cube {
             quads.push(Quad::new(
                 position,
                 Rotation3::from_euler_angles(0., pitch.to_radians(), 0.),
-                offset,
+                uv(QuadDirection::SIDE),
                 QuadDirection::SIDE,
             ))
         }
@@ -95,7 +93,7 @@ impl Cube {
             quads.push(Quad::new(
                 position,
                 Rotation3::from_euler_angles(roll.to_radians(), 0., 0.),
-                offset,
+                uv(QuadDirection::UP),
                 QuadDirection::UP,
             ))
         }
@@ -104,7 +102,7 @@ impl Cube {
             quads.push(Quad::new(
                 position,
                 Rotation3::from_euler_angles(roll.to_radians(), 0., 0.),
-                offset,
+                uv(QuadDirection::DOWN),
                 QuadDirection::DOWN,
             ))
         }
@@ -118,4 +116,65 @@ pub enum CubeType {
     DIRT = 1,
     STONE = 2,
     WOOD = 3,
+    WATER = 4,
+}
+
+impl CubeType {
+    pub const ALL: [CubeType; 5] = [
+        CubeType::GRASS,
+        CubeType::DIRT,
+        CubeType::STONE,
+        CubeType::WOOD,
+        CubeType::WATER,
+    ];
+
+    /// Whether the camera can be inside the block, which turns on the
+    /// underwater fog.
+    pub fn is_fluid(self) -> bool {
+        matches!(self, CubeType::WATER)
+    }
+
+    /// Name of the atlas texture on the faces facing `direction`.
+    pub fn face_texture(self, direction: QuadDirection) -> &'static str {
+        match (self, direction) {
+            (CubeType::GRASS, QuadDirection::UP) => "grass_top",
+            (CubeType::GRASS, QuadDirection::SIDE) => "grass_side",
+            (CubeType::GRASS, QuadDirection::DOWN) | (CubeType::DIRT, _) => "dirt",
+            (CubeType::STONE, _) => "stone",
+            (CubeType::WOOD, QuadDirection::SIDE) => "wood_side",
+            (CubeType::WOOD, _) => "wood_top",
+            (CubeType::WATER, _) => "water",
+        }
+    }
+}
+
+const DIRECTIONS: [QuadDirection; 3] =
+    [QuadDirection::SIDE, QuadDirection::DOWN, QuadDirection::UP];
+
+/// Atlas rects of every face of every block type, looked up once so building
+/// a chunk doesn't search the manifest for every quad.
+pub struct BlockTextures {
+    // Indexed by `CubeType` and then `QuadDirection`.
+    uvs: Vec<[[f32; 4]; 3]>,
+}
+
+impl BlockTextures {
+    pub fn new(atlas: &AtlasManifest) -> Result<Self> {
+        let mut uvs = Vec::with_capacity(CubeType::ALL.len());
+        for cube_type in CubeType::ALL.iter() {
+            let mut faces = [[0.; 4]; 3];
+            for &direction in DIRECTIONS.iter() {
+                let name = cube_type.face_texture(direction);
+                faces[direction as usize] = atlas
+                    .uv(name)
+                    .with_context(|| format!("The block atlas has no texture {:?}", name))?;
+            }
+            uvs.push(faces);
+        }
+        Ok(Self { uvs })
+    }
+
+    pub fn uv(&self, cube_type: CubeType, direction: QuadDirection) -> [f32; 4] {
+        self.uvs[cube_type as usize][direction as usize]
+    }
 }
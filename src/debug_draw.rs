@@ -0,0 +1,366 @@
+//! Immediate mode debug drawing. Shapes are added to a [`DebugDraw`] during a
+//! frame, turned into lines and drawn on top of the scene by
+//! [`DebugDrawRenderer`], then cleared for the next frame.
+//!
+//! Lines in [`DebugDraw::depth_tested`] are hidden behind the scene, the ones
+//! in [`DebugDraw::on_top`] are always visible. [`DebugDraw::screen`] is for
+//! lines that are already in device coordinates, like the frame time graph.
+
+use crate::hot_reload;
+use crate::modeling::vertex_index::VertexLayout;
+use crate::render_pipeline_tools::{new_render_pipeline, PipelineVariant};
+use crate::rendering::preprocessor::Preprocessor;
+use crate::rendering::reflection::PipelineInterface;
+use crate::texture::Texture;
+use crate::uniform_matrix::MatrixUniform;
+use anyhow::{Context, Result};
+use nalgebra::{Point3, Vector3};
+use std::f32::consts::PI;
+use std::ops::Range;
+
+pub const RED: [f32; 3] = [1., 0., 0.];
+pub const GREEN: [f32; 3] = [0., 1., 0.];
+pub const BLUE: [f32; 3] = [0., 0., 1.];
+pub const YELLOW: [f32; 3] = [1., 1., 0.];
+pub const WHITE: [f32; 3] = [1., 1., 1.];
+
+/// Segments of each circle of a sphere.
+const SPHERE_SEGMENTS: usize = 24;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+pub struct LineVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 3],
+}
+
+/// The shapes of one frame.
+#[derive(Debug, Default)]
+pub struct DebugDraw {
+    /// Lines that the scene can hide.
+    pub depth_tested: DebugLines,
+    /// Lines that are drawn over everything.
+    pub on_top: DebugLines,
+    /// Lines in normalized device coordinates, from -1 to 1 with y up. Drawn
+    /// over everything.
+    pub screen: DebugLines,
+}
+
+impl DebugDraw {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.depth_tested.clear();
+        self.on_top.clear();
+        self.screen.clear();
+    }
+
+    fn lists(&self) -> [&DebugLines; 3] {
+        [&self.depth_tested, &self.on_top, &self.screen]
+    }
+}
+
+/// A list of lines, two vertices each.
+#[derive(Debug, Default)]
+pub struct DebugLines {
+    vertices: Vec<LineVertex>,
+}
+
+impl DebugLines {
+    pub fn vertices(&self) -> &[LineVertex] {
+        &self.vertices
+    }
+
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+    }
+
+    pub fn line(&mut self, from: Point3<f32>, to: Point3<f32>, color: [f32; 3]) {
+        self.vertices.push(LineVertex {
+            position: from.coords.into(),
+            color,
+        });
+        self.vertices.push(LineVertex {
+            position: to.coords.into(),
+            color,
+        });
+    }
+
+    /// The edges of an axis aligned box.
+    pub fn aabb(&mut self, min: Point3<f32>, max: Point3<f32>, color: [f32; 3]) {
+        let corner = |x: bool, y: bool, z: bool| {
+            Point3::new(
+                if x { max.x } else { min.x },
+                if y { max.y } else { min.y },
+                if z { max.z } else { min.z },
+            )
+        };
+        for &a in [false, true].iter() {
+            for &b in [false, true].iter() {
+                self.line(corner(false, a, b), corner(true, a, b), color);
+                self.line(corner(a, false, b), corner(a, true, b), color);
+                self.line(corner(a, b, false), corner(a, b, true), color);
+            }
+        }
+    }
+
+    /// A circle around each axis.
+    pub fn sphere(&mut self, center: Point3<f32>, radius: f32, color: [f32; 3]) {
+        let axes = [Vector3::x(), Vector3::y(), Vector3::z()];
+        for i in 0..3 {
+            let (u, v) = (axes[i], axes[(i + 1) % 3]);
+            let point = |segment: usize| {
+                let angle = segment as f32 / SPHERE_SEGMENTS as f32 * 2. * PI;
+                center + (u * angle.cos() + v * angle.sin()) * radius
+            };
+            for segment in 0..SPHERE_SEGMENTS {
+                self.line(point(segment), point(segment + 1), color);
+            }
+        }
+    }
+
+    /// A line with a head at `to`, a fifth of its length.
+    pub fn arrow(&mut self, from: Point3<f32>, to: Point3<f32>, color: [f32; 3]) {
+        self.line(from, to, color);
+        let direction = to - from;
+        let length = direction.norm();
+        if length == 0. {
+            return;
+        }
+        let direction = direction / length;
+        // Any vector that isn't parallel to the arrow gives us a side.
+        let other = if direction.y.abs() < 0.9 {
+            Vector3::y()
+        } else {
+            Vector3::x()
+        };
+        let side = direction.cross(&other).normalize();
+        let up = direction.cross(&side);
+        let head = length * 0.2;
+        for offset in [side, -side, up, -up].iter() {
+            self.line(to, to - direction * head + offset * head * 0.5, color);
+        }
+    }
+
+    /// The x (red), y (green) and z (blue) axes at `origin`.
+    pub fn axes(&mut self, origin: Point3<f32>, length: f32) {
+        self.arrow(origin, origin + Vector3::x() * length, RED);
+        self.arrow(origin, origin + Vector3::y() * length, GREEN);
+        self.arrow(origin, origin + Vector3::z() * length, BLUE);
+    }
+
+    /// A grid of `cells` by `cells` squares on the XZ plane, centered on
+    /// `center`.
+    pub fn grid(&mut self, center: Point3<f32>, cells: u32, cell_size: f32, color: [f32; 3]) {
+        let half = cells as f32 * cell_size / 2.;
+        for i in 0..=cells {
+            let offset = i as f32 * cell_size - half;
+            self.line(
+                center + Vector3::new(offset, 0., -half),
+                center + Vector3::new(offset, 0., half),
+                color,
+            );
+            self.line(
+                center + Vector3::new(-half, 0., offset),
+                center + Vector3::new(half, 0., offset),
+                color,
+            );
+        }
+    }
+}
+
+/// Draws a [`DebugDraw`] with a line list pipeline for each of its lists.
+pub struct DebugDrawRenderer {
+    /// In the order of [`DebugDraw::lists`].
+    pipelines: [wgpu::RenderPipeline; 3],
+    /// Grows when a frame has more lines than fit.
+    buffer: wgpu::Buffer,
+    capacity: usize,
+    /// The vertices of each list in `buffer`.
+    ranges: [Range<u32>; 3],
+}
+
+impl DebugDrawRenderer {
+    pub fn new(
+        device: &wgpu::Device,
+        dev: bool,
+        matrix_layout: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Result<Self> {
+        let capacity = 1024;
+        Ok(Self {
+            pipelines: create_pipelines(device, dev, matrix_layout, format, sample_count)?,
+            buffer: create_buffer(device, capacity),
+            capacity,
+            ranges: [0..0, 0..0, 0..0],
+        })
+    }
+
+    /// Builds the pipelines again after the shaders or the sample count
+    /// changed. The current ones stay until they're passed to
+    /// [`DebugDrawRenderer::set_pipelines`].
+    pub fn create_pipelines(
+        device: &wgpu::Device,
+        dev: bool,
+        matrix_layout: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Result<DebugLinePipelines> {
+        create_pipelines(device, dev, matrix_layout, format, sample_count).map(DebugLinePipelines)
+    }
+
+    pub fn set_pipelines(&mut self, pipelines: DebugLinePipelines) {
+        self.pipelines = pipelines.0;
+    }
+
+    /// Uploads the lines of `debug_draw`, before the render pass that draws
+    /// them begins.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, debug_draw: &DebugDraw) {
+        let lists = debug_draw.lists();
+        let len = lists.iter().map(|l| l.vertices().len()).sum::<usize>();
+        if len > self.capacity {
+            self.capacity = len.next_power_of_two();
+            self.buffer = create_buffer(device, self.capacity);
+        }
+        let vertex_size = std::mem::size_of::<LineVertex>() as wgpu::BufferAddress;
+        let mut start = 0;
+        for (list, range) in lists.iter().zip(self.ranges.iter_mut()) {
+            let vertices = list.vertices();
+            if !vertices.is_empty() {
+                queue.write_buffer(
+                    &self.buffer,
+                    start as wgpu::BufferAddress * vertex_size,
+                    bytemuck::cast_slice(vertices),
+                );
+            }
+            *range = start..start + vertices.len() as u32;
+            start = range.end;
+        }
+    }
+
+    /// Draws the lines uploaded by [`prepare`](Self::prepare) and returns the
+    /// number of draw calls. The pass needs the depth buffer of the scene.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        matrix_bind_group: &'a wgpu::BindGroup,
+    ) -> u32 {
+        let mut draw_calls = 0;
+        render_pass.set_vertex_buffer(0, self.buffer.slice(..));
+        render_pass.set_bind_group(0, matrix_bind_group, &[]);
+        for (pipeline, range) in self.pipelines.iter().zip(self.ranges.iter()) {
+            if !range.is_empty() {
+                render_pass.set_pipeline(pipeline);
+                render_pass.draw(range.clone(), 0..1);
+                draw_calls += 1;
+            }
+        }
+        draw_calls
+    }
+}
+
+fn create_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Debug line buffer"),
+        size: (capacity * std::mem::size_of::<LineVertex>()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// Pipelines made by [`DebugDrawRenderer::create_pipelines`].
+pub struct DebugLinePipelines([wgpu::RenderPipeline; 3]);
+
+/// The depth tested, on top and screen space pipelines.
+fn create_pipelines(
+    device: &wgpu::Device,
+    dev: bool,
+    matrix_layout: &wgpu::BindGroupLayout,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> Result<[wgpu::RenderPipeline; 3]> {
+    let vert = hot_reload::shader_code(
+        dev,
+        "debug_line.vert",
+        &Preprocessor::new(),
+        include_bytes!(concat!(env!("OUT_DIR"), "/shaders/debug_line.vert.spv")),
+    )?;
+    // `build.rs` compiles this variant as well.
+    let screen_vert = hot_reload::shader_code(
+        dev,
+        "debug_line.vert",
+        &Preprocessor::new().define("SCREEN_SPACE", ""),
+        include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/shaders/debug_line.screen.vert.spv"
+        )),
+    )?;
+    let frag = hot_reload::shader_code(
+        dev,
+        "debug_line.frag",
+        &Preprocessor::new(),
+        include_bytes!(concat!(env!("OUT_DIR"), "/shaders/debug_line.frag.spv")),
+    )?;
+    // The attributes are generated from the shader, a test checks them
+    // against `LineVertex`.
+    let attributes = PipelineInterface::new(&[&vert.reflect()?])?.vertex_attributes(0..2);
+    let buffers = [wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<LineVertex>() as wgpu::BufferAddress,
+        step_mode: wgpu::InputStepMode::Vertex,
+        attributes: &attributes,
+    }];
+    for vert in [&vert, &screen_vert].iter() {
+        PipelineInterface::new(&[&vert.reflect()?, &frag.reflect()?])?
+            .validate(&[&MatrixUniform::BIND_GROUP_LAYOUT_ENTRIES], &buffers)
+            .context("Layouts of the debug line pipeline don't match its shaders")?;
+    }
+
+    let vert = vert.create_module(device, "debug line vertex shader");
+    let screen_vert = screen_vert.create_module(device, "screen line vertex shader");
+    let frag = frag.create_module(device, "debug line fragment shader");
+    let create = |label: &str, vert: &wgpu::ShaderModule, variant: PipelineVariant| {
+        new_render_pipeline(
+            label,
+            device,
+            &[matrix_layout],
+            vert,
+            &frag,
+            format,
+            Texture::DEPTH_FORMAT,
+            sample_count,
+            &buffers,
+            variant,
+        )
+    };
+    let on_top = PipelineVariant {
+        depth_compare: wgpu::CompareFunction::Always,
+        ..PipelineVariant::lines()
+    };
+    Ok([
+        create("debug line", &vert, PipelineVariant::lines()),
+        create("debug line on top", &vert, on_top),
+        create("screen line", &screen_vert, on_top),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rendering::reflection::ShaderInterface;
+
+    #[test]
+    fn generated_vertex_layout_matches_line_vertex() {
+        let vert = ShaderInterface::from_spirv(include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/shaders/debug_line.vert.spv"
+        )))
+        .unwrap();
+        let generated = PipelineInterface::new(&[&vert])
+            .unwrap()
+            .vertex_attributes(0..2);
+        assert_eq!(generated, LineVertex::init_buffer_layout().attributes);
+    }
+}
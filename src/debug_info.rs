@@ -1,7 +1,21 @@
 use crate::camera::Camera;
+use crate::console::Console;
+use crate::debug_draw::{self, DebugLines};
+use crate::debug_view::DebugView;
+use crate::profiler::Scope;
 use futures::task::SpawnExt;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use wgpu_glyph::{ab_glyph, GlyphBrushBuilder, Section, Text};
+use nalgebra::Point3;
+use std::collections::VecDeque;
+use std::time::Instant;
+use wgpu_glyph::{ab_glyph, GlyphBrushBuilder, Layout, Section, Text, VerticalAlign};
+
+/// Frames the frame time statistics and graph cover.
+const FRAME_TIME_SAMPLES: usize = 240;
+/// Frame times at which the graph draws a line, 60 and 30 FPS.
+const GRAPH_MARKS_MS: [f32; 2] = [1000. / 60., 1000. / 30.];
+/// Frame time at the top of the graph.
+const GRAPH_MAX_MS: f32 = 50.;
+const GRAPH_HEIGHT: f32 = 80.;
 
 pub struct DebugInfoBuilder {
     position: (f32, f32),
@@ -26,6 +40,9 @@ impl DebugInfoBuilder {
         }
     }
 
+    /// The overlay starts with the frame time, profiler scopes, position,
+    /// direction and debug view, more can be added with
+    /// [`DebugInfo::add_stat`].
     pub fn build(&self, device: &wgpu::Device) -> Result<DebugInfo, Box<dyn std::error::Error>> {
         let font =
             ab_glyph::FontArc::try_from_slice(include_bytes!("fonts/Inconsolata-Regular.ttf"))?;
@@ -35,68 +52,148 @@ impl DebugInfoBuilder {
         let local_pool = futures::executor::LocalPool::new();
         let local_spawner = local_pool.spawner();
 
-        let info = DebugInfo {
+        let mut info = DebugInfo {
             position: self.position,
             scale: self.scale,
             screen_bounds: self.screen_bounds,
             brush,
-            text: vec![DebugTools::FPS, DebugTools::Position],
-            fps: 0.,
+            stats: Vec::new(),
+            graph: true,
             staging_belt,
             local_pool,
             local_spawner,
         };
+        info.add_stat("Frame time", |s| {
+            let times = s.frame_times;
+            match (
+                times.average(),
+                times.percentile(95.),
+                times.percentile(99.),
+            ) {
+                (Some(average), Some(p95), Some(p99)) => format!(
+                    "{:.2} ms, 95% {:.2} ms, 99% {:.2} ms ({:.0} FPS)",
+                    average,
+                    p95,
+                    p99,
+                    1000. / average
+                ),
+                _ => String::from("-"),
+            }
+        });
+        info.add_stat("CPU", |s| scope_lines(s.cpu_scopes));
+        info.add_stat("GPU", |s| match s.gpu_scopes {
+            Some(scopes) => scope_lines(scopes),
+            None => String::from("no timestamp queries"),
+        });
+        info.add_stat("Position", |s| {
+            let eye = s.camera.eye;
+            format!("x: {:.2}, y: {:.2}, z: {:.2}", eye.x, eye.y, eye.z)
+        });
+        info.add_stat("Direction", |s| {
+            let direction = s.camera.direction();
+            format!(
+                "x: {:.2}, y: {:.2}, z: {:.2}",
+                direction.x, direction.y, direction.z
+            )
+        });
+        info.add_stat("View", |s| format!("{} (F3)", s.debug_view.name()));
         Ok(info)
     }
 }
 
+/// What stat providers can show, gathered by the renderer every frame.
+pub struct FrameStats<'a> {
+    pub frame_times: &'a FrameTimes,
+    /// The scopes of the last frame, see [`Profiler`](crate::profiler::Profiler).
+    pub cpu_scopes: &'a [Scope],
+    /// A few frames older than the CPU scopes. `None` if the adapter can't
+    /// time passes.
+    pub gpu_scopes: Option<&'a [Scope]>,
+    pub camera: &'a Camera,
+    pub debug_view: DebugView,
+    /// Draw calls of the frame, without the overlay itself.
+    pub draw_calls: u32,
+    pub chunks: usize,
+    pub quads: usize,
+    /// Bytes of the buffers and textures we keep track of. wgpu can't tell us
+    /// how much the device uses in total.
+    pub gpu_memory: u64,
+    /// The block the camera looks at.
+    pub target_block: Option<[usize; 3]>,
+}
+
+/// One line per scope, indented by how deep it's nested.
+fn scope_lines(scopes: &[Scope]) -> String {
+    if scopes.is_empty() {
+        return String::from("-");
+    }
+    scopes
+        .iter()
+        .map(|scope| {
+            format!(
+                "\n  {}{} {:.2} ms",
+                "  ".repeat(scope.depth),
+                scope.name,
+                scope.duration.as_secs_f32() * 1000.
+            )
+        })
+        .collect()
+}
+
+/// Turns the stats of a frame into the text of one line of the overlay.
+pub type StatProvider = Box<dyn Fn(&FrameStats) -> String>;
+
 pub struct DebugInfo {
     position: (f32, f32),
     scale: f32,
     screen_bounds: (u32, u32),
     brush: wgpu_glyph::GlyphBrush<()>,
-    text: Vec<DebugTools>,
-    fps: f64,
+    stats: Vec<(String, StatProvider)>,
+    /// Whether the frame time graph is drawn.
+    pub graph: bool,
     staging_belt: wgpu::util::StagingBelt,
     local_pool: futures::executor::LocalPool,
     local_spawner: futures::executor::LocalSpawner,
 }
 
-static mut TIME: Duration = Duration::from_millis(0);
-static mut FPS_SHOW_TIME: Duration = Duration::from_millis(0);
-
 impl DebugInfo {
-    pub fn draw<'a>(
+    /// Adds a line at the bottom of the overlay, shown as `name: value`. A
+    /// stat with the same name is replaced.
+    pub fn add_stat<F>(&mut self, name: &str, provider: F)
+    where
+        F: Fn(&FrameStats) -> String + 'static,
+    {
+        let provider = Box::new(provider);
+        match self.stats.iter_mut().find(|(n, _)| n == name) {
+            Some((_, p)) => *p = provider,
+            None => self.stats.push((name.to_string(), provider)),
+        }
+    }
+
+    pub fn remove_stat(&mut self, name: &str) {
+        self.stats.retain(|(n, _)| n != name);
+    }
+
+    pub fn draw(
         &mut self,
         device: &wgpu::Device,
         encoder: &mut wgpu::CommandEncoder,
         target: &wgpu::TextureView,
-        camera: &Camera,
+        stats: &FrameStats,
     ) -> Result<(), String> {
-        let fps = String::from(format!("FPS: {:.2}\n", self.fps as u32));
-        let pos = String::from(format!(
-            "Position: x: {:.2}, y: {:.2}, z: {:.2}\n",
-            camera.eye.x, camera.eye.y, camera.eye.z
-        ));
-        let mut debug_text: Vec<Text> = Vec::new();
-        for t in self.text.iter() {
-            match t {
-                DebugTools::FPS => {
-                    debug_text.push(
-                        Text::new(&fps)
-                            .with_color([1., 1., 1., 1.])
-                            .with_scale(self.scale),
-                    );
-                }
-                DebugTools::Position => {
-                    debug_text.push(
-                        Text::new(&pos)
-                            .with_color([1., 1., 1., 1.])
-                            .with_scale(self.scale),
-                    );
-                }
-            }
-        }
+        let lines = self
+            .stats
+            .iter()
+            .map(|(name, provider)| format!("{}: {}\n", name, provider(stats)))
+            .collect::<Vec<_>>();
+        let debug_text = lines
+            .iter()
+            .map(|line| {
+                Text::new(line)
+                    .with_color([1., 1., 1., 1.])
+                    .with_scale(self.scale)
+            })
+            .collect::<Vec<_>>();
         self.brush.queue(Section {
             screen_position: (self.position.0, self.position.1),
             bounds: (self.screen_bounds.0 as f32, self.screen_bounds.1 as f32),
@@ -113,38 +210,171 @@ impl DebugInfo {
         )
     }
 
+    /// Queues the console above the frame time graph, it's drawn along with
+    /// the stats by [`draw`](Self::draw).
+    pub fn queue_console(&mut self, console: &Console) {
+        let mut text = console
+            .output()
+            .map(|line| format!("{}\n", line))
+            .collect::<String>();
+        text.push_str(&format!("> {}_", console.input));
+        let bottom = self.screen_bounds.1 as f32 - self.position.1 - GRAPH_HEIGHT - self.scale;
+        self.brush.queue(Section {
+            screen_position: (self.position.0, bottom),
+            bounds: (self.screen_bounds.0 as f32, self.screen_bounds.1 as f32),
+            layout: Layout::default().v_align(VerticalAlign::Bottom),
+            text: vec![Text::new(&text)
+                .with_color([1., 1., 0.6, 1.])
+                .with_scale(self.scale)],
+        });
+    }
+
+    /// Adds the frame time graph to the screen space lines, in the bottom
+    /// left corner. Each frame is a bar, green if it was faster than 60 FPS,
+    /// yellow if faster than 30 and red otherwise.
+    pub fn draw_graph(&self, frame_times: &FrameTimes, lines: &mut DebugLines) {
+        if !self.graph {
+            return;
+        }
+        let (width, height) = (self.screen_bounds.0 as f32, self.screen_bounds.1 as f32);
+        // From pixels, with the origin in the top left, to device coordinates.
+        let point = |x: f32, y: f32| Point3::new(x / width * 2. - 1., 1. - y / height * 2., 0.);
+        let left = self.position.0;
+        let bottom = height - self.position.1;
+        let y = |ms: f32| bottom - ms.min(GRAPH_MAX_MS) / GRAPH_MAX_MS * GRAPH_HEIGHT;
+
+        for (i, ms) in frame_times.samples().enumerate() {
+            let color = if ms <= GRAPH_MARKS_MS[0] {
+                debug_draw::GREEN
+            } else if ms <= GRAPH_MARKS_MS[1] {
+                debug_draw::YELLOW
+            } else {
+                debug_draw::RED
+            };
+            let x = left + i as f32 + 0.5;
+            lines.line(point(x, bottom), point(x, y(ms)), color);
+        }
+        let right = left + FRAME_TIME_SAMPLES as f32;
+        for &ms in GRAPH_MARKS_MS.iter() {
+            lines.line(point(left, y(ms)), point(right, y(ms)), debug_draw::WHITE);
+        }
+    }
+
     pub fn finish(&mut self) {
         self.staging_belt.finish();
     }
 
-    pub unsafe fn update_info(&mut self) {
-        // Recall unused staging buffers
+    /// Recalls the staging buffers of the text, after the frame was submitted.
+    pub fn recall(&mut self) {
         self.local_spawner
             .spawn(self.staging_belt.recall())
             .expect("Recall staging belt");
         self.local_pool.run_until_stalled();
-        let now = SystemTime::now();
-        let time = now
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards!");
-        if (time.as_millis() - FPS_SHOW_TIME.as_millis()) > 1000 {
-            let delta = time.as_micros() - TIME.as_micros();
-            self.fps = 1. / (delta as f64 / 1000000.);
-            FPS_SHOW_TIME = time;
-        }
-        TIME = time;
     }
 
     pub fn resize(&mut self, size: &winit::dpi::PhysicalSize<u32>) {
         self.screen_bounds = (size.width, size.height);
     }
-    #[allow(dead_code)]
-    pub fn rearrange(&mut self, text: Vec<DebugTools>) {
-        self.text = text;
+}
+
+/// Durations of the last frames, in milliseconds.
+pub struct FrameTimes {
+    samples: VecDeque<f32>,
+    last_frame: Option<Instant>,
+}
+
+impl FrameTimes {
+    pub fn new() -> Self {
+        Self {
+            samples: VecDeque::with_capacity(FRAME_TIME_SAMPLES),
+            last_frame: None,
+        }
+    }
+
+    /// Records the time since the last call, call it once per frame.
+    pub fn tick(&mut self) {
+        let now = Instant::now();
+        if let Some(last_frame) = self.last_frame.replace(now) {
+            self.push((now - last_frame).as_secs_f32() * 1000.);
+        }
+    }
+
+    pub fn push(&mut self, ms: f32) {
+        if self.samples.len() == FRAME_TIME_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(ms);
+    }
+
+    /// Oldest first.
+    pub fn samples(&self) -> impl Iterator<Item = f32> + '_ {
+        self.samples.iter().copied()
+    }
+
+    pub fn average(&self) -> Option<f32> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        Some(self.samples.iter().sum::<f32>() / self.samples.len() as f32)
+    }
+
+    /// The frame time that `percent` percent of the frames are faster than
+    /// or as fast as.
+    pub fn percentile(&self, percent: f32) -> Option<f32> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let mut sorted = self.samples.iter().copied().collect::<Vec<_>>();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let rank = (percent / 100. * sorted.len() as f32).ceil() as usize;
+        Some(sorted[rank.max(1).min(sorted.len()) - 1])
     }
 }
 
-pub enum DebugTools {
-    FPS,
-    Position,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_samples(samples: &[f32]) -> FrameTimes {
+        let mut frame_times = FrameTimes::new();
+        for &ms in samples {
+            frame_times.push(ms);
+        }
+        frame_times
+    }
+
+    #[test]
+    fn empty_has_no_statistics() {
+        let frame_times = FrameTimes::new();
+        assert_eq!(frame_times.average(), None);
+        assert_eq!(frame_times.percentile(99.), None);
+    }
+
+    #[test]
+    fn evicts_the_oldest_samples() {
+        let mut frame_times = with_samples(&[100.; FRAME_TIME_SAMPLES]);
+        assert_eq!(frame_times.average(), Some(100.));
+        for _ in 0..FRAME_TIME_SAMPLES / 2 {
+            frame_times.push(10.);
+        }
+        assert_eq!(frame_times.samples().count(), FRAME_TIME_SAMPLES);
+        assert_eq!(frame_times.average(), Some(55.));
+        frame_times.push(20.);
+        assert_eq!(frame_times.samples().count(), FRAME_TIME_SAMPLES);
+        assert_eq!(frame_times.samples().next(), Some(100.));
+        assert_eq!(frame_times.samples().last(), Some(20.));
+    }
+
+    #[test]
+    fn percentile_rounds_the_rank_up() {
+        let frame_times = with_samples(&[40., 10., 30., 20.]);
+        assert_eq!(frame_times.average(), Some(25.));
+        assert_eq!(frame_times.percentile(0.), Some(10.));
+        assert_eq!(frame_times.percentile(25.), Some(10.));
+        assert_eq!(frame_times.percentile(26.), Some(20.));
+        assert_eq!(frame_times.percentile(50.), Some(20.));
+        assert_eq!(frame_times.percentile(99.), Some(40.));
+        assert_eq!(frame_times.percentile(100.), Some(40.));
+        assert_eq!(with_samples(&[7.]).percentile(1.), Some(7.));
+    }
 }
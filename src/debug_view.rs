@@ -0,0 +1,96 @@
+//! Debug views, which show something other than the shaded image. F3 cycles
+//! through them and the debug overlay names the current one.
+//!
+//! Most views only change what the chunk shader outputs, it reads the view
+//! from the matrix uniform. The wireframe and overdraw views also draw the
+//! chunk with pipelines of their own, see
+//! [`PipelineVariant`](crate::render_pipeline_tools::PipelineVariant).
+
+use std::str::FromStr;
+
+/// The order matches the `DEBUG_VIEW_` defines in `common.glsl`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugView {
+    Shaded,
+    /// The edges of every triangle on top of the shaded image. Needs
+    /// `NON_FILL_POLYGON_MODE`.
+    Wireframe,
+    /// World space normals as colors.
+    Normals,
+    /// A color for every texture layer.
+    TextureLayers,
+    /// Lines where chunks meet.
+    ChunkBorders,
+    /// How much light each face gets, without its texture.
+    Lighting,
+    /// How many times each pixel was drawn, brighter is more.
+    Overdraw,
+}
+
+impl DebugView {
+    pub const ALL: [DebugView; 7] = [
+        DebugView::Shaded,
+        DebugView::Wireframe,
+        DebugView::Normals,
+        DebugView::TextureLayers,
+        DebugView::ChunkBorders,
+        DebugView::Lighting,
+        DebugView::Overdraw,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DebugView::Shaded => "Shaded",
+            DebugView::Wireframe => "Wireframe",
+            DebugView::Normals => "Normals",
+            DebugView::TextureLayers => "Texture layers",
+            DebugView::ChunkBorders => "Chunk borders",
+            DebugView::Lighting => "Lighting",
+            DebugView::Overdraw => "Overdraw",
+        }
+    }
+
+    /// Value of `debug_view` in the matrix uniform.
+    pub fn shader_mode(self) -> u32 {
+        self as u32
+    }
+
+    /// The view after this one, skipping the wireframe if the adapter can't
+    /// draw lines.
+    pub fn next(self, wireframe: bool) -> Self {
+        let index = Self::ALL.iter().position(|&v| v == self).unwrap();
+        let next = Self::ALL[(index + 1) % Self::ALL.len()];
+        if next == DebugView::Wireframe && !wireframe {
+            next.next(wireframe)
+        } else {
+            next
+        }
+    }
+
+    /// Overdraw adds up from black, everything else keeps the sky color.
+    pub fn clear_color(self, clear: wgpu::Color) -> wgpu::Color {
+        match self {
+            DebugView::Overdraw => wgpu::Color::BLACK,
+            _ => clear,
+        }
+    }
+}
+
+impl FromStr for DebugView {
+    type Err = String;
+
+    /// Takes the name in lower case, with `-` instead of spaces.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|v| v.name().to_lowercase().replace(' ', "-") == s)
+            .ok_or_else(|| {
+                let names = Self::ALL
+                    .iter()
+                    .map(|v| v.name().to_lowercase().replace(' ', "-"))
+                    .collect::<Vec<_>>();
+                format!("expected one of {}", names.join(", "))
+            })
+    }
+}
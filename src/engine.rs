@@ -5,21 +5,27 @@ use crate::rendering::renderer::Renderer;
 use crate::rendering::graphics::Graphics;
 use crate::world::World;
 use crate::chunk::Chunk;
+use crate::debug_draw::DebugDraw;
 use crate::rendering::object::Object;
 
 pub struct Engine {
     renderer: Renderer,
-    world: World
+    world: World,
+    /// Debug lines of the current frame, anything in the engine can add to it.
+    pub debug_draw: DebugDraw,
 }
 
 impl Engine {
     pub fn new(graphics: &Graphics) -> Self {
         let renderer = Renderer::new(graphics);
 
-        Self { renderer }
+        Self {
+            renderer,
+            debug_draw: DebugDraw::new(),
+        }
     }
 
-    pub fn render(&self, graphics: &Graphics) -> Result<(), wgpu::SwapChainError> {
+    pub fn render(&mut self, graphics: &Graphics) -> Result<(), wgpu::SwapChainError> {
         self.renderer.render(&graphics)?;
         Ok(())
     }
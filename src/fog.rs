@@ -0,0 +1,250 @@
+//! Distance fog of the chunk, so it fades into the sky before the camera's far
+//! plane cuts it off. It blends toward the sky's color, and under water toward
+//! [`UNDERWATER_COLOR`] with a much higher density instead.
+//!
+//! The settings are uploaded as a uniform in bind group 3 of the main pipeline,
+//! the `Fog` block of `common.glsl`.
+
+use anyhow::{ensure, Result};
+use std::fmt;
+use std::str::FromStr;
+use wgpu::util::DeviceExt;
+
+/// Color of the water around the camera.
+pub const UNDERWATER_COLOR: [f32; 3] = [0.02, 0.12, 0.18];
+/// Exponential density under water, the chunk is gone after a few blocks.
+const UNDERWATER_DENSITY: f32 = 0.25;
+
+/// How the fog thickens with distance. The order matches the `FOG_` defines in
+/// `common.glsl`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FogMode {
+    Off,
+    /// None before `start` and opaque from `end` on.
+    Linear,
+    /// `1 - e^(-density * distance)`, it never becomes fully opaque.
+    Exponential,
+}
+
+impl FogMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            FogMode::Off => "off",
+            FogMode::Linear => "linear",
+            FogMode::Exponential => "exponential",
+        }
+    }
+}
+
+impl FromStr for FogMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(FogMode::Off),
+            "linear" => Ok(FogMode::Linear),
+            "exponential" => Ok(FogMode::Exponential),
+            _ => Err(String::from("expected off, linear or exponential")),
+        }
+    }
+}
+
+/// Parameters of the fog above water, they can be changed at any time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FogSettings {
+    pub mode: FogMode,
+    /// Distance where the linear fog begins.
+    pub start: f32,
+    /// Distance where the linear fog is opaque, a bit before the far plane.
+    pub end: f32,
+    pub density: f32,
+}
+
+impl Default for FogSettings {
+    fn default() -> Self {
+        Self {
+            mode: FogMode::Linear,
+            start: 40.,
+            end: 95.,
+            density: 0.03,
+        }
+    }
+}
+
+impl FogSettings {
+    pub fn set_linear(&mut self, start: f32, end: f32) -> Result<()> {
+        ensure!(
+            0. <= start && start < end,
+            "The fog has to start before it ends"
+        );
+        self.start = start;
+        self.end = end;
+        Ok(())
+    }
+
+    pub fn set_density(&mut self, density: f32) -> Result<()> {
+        ensure!(density > 0., "The density has to be positive");
+        self.density = density;
+        Ok(())
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+struct FogUniform {
+    color: [f32; 3],
+    mode: u32,
+    start: f32,
+    end: f32,
+    density: f32,
+    _padding: u32,
+}
+
+impl FogUniform {
+    fn new(settings: &FogSettings, underwater: bool, sky_color: [f32; 3]) -> Self {
+        if underwater {
+            return Self {
+                color: UNDERWATER_COLOR,
+                mode: FogMode::Exponential as u32,
+                start: 0.,
+                end: 0.,
+                density: UNDERWATER_DENSITY,
+                _padding: 0,
+            };
+        }
+        Self {
+            color: sky_color,
+            mode: settings.mode as u32,
+            start: settings.start,
+            end: settings.end,
+            density: settings.density,
+            _padding: 0,
+        }
+    }
+}
+
+pub struct Fog {
+    pub settings: FogSettings,
+    /// Whether the camera is inside a fluid block, see
+    /// [`CubeType::is_fluid`](crate::cube::CubeType::is_fluid).
+    pub underwater: bool,
+    buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+}
+
+impl Fog {
+    pub const BIND_GROUP_LAYOUT_ENTRIES: [wgpu::BindGroupLayoutEntry; 1] =
+        [wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }];
+
+    pub fn new(device: &wgpu::Device) -> Self {
+        let settings = FogSettings::default();
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Fog uniform"),
+            contents: bytemuck::cast_slice(&[FogUniform::new(&settings, false, [0.; 3])]),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Fog layout"),
+            entries: &Self::BIND_GROUP_LAYOUT_ENTRIES,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Fog uniform"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+        Self {
+            settings,
+            underwater: false,
+            buffer,
+            bind_group,
+            bind_group_layout,
+        }
+    }
+
+    /// The uniform, for shaders that bind it with the light.
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    /// The color the scene fades into, and the main pass clears to.
+    pub fn color(&self, sky_color: [f32; 3]) -> [f32; 3] {
+        if self.underwater {
+            UNDERWATER_COLOR
+        } else {
+            sky_color
+        }
+    }
+
+    /// Uploads the settings, once per frame before the main pass.
+    pub fn update(&self, queue: &wgpu::Queue, sky_color: [f32; 3]) {
+        let uniform = FogUniform::new(&self.settings, self.underwater, sky_color);
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+}
+
+impl fmt::Display for Fog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let settings = &self.settings;
+        match settings.mode {
+            FogMode::Off => write!(f, "No fog")?,
+            FogMode::Linear => write!(f, "Linear fog from {} to {}", settings.start, settings.end)?,
+            FogMode::Exponential => write!(f, "Exponential fog, density {}", settings.density)?,
+        }
+        if self.underwater {
+            write!(f, ", under water")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn underwater_overrides_the_settings() {
+        let settings = FogSettings {
+            mode: FogMode::Off,
+            ..FogSettings::default()
+        };
+        let sky = [0.5, 0.6, 0.7];
+        let above = FogUniform::new(&settings, false, sky);
+        assert_eq!(above.mode, FogMode::Off as u32);
+        assert_eq!(above.color, sky);
+        let below = FogUniform::new(&settings, true, sky);
+        assert_eq!(below.mode, FogMode::Exponential as u32);
+        assert_eq!(below.color, UNDERWATER_COLOR);
+        assert_eq!(below.density, UNDERWATER_DENSITY);
+    }
+
+    #[test]
+    fn linear_fog_starts_before_it_ends() {
+        let mut settings = FogSettings::default();
+        assert!(settings.set_linear(50., 20.).is_err());
+        assert!(settings.set_linear(-1., 20.).is_err());
+        assert_eq!(settings, FogSettings::default());
+        settings.set_linear(10., 20.).unwrap();
+        assert_eq!((settings.start, settings.end), (10., 20.));
+    }
+
+    #[test]
+    fn parses_modes() {
+        for mode in [FogMode::Off, FogMode::Linear, FogMode::Exponential].iter() {
+            assert_eq!(mode.name().parse::<FogMode>(), Ok(*mode));
+        }
+        assert!("dense".parse::<FogMode>().is_err());
+    }
+}
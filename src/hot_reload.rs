@@ -0,0 +1,90 @@
+use crate::rendering::preprocessor::Preprocessor;
+use crate::rendering::shader::ShaderCode;
+use anyhow::*;
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::time::Duration;
+
+/// How long the file system has to be quiet before a change is reported.
+/// Editors often write a file in several steps.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Directory assets are loaded from. In development mode this is the `res/`
+/// folder of the source tree, so edits show up without a rebuild. Otherwise
+/// it's the copy `build.rs` puts into `OUT_DIR`.
+pub fn res_dir(dev: bool) -> PathBuf {
+    if dev {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("res")
+    } else {
+        Path::new(env!("OUT_DIR")).join("res")
+    }
+}
+
+/// Directory of the shader sources, which are compiled at runtime in
+/// development mode.
+pub fn shader_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("src/shaders")
+}
+
+/// Compiles the shader `name` from [`shader_dir`] in development mode, or
+/// uses the SPIR-V `build.rs` compiled into the binary. `bundled` must have
+/// been compiled with the same defines as `preprocessor`.
+pub fn shader_code(
+    dev: bool,
+    name: &str,
+    preprocessor: &Preprocessor,
+    bundled: &'static [u8],
+) -> Result<ShaderCode> {
+    if dev {
+        ShaderCode::load(shader_dir().join(name), preprocessor)
+    } else {
+        Ok(ShaderCode::SpirV(bundled.to_vec()))
+    }
+}
+
+/// Watches directories for files that were changed while the program runs.
+pub struct AssetWatcher {
+    // Dropping the watcher stops it, so it has to be kept around.
+    _watcher: RecommendedWatcher,
+    events: Receiver<DebouncedEvent>,
+}
+
+impl AssetWatcher {
+    pub fn new<P: AsRef<Path>>(dirs: &[P]) -> Result<Self> {
+        let (tx, events) = channel();
+        let mut watcher = notify::watcher(tx, DEBOUNCE)?;
+        for dir in dirs {
+            watcher
+                .watch(dir.as_ref(), RecursiveMode::Recursive)
+                .with_context(|| format!("Failed to watch {:?}", dir.as_ref()))?;
+            log::info!("Watching {:?} for changes", dir.as_ref());
+        }
+        Ok(Self {
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    /// Files that were written, created or renamed since the last call. Never
+    /// blocks and reports every file at most once.
+    pub fn changed_files(&self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        for event in self.events.try_iter() {
+            let path = match event {
+                DebouncedEvent::Write(path)
+                | DebouncedEvent::Create(path)
+                | DebouncedEvent::Rename(_, path) => path,
+                DebouncedEvent::Error(error, path) => {
+                    log::warn!("Error while watching {:?}: {}", path, error);
+                    continue;
+                }
+                _ => continue,
+            };
+            if path.is_file() && !changed.contains(&path) {
+                changed.push(path);
+            }
+        }
+        changed
+    }
+}
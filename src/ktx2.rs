@@ -0,0 +1,252 @@
+//! Reads and writes KTX2 containers. It's used by `build.rs` and the `atlas`
+//! binary as well, so like `atlas.rs` it only depends on `std` and `anyhow`.
+//!
+//! Only what we need is supported: 2D textures and arrays without
+//! supercompression. The data format descriptor and key/value data are
+//! skipped when reading, and only RGBA8 textures can be written.
+
+use anyhow::{bail, ensure, Context, Result};
+use std::path::Path;
+
+const IDENTIFIER: [u8; 12] = [
+    0xAB, b'K', b'T', b'X', b' ', b'2', b'0', 0xBB, b'\r', b'\n', 0x1A, b'\n',
+];
+const HEADER_SIZE: usize = 80;
+const LEVEL_INDEX_ENTRY_SIZE: usize = 24;
+
+/// `VkFormat` values of the formats we write.
+pub const VK_FORMAT_R8G8B8A8_UNORM: u32 = 37;
+pub const VK_FORMAT_R8G8B8A8_SRGB: u32 = 43;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ktx2 {
+    pub vk_format: u32,
+    pub width: u32,
+    pub height: u32,
+    /// 1 for textures that aren't arrays.
+    pub layer_count: u32,
+    /// Every mip level, largest first, with the layers of a level one after
+    /// another.
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl Ktx2 {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).with_context(|| format!("Failed to read {:?}", path))?;
+        Self::parse(&bytes).with_context(|| format!("Invalid KTX2 file {:?}", path))
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        ensure!(
+            bytes.len() >= HEADER_SIZE && bytes[..12] == IDENTIFIER,
+            "not a KTX2 file"
+        );
+        let u32_at = |offset: usize| u32::from_le_bytes(read(bytes, offset));
+        let u64_at = |offset: usize| u64::from_le_bytes(read(bytes, offset));
+
+        let vk_format = u32_at(12);
+        let (width, height, depth) = (u32_at(20), u32_at(24), u32_at(28));
+        let (layer_count, face_count, level_count) = (u32_at(32), u32_at(36), u32_at(40));
+        let supercompression = u32_at(44);
+        if vk_format == 0 {
+            bail!("Basis Universal and other VK_FORMAT_UNDEFINED textures aren't supported");
+        }
+        if depth > 1 || face_count != 1 {
+            bail!("only 2D textures are supported, not 3D textures or cube maps");
+        }
+        if supercompression != 0 {
+            bail!(
+                "supercompression scheme {} isn't supported",
+                supercompression
+            );
+        }
+
+        // A level count of 0 asks the loader to generate the mips, we take
+        // the one level that is stored.
+        let level_count = level_count.max(1) as usize;
+        ensure!(
+            bytes.len() >= HEADER_SIZE + level_count * LEVEL_INDEX_ENTRY_SIZE,
+            "the level index is cut off"
+        );
+        let mut levels = Vec::with_capacity(level_count);
+        for level in 0..level_count {
+            let entry = HEADER_SIZE + level * LEVEL_INDEX_ENTRY_SIZE;
+            let offset = u64_at(entry) as usize;
+            let length = u64_at(entry + 8) as usize;
+            let data = offset
+                .checked_add(length)
+                .and_then(|end| bytes.get(offset..end))
+                .with_context(|| format!("mip level {} is outside of the file", level))?;
+            levels.push(data.to_vec());
+        }
+        Ok(Self {
+            vk_format,
+            width,
+            height,
+            layer_count: layer_count.max(1),
+            levels,
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_bytes()).with_context(|| format!("Failed to write {:?}", path))
+    }
+
+    /// Panics if the format isn't one of the `VK_FORMAT_R8G8B8A8` ones.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let dfd = rgba8_dfd(self.vk_format);
+        let level_count = self.levels.len();
+        let dfd_offset = HEADER_SIZE + level_count * LEVEL_INDEX_ENTRY_SIZE;
+        let index_end = dfd_offset + dfd.len();
+
+        // The smallest level comes first in the file, each one aligned to 8
+        // bytes, which is enough for the formats we write.
+        let mut offsets = vec![0; level_count];
+        let mut end = index_end;
+        for level in (0..level_count).rev() {
+            end += (8 - end % 8) % 8;
+            offsets[level] = end;
+            end += self.levels[level].len();
+        }
+
+        let mut bytes = Vec::with_capacity(end);
+        bytes.extend_from_slice(&IDENTIFIER);
+        let layer_count = if self.layer_count > 1 {
+            self.layer_count
+        } else {
+            0
+        };
+        let header = [
+            self.vk_format,
+            1, // typeSize
+            self.width,
+            self.height,
+            0, // pixelDepth
+            layer_count,
+            1, // faceCount
+            level_count as u32,
+            0, // supercompressionScheme
+            dfd_offset as u32,
+            dfd.len() as u32,
+            0, // kvdByteOffset
+            0, // kvdByteLength
+        ];
+        for value in header.iter() {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(&0u64.to_le_bytes()); // sgdByteOffset
+        bytes.extend_from_slice(&0u64.to_le_bytes()); // sgdByteLength
+        for (level, data) in self.levels.iter().enumerate() {
+            bytes.extend_from_slice(&(offsets[level] as u64).to_le_bytes());
+            bytes.extend_from_slice(&(data.len() as u64).to_le_bytes());
+            bytes.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        }
+        bytes.extend_from_slice(&dfd);
+        for level in (0..level_count).rev() {
+            bytes.resize(offsets[level], 0);
+            bytes.extend_from_slice(&self.levels[level]);
+        }
+        bytes
+    }
+}
+
+/// The data format descriptor of 8 bit RGBA, with an sRGB or linear transfer
+/// function.
+fn rgba8_dfd(vk_format: u32) -> Vec<u8> {
+    let transfer_function = match vk_format {
+        VK_FORMAT_R8G8B8A8_UNORM => 1,
+        VK_FORMAT_R8G8B8A8_SRGB => 2,
+        _ => panic!(
+            "Only RGBA8 textures can be written, not VkFormat {}",
+            vk_format
+        ),
+    };
+    const SAMPLES: usize = 4;
+    let block_size = 24 + 16 * SAMPLES as u32;
+    let mut dfd = Vec::new();
+    // Total size, then a basic descriptor block header: vendor 0, type 0,
+    // version 2 and the size of the block.
+    dfd.extend_from_slice(&(4 + block_size).to_le_bytes());
+    dfd.extend_from_slice(&0u32.to_le_bytes());
+    dfd.extend_from_slice(&(2 | block_size << 16).to_le_bytes());
+    // RGBSDA color model, BT.709 primaries, straight alpha.
+    dfd.extend_from_slice(&[1, 1, transfer_function, 0]);
+    dfd.extend_from_slice(&[0; 4]); // texelBlockDimension
+    dfd.extend_from_slice(&[4, 0, 0, 0, 0, 0, 0, 0]); // bytesPlane
+    for (channel, id) in [0u32, 1, 2, 15].iter().enumerate() {
+        // Alpha is always linear.
+        let linear = if *id == 15 && transfer_function == 2 {
+            0x10
+        } else {
+            0
+        };
+        let bit_offset = 8 * channel as u32;
+        dfd.extend_from_slice(&(bit_offset | 7 << 16 | (id | linear) << 24).to_le_bytes());
+        dfd.extend_from_slice(&[0; 4]); // samplePosition
+        dfd.extend_from_slice(&0u32.to_le_bytes()); // sampleLower
+        dfd.extend_from_slice(&255u32.to_le_bytes()); // sampleUpper
+    }
+    dfd
+}
+
+fn read<const N: usize>(bytes: &[u8], offset: usize) -> [u8; N] {
+    let mut array = [0; N];
+    array.copy_from_slice(&bytes[offset..offset + N]);
+    array
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texture(layer_count: u32) -> Ktx2 {
+        let level = |size: usize, value: u8| vec![value; size * layer_count as usize];
+        Ktx2 {
+            vk_format: VK_FORMAT_R8G8B8A8_SRGB,
+            width: 4,
+            height: 2,
+            layer_count,
+            levels: vec![level(4 * 4 * 2, 1), level(4 * 2, 2), level(4, 3)],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for &layer_count in [1, 3].iter() {
+            let ktx2 = texture(layer_count);
+            let bytes = ktx2.to_bytes();
+            assert_eq!(Ktx2::parse(&bytes).unwrap(), ktx2);
+        }
+        let unorm = Ktx2 {
+            vk_format: VK_FORMAT_R8G8B8A8_UNORM,
+            ..texture(1)
+        };
+        assert_eq!(Ktx2::parse(&unorm.to_bytes()).unwrap(), unorm);
+    }
+
+    #[test]
+    fn levels_are_aligned_smallest_first() {
+        let bytes = texture(1).to_bytes();
+        let offset = |level: usize| {
+            u64::from_le_bytes(read(&bytes, HEADER_SIZE + level * LEVEL_INDEX_ENTRY_SIZE))
+        };
+        assert!((0..3).all(|level| offset(level) % 8 == 0));
+        assert!(offset(2) < offset(1) && offset(1) < offset(0));
+        assert_eq!(offset(0) as usize + 4 * 4 * 2, bytes.len());
+    }
+
+    #[test]
+    fn rejects_broken_files() {
+        let bytes = texture(1).to_bytes();
+        assert!(Ktx2::parse(&bytes[..HEADER_SIZE - 1]).is_err());
+        assert!(Ktx2::parse(&bytes[..bytes.len() - 1]).is_err());
+        let mut not_ktx2 = bytes.clone();
+        not_ktx2[1] = b'X';
+        assert!(Ktx2::parse(&not_ktx2).is_err());
+        let mut supercompressed = bytes;
+        supercompressed[44] = 1;
+        assert!(Ktx2::parse(&supercompressed).is_err());
+    }
+}
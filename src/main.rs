@@ -1,26 +1,43 @@
+mod assets;
+mod atlas;
 mod camera;
 mod chunk;
+mod console;
 mod cube;
+mod debug_draw;
 mod debug_info;
+mod debug_view;
 mod engine;
+mod fog;
 mod generation;
+mod hot_reload;
+mod ktx2;
 mod light;
 mod main_state;
+mod mip_chain;
 mod mipmap;
 mod modeling;
+mod post_process;
+mod profiler;
 mod quad;
 mod render_pipeline_tools;
+mod sky;
+mod ssao;
 mod texture;
+mod texture_file;
 mod uniform_matrix;
 mod window;
 mod rendering;
 mod world;
 
+use futures::executor::block_on;
+use std::path::PathBuf;
 use winit::event::{ElementState, Event, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit::window::{Window, WindowBuilder};
 
 use crate::engine::Engine;
+use crate::main_state::Program;
 use crate::graphics::Graphics;
 use crate::window::ClientWindow;
 use crate::rendering::graphics::Graphics;
@@ -32,7 +49,7 @@ pub struct Client {
 
 impl Client {
     pub fn new(window: &Window) -> Self {
-        let graphics = Graphics::new(window).expect("Failed to create graphics!");
+        let graphics = block_on(Graphics::new(window)).expect("Failed to create graphics!");
 
         let engine = Engine::new(&graphics).expect("Failed to create an engine.");
 
@@ -48,67 +65,112 @@ impl Client {
 }
 
 fn main() {
+    println!("Starting!");
+    wgpu_subscriber::initialize_default_subscriber(None);
+    // `--capabilities` prints what the adapter supports, for bug reports.
+    if std::env::args().any(|arg| arg == "--capabilities") {
+        match block_on(rendering::capabilities::report()) {
+            Ok(report) => print!("{}", report),
+            Err(e) => eprintln!("{:?}", e),
+        }
+        return;
+    }
+
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new().build(&event_loop).unwrap();
 
+    // `--dev` reads assets from the source tree and reloads them on change.
+    let dev = std::env::args().any(|arg| arg == "--dev");
+    // `--msaa <samples>` picks the sample count, 1 turns MSAA off.
+    let msaa = std::env::args()
+        .skip_while(|arg| arg != "--msaa")
+        .nth(1)
+        .and_then(|samples| samples.parse().ok())
+        .unwrap_or(rendering::capabilities::DEFAULT_MSAA_SAMPLES);
+    // `--skinned <file>` places an animated glTF model next to the chunk.
+    let skinned = std::env::args()
+        .skip_while(|arg| arg != "--skinned")
+        .nth(1)
+        .map(PathBuf::from);
+    let mut state = match block_on(Program::new(&window, dev, msaa, skinned)) {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("Failed to start: {:?}", e);
+            std::process::exit(1);
+        }
+    };
+
     window.set_title("The Voxel Engine");
     window.set_cursor_grab(true);
     window.set_cursor_visible(false);
 
-    let client = Client::new(&window);
-
     let mut mouse_input = true;
 
     event_loop.run(move |event, _, control_flow| {
         *control_flow = ControlFlow::Poll;
         match event {
             Event::DeviceEvent { event, .. } => {
-                if mouse_input {
-                    client.engine.input(&event);
+                // Typing into the console shouldn't move the camera.
+                if mouse_input && !state.console_open() {
+                    state.input(&event);
                 }
             }
-            Event::WindowEvent { window_id, event } if window_id == window.id() => match event {
-                WindowEvent::KeyboardInput {
-                    input:
-                        KeyboardInput {
-                            state: ElementState::Pressed,
-                            virtual_keycode: Some(VirtualKeyCode::Escape),
-                            ..
-                        },
-                    ..
-                } => *control_flow = ControlFlow::Exit,
-                WindowEvent::MouseInput { button, .. } => match button {
-                    MouseButton::Left => {
-                        window.set_cursor_grab(true);
-                        window.set_cursor_visible(false);
-                        mouse_input = true;
+            Event::WindowEvent { window_id, event } if window_id == window.id() => {
+                if state.console_event(&event) {
+                    return;
+                }
+                match event {
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::Escape),
+                                ..
+                            },
+                        ..
+                    } => *control_flow = ControlFlow::Exit,
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F3),
+                                ..
+                            },
+                        ..
+                    } => state.cycle_debug_view(),
+                    WindowEvent::MouseInput { button, .. } => match button {
+                        MouseButton::Left => {
+                            window.set_cursor_grab(true);
+                            window.set_cursor_visible(false);
+                            mouse_input = true;
+                        }
+                        MouseButton::Right => {
+                            window.set_cursor_grab(false);
+                            window.set_cursor_visible(true);
+                            mouse_input = false;
+                        }
+                        _ => {}
+                    },
+                    WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+                    WindowEvent::Resized(new_size) => {
+                        state.resize(new_size);
                     }
-                    MouseButton::Right => {
-                        window.set_cursor_grab(false);
-                        window.set_cursor_visible(true);
-                        mouse_input = false;
+                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                        state.resize(*new_inner_size);
                     }
-                    _ => {}
-                },
-                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
-                WindowEvent::Resized(new_size) => {
-                    client.resize(new_size);
+                    _ => (),
                 }
-                WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                    client.resize(*new_inner_size);
-                }
-                _ => (),
-            },
+            }
             Event::MainEventsCleared => {
                 window.request_redraw();
             }
             Event::RedrawRequested(_) => {
-                client.update();
+                state.update();
 
                 match state.render() {
                     Ok(_) => {}
                     // Recreate the swap_chain if lost
-                    Err(wgpu::SwapChainError::Lost) => client.resize(client.size),
+                    Err(wgpu::SwapChainError::Lost) => state.resize(state.size),
                     // The system is out of memory, we should probably quit
                     Err(wgpu::SwapChainError::OutOfMemory) => *control_flow = ControlFlow::Exit,
                     // All other errors (Outdated, Timeout) should be resolved by the next frame
@@ -1,21 +1,44 @@
+use crate::assets::{AssetManager, Handle};
+use crate::atlas::{self, AtlasManifest};
 use crate::chunk::{Chunk, DrawChunk};
-use crate::debug_info::{DebugInfo, DebugInfoBuilder};
+use crate::console::{CommandRegistry, Console, ConsoleAction};
+use crate::cube::BlockTextures;
+use crate::debug_draw::{self, DebugDraw, DebugDrawRenderer};
+use crate::debug_info::{DebugInfo, DebugInfoBuilder, FrameStats, FrameTimes};
+use crate::debug_view::DebugView;
 use crate::engine::Engine;
+use crate::fog::Fog;
 use crate::generation::flat_terrain;
+use crate::hot_reload::{self, AssetWatcher};
 use crate::light::Light;
-use crate::mipmap;
+use crate::mipmap::{MipFilter, MipmapGenerator};
 use crate::modeling::instance::{Instance, InstanceRaw, ModelRenderInfo};
-use crate::modeling::model::{DrawLight, DrawModel, Material, Model};
+use crate::modeling::model::{DrawLight, DrawModel, DrawSkinnedModel, Material, SkinnedModel};
+use crate::modeling::skeleton::{JointBuffer, SkinnedVertex};
+use crate::post_process::{Effect, PostContext, PostProcess, HDR_FORMAT};
+use crate::profiler::{GpuTimer, Profiler};
 use crate::quad::QuadRaw;
-use crate::render_pipeline_tools::new_render_pipeline;
-use crate::texture::{Texture, TextureArray};
+use crate::render_pipeline_tools::{new_render_pipeline, PipelineVariant};
+use crate::rendering::capabilities::{Capabilities, MSAA_SAMPLE_COUNTS};
+use crate::rendering::preprocessor::Preprocessor;
+use crate::rendering::reflection::PipelineInterface;
+use crate::rendering::render_graph::{RenderGraph, ResourceId, TextureDesc};
+use crate::rendering::shader::ShaderCode;
+use crate::sky::{Sky, SkyLighting};
+use crate::ssao::{Ssao, GBUFFER_FORMAT, OCCLUSION_FORMAT};
+use crate::texture::{Texture, TextureArray, TextureArrayKind};
+use crate::texture_file::TextureFile;
 use crate::uniform_matrix::MatrixUniform;
 use crate::{
     camera::{Camera, CameraController},
     modeling::vertex_index::{Vertex, VertexLayout},
     texture,
 };
+use anyhow::{anyhow, ensure, Context, Result};
 use nalgebra::{Point3, Vector3};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use wgpu::util::DeviceExt;
 
 pub struct Program {
     surface: wgpu::Surface,                      // Window
@@ -25,48 +48,82 @@ pub struct Program {
     swap_chain: wgpu::SwapChain,                 // Graphics
     pub size: winit::dpi::PhysicalSize<u32>,     // Window
     main_render_pipeline: wgpu::RenderPipeline,  // Rendering
+    debug_pipelines: DebugPipelines,             // Rendering
     light_render_pipeline: wgpu::RenderPipeline, // Rendering
     light_info: ModelRenderInfo,                 // Rendering
+    skinned_pipeline: wgpu::RenderPipeline,      // Rendering
+    skinned_model: Option<SkinnedModel>,         // Rendering
+    skinned_instances: wgpu::Buffer,             // Rendering
     clear: wgpu::Color,                          // Rendering
     camera: Camera,                              // Rendering
     camera_controller: CameraController,         // Camera
     matrix_uniform: MatrixUniform,               // Rendering->Shader
-    depth_texture: Texture,                      // Texture
-    light_bind_group: wgpu::BindGroup,           // Shader->Shader
+    graph: RenderGraph<Program>,                 // Rendering
+    post: PostProcess,                           // Rendering
+    msaa_samples: u32,                           // Rendering
+    draw_calls: u32,                             // Debug menu
+    light: Light,                                // Rendering
+    light_buffer: wgpu::Buffer,                  // Shader->Shader
+    light_bind_group: Option<wgpu::BindGroup>,   // Shader->Shader
+    sky: Sky,                                    // Rendering
+    fog: Fog,                                    // Rendering
+    ssao: Ssao,                                  // Rendering
     debug_info: DebugInfo,                       // Debug menu
+    frame_times: FrameTimes,                     // Debug menu
+    debug_view: DebugView,                       // Debug menu
+    pub debug_draw: DebugDraw,                   // Debug menu
+    debug_draw_renderer: DebugDrawRenderer,      // Debug menu
+    console: Console,                            // Debug menu
+    profiler: Profiler,                          // Debug menu
+    gpu_timer: GpuTimer,                         // Debug menu
+    commands: Rc<CommandRegistry<Program>>,      // Debug menu
     chunk: Chunk,                                // Chunk
+    block_textures: BlockTextures,               // Chunk
     texture_array: TextureArray,                 // Texture
-    chunk_texture: Material,                     // Chunk
+    chunk_texture: Handle<Material>,             // Chunk
+    assets: AssetManager,                        // Assets
+    light_layout: wgpu::BindGroupLayout,         // Shader->Shader
+    joint_layout: wgpu::BindGroupLayout,         // Shader->Shader
+    res_dir: PathBuf,                            // Assets
+    dev: bool,                                   // Assets
+    watcher: Option<AssetWatcher>,               // Assets
+    capabilities: Capabilities,                  // Graphics
 }
 
 impl Program {
-    pub async fn new(window: &winit::window::Window) -> Self {
+    /// In development mode (`dev`) assets and shaders are read from the
+    /// source tree and reloaded whenever they change on disk. The scene is
+    /// drawn with `msaa` samples, or the most the adapter supports below
+    /// that. A `skinned` glTF model is placed next to the chunk, playing its
+    /// first animation.
+    pub async fn new(
+        window: &winit::window::Window,
+        dev: bool,
+        msaa: u32,
+        skinned: Option<PathBuf>,
+    ) -> Result<Self> {
         let size = window.inner_size();
         let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
         let surface = unsafe { instance.create_surface(window) };
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
-            })
-            .await
-            .unwrap();
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: None,
-                    features: wgpu::Features::NON_FILL_POLYGON_MODE
-                        | wgpu::Features::SAMPLED_TEXTURE_BINDING_ARRAY
-                        | wgpu::Features::SAMPLED_TEXTURE_ARRAY_NON_UNIFORM_INDEXING
-                        | wgpu::Features::UNSIZED_BINDING_ARRAY,
-                    limits: wgpu::Limits::default(),
-                },
-                None,
-            )
-            .await
-            .unwrap();
+        let adapter = Capabilities::request_adapter(&instance, Some(&surface)).await?;
+        let capabilities = Capabilities::probe(&adapter);
+        if capabilities.texture_array_kind == TextureArrayKind::Layers {
+            log::info!("Binding arrays aren't supported, using a layered texture instead");
+        }
+        let (device, queue) = capabilities.request_device(&adapter).await?;
+        let msaa_samples = capabilities.msaa_samples(msaa);
+        if msaa_samples != msaa {
+            log::warn!("{}x MSAA isn't supported, using {}x", msaa, msaa_samples);
+        }
+        let gpu_timer = if capabilities.timestamp_query {
+            GpuTimer::new(&device, &queue)
+        } else {
+            GpuTimer::disabled()
+        };
 
-        let sc_format = adapter.get_swap_chain_preferred_format(&surface).unwrap();
+        let sc_format = adapter
+            .get_swap_chain_preferred_format(&surface)
+            .context("The adapter can't draw to the window")?;
 
         let sc_desc = wgpu::SwapChainDescriptor {
             usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
@@ -86,115 +143,110 @@ impl Program {
         );
         let camera_controller = CameraController::new();
 
-        let mut matrix_uniform = MatrixUniform::new(&device, &camera);
+        let mut matrix_uniform = MatrixUniform::new(&device, &camera)?;
         matrix_uniform.update_uniform(&mut camera);
 
-        let texture_layout = Texture::texture_bind_group_layout(&device);
+        let mut assets = AssetManager::new(&device);
         let light_layout = Light::bind_group_layout(&device);
 
-        let depth_texture =
-            texture::Texture::create_depth_texture(&device, &sc_desc, "depth_texture");
-        let res_dir = std::path::Path::new(env!("OUT_DIR")).join("res");
-        let chunk = Chunk::new(&device);
-        let chunk_texture = Material::custom_material(res_dir.join("trava.png"), &device, &queue);
-        let mut encoder =
-            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-        let tmp_tex =
-            Texture::from_bytes(&device, &queue, include_bytes!("../res/wolf.jpg"), "wolf")
-                .unwrap();
-        let tmp_tex2 =
-            Texture::from_bytes(&device, &queue, include_bytes!("../res/trava.png"), "trava")
-                .unwrap();
-        mipmap::generate_texture_mipmaps(
+        let post = PostProcess::new(&device, &queue, dev)?;
+        let joint_layout = JointBuffer::bind_group_layout(&device);
+        let ssao = Ssao::new(
             &device,
-            &mut encoder,
             &queue,
-            &tmp_tex.texture,
-            tmp_tex.mip_level_count,
-        );
-        queue.submit(Some(encoder.finish()));
-        let mut encoder =
-            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-        mipmap::generate_texture_mipmaps(
+            dev,
+            &matrix_uniform.bind_group_layout,
+            &joint_layout,
+        )?;
+        let mut graph = render_graph(&post, &ssao, sc_format, msaa_samples)?;
+        graph.resize(&device, size.width, size.height);
+        let res_dir = hot_reload::res_dir(dev);
+        let watcher = if dev {
+            AssetWatcher::new(&[res_dir.clone(), hot_reload::shader_dir()])
+                .map_err(|e| log::warn!("Hot reloading is disabled: {:?}", e))
+                .ok()
+        } else {
+            None
+        };
+        let (block_manifest, texture_array) = load_block_atlas(
             &device,
-            &mut encoder,
             &queue,
-            &tmp_tex2.texture,
-            tmp_tex2.mip_level_count,
-        );
-        queue.submit(Some(encoder.finish()));
-        let textures = vec![&tmp_tex, &tmp_tex2];
-        let texture_views = textures
-            .iter()
-            .map(|t| &t.view)
-            .collect::<Vec<&wgpu::TextureView>>();
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Linear,
-            mipmap_filter: wgpu::FilterMode::Linear,
-            ..Default::default()
-        });
-        let texture_array = TextureArray::create(&device, texture_views, &sampler);
+            &mut assets.mipmaps,
+            &res_dir,
+            dev,
+            capabilities.texture_array_kind,
+        )?;
+        let block_textures = BlockTextures::new(&block_manifest)?;
+        let chunk = Chunk::new(&device, &block_textures);
+        let chunk_texture = assets.load_material(&device, &queue, res_dir.join("trava.png"))?;
 
-        // Main Render Pipeline
-        let main_layouts = &[
-            &matrix_uniform.bind_group_layout,
-            &texture_array.bind_group_layout,
-            &light_layout,
-        ];
-
-        let main_render_pipeline = {
-            let vert_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
-                label: Some("vertex shader"),
-                source: wgpu::util::make_spirv(include_bytes!("shaders/shader.vert.spv")),
-                flags: wgpu::ShaderFlags::all() | wgpu::ShaderFlags::all(),
-            });
-            let frag_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
-                label: Some("fragment shader"),
-                source: wgpu::util::make_spirv(include_bytes!("shaders/shader.frag.spv")),
-                flags: wgpu::ShaderFlags::empty(),
-            });
-            new_render_pipeline(
-                "main",
-                &device,
-                main_layouts,
-                &vert_shader,
-                &frag_shader,
-                sc_desc.format,
-                texture::Texture::DEPTH_FORMAT,
-                &[Vertex::init_buffer_layout(), QuadRaw::init_buffer_layout()],
-            )
-        };
-        // Lightning Pipeline
         let light = Light {
             position: [-10., 27., -8.],
-            _padding: 0,
+            ambient: 0.05,
             color: [1., 1., 1.],
         };
-        let light_bind_group = Light::new_light_buffer(light, &device, &light_layout);
-
-        let light_render_pipeline = {
-            let vert_light_shader =
-                device.create_shader_module(&wgpu::include_spirv!("shaders/light.vert.spv"));
-            let frag_light_shader =
-                device.create_shader_module(&wgpu::include_spirv!("shaders/light.frag.spv"));
-            new_render_pipeline(
-                "light",
-                &device,
-                &[&matrix_uniform.bind_group_layout, &light_layout],
-                &vert_light_shader,
-                &frag_light_shader,
-                sc_desc.format,
-                texture::Texture::DEPTH_FORMAT,
-                &[
-                    Vertex::init_buffer_layout(),
-                    InstanceRaw::init_buffer_layout(),
-                ],
-            )
-        };
+        let light_buffer = Light::new_light_buffer(light, &device);
+        let sky = Sky::new(&device, dev, msaa_samples)?;
+        let fog = Fog::new(&device);
+        let debug_draw_renderer = DebugDrawRenderer::new(
+            &device,
+            dev,
+            &matrix_uniform.bind_group_layout,
+            HDR_FORMAT,
+            msaa_samples,
+        )?;
+
+        let (main_render_pipeline, debug_pipelines) = create_main_pipeline(
+            &device,
+            dev,
+            texture_array.kind,
+            &[
+                (
+                    &matrix_uniform.bind_group_layout,
+                    &MatrixUniform::BIND_GROUP_LAYOUT_ENTRIES,
+                ),
+                (
+                    &texture_array.bind_group_layout,
+                    &texture_array.layout_entries(),
+                ),
+                (&light_layout, &Light::BIND_GROUP_LAYOUT_ENTRIES),
+                (&fog.bind_group_layout, &Fog::BIND_GROUP_LAYOUT_ENTRIES),
+            ],
+            HDR_FORMAT,
+            msaa_samples,
+            capabilities.wireframe,
+        )?;
+        let light_render_pipeline = create_light_pipeline(
+            &device,
+            dev,
+            &[
+                (
+                    &matrix_uniform.bind_group_layout,
+                    &MatrixUniform::BIND_GROUP_LAYOUT_ENTRIES,
+                ),
+                (&light_layout, &Light::BIND_GROUP_LAYOUT_ENTRIES),
+            ],
+            HDR_FORMAT,
+            msaa_samples,
+        )?;
+        let skinned_pipeline = create_skinned_pipeline(
+            &device,
+            dev,
+            &[
+                (
+                    &matrix_uniform.bind_group_layout,
+                    &MatrixUniform::BIND_GROUP_LAYOUT_ENTRIES,
+                ),
+                (
+                    assets.material_layout(),
+                    &Material::BIND_GROUP_LAYOUT_ENTRIES,
+                ),
+                (&light_layout, &Light::BIND_GROUP_LAYOUT_ENTRIES),
+                (&joint_layout, &JointBuffer::BIND_GROUP_LAYOUT_ENTRIES),
+            ],
+            HDR_FORMAT,
+            msaa_samples,
+        )?;
 
         let clear = wgpu::Color {
             r: 0.1,
@@ -204,21 +256,60 @@ impl Program {
         };
 
         // Light object
-        let light =
-            Model::load(&device, &queue, &texture_layout, res_dir.join("test.obj")).unwrap();
+        let light_model = assets.load_model(&device, &queue, res_dir.join("test.obj"))?;
 
         let light_instances = vec![Instance::new(
             Vector3::new(0., 0., 0.),
             Vector3::new(0., 0., 0.),
             Vector3::new(0., 0., 0.),
         )];
-        let light_info =
-            ModelRenderInfo::new("Model Instance Buffer", light, light_instances, &device);
+        let light_info = ModelRenderInfo::new(
+            "Model Instance Buffer",
+            light_model,
+            light_instances,
+            &device,
+        );
+
+        let skinned_model = match skinned {
+            Some(path) => {
+                let mut model = SkinnedModel::load(
+                    &device,
+                    &queue,
+                    assets.material_layout(),
+                    &joint_layout,
+                    path,
+                )?;
+                model.player.play(0);
+                Some(model)
+            }
+            None => None,
+        };
+        let skinned_instance = Instance::new(
+            Vector3::new(2., 0., 0.),
+            Vector3::new(0., 0., 0.),
+            Vector3::new(0., 0., 0.),
+        );
+        let skinned_instances = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Skinned Model Instance Buffer"),
+            contents: bytemuck::cast_slice(&[skinned_instance.to_raw()]),
+            usage: wgpu::BufferUsage::VERTEX,
+        });
 
-        let debug_info = DebugInfoBuilder::new(10., 10., 20., sc_format, (size.width, size.height))
-            .build(&device)
-            .unwrap();
-        State {
+        let mut debug_info =
+            DebugInfoBuilder::new(10., 10., 20., sc_format, (size.width, size.height))
+                .build(&device)
+                .map_err(|e| anyhow!("Failed to create the debug overlay: {}", e))?;
+        debug_info.add_stat("Draw calls", |s| s.draw_calls.to_string());
+        debug_info.add_stat("Chunks", |s| s.chunks.to_string());
+        debug_info.add_stat("Quads", |s| s.quads.to_string());
+        debug_info.add_stat("GPU memory", |s| {
+            format!("{:.1} MiB", s.gpu_memory as f64 / (1024. * 1024.))
+        });
+        debug_info.add_stat("Target", |s| match s.target_block {
+            Some([x, y, z]) => format!("x: {}, y: {}, z: {}", x, y, z),
+            None => String::from("-"),
+        });
+        Ok(State {
             surface,
             device,
             queue,
@@ -226,22 +317,58 @@ impl Program {
             swap_chain,
             size,
             main_render_pipeline,
+            debug_pipelines,
             light_render_pipeline,
             light_info,
+            skinned_pipeline,
+            skinned_model,
+            skinned_instances,
             clear,
             camera,
             camera_controller,
             matrix_uniform,
-            depth_texture,
-            light_bind_group,
+            graph,
+            post,
+            msaa_samples,
+            draw_calls: 0,
+            light,
+            light_buffer,
+            light_bind_group: None,
+            sky,
+            fog,
+            ssao,
             debug_info,
+            frame_times: FrameTimes::new(),
+            debug_view: DebugView::Shaded,
+            debug_draw: DebugDraw::new(),
+            debug_draw_renderer,
+            console: Console::new(),
+            profiler: Profiler::new(),
+            gpu_timer,
+            commands: Rc::new(commands()),
             chunk,
+            block_textures,
             texture_array,
             chunk_texture,
-        }
+            assets,
+            light_layout,
+            joint_layout,
+            res_dir,
+            dev,
+            watcher,
+            capabilities,
+        })
     }
 
     pub fn update(&mut self) {
+        self.profiler.begin("update");
+        if let Some(watcher) = &self.watcher {
+            for path in watcher.changed_files() {
+                if let Err(e) = self.reload(&path) {
+                    log::warn!("Failed to reload {:?}: {:?}", path, e);
+                }
+            }
+        }
         self.camera_controller.update_camera(&mut self.camera);
         self.matrix_uniform.update_uniform(&mut self.camera);
         self.queue.write_buffer(
@@ -249,27 +376,202 @@ impl Program {
             0,
             bytemuck::cast_slice(&[self.matrix_uniform.data]),
         );
+        let lighting = self.update_sky();
+        self.update_fog(lighting.sky_color);
+        let delta = self.frame_times.samples().last().unwrap_or(0.) / 1000.;
+        if let Some(model) = &mut self.skinned_model {
+            model.update(&self.queue, delta);
+        }
+        if self.ssao.enabled() {
+            self.ssao.update(&self.queue, &self.camera);
+        }
+        if self.debug_view != DebugView::Shaded {
+            let (min, max) = self.chunk.bounds();
+            self.debug_draw
+                .depth_tested
+                .aabb(min, max, debug_draw::YELLOW);
+            self.debug_draw
+                .on_top
+                .sphere(self.light.position.into(), 0.5, debug_draw::WHITE);
+            self.debug_draw.on_top.axes(Point3::origin(), 2.);
+            if let Some([x, y, z]) = self.target_block() {
+                let center = Point3::new(x as f32, y as f32, z as f32);
+                let half = Vector3::repeat(0.51);
+                self.debug_draw
+                    .on_top
+                    .aabb(center - half, center + half, debug_draw::WHITE);
+            }
+        }
+        self.profiler.end();
+    }
+
+    /// Advances the time of day and lets the sun light the chunk, unless the
+    /// light was placed by hand.
+    fn update_sky(&mut self) -> SkyLighting {
+        self.sky.clock.tick();
+        let lighting = self.sky.lighting();
+        if self.sky.drives_light {
+            let (min, max) = self.chunk.bounds();
+            let center = nalgebra::center(&min, &max);
+            self.light.position = (center + lighting.light_direction * SUN_DISTANCE).into();
+            self.light.color = lighting.light_color;
+            self.light.ambient = lighting.ambient;
+            self.update_light();
+        }
+        self.sky.update(&self.queue, &self.camera);
+        lighting
+    }
+
+    /// Switches to the underwater fog while the camera is in a fluid block,
+    /// and clears to the color the fog blends toward.
+    fn update_fog(&mut self, sky_color: [f32; 3]) {
+        self.fog.underwater = self
+            .chunk
+            .block_at(self.camera.eye)
+            .map_or(false, |block| block.cube_type.is_fluid());
+        let [r, g, b] = self.fog.color(sky_color);
+        self.clear = wgpu::Color {
+            r: r as f64,
+            g: g as f64,
+            b: b as f64,
+            a: 1.0,
+        };
+        self.fog.update(&self.queue, sky_color);
+    }
+
+    /// Meshes the chunk again, after the blocks or their textures changed.
+    fn rebuild_chunk(&mut self) {
+        self.profiler.begin("meshing");
+        self.chunk = Chunk::new(&self.device, &self.block_textures);
+        self.profiler.end();
+    }
+
+    /// The block the camera looks at, if it's close enough to reach.
+    fn target_block(&self) -> Option<[usize; 3]> {
+        self.chunk
+            .raycast(self.camera.eye, self.camera.direction(), TARGET_DISTANCE)
     }
 
     pub fn render(&mut self) -> Result<(), wgpu::SwapChainError> {
         let frame = self.swap_chain.get_current_frame()?.output;
+        self.frame_times.tick();
+        self.profiler.begin("render");
+        self.profiler.begin("upload");
+        self.debug_info
+            .draw_graph(&self.frame_times, &mut self.debug_draw.screen);
+        self.debug_draw_renderer
+            .prepare(&self.device, &self.queue, &self.debug_draw);
+        self.post.prepare(&self.queue);
+        self.profiler.end();
+        self.profiler.begin("encode");
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Command Encoder"),
             });
+        self.draw_calls = 0;
+        // The passes take the program, so the graph can't stay borrowed from
+        // it while they run.
+        let graph = std::mem::take(&mut self.graph);
+        graph.execute(self, &mut encoder, &[&frame.view]);
+        self.graph = graph;
+        self.gpu_timer.resolve(&mut encoder);
+        self.debug_info.finish();
+        self.profiler.end();
+
+        self.profiler.begin("submit");
+        self.queue.submit(Some(encoder.finish()));
+        self.gpu_timer.finish(self.profiler.now());
+        self.debug_draw.clear();
+        self.debug_info.recall();
+        self.profiler.end();
+        // Ends the render scope.
+        self.profiler.end();
+        self.end_frame();
+
+        Ok(())
+    }
+
+    /// The normals and distances to the camera of the chunk and the skinned
+    /// model, which the ambient occlusion is found from.
+    fn gbuffer_pass(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        depth: &wgpu::TextureView,
+    ) {
+        self.gpu_timer.begin(encoder, "gbuffer");
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("Render Pass"),
+            label: Some("G-buffer pass"),
             color_attachments: &[wgpu::RenderPassColorAttachment {
-                view: &frame.view,
+                view: target,
                 resolve_target: None,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(self.clear),
+                    // A distance of 0 is where nothing was drawn.
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
                     store: true,
                 },
             }],
             depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                view: &self.depth_texture.view,
+                view: depth,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: false,
+                }),
+                stencil_ops: None,
+            }),
+        });
+        render_pass.set_pipeline(self.ssao.gbuffer_pipeline());
+        render_pass.draw_chunk_geometry(&self.chunk.chunk_mesh, &self.matrix_uniform.bind_group);
+        let mut draw_calls = 1;
+        if let Some(model) = &self.skinned_model {
+            render_pass.set_pipeline(self.ssao.skinned_gbuffer_pipeline());
+            render_pass.draw_skinned_geometry(model, &self.skinned_instances, 1);
+            draw_calls += model.model.mesh.len() as u32;
+        }
+        drop(render_pass);
+        self.gpu_timer.end(encoder);
+        self.draw_calls += draw_calls;
+    }
+
+    /// The sky, the light, the chunk and the debug lines, into the HDR target.
+    /// With MSAA `target` is multisampled and resolved into `resolve_target`.
+    /// The ambient light is scaled by `ambient_occlusion` if SSAO is enabled.
+    fn main_pass(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        resolve_target: Option<&wgpu::TextureView>,
+        depth: &wgpu::TextureView,
+        ambient_occlusion: Option<&wgpu::TextureView>,
+    ) {
+        // The occlusion stays the same texture until the graph is built
+        // again or resized, which drops the bind group.
+        if self.light_bind_group.is_none() {
+            self.light_bind_group = Some(Light::bind_group(
+                &self.device,
+                &self.light_layout,
+                &self.light_buffer,
+                ambient_occlusion.unwrap_or_else(|| self.ssao.unoccluded()),
+                self.ssao.sampler(),
+                self.fog.buffer(),
+            ));
+        }
+        let light_bind_group = self.light_bind_group.as_ref().unwrap();
+        self.gpu_timer.begin(encoder, "main pass");
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(self.debug_view.clear_color(self.clear)),
+                    // Only the resolved samples are used after the pass.
+                    store: resolve_target.is_none(),
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth,
                 depth_ops: Some(wgpu::Operations {
                     load: wgpu::LoadOp::Clear(1.0),
                     store: true,
@@ -278,33 +580,359 @@ impl Program {
             }),
         });
 
+        let mut draw_calls = 0;
+        // The overdraw view counts the scene's fragments only, and under
+        // water the fog hides the sky.
+        if self.debug_view != DebugView::Overdraw && !self.fog.underwater {
+            self.sky.draw(&mut render_pass);
+            draw_calls += 1;
+        }
         render_pass.set_pipeline(&self.light_render_pipeline);
         render_pass.set_bind_group(0, &self.matrix_uniform.bind_group, &[]);
-        render_pass.draw_light(&self.light_info, &self.light_bind_group);
+        render_pass.draw_light(&self.light_info, light_bind_group);
+        draw_calls += self.light_info.model.mesh.len() as u32;
+        // The overdraw view only counts the chunk's fragments.
+        if self.debug_view != DebugView::Overdraw {
+            if let Some(model) = &self.skinned_model {
+                render_pass.set_pipeline(&self.skinned_pipeline);
+                render_pass.draw_skinned_model(model, &self.skinned_instances, 1, light_bind_group);
+                draw_calls += model.model.mesh.len() as u32;
+            }
+        }
 
-        render_pass.set_pipeline(&self.main_render_pipeline);
+        if self.debug_view == DebugView::Overdraw {
+            render_pass.set_pipeline(&self.debug_pipelines.overdraw);
+        } else {
+            render_pass.set_pipeline(&self.main_render_pipeline);
+        }
         render_pass.set_bind_group(1, &self.texture_array.bind_group, &[]);
+        render_pass.set_bind_group(3, &self.fog.bind_group, &[]);
         render_pass.draw_chunk(
             &self.chunk.chunk_mesh,
-            &self.light_bind_group,
+            light_bind_group,
             &self.matrix_uniform.bind_group,
         );
+        draw_calls += 1;
+        if let (DebugView::Wireframe, Some(wireframe)) =
+            (self.debug_view, &self.debug_pipelines.wireframe)
+        {
+            render_pass.set_pipeline(wireframe);
+            render_pass.draw_chunk(
+                &self.chunk.chunk_mesh,
+                light_bind_group,
+                &self.matrix_uniform.bind_group,
+            );
+            draw_calls += 1;
+        }
+        draw_calls += self
+            .debug_draw_renderer
+            .draw(&mut render_pass, &self.matrix_uniform.bind_group);
 
         drop(render_pass);
+        self.gpu_timer.end(encoder);
+        self.draw_calls += draw_calls;
+    }
 
-        self.debug_info
-            .draw(&self.device, &mut encoder, &frame.view, &self.camera)
-            .unwrap();
-        self.debug_info.finish();
-        self.queue.submit(Some(encoder.finish()));
+    /// The debug overlay and the console, drawn by the glyph brush.
+    fn overlay_pass(&mut self, encoder: &mut wgpu::CommandEncoder, target: &wgpu::TextureView) {
+        let depth_bytes =
+            self.sc_desc.width as u64 * self.sc_desc.height as u64 * 4 * self.msaa_samples as u64;
+        let stats = FrameStats {
+            frame_times: &self.frame_times,
+            camera: &self.camera,
+            debug_view: self.debug_view,
+            draw_calls: self.draw_calls,
+            chunks: 1,
+            quads: self.chunk.chunk_mesh.quad_count(),
+            gpu_memory: self.chunk.chunk_mesh.gpu_memory() + depth_bytes,
+            target_block: self.target_block(),
+            cpu_scopes: self.profiler.last_frame(),
+            gpu_scopes: if self.gpu_timer.enabled() {
+                Some(self.profiler.gpu_frame())
+            } else {
+                None
+            },
+        };
+        if self.console.open {
+            self.debug_info.queue_console(&self.console);
+        }
+        self.gpu_timer.begin(encoder, "overlay");
+        // The frame is still worth showing without the overlay.
+        if let Err(e) = self.debug_info.draw(&self.device, encoder, target, &stats) {
+            log::error!("Failed to draw the debug overlay: {}", e);
+        }
+        self.gpu_timer.end(encoder);
+    }
+
+    /// Creates the pipelines of the main pass again, after their shaders or
+    /// the sample count changed. The old ones stay if any of them fails.
+    fn create_scene_pipelines(&mut self) -> Result<()> {
+        let (main_render_pipeline, debug_pipelines) = create_main_pipeline(
+            &self.device,
+            self.dev,
+            self.texture_array.kind,
+            &[
+                (
+                    &self.matrix_uniform.bind_group_layout,
+                    &MatrixUniform::BIND_GROUP_LAYOUT_ENTRIES,
+                ),
+                (
+                    &self.texture_array.bind_group_layout,
+                    &self.texture_array.layout_entries(),
+                ),
+                (&self.light_layout, &Light::BIND_GROUP_LAYOUT_ENTRIES),
+                (&self.fog.bind_group_layout, &Fog::BIND_GROUP_LAYOUT_ENTRIES),
+            ],
+            HDR_FORMAT,
+            self.msaa_samples,
+            self.capabilities.wireframe,
+        )?;
+        let light_render_pipeline = create_light_pipeline(
+            &self.device,
+            self.dev,
+            &[
+                (
+                    &self.matrix_uniform.bind_group_layout,
+                    &MatrixUniform::BIND_GROUP_LAYOUT_ENTRIES,
+                ),
+                (&self.light_layout, &Light::BIND_GROUP_LAYOUT_ENTRIES),
+            ],
+            HDR_FORMAT,
+            self.msaa_samples,
+        )?;
+        let skinned_pipeline = create_skinned_pipeline(
+            &self.device,
+            self.dev,
+            &[
+                (
+                    &self.matrix_uniform.bind_group_layout,
+                    &MatrixUniform::BIND_GROUP_LAYOUT_ENTRIES,
+                ),
+                (
+                    self.assets.material_layout(),
+                    &Material::BIND_GROUP_LAYOUT_ENTRIES,
+                ),
+                (&self.light_layout, &Light::BIND_GROUP_LAYOUT_ENTRIES),
+                (&self.joint_layout, &JointBuffer::BIND_GROUP_LAYOUT_ENTRIES),
+            ],
+            HDR_FORMAT,
+            self.msaa_samples,
+        )?;
+        let debug_line_pipelines = DebugDrawRenderer::create_pipelines(
+            &self.device,
+            self.dev,
+            &self.matrix_uniform.bind_group_layout,
+            HDR_FORMAT,
+            self.msaa_samples,
+        )?;
+        let sky_pipeline = self.sky.create_pipeline(&self.device, self.msaa_samples)?;
+
+        // Nothing is replaced before every pipeline is built, so when one
+        // fails all of them keep the old shaders and sample count.
+        self.debug_draw_renderer.set_pipelines(debug_line_pipelines);
+        self.sky.set_pipeline(sky_pipeline);
+        self.main_render_pipeline = main_render_pipeline;
+        self.debug_pipelines = debug_pipelines;
+        self.light_render_pipeline = light_render_pipeline;
+        self.skinned_pipeline = skinned_pipeline;
+        Ok(())
+    }
 
-        unsafe {
-            self.debug_info.update_info();
+    /// Reloads a file that changed on disk and rebuilds whatever uses it.
+    fn reload(&mut self, path: &Path) -> Result<()> {
+        log::info!("Reloading {:?}", path);
+        let extension = path.extension().and_then(|e| e.to_str());
+        if matches!(extension, Some("vert") | Some("frag") | Some("glsl")) {
+            self.create_scene_pipelines()?;
+            self.post.reload(&self.device, self.dev)?;
+            self.ssao.reload(
+                &self.device,
+                &self.matrix_uniform.bind_group_layout,
+                &self.joint_layout,
+            )?;
+            return Ok(());
         }
 
+        if path.starts_with(self.res_dir.join(BLOCK_DIR)) {
+            // The rects may have moved, so the chunk is rebuilt as well.
+            let (block_manifest, texture_array) = load_block_atlas(
+                &self.device,
+                &self.queue,
+                &mut self.assets.mipmaps,
+                &self.res_dir,
+                self.dev,
+                self.texture_array.kind,
+            )?;
+            self.block_textures = BlockTextures::new(&block_manifest)?;
+            self.texture_array = texture_array;
+            self.rebuild_chunk();
+            return Ok(());
+        }
+        if self.assets.reload(&self.device, &self.queue, path)? {
+            // Swap our handles for the reloaded assets.
+            self.chunk_texture = self.assets.load_material(
+                &self.device,
+                &self.queue,
+                self.res_dir.join("trava.png"),
+            )?;
+            let light =
+                self.assets
+                    .load_model(&self.device, &self.queue, self.res_dir.join("test.obj"))?;
+            self.light_info = ModelRenderInfo::new(
+                "Model Instance Buffer",
+                light,
+                self.light_info.instances.clone(),
+                &self.device,
+            );
+            self.assets.collect_garbage();
+        }
+        Ok(())
+    }
+
+    /// Builds the render graph again, after effects or SSAO were enabled or
+    /// disabled.
+    fn rebuild_graph(&mut self) -> Result<()> {
+        let graph = self.build_graph()?;
+        self.set_graph(graph);
         Ok(())
     }
 
+    /// A render graph for the current settings, sized to the window.
+    fn build_graph(&self) -> Result<RenderGraph<Program>> {
+        let mut graph = render_graph(
+            &self.post,
+            &self.ssao,
+            self.sc_desc.format,
+            self.msaa_samples,
+        )?;
+        graph.resize(&self.device, self.sc_desc.width, self.sc_desc.height);
+        Ok(graph)
+    }
+
+    fn set_graph(&mut self, graph: RenderGraph<Program>) {
+        self.graph = graph;
+        // It binds the occlusion target of the old graph.
+        self.light_bind_group = None;
+    }
+
+    /// Draws the scene with `samples` samples per pixel from the next frame
+    /// on, 1 turns MSAA off.
+    pub fn set_msaa_samples(&mut self, samples: u32) -> Result<()> {
+        ensure!(
+            MSAA_SAMPLE_COUNTS.contains(&samples),
+            "The adapter supports {:?} samples",
+            MSAA_SAMPLE_COUNTS
+        );
+        let previous = std::mem::replace(&mut self.msaa_samples, samples);
+        // The pipelines are only replaced once the graph is built, and the
+        // graph once the pipelines are, so a failure leaves the old ones.
+        let graph = self
+            .build_graph()
+            .and_then(|graph| self.create_scene_pipelines().map(|()| graph));
+        match graph {
+            Ok(graph) => {
+                self.set_graph(graph);
+                Ok(())
+            }
+            Err(e) => {
+                self.msaa_samples = previous;
+                Err(e)
+            }
+        }
+    }
+
+    /// Switches to the next debug view, see [`DebugView`].
+    pub fn cycle_debug_view(&mut self) {
+        let view = self.debug_view.next(self.capabilities.wireframe);
+        self.set_debug_view(view)
+            .expect("Only views the adapter supports come next");
+    }
+
+    pub fn set_debug_view(&mut self, view: DebugView) -> Result<()> {
+        ensure!(
+            view != DebugView::Wireframe || self.capabilities.wireframe,
+            "The adapter can't draw wireframes"
+        );
+        self.debug_view = view;
+        self.matrix_uniform.data.debug_view = view.shader_mode();
+        log::info!("Debug view: {}", view.name());
+        Ok(())
+    }
+
+    /// Uploads `light` again after it was changed.
+    fn update_light(&mut self) {
+        self.queue
+            .write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&[self.light]));
+    }
+
+    /// Hands the profiler its frame and the GPU timings that are ready, and
+    /// saves a capture once it's complete.
+    fn end_frame(&mut self) {
+        self.profiler.end_frame();
+        if self.gpu_timer.enabled() {
+            self.device.poll(wgpu::Maintain::Poll);
+            if let Some(scopes) = self.gpu_timer.poll() {
+                self.profiler.record_gpu(scopes);
+            }
+        }
+        if let Some(capture) = self.profiler.take_capture() {
+            let message = match capture.save(PROFILE_PATH) {
+                Ok(()) => format!("Saved the capture to {}", PROFILE_PATH),
+                Err(e) => format!("{:#}", e),
+            };
+            log::info!("{}", message);
+            self.console.print(&message);
+        }
+    }
+
+    pub fn console_open(&self) -> bool {
+        self.console.open
+    }
+
+    /// Passes typing to the console while it's open, and opens it with the
+    /// key below Escape. Returns whether the event was used up.
+    pub fn console_event(&mut self, event: &winit::event::WindowEvent) -> bool {
+        use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
+        match event {
+            WindowEvent::ReceivedCharacter(c) if self.console.open => {
+                self.console.char(*c);
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } => {
+                if !self.console.open {
+                    if *key == VirtualKeyCode::Grave {
+                        self.console.toggle();
+                        return true;
+                    }
+                    return false;
+                }
+                match self.console.key(*key) {
+                    ConsoleAction::Submit(line) => {
+                        // The commands can change anything, the console too.
+                        let commands = Rc::clone(&self.commands);
+                        match commands.execute(self, &line) {
+                            Ok(message) => self.console.print(&message),
+                            Err(e) => self.console.print(&format!("{:#}", e)),
+                        }
+                    }
+                    ConsoleAction::Complete => self.console.complete(&self.commands),
+                    ConsoleAction::Close => self.console.toggle(),
+                    ConsoleAction::None => {}
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
     pub fn input(&mut self, event: &winit::event::DeviceEvent) {
         self.camera_controller.process_input(event);
     }
@@ -315,8 +943,753 @@ impl Program {
         self.sc_desc.height = new_size.height;
         self.swap_chain = self.device.create_swap_chain(&self.surface, &self.sc_desc);
         self.camera.aspect = self.sc_desc.width as f32 / self.sc_desc.height as f32;
-        self.depth_texture =
-            texture::Texture::create_depth_texture(&self.device, &self.sc_desc, "depth_texture");
+        self.graph
+            .resize(&self.device, new_size.width, new_size.height);
+        self.light_bind_group = None;
         self.debug_info.resize(&self.size);
     }
 }
+
+/// The console commands, see [`CommandRegistry::register`].
+fn commands() -> CommandRegistry<Program> {
+    let mut commands = CommandRegistry::new();
+    commands.register("help", "", "Lists the commands", |p: &mut Program, ()| {
+        Ok(p.commands.help())
+    });
+    commands.register("clear", "", "Clears the console", |p: &mut Program, ()| {
+        p.console.clear();
+        Ok(String::new())
+    });
+    commands.register(
+        "tp",
+        "<x> <y> <z>",
+        "Moves the camera",
+        |p: &mut Program, (x, y, z)| {
+            p.camera.eye = Point3::new(x, y, z);
+            Ok(format!("Moved to {} {} {}", x, y, z))
+        },
+    );
+    commands.register(
+        "set fov",
+        "<degrees>",
+        "Sets the vertical field of view",
+        |p: &mut Program, (fov,): (f32,)| {
+            ensure!(
+                fov > 0. && fov < 180.,
+                "The field of view has to be between 0 and 180 degrees"
+            );
+            p.camera.set_fov(fov);
+            Ok(String::new())
+        },
+    );
+    commands.register(
+        "view",
+        "<name>",
+        "Switches the debug view",
+        |p: &mut Program, (view,)| {
+            p.set_debug_view(view)?;
+            Ok(String::new())
+        },
+    );
+    commands.register(
+        "light color",
+        "<r> <g> <b>",
+        "Sets the color of the light",
+        |p: &mut Program, (r, g, b)| {
+            p.light.color = [r, g, b];
+            p.update_light();
+            Ok(take_light_from_sky(p))
+        },
+    );
+    commands.register(
+        "light pos",
+        "<x> <y> <z>",
+        "Moves the light",
+        |p: &mut Program, (x, y, z)| {
+            p.light.position = [x, y, z];
+            p.update_light();
+            Ok(take_light_from_sky(p))
+        },
+    );
+    commands.register(
+        "sky",
+        "",
+        "Shows the sky and the time of day",
+        |p: &mut Program, ()| Ok(p.sky.to_string()),
+    );
+    commands.register(
+        "sky procedural",
+        "",
+        "Draws the sky from the sun's position",
+        |p: &mut Program, ()| {
+            p.sky.set_procedural(&p.device)?;
+            Ok(String::new())
+        },
+    );
+    commands.register(
+        "sky cubemap",
+        "<dir>",
+        "Draws the faces px, nx, py, ny, pz and nz from a directory in res/",
+        |p: &mut Program, (dir,): (String,)| {
+            let path = p.res_dir.join(&dir);
+            p.sky.load_cubemap(&p.device, &p.queue, path)?;
+            Ok(format!("Loaded {}", dir))
+        },
+    );
+    commands.register(
+        "sky equirect",
+        "<file>",
+        "Draws an equirectangular image from res/, HDR or LDR",
+        |p: &mut Program, (file,): (String,)| {
+            let path = p.res_dir.join(&file);
+            p.sky.load_equirect(&p.device, &p.queue, path)?;
+            Ok(format!("Loaded {}", file))
+        },
+    );
+    commands.register(
+        "time",
+        "<hours>",
+        "Sets the time of day, and lets the sun drive the light again",
+        |p: &mut Program, (hours,)| {
+            p.sky.clock.set_hours(hours);
+            p.sky.drives_light = true;
+            Ok(p.sky.clock.to_string())
+        },
+    );
+    commands.register(
+        "time speed",
+        "<hours per second>",
+        "Sets how fast the day passes, 0 stops the clock",
+        |p: &mut Program, (speed,)| {
+            p.sky.clock.speed = speed;
+            Ok(String::new())
+        },
+    );
+    commands.register(
+        "fog",
+        "",
+        "Shows the fog settings",
+        |p: &mut Program, ()| Ok(p.fog.to_string()),
+    );
+    commands.register(
+        "fog mode",
+        "<off|linear|exponential>",
+        "Switches how the fog thickens with distance",
+        |p: &mut Program, (mode,)| {
+            p.fog.settings.mode = mode;
+            Ok(String::new())
+        },
+    );
+    commands.register(
+        "fog linear",
+        "<start> <end>",
+        "Sets where the linear fog begins and where it's opaque",
+        |p: &mut Program, (start, end)| {
+            p.fog.settings.set_linear(start, end)?;
+            Ok(String::new())
+        },
+    );
+    commands.register(
+        "fog density",
+        "<density>",
+        "Sets the density of the exponential fog",
+        |p: &mut Program, (density,)| {
+            p.fog.settings.set_density(density)?;
+            Ok(String::new())
+        },
+    );
+    commands.register(
+        "ssao",
+        "",
+        "Shows whether SSAO is enabled and its settings",
+        |p: &mut Program, ()| Ok(p.ssao.to_string()),
+    );
+    commands.register(
+        "ssao enable",
+        "",
+        "Darkens the ambient light in creases and corners",
+        |p: &mut Program, ()| {
+            if p.ssao.set_enabled(true) {
+                p.rebuild_graph()?;
+            }
+            Ok(String::new())
+        },
+    );
+    commands.register(
+        "ssao disable",
+        "",
+        "Lights everything with the full ambient light",
+        |p: &mut Program, ()| {
+            if p.ssao.set_enabled(false) {
+                p.rebuild_graph()?;
+            }
+            Ok(String::new())
+        },
+    );
+    commands.register(
+        "ssao radius",
+        "<blocks>",
+        "Sets how far around a point occluders are looked for",
+        |p: &mut Program, (radius,)| {
+            p.ssao.settings.set_radius(radius)?;
+            Ok(String::new())
+        },
+    );
+    commands.register(
+        "ssao bias",
+        "<blocks>",
+        "Sets how far behind a surface a point has to be to count",
+        |p: &mut Program, (bias,)| {
+            p.ssao.settings.bias = bias;
+            Ok(String::new())
+        },
+    );
+    commands.register(
+        "ssao intensity",
+        "<exponent>",
+        "Sets how dark the occlusion is, 1 leaves it as it is",
+        |p: &mut Program, (intensity,): (f32,)| {
+            ensure!(intensity > 0., "The intensity has to be positive");
+            p.ssao.settings.intensity = intensity;
+            Ok(String::new())
+        },
+    );
+    commands.register("regen", "", "Rebuilds the chunk", |p: &mut Program, ()| {
+        p.rebuild_chunk();
+        Ok(String::from("Rebuilt the chunk"))
+    });
+    commands.register(
+        "profile",
+        "<frames>",
+        "Saves the profiler scopes of the next frames as a Chrome trace",
+        |p: &mut Program, (frames,)| {
+            ensure!(frames > 0, "Capture at least one frame");
+            p.profiler.start_capture(frames);
+            Ok(format!("Capturing {} frames", frames))
+        },
+    );
+    commands.register(
+        "msaa",
+        "<samples>",
+        "Sets the samples per pixel of the scene, 1 turns MSAA off",
+        |p: &mut Program, (samples,)| {
+            p.set_msaa_samples(samples)?;
+            Ok(format!("{}x MSAA", samples))
+        },
+    );
+    commands.register(
+        "post",
+        "",
+        "Shows the post-processing effects and settings",
+        |p: &mut Program, ()| Ok(p.post.to_string()),
+    );
+    commands.register(
+        "post enable",
+        "<effect>",
+        "Enables a post-processing effect",
+        |p: &mut Program, (effect,)| {
+            if p.post.set_enabled(effect, true) {
+                p.rebuild_graph()?;
+            }
+            Ok(String::new())
+        },
+    );
+    commands.register(
+        "post disable",
+        "<effect>",
+        "Disables a post-processing effect",
+        |p: &mut Program, (effect,): (Effect,)| {
+            if p.post.set_enabled(effect, false) {
+                p.rebuild_graph()?;
+            }
+            Ok(String::new())
+        },
+    );
+    commands.register(
+        "post exposure",
+        "<exposure>",
+        "Sets the exposure before tonemapping",
+        |p: &mut Program, (exposure,): (f32,)| {
+            ensure!(exposure > 0., "The exposure has to be positive");
+            p.post.settings.exposure = exposure;
+            Ok(String::new())
+        },
+    );
+    commands.register(
+        "post tonemapper",
+        "<aces|reinhard>",
+        "Switches the tonemapping curve",
+        |p: &mut Program, (tonemapper,)| {
+            p.post.settings.tonemapper = tonemapper;
+            Ok(String::new())
+        },
+    );
+    commands.register(
+        "post gamma",
+        "<gamma>",
+        "Sets the gamma on top of the sRGB encoding",
+        |p: &mut Program, (gamma,): (f32,)| {
+            ensure!(gamma > 0., "The gamma has to be positive");
+            p.post.settings.gamma = gamma;
+            Ok(String::new())
+        },
+    );
+    commands.register(
+        "post bloom",
+        "<threshold> <intensity>",
+        "Sets how bright pixels have to be to bloom, and how much",
+        |p: &mut Program, (threshold, intensity)| {
+            p.post.settings.bloom_threshold = threshold;
+            p.post.settings.bloom_intensity = intensity;
+            Ok(String::new())
+        },
+    );
+    commands.register(
+        "post grading",
+        "<strength>",
+        "Sets how much of the LUT's color is used, from 0 to 1",
+        |p: &mut Program, (strength,): (f32,)| {
+            ensure!(
+                (0. ..=1.).contains(&strength),
+                "The strength has to be between 0 and 1"
+            );
+            p.post.settings.lut_strength = strength;
+            Ok(String::new())
+        },
+    );
+    commands.register(
+        "post lut",
+        "<file>",
+        "Loads a color grading LUT from res/",
+        |p: &mut Program, (file,): (String,)| {
+            let path = p.res_dir.join(&file);
+            p.post.load_lut(&p.device, &p.queue, path)?;
+            Ok(format!("Loaded {}", file))
+        },
+    );
+    commands.register(
+        "post fxaa",
+        "<edge threshold> <span>",
+        "Sets the contrast FXAA smooths and how far it blends, in pixels",
+        |p: &mut Program, (edge_threshold, span)| {
+            p.post.settings.fxaa_edge_threshold = edge_threshold;
+            p.post.settings.fxaa_span_max = span;
+            Ok(String::new())
+        },
+    );
+    commands
+}
+
+/// The passes of a frame. The swap chain image is the only import. The scene
+/// is drawn in HDR, with `samples` samples per pixel, after the ambient
+/// occlusion if SSAO is enabled. The post-processing passes bring it to the
+/// swap chain, and the overlay is drawn on top of that.
+fn render_graph(
+    post: &PostProcess,
+    ssao: &Ssao,
+    format: wgpu::TextureFormat,
+    samples: u32,
+) -> Result<RenderGraph<Program>> {
+    let mut graph = RenderGraph::new();
+    let frame = graph.import("swap chain");
+    let ambient_occlusion = if ssao.enabled() {
+        Some(add_ssao_passes(&mut graph))
+    } else {
+        None
+    };
+    let reads = ambient_occlusion.iter().copied().collect::<Vec<_>>();
+    let hdr = graph.add_texture(TextureDesc::new("hdr", HDR_FORMAT));
+    let depth = graph.add_texture(TextureDesc {
+        sample_count: samples,
+        ..TextureDesc::new("depth", Texture::DEPTH_FORMAT)
+    });
+    if samples > 1 {
+        let multisampled = graph.add_texture(TextureDesc {
+            sample_count: samples,
+            ..TextureDesc::new("multisampled hdr", HDR_FORMAT)
+        });
+        graph.add_pass(
+            "main",
+            &reads,
+            &[multisampled, depth, hdr],
+            move |p: &mut Program, encoder, resources| {
+                p.main_pass(
+                    encoder,
+                    resources.view(multisampled),
+                    Some(resources.view(hdr)),
+                    resources.view(depth),
+                    ambient_occlusion.map(|id| resources.view(id)),
+                )
+            },
+        );
+    } else {
+        graph.add_pass(
+            "main",
+            &reads,
+            &[hdr, depth],
+            move |p: &mut Program, encoder, resources| {
+                p.main_pass(
+                    encoder,
+                    resources.view(hdr),
+                    None,
+                    resources.view(depth),
+                    ambient_occlusion.map(|id| resources.view(id)),
+                )
+            },
+        );
+    }
+    post.add_passes(&mut graph, hdr, frame, format, post_context);
+    graph.add_pass(
+        "overlay",
+        &[],
+        &[frame],
+        move |p: &mut Program, encoder, resources| p.overlay_pass(encoder, resources.view(frame)),
+    );
+    graph.compile()?;
+    Ok(graph)
+}
+
+/// Stops the sun from moving the light after it was set by hand.
+fn take_light_from_sky(p: &mut Program) -> String {
+    if !p.sky.drives_light {
+        return String::new();
+    }
+    p.sky.drives_light = false;
+    String::from("The light no longer follows the sun, `time` brings it back")
+}
+
+/// The G-buffer, the occlusion and its blur. Returns the blurred occlusion.
+fn add_ssao_passes(graph: &mut RenderGraph<Program>) -> ResourceId {
+    let gbuffer = graph.add_texture(TextureDesc::new("gbuffer", GBUFFER_FORMAT));
+    let gbuffer_depth = graph.add_texture(TextureDesc::new("gbuffer depth", Texture::DEPTH_FORMAT));
+    let occlusion = graph.add_texture(TextureDesc::new("occlusion", OCCLUSION_FORMAT));
+    let blurred = graph.add_texture(TextureDesc::new("ambient occlusion", OCCLUSION_FORMAT));
+    graph.add_pass(
+        "gbuffer",
+        &[],
+        &[gbuffer, gbuffer_depth],
+        move |p: &mut Program, encoder, resources| {
+            p.gbuffer_pass(
+                encoder,
+                resources.view(gbuffer),
+                resources.view(gbuffer_depth),
+            )
+        },
+    );
+    graph.add_pass(
+        "ssao",
+        &[gbuffer],
+        &[occlusion],
+        move |p: &mut Program, encoder, resources| {
+            p.gpu_timer.begin(encoder, "ssao");
+            p.ssao.occlusion_pass(
+                &p.device,
+                encoder,
+                resources.view(gbuffer),
+                resources.view(occlusion),
+            );
+            p.gpu_timer.end(encoder);
+        },
+    );
+    graph.add_pass(
+        "ssao blur",
+        &[occlusion],
+        &[blurred],
+        move |p: &mut Program, encoder, resources| {
+            p.gpu_timer.begin(encoder, "ssao blur");
+            p.ssao.blur_pass(
+                &p.device,
+                encoder,
+                resources.view(occlusion),
+                resources.view(blurred),
+            );
+            p.gpu_timer.end(encoder);
+        },
+    );
+    blurred
+}
+
+fn post_context(p: &mut Program) -> PostContext {
+    PostContext {
+        post: &mut p.post,
+        device: &p.device,
+        gpu_timer: &mut p.gpu_timer,
+    }
+}
+
+/// How far from the chunk's center the light is placed towards the sun. Far
+/// enough for the rays to be almost parallel over the chunk, and within the
+/// camera's far plane, so the light's model shows where the sun is.
+const SUN_DISTANCE: f32 = 80.;
+
+/// Where the `profile` command saves captures, in the working directory.
+const PROFILE_PATH: &str = "profile.json";
+
+/// How far away the block the camera looks at can be.
+const TARGET_DISTANCE: f32 = 8.;
+
+/// Directory of the block textures in `res/`, which are packed into an atlas.
+const BLOCK_DIR: &str = "blocks";
+
+/// Loads the block atlas into a texture array. In development mode the block
+/// textures are packed and their mip levels generated when the program starts,
+/// so changes don't need a rebuild. Otherwise `build.rs` already packed them
+/// and stored the mip levels in `atlas.ktx2`.
+fn load_block_atlas(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    mipmaps: &mut MipmapGenerator,
+    res_dir: &Path,
+    dev: bool,
+    kind: TextureArrayKind,
+) -> Result<(AtlasManifest, TextureArray)> {
+    // Levels past `mip_levels` mix neighbouring textures, so they're never
+    // sampled, even when the texture has them.
+    let create_sampler = |manifest: &AtlasManifest| {
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            lod_max_clamp: manifest.mip_levels.saturating_sub(1) as f32,
+            ..Default::default()
+        })
+    };
+
+    if !dev {
+        let manifest = AtlasManifest::load(res_dir.join("atlas.txt"))?;
+        let sampler = create_sampler(&manifest);
+        let file = TextureFile::load(res_dir.join("atlas.ktx2"))?;
+        let texture = Texture::from_file(device, queue, &file, Some("block atlas"))?;
+        let texture_array = match kind {
+            TextureArrayKind::BindingArray => {
+                TextureArray::create(device, vec![&texture.view], &sampler)
+            }
+            TextureArrayKind::Layers => {
+                TextureArray::from_layers(device, &texture.texture, file.layer_count, &sampler)
+            }
+        };
+        return Ok((manifest, texture_array));
+    }
+
+    let block_atlas = atlas::block_atlas(res_dir.join(BLOCK_DIR))?;
+    let sampler = create_sampler(&block_atlas.manifest);
+    if kind == TextureArrayKind::Layers {
+        let texture_array = TextureArray::create_layers(
+            device,
+            queue,
+            mipmaps,
+            &[block_atlas.image.clone()],
+            &sampler,
+        )?;
+        return Ok((block_atlas.manifest, texture_array));
+    }
+    let image = image::DynamicImage::ImageRgba8(block_atlas.image.clone());
+    let textures = vec![Texture::from_image(
+        device,
+        queue,
+        &image,
+        Some("block atlas"),
+    )?];
+    let targets = textures
+        .iter()
+        .map(|t| t.mipmap_target(MipFilter::Linear))
+        .collect::<Vec<_>>();
+    mipmaps.generate_all(device, queue, &targets);
+    let texture_views = textures
+        .iter()
+        .map(|t| &t.view)
+        .collect::<Vec<&wgpu::TextureView>>();
+    Ok((
+        block_atlas.manifest,
+        TextureArray::create(device, texture_views, &sampler),
+    ))
+}
+
+/// Variants of the main pipeline for the debug views that need them.
+struct DebugPipelines {
+    /// `None` if the adapter can't draw lines.
+    wireframe: Option<wgpu::RenderPipeline>,
+    overdraw: wgpu::RenderPipeline,
+}
+
+/// Creates the main pipeline along with its debug variants. `wireframe` is
+/// whether the device has `NON_FILL_POLYGON_MODE`.
+fn create_main_pipeline(
+    device: &wgpu::Device,
+    dev: bool,
+    texture_array_kind: TextureArrayKind,
+    layouts: &[(&wgpu::BindGroupLayout, &[wgpu::BindGroupLayoutEntry])],
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+    wireframe: bool,
+) -> Result<(wgpu::RenderPipeline, DebugPipelines)> {
+    let vert_shader = hot_reload::shader_code(
+        dev,
+        "shader.vert",
+        &Preprocessor::new(),
+        include_bytes!(concat!(env!("OUT_DIR"), "/shaders/shader.vert.spv")),
+    )?;
+    // `build.rs` compiles a variant of the fragment shader for each kind.
+    let (frag_preprocessor, frag_bundled): (_, &'static [u8]) = match texture_array_kind.define() {
+        Some(define) => (
+            Preprocessor::new().define(define, ""),
+            include_bytes!(concat!(env!("OUT_DIR"), "/shaders/shader.layers.frag.spv")),
+        ),
+        None => (
+            Preprocessor::new(),
+            include_bytes!(concat!(env!("OUT_DIR"), "/shaders/shader.frag.spv")),
+        ),
+    };
+    let frag_shader =
+        hot_reload::shader_code(dev, "shader.frag", &frag_preprocessor, frag_bundled)?;
+    let buffers = [Vertex::init_buffer_layout(), QuadRaw::init_buffer_layout()];
+    let main = create_pipeline(
+        "main",
+        device,
+        layouts,
+        (&vert_shader, &frag_shader),
+        (format, sample_count),
+        &buffers,
+        PipelineVariant::default(),
+    )?;
+    let overdraw = create_pipeline(
+        "overdraw",
+        device,
+        layouts,
+        (&vert_shader, &frag_shader),
+        (format, sample_count),
+        &buffers,
+        PipelineVariant::overdraw(),
+    )?;
+    let wireframe = if wireframe {
+        let wireframe_shader = hot_reload::shader_code(
+            dev,
+            "wireframe.frag",
+            &Preprocessor::new(),
+            include_bytes!(concat!(env!("OUT_DIR"), "/shaders/wireframe.frag.spv")),
+        )?;
+        Some(create_pipeline(
+            "wireframe",
+            device,
+            layouts,
+            (&vert_shader, &wireframe_shader),
+            (format, sample_count),
+            &buffers,
+            PipelineVariant::wireframe(),
+        )?)
+    } else {
+        None
+    };
+    Ok((
+        main,
+        DebugPipelines {
+            wireframe,
+            overdraw,
+        },
+    ))
+}
+
+fn create_light_pipeline(
+    device: &wgpu::Device,
+    dev: bool,
+    layouts: &[(&wgpu::BindGroupLayout, &[wgpu::BindGroupLayoutEntry])],
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> Result<wgpu::RenderPipeline> {
+    let vert_light_shader = hot_reload::shader_code(
+        dev,
+        "light.vert",
+        &Preprocessor::new(),
+        include_bytes!(concat!(env!("OUT_DIR"), "/shaders/light.vert.spv")),
+    )?;
+    let frag_light_shader = hot_reload::shader_code(
+        dev,
+        "light.frag",
+        &Preprocessor::new(),
+        include_bytes!(concat!(env!("OUT_DIR"), "/shaders/light.frag.spv")),
+    )?;
+    create_pipeline(
+        "light",
+        device,
+        layouts,
+        (&vert_light_shader, &frag_light_shader),
+        (format, sample_count),
+        &[
+            Vertex::init_buffer_layout(),
+            InstanceRaw::init_buffer_layout(),
+        ],
+        PipelineVariant::default(),
+    )
+}
+
+/// Draws [`SkinnedModel`]s with the joint matrices of their animation player.
+fn create_skinned_pipeline(
+    device: &wgpu::Device,
+    dev: bool,
+    layouts: &[(&wgpu::BindGroupLayout, &[wgpu::BindGroupLayoutEntry])],
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> Result<wgpu::RenderPipeline> {
+    let vert_shader = hot_reload::shader_code(
+        dev,
+        "skinned.vert",
+        &Preprocessor::new(),
+        include_bytes!(concat!(env!("OUT_DIR"), "/shaders/skinned.vert.spv")),
+    )?;
+    let frag_shader = hot_reload::shader_code(
+        dev,
+        "model.frag",
+        &Preprocessor::new(),
+        include_bytes!(concat!(env!("OUT_DIR"), "/shaders/model.frag.spv")),
+    )?;
+    create_pipeline(
+        "skinned",
+        device,
+        layouts,
+        (&vert_shader, &frag_shader),
+        (format, sample_count),
+        &[
+            SkinnedVertex::init_buffer_layout(),
+            InstanceRaw::init_buffer_layout(),
+        ],
+        PipelineVariant::default(),
+    )
+}
+
+/// Checks the bind group layouts (along with the entries they were created
+/// from) and vertex buffers against the shaders before building the pipeline,
+/// so a mismatch names the binding or location instead of failing in wgpu.
+/// The pipeline draws to a color target of `format` with `sample_count`
+/// samples.
+fn create_pipeline(
+    label: &str,
+    device: &wgpu::Device,
+    layouts: &[(&wgpu::BindGroupLayout, &[wgpu::BindGroupLayoutEntry])],
+    (vert, frag): (&ShaderCode, &ShaderCode),
+    (format, sample_count): (wgpu::TextureFormat, u32),
+    buffers: &[wgpu::VertexBufferLayout],
+    variant: PipelineVariant,
+) -> Result<wgpu::RenderPipeline> {
+    let interface = PipelineInterface::new(&[&vert.reflect()?, &frag.reflect()?])?;
+    let entries = layouts.iter().map(|(_, e)| *e).collect::<Vec<_>>();
+    interface
+        .validate(&entries, buffers)
+        .with_context(|| format!("Layouts of the {} pipeline don't match its shaders", label))?;
+
+    let bind_group_layouts = layouts.iter().map(|(l, _)| *l).collect::<Vec<_>>();
+    Ok(new_render_pipeline(
+        label,
+        device,
+        &bind_group_layouts,
+        &vert.create_module(device, &format!("{} vertex shader", label)),
+        &frag.create_module(device, &format!("{} fragment shader", label)),
+        format,
+        texture::Texture::DEPTH_FORMAT,
+        sample_count,
+        buffers,
+        variant,
+    ))
+}
@@ -0,0 +1,356 @@
+//! Builds mip chains on the CPU, so they can be stored with the texture
+//! instead of generated on every start. Like `atlas.rs` it's used by
+//! `build.rs` and the `atlas` binary, so it only depends on `std` and `image`.
+
+use image::RgbaImage;
+use std::f32::consts::PI;
+use std::str::FromStr;
+
+/// Filter that computes a texel of the next level from the one above it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownsampleFilter {
+    /// Averages 2x2 texels. Cheap, but a bit blurry.
+    Box,
+    /// Windowed sinc with a Kaiser window, sharp without much ringing.
+    Kaiser,
+    /// Lanczos with 3 lobes, the sharpest, but can ring around hard edges.
+    Lanczos,
+}
+
+impl DownsampleFilter {
+    /// Half the width of the filter, in texels of the smaller level.
+    fn radius(self) -> f32 {
+        match self {
+            DownsampleFilter::Box => 0.5,
+            DownsampleFilter::Kaiser | DownsampleFilter::Lanczos => 3.,
+        }
+    }
+
+    /// How many levels, the first one included, only read texels at most
+    /// `gutter` texels of the first level outside a block of it. Every level
+    /// reaches `radius` of its texels further, so it's a conservative bound.
+    pub fn clean_levels(self, gutter: u32) -> u32 {
+        let mut levels = 1;
+        let mut reach = 0.;
+        while levels < 32 {
+            reach += self.radius() * (1u64 << levels) as f32;
+            if reach > gutter as f32 {
+                break;
+            }
+            levels += 1;
+        }
+        levels
+    }
+
+    /// Weight of a texel `x` texels of the smaller level away from the
+    /// center.
+    fn weight(self, x: f32) -> f32 {
+        let x = x.abs();
+        if x >= self.radius() {
+            return 0.;
+        }
+        match self {
+            DownsampleFilter::Box => 1.,
+            DownsampleFilter::Kaiser => {
+                const ALPHA: f32 = 4.;
+                let t = x / self.radius();
+                sinc(x) * bessel_i0(ALPHA * (1. - t * t).sqrt()) / bessel_i0(ALPHA)
+            }
+            DownsampleFilter::Lanczos => sinc(x) * sinc(x / self.radius()),
+        }
+    }
+}
+
+impl FromStr for DownsampleFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "box" => Ok(DownsampleFilter::Box),
+            "kaiser" => Ok(DownsampleFilter::Kaiser),
+            "lanczos" => Ok(DownsampleFilter::Lanczos),
+            _ => Err(format!(
+                "unknown filter {:?}, expected box, kaiser or lanczos",
+                s
+            )),
+        }
+    }
+}
+
+/// Every mip level of `image`, starting with the image itself and ending
+/// with 1x1. The color channels of `srgb` images are filtered in linear
+/// space, alpha always is.
+pub fn mip_chain(image: &RgbaImage, filter: DownsampleFilter, srgb: bool) -> Vec<RgbaImage> {
+    let mut levels = vec![image.clone()];
+    let (mut width, mut height) = image.dimensions();
+    let mut texels = to_linear(image, srgb);
+    while width > 1 || height > 1 {
+        let (next_width, next_height) = ((width / 2).max(1), (height / 2).max(1));
+        let rows = downsample_rows(&texels, width, height, next_width, filter);
+        texels = downsample_columns(&rows, next_width, height, next_height, filter);
+        width = next_width;
+        height = next_height;
+        levels.push(from_linear(&texels, width, height, srgb));
+    }
+    levels
+}
+
+/// Halves the width of every row.
+fn downsample_rows(
+    texels: &[[f32; 4]],
+    width: u32,
+    height: u32,
+    next_width: u32,
+    filter: DownsampleFilter,
+) -> Vec<[f32; 4]> {
+    let weights = weights(width, next_width, filter);
+    let mut out = Vec::with_capacity((next_width * height) as usize);
+    for y in 0..height as usize {
+        let row = &texels[y * width as usize..(y + 1) * width as usize];
+        for taps in weights.iter() {
+            out.push(apply(taps, |i| row[i]));
+        }
+    }
+    out
+}
+
+/// Halves the height of every column.
+fn downsample_columns(
+    texels: &[[f32; 4]],
+    width: u32,
+    height: u32,
+    next_height: u32,
+    filter: DownsampleFilter,
+) -> Vec<[f32; 4]> {
+    let weights = weights(height, next_height, filter);
+    let mut out = Vec::with_capacity((width * next_height) as usize);
+    for taps in weights.iter() {
+        for x in 0..width as usize {
+            out.push(apply(taps, |i| texels[i * width as usize + x]));
+        }
+    }
+    out
+}
+
+/// For every texel of the smaller axis, the texels of the larger one that
+/// contribute to it along with their weights, which add up to 1. Texels past
+/// the edge repeat the outermost one.
+fn weights(size: u32, next_size: u32, filter: DownsampleFilter) -> Vec<Vec<(usize, f32)>> {
+    let scale = size as f32 / next_size as f32;
+    let reach = filter.radius() * scale;
+    (0..next_size)
+        .map(|i| {
+            let center = (i as f32 + 0.5) * scale;
+            let first = (center - reach).floor() as i64;
+            let last = (center + reach).ceil() as i64;
+            let mut taps = Vec::new();
+            for j in first..=last {
+                let weight = filter.weight((j as f32 + 0.5 - center) / scale);
+                if weight != 0. {
+                    taps.push((j.max(0).min(size as i64 - 1) as usize, weight));
+                }
+            }
+            let sum = taps.iter().map(|(_, w)| w).sum::<f32>();
+            for (_, weight) in taps.iter_mut() {
+                *weight /= sum;
+            }
+            taps
+        })
+        .collect()
+}
+
+fn apply(taps: &[(usize, f32)], texel: impl Fn(usize) -> [f32; 4]) -> [f32; 4] {
+    let mut sum = [0.; 4];
+    for &(i, weight) in taps {
+        let t = texel(i);
+        for c in 0..4 {
+            sum[c] += t[c] * weight;
+        }
+    }
+    sum
+}
+
+fn to_linear(image: &RgbaImage, srgb: bool) -> Vec<[f32; 4]> {
+    image
+        .pixels()
+        .map(|p| {
+            let channel = |c: u8| {
+                let c = c as f32 / 255.;
+                if srgb {
+                    srgb_to_linear(c)
+                } else {
+                    c
+                }
+            };
+            [
+                channel(p[0]),
+                channel(p[1]),
+                channel(p[2]),
+                p[3] as f32 / 255.,
+            ]
+        })
+        .collect()
+}
+
+fn from_linear(texels: &[[f32; 4]], width: u32, height: u32, srgb: bool) -> RgbaImage {
+    // Sharper filters overshoot near edges, so the result is clamped.
+    let byte = |c: f32| (c.max(0.).min(1.) * 255. + 0.5) as u8;
+    let channel = |c: f32| {
+        if srgb {
+            byte(linear_to_srgb(c.max(0.)))
+        } else {
+            byte(c)
+        }
+    };
+    let mut image = RgbaImage::new(width, height);
+    for (pixel, t) in image.pixels_mut().zip(texels) {
+        *pixel = image::Rgba([channel(t[0]), channel(t[1]), channel(t[2]), byte(t[3])]);
+    }
+    image
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1. / 2.4) - 0.055
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x == 0. {
+        1.
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Modified Bessel function of the first kind and order 0, as a power series.
+fn bessel_i0(x: f32) -> f32 {
+    let mut sum = 1.;
+    let mut term = 1.;
+    let half = x / 2.;
+    for k in 1..20 {
+        term *= (half / k as f32) * (half / k as f32);
+        sum += term;
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILTERS: [DownsampleFilter; 3] = [
+        DownsampleFilter::Box,
+        DownsampleFilter::Kaiser,
+        DownsampleFilter::Lanczos,
+    ];
+
+    /// Black and white texels, with alpha the other way around.
+    fn checker(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            let c = if (x + y) % 2 == 0 { 0 } else { 255 };
+            image::Rgba([c, c, c, 255 - c])
+        })
+    }
+
+    #[test]
+    fn halves_down_to_one_texel() {
+        let sizes = |width, height| {
+            mip_chain(&checker(width, height), DownsampleFilter::Box, false)
+                .iter()
+                .map(|level| level.dimensions())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sizes(5, 3), vec![(5, 3), (2, 1), (1, 1)]);
+        assert_eq!(
+            sizes(100, 1),
+            vec![(100, 1), (50, 1), (25, 1), (12, 1), (6, 1), (3, 1), (1, 1)]
+        );
+        assert_eq!(sizes(1, 6), vec![(1, 6), (1, 3), (1, 1)]);
+        assert_eq!(sizes(1, 1), vec![(1, 1)]);
+    }
+
+    #[test]
+    fn wider_filters_need_wider_gutters() {
+        let levels = |filter: DownsampleFilter| {
+            [0, 1, 2, 4, 8, 16]
+                .iter()
+                .map(|&gutter| filter.clean_levels(gutter))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(levels(DownsampleFilter::Box), vec![1, 2, 2, 3, 4, 5]);
+        assert_eq!(levels(DownsampleFilter::Kaiser), vec![1, 1, 1, 1, 2, 2]);
+        assert_eq!(
+            levels(DownsampleFilter::Lanczos),
+            levels(DownsampleFilter::Kaiser)
+        );
+    }
+
+    #[test]
+    fn box_averages_2x2_texels() {
+        let levels = mip_chain(&checker(2, 2), DownsampleFilter::Box, false);
+        assert_eq!(levels.len(), 2);
+        assert_eq!(
+            levels[1].get_pixel(0, 0),
+            &image::Rgba([128, 128, 128, 128])
+        );
+    }
+
+    #[test]
+    fn srgb_colors_are_averaged_in_linear_space() {
+        let levels = mip_chain(&checker(2, 2), DownsampleFilter::Box, true);
+        // Half of the light of white is brighter than 128 in sRGB, alpha is
+        // averaged as is.
+        let expected = (linear_to_srgb(0.5) * 255. + 0.5) as u8;
+        assert_eq!(expected, 188);
+        assert_eq!(
+            levels[1].get_pixel(0, 0),
+            &image::Rgba([expected, expected, expected, 128])
+        );
+    }
+
+    #[test]
+    fn flat_images_stay_flat() {
+        let image = RgbaImage::from_pixel(7, 5, image::Rgba([10, 100, 200, 50]));
+        for &filter in FILTERS.iter() {
+            for level in mip_chain(&image, filter, true).iter().skip(1) {
+                assert!(level.pixels().all(|p| p == image.get_pixel(0, 0)));
+            }
+        }
+    }
+
+    #[test]
+    fn weights_add_up_to_one() {
+        for &filter in FILTERS.iter() {
+            for &size in [1, 2, 3, 5, 8, 13, 64].iter() {
+                let next_size = (size / 2).max(1);
+                let weights = weights(size, next_size, filter);
+                assert_eq!(weights.len(), next_size as usize);
+                for taps in weights.iter() {
+                    let sum = taps.iter().map(|(_, w)| w).sum::<f32>();
+                    assert!((sum - 1.).abs() < 1e-5, "{:?} {} {}", filter, size, sum);
+                    assert!(taps.iter().all(|&(i, _)| i < size as usize));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn srgb_conversion_round_trips() {
+        for i in 0..=255u8 {
+            let c = i as f32 / 255.;
+            let back = linear_to_srgb(srgb_to_linear(c));
+            assert!((back - c).abs() < 1e-5, "{} {}", c, back);
+        }
+    }
+}
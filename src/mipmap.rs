@@ -1,107 +1,195 @@
 use crate::texture::Texture;
+use std::collections::HashMap;
 use std::num::NonZeroU32;
 
-const TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+/// How a mip level is computed from the one above it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MipFilter {
+    /// Bilinear average, for color textures.
+    Linear,
+    /// Averages the normals and normalises them again, so smaller levels
+    /// don't end up with shorter normals. Needs a non-sRGB format.
+    NormalMap,
+}
 
-pub fn generate_multiple_texture_mipmaps(
-    device: &wgpu::Device,
-    encoder: &mut wgpu::CommandEncoder,
-    queue: &wgpu::Queue,
-    textures: Vec<&Texture>,
-) {
-    for t in textures {
-        generate_texture_mipmaps(device, encoder, &queue, &t.texture, t.mip_level_count);
-    }
+/// A texture whose mip levels are generated from its first level.
+pub struct MipmapTarget<'a> {
+    pub texture: &'a wgpu::Texture,
+    pub format: wgpu::TextureFormat,
+    pub mip_level_count: u32,
+    pub layer_count: u32,
+    pub filter: MipFilter,
 }
 
-pub fn generate_texture_mipmaps(
-    device: &wgpu::Device,
-    encoder: &mut wgpu::CommandEncoder,
-    queue: &wgpu::Queue,
-    texture: &wgpu::Texture,
-    mip_level_count: u32,
-) {
-    let vert_shader =
-        device.create_shader_module(&wgpu::include_spirv!("shaders/blit/blit.vert.spv"));
-    let frag_shader =
-        device.create_shader_module(&wgpu::include_spirv!("shaders/blit/blit.frag.spv"));
+/// Generates mip levels with a render pass per level. The shaders, sampler
+/// and layouts are created once, pipelines once per format and filter.
+pub struct MipmapGenerator {
+    vert_shader: wgpu::ShaderModule,
+    linear_shader: wgpu::ShaderModule,
+    normal_shader: wgpu::ShaderModule,
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    sampler: wgpu::Sampler,
+    pipelines: HashMap<(wgpu::TextureFormat, MipFilter), wgpu::RenderPipeline>,
+}
 
-    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        label: None,
-        layout: None,
-        vertex: wgpu::VertexState {
-            module: &vert_shader,
-            entry_point: "main",
-            buffers: &[],
-        },
-        primitive: wgpu::PrimitiveState {
-            topology: wgpu::PrimitiveTopology::TriangleList,
+impl MipmapGenerator {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let vert_shader = device.create_shader_module(&wgpu::include_spirv!(concat!(
+            env!("OUT_DIR"),
+            "/shaders/blit/blit.vert.spv"
+        )));
+        let linear_shader = device.create_shader_module(&wgpu::include_spirv!(concat!(
+            env!("OUT_DIR"),
+            "/shaders/blit/blit.frag.spv"
+        )));
+        let normal_shader = device.create_shader_module(&wgpu::include_spirv!(concat!(
+            env!("OUT_DIR"),
+            "/shaders/blit/blit_normal.frag.spv"
+        )));
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Mipmap bind group layout"),
+            entries: &Texture::BIND_GROUP_LAYOUT_ENTRIES,
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Mipmap pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Mipmap sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
             ..Default::default()
-        },
-        depth_stencil: None,
-        multisample: wgpu::MultisampleState::default(),
-        fragment: Some(wgpu::FragmentState {
-            module: &frag_shader,
-            entry_point: "main",
-            targets: &[TEXTURE_FORMAT.into()],
-        }),
-    });
+        });
+        Self {
+            vert_shader,
+            linear_shader,
+            normal_shader,
+            bind_group_layout,
+            pipeline_layout,
+            sampler,
+            pipelines: HashMap::new(),
+        }
+    }
 
-    let bind_group_layout = pipeline.get_bind_group_layout(0);
+    /// Records the passes that fill every mip level of every layer of
+    /// `target`. The texture needs the `SAMPLED` and `RENDER_ATTACHMENT`
+    /// usages.
+    pub fn generate(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &MipmapTarget,
+    ) {
+        let key = (target.format, target.filter);
+        if !self.pipelines.contains_key(&key) {
+            let pipeline = self.create_pipeline(device, target.format, target.filter);
+            self.pipelines.insert(key, pipeline);
+        }
+        let pipeline = &self.pipelines[&key];
 
-    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-        label: None,
-        address_mode_u: wgpu::AddressMode::ClampToEdge,
-        address_mode_v: wgpu::AddressMode::ClampToEdge,
-        address_mode_w: wgpu::AddressMode::ClampToEdge,
-        mag_filter: wgpu::FilterMode::Linear,
-        min_filter: wgpu::FilterMode::Linear,
-        mipmap_filter: wgpu::FilterMode::Linear,
-        ..Default::default()
-    });
-    let views = (0..mip_level_count)
-        .map(|mip| {
-            texture.create_view(&wgpu::TextureViewDescriptor {
-                label: None,
-                format: None,
-                dimension: None,
-                aspect: wgpu::TextureAspect::All,
-                base_mip_level: mip,
-                mip_level_count: NonZeroU32::new(1),
-                base_array_layer: 0,
-                array_layer_count: None,
-            })
-        })
-        .collect::<Vec<_>>();
-    for target_mip in 1..mip_level_count as usize {
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&views[target_mip - 1]),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&sampler),
-                },
-            ],
-        });
-        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: None,
-            color_attachments: &[wgpu::RenderPassColorAttachment {
-                view: &views[target_mip],
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color::GREEN),
-                    store: true,
-                },
-            }],
-            depth_stencil_attachment: None,
+        for layer in 0..target.layer_count {
+            let views = (0..target.mip_level_count)
+                .map(|mip| {
+                    target.texture.create_view(&wgpu::TextureViewDescriptor {
+                        label: None,
+                        format: None,
+                        dimension: Some(wgpu::TextureViewDimension::D2),
+                        aspect: wgpu::TextureAspect::All,
+                        base_mip_level: mip,
+                        mip_level_count: NonZeroU32::new(1),
+                        base_array_layer: layer,
+                        array_layer_count: NonZeroU32::new(1),
+                    })
+                })
+                .collect::<Vec<_>>();
+            for target_mip in 1..views.len() {
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: None,
+                    layout: &self.bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&views[target_mip - 1]),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&self.sampler),
+                        },
+                    ],
+                });
+                let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Mipmap pass"),
+                    color_attachments: &[wgpu::RenderPassColorAttachment {
+                        view: &views[target_mip],
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::GREEN),
+                            store: true,
+                        },
+                    }],
+                    depth_stencil_attachment: None,
+                });
+                pass.set_pipeline(pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.draw(0..3, 0..1);
+            }
+        }
+    }
+
+    /// Generates the mip levels of all `targets` in a single submission.
+    pub fn generate_all(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        targets: &[MipmapTarget],
+    ) {
+        if targets.is_empty() {
+            return;
+        }
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Mipmap encoder"),
         });
-        pass.set_pipeline(&pipeline);
-        pass.set_bind_group(0, &bind_group, &[]);
-        pass.draw(0..3, 0..1);
+        for target in targets {
+            self.generate(device, &mut encoder, target);
+        }
+        queue.submit(Some(encoder.finish()));
+    }
+
+    fn create_pipeline(
+        &self,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        filter: MipFilter,
+    ) -> wgpu::RenderPipeline {
+        let frag_shader = match filter {
+            MipFilter::Linear => &self.linear_shader,
+            MipFilter::NormalMap => &self.normal_shader,
+        };
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&format!("Mipmap pipeline {:?} {:?}", format, filter)),
+            layout: Some(&self.pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &self.vert_shader,
+                entry_point: "main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: frag_shader,
+                entry_point: "main",
+                targets: &[format.into()],
+            }),
+        })
     }
 }
@@ -0,0 +1,254 @@
+use crate::modeling::skeleton::{JointBuffer, Skeleton, Transform};
+use nalgebra::{UnitQuaternion, Vector3};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interpolation {
+    Step,
+    Linear,
+}
+
+#[derive(Debug, Clone)]
+pub enum Keyframes {
+    Translation(Vec<Vector3<f32>>),
+    Rotation(Vec<UnitQuaternion<f32>>),
+    Scale(Vec<Vector3<f32>>),
+}
+
+/// Keyframes of a single joint property.
+#[derive(Debug, Clone)]
+pub struct Channel {
+    pub joint: usize,
+    pub interpolation: Interpolation,
+    pub times: Vec<f32>,
+    pub keyframes: Keyframes,
+}
+
+impl Channel {
+    /// Returns the two keyframes surrounding `time` and the blend factor
+    /// between them. Times outside of the channel are clamped.
+    fn keyframe_span(&self, time: f32) -> (usize, usize, f32) {
+        let last = self.times.len() - 1;
+        if time <= self.times[0] {
+            return (0, 0, 0.);
+        }
+        if time >= self.times[last] {
+            return (last, last, 0.);
+        }
+        let next = self.times.iter().position(|t| *t > time).unwrap_or(last);
+        let prev = next - 1;
+        let span = self.times[next] - self.times[prev];
+        let factor = if span > 0. {
+            (time - self.times[prev]) / span
+        } else {
+            0.
+        };
+        match self.interpolation {
+            Interpolation::Step => (prev, prev, 0.),
+            Interpolation::Linear => (prev, next, factor),
+        }
+    }
+
+    /// Writes the sampled value of this channel into `transform`.
+    pub fn sample(&self, time: f32, transform: &mut Transform) {
+        if self.times.is_empty() {
+            return;
+        }
+        let (a, b, t) = self.keyframe_span(time);
+        match &self.keyframes {
+            Keyframes::Translation(v) => transform.translation = v[a].lerp(&v[b], t),
+            Keyframes::Rotation(r) => {
+                // Flip the target when needed so we always take the shortest arc.
+                let target = if r[a].coords.dot(&r[b].coords) < 0. {
+                    UnitQuaternion::new_unchecked(-r[b].into_inner())
+                } else {
+                    r[b]
+                };
+                transform.rotation = r[a]
+                    .try_slerp(&target, t, 1.0e-6)
+                    .unwrap_or_else(|| r[a].nlerp(&target, t))
+            }
+            Keyframes::Scale(v) => transform.scale = v[a].lerp(&v[b], t),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AnimationClip {
+    pub name: String,
+    pub duration: f32,
+    pub channels: Vec<Channel>,
+}
+
+impl AnimationClip {
+    pub fn new(name: &str, channels: Vec<Channel>) -> Self {
+        let duration = channels
+            .iter()
+            .filter_map(|c| c.times.last().copied())
+            .fold(0., f32::max);
+        Self {
+            name: String::from(name),
+            duration,
+            channels,
+        }
+    }
+
+    /// Evaluates the local pose of `skeleton` at `time`. Joints that aren't
+    /// animated by the clip keep their rest pose.
+    pub fn sample_pose(&self, skeleton: &Skeleton, time: f32) -> Vec<Transform> {
+        let mut pose = skeleton.rest_pose();
+        for channel in self.channels.iter() {
+            if let Some(transform) = pose.get_mut(channel.joint) {
+                channel.sample(time, transform);
+            }
+        }
+        pose
+    }
+}
+
+/// Plays one clip at a time on a skeleton and uploads the resulting joint
+/// matrices for the skinning shader.
+pub struct AnimationPlayer {
+    pub clip: Option<usize>,
+    pub time: f32,
+    pub speed: f32,
+    pub looping: bool,
+    joint_buffer: JointBuffer,
+}
+
+impl AnimationPlayer {
+    pub fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, skeleton: &Skeleton) -> Self {
+        Self {
+            clip: None,
+            time: 0.,
+            speed: 1.,
+            looping: true,
+            joint_buffer: JointBuffer::new(device, layout, skeleton),
+        }
+    }
+
+    pub fn play(&mut self, clip: usize) {
+        self.clip = Some(clip);
+        self.time = 0.;
+    }
+
+    pub fn stop(&mut self) {
+        self.clip = None;
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.joint_buffer.bind_group
+    }
+
+    /// Advances the current clip by `delta` seconds and uploads the new pose.
+    pub fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        skeleton: &Skeleton,
+        clips: &[AnimationClip],
+        delta: f32,
+    ) {
+        let pose = match self.clip.and_then(|c| clips.get(c)) {
+            Some(clip) => {
+                self.time = advance(self.time, delta * self.speed, clip.duration, self.looping);
+                clip.sample_pose(skeleton, self.time)
+            }
+            None => skeleton.rest_pose(),
+        };
+        self.joint_buffer
+            .update(queue, &skeleton.joint_matrices(&pose));
+    }
+}
+
+fn advance(time: f32, delta: f32, duration: f32, looping: bool) -> f32 {
+    let time = time + delta;
+    if duration <= 0. {
+        0.
+    } else if looping {
+        time.rem_euclid(duration)
+    } else {
+        time.max(0.).min(duration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modeling::skeleton::Joint;
+    use nalgebra::Matrix4;
+    use std::f32::consts::FRAC_PI_2;
+
+    fn translation_channel(interpolation: Interpolation) -> Channel {
+        Channel {
+            joint: 0,
+            interpolation,
+            times: vec![1., 2.],
+            keyframes: Keyframes::Translation(vec![Vector3::zeros(), Vector3::new(2., 0., 0.)]),
+        }
+    }
+
+    fn sampled_translation(channel: &Channel, time: f32) -> Vector3<f32> {
+        let mut transform = Transform::identity();
+        channel.sample(time, &mut transform);
+        transform.translation
+    }
+
+    #[test]
+    fn clamps_outside_of_the_keyframes() {
+        let channel = translation_channel(Interpolation::Linear);
+        assert_eq!(sampled_translation(&channel, 0.), Vector3::zeros());
+        assert_eq!(sampled_translation(&channel, 5.), Vector3::new(2., 0., 0.));
+        assert_eq!(sampled_translation(&channel, 1.5), Vector3::new(1., 0., 0.));
+    }
+
+    #[test]
+    fn step_keeps_the_previous_keyframe() {
+        let channel = translation_channel(Interpolation::Step);
+        assert_eq!(sampled_translation(&channel, 1.9), Vector3::zeros());
+        assert_eq!(sampled_translation(&channel, 2.), Vector3::new(2., 0., 0.));
+    }
+
+    #[test]
+    fn slerp_takes_the_shortest_arc() {
+        let quarter = UnitQuaternion::from_axis_angle(&Vector3::z_axis(), FRAC_PI_2);
+        // The same rotation, but on the other side of the hypersphere.
+        let flipped = UnitQuaternion::new_unchecked(-quarter.into_inner());
+        let channel = Channel {
+            joint: 0,
+            interpolation: Interpolation::Linear,
+            times: vec![0., 1.],
+            keyframes: Keyframes::Rotation(vec![UnitQuaternion::identity(), flipped]),
+        };
+        let mut transform = Transform::identity();
+        channel.sample(0.5, &mut transform);
+        let eighth = UnitQuaternion::from_axis_angle(&Vector3::z_axis(), FRAC_PI_2 / 2.);
+        assert!(transform.rotation.angle_to(&eighth) < 1e-4);
+    }
+
+    #[test]
+    fn loops_or_clamps_the_time() {
+        assert_eq!(advance(1.5, 1., 2., true), 0.5);
+        assert_eq!(advance(0.5, -1., 2., true), 1.5);
+        assert_eq!(advance(1.5, 1., 2., false), 2.);
+        assert_eq!(advance(0.5, -1., 2., false), 0.);
+        assert_eq!(advance(1., 1., 0., true), 0.);
+    }
+
+    #[test]
+    fn unanimated_joints_keep_their_rest_pose() {
+        let joint = |parent, x| Joint {
+            name: String::new(),
+            parent,
+            inverse_bind_matrix: Matrix4::identity(),
+            rest_pose: Transform {
+                translation: Vector3::new(x, 0., 0.),
+                ..Transform::identity()
+            },
+        };
+        let (skeleton, _) = Skeleton::new(vec![joint(None, 0.), joint(Some(0), 3.)]);
+        let clip = AnimationClip::new("move", vec![translation_channel(Interpolation::Linear)]);
+        assert_eq!(clip.duration, 2.);
+        let pose = clip.sample_pose(&skeleton, 1.5);
+        assert_eq!(pose[0].translation, Vector3::new(1., 0., 0.));
+        assert_eq!(pose[1].translation, Vector3::new(3., 0., 0.));
+    }
+}
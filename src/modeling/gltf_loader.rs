@@ -0,0 +1,696 @@
+use crate::modeling::animation::{AnimationClip, Channel, Interpolation, Keyframes};
+use crate::modeling::skeleton::{Joint, Skeleton, Transform};
+use crate::modeling::vertex_index::Vertex;
+use anyhow::*;
+use nalgebra::{Matrix3, Matrix4, Quaternion, UnitQuaternion, Vector3, Vector4};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Geometry of a single glTF primitive, already transformed into model space.
+pub struct MeshData {
+    pub name: String,
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    pub material: usize,
+    pub skin: Option<SkinWeights>,
+}
+
+/// Per-vertex joint influences of a skinned primitive.
+pub struct SkinWeights {
+    pub skin: usize,
+    pub joints: Vec<[u32; 4]>,
+    pub weights: Vec<[f32; 4]>,
+}
+
+/// A skeleton together with all the animation clips that move it.
+pub struct SkinData {
+    pub skeleton: Skeleton,
+    pub animations: Vec<AnimationClip>,
+}
+
+/// Everything needed to build a [`Material`](crate::modeling::model::Material)
+/// without touching the GPU.
+pub struct MaterialData {
+    pub name: String,
+    pub base_color_factor: [f32; 4],
+    /// The decoded base color texture. The OBJ loader leaves it out when the
+    /// texture has a path, so it's only decoded if it isn't cached yet.
+    /// `gltf::import` decodes every image up front, so glTF materials with a
+    /// texture always have it.
+    pub base_color: Option<image::RgbaImage>,
+    /// File the base color texture was read from, if it wasn't embedded.
+    /// Materials with the same path share one texture on the GPU.
+    pub base_color_path: Option<PathBuf>,
+    pub normal: Option<image::RgbaImage>,
+}
+
+impl MaterialData {
+    fn default_material() -> Self {
+        Self {
+            name: String::from("default"),
+            base_color_factor: [1., 1., 1., 1.],
+            base_color: None,
+            base_color_path: None,
+            normal: None,
+        }
+    }
+
+    /// Base color texture, decoded from `base_color_path` if it wasn't
+    /// already and multiplied by the base color factor, or a 1x1 image
+    /// filled with the factor when the material has no texture. The result
+    /// is sRGB encoded, like the texture files.
+    pub fn base_color_image(&self) -> Result<image::RgbaImage> {
+        let mut img = if let Some(img) = &self.base_color {
+            img.clone()
+        } else if let Some(path) = &self.base_color_path {
+            image::open(path)
+                .with_context(|| format!("Failed to decode texture {:?}", path))?
+                .to_rgba8()
+        } else {
+            image::RgbaImage::from_pixel(1, 1, image::Rgba([255; 4]))
+        };
+        if self.has_base_color_factor() {
+            let [r, g, b, a] = self.base_color_factor;
+            for pixel in img.pixels_mut() {
+                let image::Rgba([pr, pg, pb, pa]) = *pixel;
+                *pixel = image::Rgba([
+                    scale_srgb(pr, r),
+                    scale_srgb(pg, g),
+                    scale_srgb(pb, b),
+                    to_u8(f32::from(pa) / 255. * a),
+                ]);
+            }
+        }
+        Ok(img)
+    }
+
+    /// Whether the base color factor changes the texture at all. Only
+    /// materials without one can share their texture with others.
+    pub fn has_base_color_factor(&self) -> bool {
+        self.base_color_factor != [1., 1., 1., 1.]
+    }
+}
+
+fn to_u8(c: f32) -> u8 {
+    (c.max(0.).min(1.) * 255.).round() as u8
+}
+
+/// Multiplies an sRGB encoded channel by a linear factor.
+fn scale_srgb(channel: u8, factor: f32) -> u8 {
+    let c = f32::from(channel) / 255.;
+    let linear = if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    };
+    let c = (linear * factor).max(0.).min(1.);
+    to_u8(if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1. / 2.4) - 0.055
+    })
+}
+
+/// CPU side of a loaded `.gltf`/`.glb` file.
+pub struct GltfData {
+    pub meshes: Vec<MeshData>,
+    pub materials: Vec<MaterialData>,
+    pub skins: Vec<SkinData>,
+}
+
+impl GltfData {
+    /// Imports a glTF 2.0 file. Buffers and images can be embedded (data URIs
+    /// or the GLB binary chunk) or stored next to the file. Every image is
+    /// decoded, even when its texture is cached already.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let (document, buffers, images) = gltf::import(path.as_ref())
+            .with_context(|| format!("Failed to import glTF file {:?}", path.as_ref()))?;
+
+        let folder = path.as_ref().parent().unwrap_or_else(|| Path::new(""));
+        let mut materials = Vec::new();
+        for material in document.materials() {
+            let pbr = material.pbr_metallic_roughness();
+            let (base_color, base_color_path) = match pbr.base_color_texture() {
+                Some(info) => {
+                    let source = info.texture().source();
+                    (
+                        Some(to_rgba(&images[source.index()])?),
+                        external_image_path(folder, &source),
+                    )
+                }
+                None => (None, None),
+            };
+            let normal = match material.normal_texture() {
+                Some(info) => Some(to_rgba(&images[info.texture().source().index()])?),
+                None => None,
+            };
+            materials.push(MaterialData {
+                name: material
+                    .name()
+                    .map(String::from)
+                    .unwrap_or_else(|| format!("material {}", materials.len())),
+                base_color_factor: pbr.base_color_factor(),
+                base_color,
+                base_color_path,
+                normal,
+            });
+        }
+        // Primitives without a material use the glTF default material, which
+        // is stored after all the materials from the file.
+        let default_material = materials.len();
+        materials.push(MaterialData::default_material());
+
+        let (skins, joint_remaps) = load_skins(&document, &buffers)?;
+
+        let mut meshes = Vec::new();
+        let scene = document
+            .default_scene()
+            .or_else(|| document.scenes().next())
+            .context("glTF file has no scenes")?;
+        for node in scene.nodes() {
+            load_node(
+                &node,
+                Matrix4::identity(),
+                &buffers,
+                default_material,
+                &joint_remaps,
+                &mut meshes,
+            )?;
+        }
+
+        Ok(Self {
+            meshes,
+            materials,
+            skins,
+        })
+    }
+}
+
+fn load_node(
+    node: &gltf::Node,
+    parent_transform: Matrix4<f32>,
+    buffers: &[gltf::buffer::Data],
+    default_material: usize,
+    joint_remaps: &[Vec<usize>],
+    meshes: &mut Vec<MeshData>,
+) -> Result<()> {
+    let transform = parent_transform * Matrix4::from(node.transform().matrix());
+
+    if let Some(mesh) = node.mesh() {
+        // Skinned meshes are positioned by their joints, so the node
+        // transform doesn't apply to them.
+        let skin = node.skin().map(|s| s.index());
+        let transform = match skin {
+            Some(_) => Matrix4::identity(),
+            None => transform,
+        };
+        let normal_matrix = Matrix3::from_fn(|r, c| transform[(r, c)])
+            .try_inverse()
+            .context("Node transform is not invertible")?
+            .transpose();
+
+        for primitive in mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                bail!(
+                    "Unsupported primitive mode {:?} in mesh {:?}",
+                    primitive.mode(),
+                    mesh.name()
+                );
+            }
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+            let positions: Vec<[f32; 3]> = reader
+                .read_positions()
+                .context("Primitive has no POSITION attribute")?
+                .collect();
+            let normals: Vec<[f32; 3]> = match reader.read_normals() {
+                Some(normals) => normals.collect(),
+                None => vec![[0., 0., 0.]; positions.len()],
+            };
+            let tex_cords: Vec<[f32; 2]> = match reader.read_tex_coords(0) {
+                Some(tex_cords) => tex_cords.into_f32().collect(),
+                None => vec![[0., 0.]; positions.len()],
+            };
+            let indices: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..positions.len() as u32).collect(),
+            };
+            let skin_weights = match (skin, reader.read_joints(0), reader.read_weights(0)) {
+                (Some(skin), Some(joints), Some(weights)) => {
+                    let remap = &joint_remaps[skin];
+                    let joints = joints
+                        .into_u16()
+                        .map(|j| {
+                            let mut out = [0; 4];
+                            for (o, j) in out.iter_mut().zip(j.iter()) {
+                                *o = *remap
+                                    .get(*j as usize)
+                                    .context("Vertex references a joint outside of its skin")?
+                                    as u32;
+                            }
+                            Ok(out)
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    Some(SkinWeights {
+                        skin,
+                        joints,
+                        weights: weights.into_f32().collect(),
+                    })
+                }
+                _ => None,
+            };
+
+            let vertices = positions
+                .iter()
+                .zip(normals.iter())
+                .zip(tex_cords.iter())
+                .map(|((p, n), t)| {
+                    let position = transform * Vector4::new(p[0], p[1], p[2], 1.);
+                    let normal = normal_matrix * Vector3::new(n[0], n[1], n[2]);
+                    let normal = if normal.norm() > 0. {
+                        normal.normalize()
+                    } else {
+                        normal
+                    };
+                    Vertex {
+                        position: [position.x, position.y, position.z],
+                        tex_cords: *t,
+                        normal: normal.into(),
+                    }
+                })
+                .collect();
+
+            meshes.push(MeshData {
+                name: format!(
+                    "{} primitive {}",
+                    mesh.name().unwrap_or("mesh"),
+                    primitive.index()
+                ),
+                vertices,
+                indices,
+                material: primitive.material().index().unwrap_or(default_material),
+                skin: skin_weights,
+            });
+        }
+    }
+
+    for child in node.children() {
+        load_node(
+            &child,
+            transform,
+            buffers,
+            default_material,
+            joint_remaps,
+            meshes,
+        )?;
+    }
+    Ok(())
+}
+
+/// Loads every skin as a [`Skeleton`] with the animations that target it.
+/// Also returns, per skin, the mapping from glTF joint order to skeleton order.
+fn load_skins(
+    document: &gltf::Document,
+    buffers: &[gltf::buffer::Data],
+) -> Result<(Vec<SkinData>, Vec<Vec<usize>>)> {
+    let mut node_parents = HashMap::new();
+    for node in document.nodes() {
+        for child in node.children() {
+            node_parents.insert(child.index(), node.index());
+        }
+    }
+
+    let mut skins = Vec::new();
+    let mut remaps = Vec::new();
+    for skin in document.skins() {
+        let joint_nodes: Vec<gltf::Node> = skin.joints().collect();
+        let node_to_joint: HashMap<usize, usize> = joint_nodes
+            .iter()
+            .enumerate()
+            .map(|(i, n)| (n.index(), i))
+            .collect();
+
+        let reader = skin.reader(|buffer| Some(&buffers[buffer.index()]));
+        let inverse_binds: Vec<Matrix4<f32>> = match reader.read_inverse_bind_matrices() {
+            Some(matrices) => matrices.map(Matrix4::from).collect(),
+            None => vec![Matrix4::identity(); joint_nodes.len()],
+        };
+        if inverse_binds.len() < joint_nodes.len() {
+            bail!(
+                "Skin {} has fewer inverse bind matrices than joints",
+                skin.index()
+            );
+        }
+
+        let joints = joint_nodes
+            .iter()
+            .zip(inverse_binds.iter())
+            .map(|(node, inverse_bind)| {
+                // The parent joint is the closest ancestor that is part of the skin.
+                let mut parent = node_parents.get(&node.index());
+                while let Some(p) = parent {
+                    if node_to_joint.contains_key(p) {
+                        break;
+                    }
+                    parent = node_parents.get(p);
+                }
+                let (translation, rotation, scale) = node.transform().decomposed();
+                Joint {
+                    name: node
+                        .name()
+                        .map(String::from)
+                        .unwrap_or_else(|| format!("joint {}", node.index())),
+                    parent: parent.map(|p| node_to_joint[p]),
+                    inverse_bind_matrix: *inverse_bind,
+                    rest_pose: Transform {
+                        translation: translation.into(),
+                        rotation: to_quaternion(rotation),
+                        scale: scale.into(),
+                    },
+                }
+            })
+            .collect();
+        let (skeleton, remap) = Skeleton::new(joints);
+
+        let mut animations = Vec::new();
+        for animation in document.animations() {
+            let mut channels = Vec::new();
+            for channel in animation.channels() {
+                let joint = match node_to_joint.get(&channel.target().node().index()) {
+                    Some(joint) => remap[*joint],
+                    None => continue,
+                };
+                if let Some(channel) = load_channel(&channel, joint, buffers)? {
+                    channels.push(channel);
+                }
+            }
+            if !channels.is_empty() {
+                let name = animation
+                    .name()
+                    .map(String::from)
+                    .unwrap_or_else(|| format!("animation {}", animation.index()));
+                animations.push(AnimationClip::new(&name, channels));
+            }
+        }
+
+        skins.push(SkinData {
+            skeleton,
+            animations,
+        });
+        remaps.push(remap);
+    }
+    Ok((skins, remaps))
+}
+
+fn load_channel(
+    channel: &gltf::animation::Channel,
+    joint: usize,
+    buffers: &[gltf::buffer::Data],
+) -> Result<Option<Channel>> {
+    use gltf::animation::util::ReadOutputs;
+
+    let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+    let times: Vec<f32> = reader
+        .read_inputs()
+        .context("Animation channel has no keyframe times")?
+        .collect();
+    let (interpolation, cubic) = match channel.sampler().interpolation() {
+        gltf::animation::Interpolation::Step => (Interpolation::Step, false),
+        gltf::animation::Interpolation::Linear => (Interpolation::Linear, false),
+        // Cubic spline keyframes store in-tangent, value and out-tangent.
+        // Only the values are kept and interpolated linearly.
+        gltf::animation::Interpolation::CubicSpline => {
+            log::warn!(
+                "Animation channel of joint {} uses cubic spline interpolation, \
+                 interpolating it linearly instead",
+                joint
+            );
+            (Interpolation::Linear, true)
+        }
+    };
+    fn values<T>(values: impl Iterator<Item = T>, cubic: bool) -> Vec<T> {
+        if cubic {
+            values.skip(1).step_by(3).collect()
+        } else {
+            values.collect()
+        }
+    }
+
+    let keyframes = match reader
+        .read_outputs()
+        .context("Animation channel has no keyframe values")?
+    {
+        ReadOutputs::Translations(t) => Keyframes::Translation(values(t.map(Vector3::from), cubic)),
+        ReadOutputs::Rotations(r) => {
+            Keyframes::Rotation(values(r.into_f32().map(to_quaternion), cubic))
+        }
+        ReadOutputs::Scales(s) => Keyframes::Scale(values(s.map(Vector3::from), cubic)),
+        // Morph targets aren't supported.
+        ReadOutputs::MorphTargetWeights(_) => return Ok(None),
+    };
+    let len = match &keyframes {
+        Keyframes::Translation(v) | Keyframes::Scale(v) => v.len(),
+        Keyframes::Rotation(r) => r.len(),
+    };
+    if len != times.len() {
+        bail!(
+            "Animation channel has {} keyframe times but {} values",
+            times.len(),
+            len
+        );
+    }
+
+    Ok(Some(Channel {
+        joint,
+        interpolation,
+        times,
+        keyframes,
+    }))
+}
+
+/// glTF stores quaternions as `[x, y, z, w]`.
+fn to_quaternion(q: [f32; 4]) -> UnitQuaternion<f32> {
+    UnitQuaternion::from_quaternion(Quaternion::new(q[3], q[0], q[1], q[2]))
+}
+
+/// Path of an image stored in its own file next to the glTF file. Images in
+/// buffer views or data URIs have no path.
+fn external_image_path(folder: &Path, image: &gltf::Image) -> Option<PathBuf> {
+    match image.source() {
+        gltf::image::Source::Uri { uri, .. } if !uri.starts_with("data:") => Some(folder.join(uri)),
+        _ => None,
+    }
+}
+
+fn to_rgba(data: &gltf::image::Data) -> Result<image::RgbaImage> {
+    use gltf::image::Format;
+
+    let pixels = data.width as usize * data.height as usize;
+    let rgba: Vec<u8> = match data.format {
+        Format::R8G8B8A8 => data.pixels.clone(),
+        Format::R8G8B8 => data
+            .pixels
+            .chunks(3)
+            .flat_map(|p| vec![p[0], p[1], p[2], 255])
+            .collect(),
+        Format::B8G8R8A8 => data
+            .pixels
+            .chunks(4)
+            .flat_map(|p| vec![p[2], p[1], p[0], p[3]])
+            .collect(),
+        Format::B8G8R8 => data
+            .pixels
+            .chunks(3)
+            .flat_map(|p| vec![p[2], p[1], p[0], 255])
+            .collect(),
+        Format::R8G8 => data
+            .pixels
+            .chunks(2)
+            .flat_map(|p| vec![p[0], p[1], 0, 255])
+            .collect(),
+        Format::R8 => data
+            .pixels
+            .iter()
+            .flat_map(|p| vec![*p, *p, *p, 255])
+            .collect(),
+        format => bail!("Unsupported glTF image format {:?}", format),
+    };
+    if rgba.len() != pixels * 4 {
+        bail!(
+            "glTF image data doesn't match its {}x{} size",
+            data.width,
+            data.height
+        );
+    }
+
+    image::RgbaImage::from_raw(data.width, data.height, rgba)
+        .context("Failed to create an image from glTF pixel data")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/gltf")
+            .join(name)
+    }
+
+    fn assert_close(a: [f32; 3], b: [f32; 3]) {
+        for (a, b) in a.iter().zip(b.iter()) {
+            assert!((a - b).abs() < 1e-5, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn loads_embedded_triangle() {
+        let data = GltfData::load(sample("triangle.gltf")).unwrap();
+        assert_eq!(data.meshes.len(), 1);
+        let mesh = &data.meshes[0];
+        assert_eq!(mesh.vertices.len(), 3);
+        assert_eq!(mesh.indices, vec![0, 1, 2]);
+        // The node's translation is applied to the positions.
+        assert_close(mesh.vertices[0].position, [1., 2., 3.]);
+        assert_close(mesh.vertices[1].position, [2., 2., 3.]);
+        // Only the default material, which the primitive falls back to.
+        assert_eq!(data.materials.len(), 1);
+        assert_eq!(mesh.material, 0);
+        assert_eq!(data.materials[0].name, "default");
+        assert!(data.skins.is_empty());
+    }
+
+    #[test]
+    fn loads_glb_with_image_in_buffer_view() {
+        let data = GltfData::load(sample("quad.glb")).unwrap();
+        assert_eq!(data.meshes.len(), 1);
+        assert_eq!(data.meshes[0].vertices.len(), 4);
+        assert_eq!(data.meshes[0].indices.len(), 6);
+        assert_eq!(data.meshes[0].material, 0);
+        assert_eq!(data.materials.len(), 2);
+
+        let material = &data.materials[0];
+        let base_color = material.base_color.as_ref().unwrap();
+        assert_eq!(base_color.dimensions(), (2, 2));
+        // Embedded images aren't shared, so they have no path.
+        assert_eq!(material.base_color_path, None);
+    }
+
+    #[test]
+    fn loads_one_mesh_per_material() {
+        let folder = sample("two_materials");
+        let data = GltfData::load(folder.join("two_materials.gltf")).unwrap();
+        assert_eq!(data.meshes.len(), 2);
+        for mesh in data.meshes.iter() {
+            assert_eq!(mesh.vertices.len(), 4);
+            assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3]);
+        }
+        assert_eq!(data.meshes[0].material, 0);
+        assert_eq!(data.meshes[1].material, 1);
+        assert_eq!(data.materials.len(), 3);
+
+        // Parent scale, then the child's rotation and translation.
+        assert_close(data.meshes[0].vertices[0].position, [0., 1., 1.]);
+
+        let checker = &data.materials[0];
+        assert_eq!(checker.name, "checker");
+        assert_eq!(checker.base_color.as_ref().unwrap().dimensions(), (4, 4));
+        assert_eq!(checker.base_color_path, Some(folder.join("base_color.png")));
+        // The normal map is a data URI.
+        assert_eq!(checker.normal.as_ref().unwrap().dimensions(), (2, 2));
+
+        let tinted = &data.materials[1];
+        assert_eq!(tinted.name, "tinted");
+        assert!(tinted.base_color.is_none());
+        assert_eq!(
+            tinted.base_color_image().unwrap().get_pixel(0, 0),
+            &image::Rgba([124, 170, 231, 255])
+        );
+    }
+
+    #[test]
+    fn multiplies_base_color_factor_into_the_texture() {
+        let material = MaterialData {
+            base_color_factor: [0.5, 1., 0.5, 0.5],
+            base_color: Some(image::RgbaImage::from_pixel(
+                1,
+                1,
+                image::Rgba([255, 128, 0, 255]),
+            )),
+            ..MaterialData::default_material()
+        };
+        assert!(material.has_base_color_factor());
+        // Colors are scaled in linear space, alpha as it is.
+        assert_eq!(
+            material.base_color_image().unwrap().get_pixel(0, 0),
+            &image::Rgba([188, 128, 0, 128])
+        );
+        assert!(!MaterialData::default_material().has_base_color_factor());
+    }
+
+    #[test]
+    fn loads_skin_and_animation() {
+        let data = GltfData::load(sample("bending_strip.gltf")).unwrap();
+        assert_eq!(data.skins.len(), 1);
+        let skin = &data.skins[0];
+        let joints = &skin.skeleton.joints;
+        assert_eq!(joints.len(), 2);
+        assert_eq!((joints[0].name.as_str(), joints[0].parent), ("root", None));
+        assert_eq!(
+            (joints[1].name.as_str(), joints[1].parent),
+            ("tip", Some(0))
+        );
+
+        let mesh = &data.meshes[0];
+        let weights = mesh.skin.as_ref().unwrap();
+        assert_eq!(weights.skin, 0);
+        assert_eq!(weights.joints.len(), mesh.vertices.len());
+        assert_eq!(weights.weights[2], [0.5, 0.5, 0., 0.]);
+
+        assert_eq!(skin.animations.len(), 1);
+        let bend = &skin.animations[0];
+        assert_eq!((bend.name.as_str(), bend.duration), ("bend", 2.));
+        assert_eq!(bend.channels[0].joint, 1);
+        let pose = bend.sample_pose(&skin.skeleton, 1.);
+        assert!((pose[1].rotation.angle() - std::f32::consts::FRAC_PI_4).abs() < 1e-4);
+        // The rest pose binds the strip as it is in the file.
+        for matrix in skin.skeleton.joint_matrices(&skin.skeleton.rest_pose()) {
+            assert!((matrix - Matrix4::identity()).abs().max() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn decodes_base_color_from_its_path() {
+        let material = MaterialData {
+            base_color_path: Some(sample("two_materials/base_color.png")),
+            ..MaterialData::default_material()
+        };
+        assert_eq!(material.base_color_image().unwrap().dimensions(), (4, 4));
+
+        let missing = MaterialData {
+            base_color_path: Some(sample("two_materials/missing.png")),
+            ..MaterialData::default_material()
+        };
+        assert!(missing.base_color_image().is_err());
+    }
+
+    #[test]
+    fn converts_image_formats_to_rgba() {
+        let data = gltf::image::Data {
+            pixels: vec![1, 2, 3, 4, 5, 6],
+            format: gltf::image::Format::B8G8R8,
+            width: 2,
+            height: 1,
+        };
+        let rgba = to_rgba(&data).unwrap();
+        assert_eq!(rgba.into_raw(), vec![3, 2, 1, 255, 6, 5, 4, 255]);
+
+        let truncated = gltf::image::Data {
+            pixels: vec![0; 5],
+            format: gltf::image::Format::R8,
+            width: 2,
+            height: 3,
+        };
+        assert!(to_rgba(&truncated).is_err());
+    }
+}
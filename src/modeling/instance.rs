@@ -1,73 +1,25 @@
+use crate::assets::Handle;
 use crate::modeling::model::Model;
+use crate::modeling::vertex_index::VertexLayout;
 use nalgebra::{Matrix3, Rotation3, Translation3, Vector3};
 use wgpu::util::DeviceExt;
 
 #[repr(C)]
-#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+#[vertex(step_mode = "instance", location = 3)]
 pub struct InstanceRaw {
     pub matrix: [[f32; 4]; 4],
+    // 3x3 Matrix for normals.
     pub n_matrix: [[f32; 3]; 3],
     pub texture_offset: u32,
 }
 
-impl InstanceRaw {
-    pub fn init_buffer_layout<'a>() -> wgpu::VertexBufferLayout<'a> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<InstanceRaw>() as wgpu::BufferAddress,
-            step_mode: wgpu::InputStepMode::Instance,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x4,
-                    offset: 0,
-                    shader_location: 3,
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x4,
-                    offset: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
-                    shader_location: 4,
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x4,
-                    offset: std::mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
-                    shader_location: 5,
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x4,
-                    offset: std::mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
-                    shader_location: 6,
-                },
-                // 3x3 Matrix for normals.
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x3,
-                    offset: std::mem::size_of::<[f32; 16]>() as wgpu::BufferAddress,
-                    shader_location: 7,
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x3,
-                    offset: std::mem::size_of::<[f32; 19]>() as wgpu::BufferAddress,
-                    shader_location: 8,
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x3,
-                    offset: std::mem::size_of::<[f32; 22]>() as wgpu::BufferAddress,
-                    shader_location: 9,
-                },
-                // Texture offset.
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32,
-                    offset: std::mem::size_of::<[f32; 25]>() as wgpu::BufferAddress,
-                    shader_location: 10,
-                },
-            ],
-        }
-    }
-}
-
 #[derive(Clone)]
 pub struct Instance {
     pub translation: Vector3<f32>,
     pub rotation: Vector3<f32>,
     pub radius: Vector3<f32>,
+    pub texture_offset: u32,
 }
 
 impl Instance {
@@ -76,6 +28,7 @@ impl Instance {
             translation,
             rotation,
             radius,
+            texture_offset: 0,
         }
     }
 
@@ -90,6 +43,7 @@ impl Instance {
         InstanceRaw {
             matrix: (translation * rot.matrix().to_homogeneous() * radius_from_center).into(),
             n_matrix: n_matrix.into(),
+            texture_offset: self.texture_offset,
         }
     }
 
@@ -106,12 +60,17 @@ impl Instance {
 
 pub struct ModelRenderInfo {
     pub instances: Vec<Instance>,
-    pub model: Model,
+    pub model: Handle<Model>,
     pub instance_buffer: wgpu::Buffer,
 }
 
 impl ModelRenderInfo {
-    pub fn new(name: &str, model: Model, instances: Vec<Instance>, device: &wgpu::Device) -> Self {
+    pub fn new(
+        name: &str,
+        model: Handle<Model>,
+        instances: Vec<Instance>,
+        device: &wgpu::Device,
+    ) -> Self {
         let instance_raw_vec = instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
         let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some(&format!("{} vertex buffer", name)),
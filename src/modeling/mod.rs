@@ -1,4 +1,8 @@
+pub mod animation;
 pub mod custom_models;
+pub mod gltf_loader;
 pub mod instance;
 pub mod model;
+pub mod obj_loader;
+pub mod skeleton;
 pub mod vertex_index;
@@ -1,36 +1,91 @@
+use crate::assets::{AssetStorage, Handle};
+use crate::modeling::animation::{AnimationClip, AnimationPlayer};
+use crate::modeling::gltf_loader::{GltfData, MaterialData, MeshData};
 use crate::modeling::instance::ModelRenderInfo;
+use crate::modeling::obj_loader::{NormalGeneration, ObjData};
+use crate::modeling::skeleton::{Skeleton, SkinnedVertex};
 use crate::modeling::vertex_index::Vertex;
 use crate::texture::Texture;
-use anyhow::Context;
-use anyhow::Result;
-use std::fs;
+use anyhow::{bail, Result};
 use std::path::Path;
-use tobj::LoadOptions;
 use wgpu::util::DeviceExt;
 
 pub struct Material {
-    pub texture: Texture,
+    pub texture: Handle<Texture>,
+    /// Tangent space normal map, or a flat 1x1 one when the material has
+    /// none.
+    pub normal_texture: Texture,
     pub bind_group: wgpu::BindGroup,
 }
 
 impl Material {
+    pub const BIND_GROUP_LAYOUT_ENTRIES: [wgpu::BindGroupLayoutEntry; 3] = [
+        Texture::BIND_GROUP_LAYOUT_ENTRIES[0],
+        Texture::BIND_GROUP_LAYOUT_ENTRIES[1],
+        wgpu::BindGroupLayoutEntry {
+            binding: 2,
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        },
+    ];
+
+    pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &Self::BIND_GROUP_LAYOUT_ENTRIES,
+            label: Some("material_bind_group_layout"),
+        })
+    }
+
     #[allow(dead_code)]
     pub fn custom_material<P: AsRef<Path>>(
-        path: /*Option<*/ P, /*>*/
+        path: P,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        //diffuse_color: Option<Vec<[f32; 3]>>
-    ) -> Self {
-        let diffuse_bytes = fs::read(path.as_ref()).unwrap();
-        let texture = Texture::from_bytes(
-            &device,
-            &queue,
-            diffuse_bytes.as_slice(),
-            path.as_ref().to_str().unwrap(),
+        layout: &wgpu::BindGroupLayout,
+    ) -> Result<Self> {
+        let texture = Texture::load(device, queue, path.as_ref())?;
+        Self::from_texture(
+            device,
+            queue,
+            layout,
+            Handle::new(texture),
+            None,
+            path.as_ref().to_str().unwrap_or("custom material"),
         )
-        .unwrap();
+    }
+
+    /// Creates a material that samples an already loaded (and possibly
+    /// shared) texture. Without a normal map the surface normals are used
+    /// as they are.
+    pub fn from_texture(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        texture: Handle<Texture>,
+        normal_texture: Option<Texture>,
+        label: &str,
+    ) -> Result<Self> {
+        let normal_texture = match normal_texture {
+            Some(normal) => normal,
+            None => Texture::from_image_with_format(
+                device,
+                queue,
+                &image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+                    1,
+                    1,
+                    image::Rgba(FLAT_NORMAL),
+                )),
+                Some(&format!("{} flat normal", label)),
+                wgpu::TextureFormat::Rgba8Unorm,
+            )?,
+        };
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &Texture::texture_bind_group_layout(&device),
+            layout,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
@@ -40,33 +95,41 @@ impl Material {
                     binding: 1,
                     resource: wgpu::BindingResource::Sampler(&texture.sampler),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&normal_texture.view),
+                },
             ],
-            label: Some(&format!(
-                "{} diffuse_bind_group",
-                path.as_ref().to_str().unwrap()
-            )),
+            label: Some(&format!("{} diffuse_bind_group", label)),
         });
 
-        Self {
+        Ok(Self {
             texture,
+            normal_texture,
             bind_group,
-        }
+        })
     }
 }
 
+/// Normal map texel pointing straight out of the surface.
+const FLAT_NORMAL: [u8; 4] = [128, 128, 255, 255];
+
 pub struct Mesh {
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
     pub index_length: u32,
     pub material: usize,
+    /// Skin of the glTF file the vertices are weighted to. Those meshes hold
+    /// [`SkinnedVertex`] data, the others [`Vertex`].
+    pub skin: Option<usize>,
 }
 
 impl Mesh {
     #[allow(dead_code)]
-    pub fn custom_mesh<P: AsRef<Path>>(
+    pub fn custom_mesh<P: AsRef<Path>, V: bytemuck::Pod>(
         name: P,
         device: &wgpu::Device,
-        vertices: &[Vertex],
+        vertices: &[V],
         indices: &[u32],
         material_id: usize,
     ) -> Self {
@@ -86,6 +149,7 @@ impl Mesh {
             index_buffer,
             index_length,
             material: material_id,
+            skin: None,
         }
     }
 }
@@ -96,98 +160,252 @@ pub struct Model {
 }
 
 impl Model {
+    /// Loads a Wavefront OBJ or a glTF 2.0 (`.gltf`/`.glb`) model, depending
+    /// on the file extension.
     pub fn load<P: AsRef<Path>>(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         layout: &wgpu::BindGroupLayout,
         path: P,
     ) -> Result<Self> {
-        let (obj_models, obj_materials) = tobj::load_obj(
-            path.as_ref(),
-            &LoadOptions {
-                triangulate: true,
-                single_index: true,
-                ..Default::default()
-            },
-        )?;
+        Self::load_cached(device, queue, layout, path, &mut AssetStorage::new())
+    }
 
-        let obj_materials = obj_materials?;
+    /// Same as [`Model::load`], but textures that were already loaded into
+    /// `textures` are shared instead of uploaded again.
+    pub fn load_cached<P: AsRef<Path>>(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        path: P,
+        textures: &mut AssetStorage<Texture>,
+    ) -> Result<Self> {
+        match path.as_ref().extension().and_then(|e| e.to_str()) {
+            Some("gltf") | Some("glb") => Self::load_gltf(device, queue, layout, path, textures),
+            _ => Self::load_obj(device, queue, layout, path, textures),
+        }
+    }
 
-        // We're assuming that the texture files are stored with the obj file
-        let containing_folder = path.as_ref().parent().context("Directory has no parent")?;
+    pub fn load_gltf<P: AsRef<Path>>(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        path: P,
+        textures: &mut AssetStorage<Texture>,
+    ) -> Result<Self> {
+        let data = GltfData::load(path.as_ref())?;
+        Self::from_gltf_data(
+            device,
+            queue,
+            layout,
+            path.as_ref().to_str().unwrap_or("glTF model"),
+            &data,
+            textures,
+        )
+    }
 
-        let mut use_texture: Vec<f32> = Vec::new();
+    /// Uploads already imported glTF data. Skinned primitives get a
+    /// [`SkinnedVertex`] buffer, everything else uses [`Vertex`].
+    pub fn from_gltf_data(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        name: &str,
+        data: &GltfData,
+        textures: &mut AssetStorage<Texture>,
+    ) -> Result<Self> {
+        Self::from_data(
+            device,
+            queue,
+            layout,
+            name,
+            &data.meshes,
+            &data.materials,
+            textures,
+        )
+    }
 
+    /// Uploads CPU side meshes and materials from one of the model loaders.
+    pub fn from_data(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        name: &str,
+        mesh_data: &[MeshData],
+        material_data: &[MaterialData],
+        textures: &mut AssetStorage<Texture>,
+    ) -> Result<Self> {
         let mut materials = Vec::new();
-        for mat in obj_materials {
-            let diffuse_path = mat.normal_texture;
-            let diffuse_texture = if mat.diffuse_texture.eq("") {
-                use_texture.push(0.);
-                Texture::load(device, queue, containing_folder.join("default.png"))?
-            } else {
-                use_texture.push(1.);
-                Texture::load(device, queue, containing_folder.join(mat.diffuse_texture))?
+        for mat in material_data.iter() {
+            let label = format!("{} {}", name, mat.name);
+            let upload = || {
+                Texture::from_image(
+                    device,
+                    queue,
+                    &image::DynamicImage::ImageRgba8(mat.base_color_image()?),
+                    Some(&label),
+                )
+            };
+            // Textures loaded from files are shared, and the ones of OBJ
+            // files are only decoded when they aren't cached yet. Embedded ones, plain colors and the
+            // ones tinted by the base color factor are unique to the
+            // material.
+            let texture = match &mat.base_color_path {
+                Some(path) if !mat.has_base_color_factor() => {
+                    textures.get_or_load(path, |_| upload())?
+                }
+                _ => Handle::new(upload()?),
+            };
+            let normal_texture = match &mat.normal {
+                Some(normal) => Some(Texture::from_image_with_format(
+                    device,
+                    queue,
+                    &image::DynamicImage::ImageRgba8(normal.clone()),
+                    Some(&format!("{} normal", label)),
+                    wgpu::TextureFormat::Rgba8Unorm,
+                )?),
+                None => None,
             };
-            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            materials.push(Material::from_texture(
+                device,
+                queue,
                 layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                    },
-                ],
-                label: None,
-            });
-
-            materials.push(Material {
-                texture: diffuse_texture,
-                bind_group,
-            });
+                texture,
+                normal_texture,
+                &label,
+            )?);
         }
 
-        let mut meshes = Vec::new();
-        for m in obj_models {
-            let mut vertices = Vec::new();
-            for i in 0..m.mesh.positions.len() / 3 {
-                vertices.push(Vertex {
-                    position: [
-                        m.mesh.positions[i * 3],
-                        m.mesh.positions[i * 3 + 1],
-                        m.mesh.positions[i * 3 + 2],
-                    ],
-                    tex_cords: {
-                        if use_texture[m.mesh.material_id.unwrap()] == 1. {
-                            [m.mesh.texcoords[i * 2], m.mesh.texcoords[i * 2 + 1]]
-                        } else {
-                            [0., 0.]
+        let meshes = mesh_data
+            .iter()
+            .map(|m| {
+                let label = format!("{} {}", name, m.name);
+                match &m.skin {
+                    Some(skin) => {
+                        let vertices = m
+                            .vertices
+                            .iter()
+                            .zip(skin.joints.iter().zip(skin.weights.iter()))
+                            .map(|(v, (joints, weights))| SkinnedVertex {
+                                position: v.position,
+                                tex_cords: v.tex_cords,
+                                normal: v.normal,
+                                joints: *joints,
+                                weights: *weights,
+                            })
+                            .collect::<Vec<_>>();
+                        Mesh {
+                            skin: Some(skin.skin),
+                            ..Mesh::custom_mesh(
+                                label,
+                                device,
+                                vertices.as_slice(),
+                                m.indices.as_slice(),
+                                m.material,
+                            )
                         }
-                    },
-                    normal: [
-                        m.mesh.normals[i * 3],
-                        m.mesh.normals[i * 3 + 1],
-                        m.mesh.normals[i * 3 + 2],
-                    ],
-                });
-            }
-            let tmp_mesh = Mesh::custom_mesh(
-                path.as_ref().as_os_str().to_str().unwrap(),
-                &device,
-                vertices.as_slice(),
-                m.mesh.indices.as_slice(),
-                m.mesh.material_id.unwrap_or(0),
-            );
-            meshes.push(tmp_mesh);
-        }
+                    }
+                    None => Mesh::custom_mesh(
+                        label,
+                        device,
+                        m.vertices.as_slice(),
+                        m.indices.as_slice(),
+                        m.material,
+                    ),
+                }
+            })
+            .collect();
 
         Ok(Self {
             mesh: meshes,
             material: materials,
         })
     }
+
+    /// Loads a Wavefront OBJ model, generating smooth normals if the file
+    /// doesn't have any.
+    pub fn load_obj<P: AsRef<Path>>(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        path: P,
+        textures: &mut AssetStorage<Texture>,
+    ) -> Result<Self> {
+        let data = ObjData::load(path.as_ref(), NormalGeneration::Smooth)?;
+        Self::from_data(
+            device,
+            queue,
+            layout,
+            path.as_ref().to_str().unwrap_or("OBJ model"),
+            &data.meshes,
+            &data.materials,
+            textures,
+        )
+    }
+}
+
+/// A glTF model driven by a skeleton. Its meshes must be drawn with the
+/// skinning pipeline (`shaders/skinned.vert`), which only draws the meshes
+/// weighted to the skeleton.
+pub struct SkinnedModel {
+    pub model: Model,
+    pub skeleton: Skeleton,
+    pub animations: Vec<AnimationClip>,
+    pub player: AnimationPlayer,
+}
+
+impl SkinnedModel {
+    /// Loads a glTF model and the first skin it contains. Only the meshes of
+    /// that skin are drawn.
+    pub fn load<P: AsRef<Path>>(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        joint_layout: &wgpu::BindGroupLayout,
+        path: P,
+    ) -> Result<Self> {
+        let mut data = GltfData::load(path.as_ref())?;
+        let model = Model::from_gltf_data(
+            device,
+            queue,
+            layout,
+            path.as_ref().to_str().unwrap_or("glTF model"),
+            &data,
+            &mut AssetStorage::new(),
+        )?;
+        if data.skins.is_empty() {
+            bail!("{:?} has no skins", path.as_ref());
+        }
+        if data.skins.len() > 1 {
+            log::warn!(
+                "{:?} has {} skins, only the first one is used",
+                path.as_ref(),
+                data.skins.len()
+            );
+        }
+        let ignored = model.mesh.iter().filter(|m| m.skin != Some(0)).count();
+        if ignored > 0 {
+            log::warn!(
+                "{} meshes of {:?} aren't part of the first skin and won't be drawn",
+                ignored,
+                path.as_ref()
+            );
+        }
+        let skin = data.skins.remove(0);
+        let player = AnimationPlayer::new(device, joint_layout, &skin.skeleton);
+
+        Ok(Self {
+            model,
+            skeleton: skin.skeleton,
+            animations: skin.animations,
+            player,
+        })
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue, delta: f32) {
+        self.player
+            .update(queue, &self.skeleton, &self.animations, delta);
+    }
 }
 
 pub trait DrawModel<'a> {
@@ -221,6 +439,64 @@ impl<'a> DrawModel<'a> for wgpu::RenderPass<'a> {
     }
 }
 
+pub trait DrawSkinnedModel<'a> {
+    fn draw_skinned_model(
+        &mut self,
+        model: &'a SkinnedModel,
+        instance_buffer: &'a wgpu::Buffer,
+        instances: u32,
+        light: &'a wgpu::BindGroup,
+    );
+
+    /// Draws the model with only the joints bound, to group 1, for pipelines
+    /// that don't shade it.
+    fn draw_skinned_geometry(
+        &mut self,
+        model: &'a SkinnedModel,
+        instance_buffer: &'a wgpu::Buffer,
+        instances: u32,
+    );
+}
+
+impl<'a> DrawSkinnedModel<'a> for wgpu::RenderPass<'a> {
+    fn draw_skinned_model(
+        &mut self,
+        model: &'a SkinnedModel,
+        instance_buffer: &'a wgpu::Buffer,
+        instances: u32,
+        light_bind_group: &'a wgpu::BindGroup,
+    ) {
+        self.set_vertex_buffer(1, instance_buffer.slice(..));
+        self.set_bind_group(2, light_bind_group, &[]);
+        self.set_bind_group(3, model.player.bind_group(), &[]);
+        // Other meshes have no joints and weights, so the vertex layout of
+        // the pipeline doesn't fit them.
+        for m in model.model.mesh.iter().filter(|m| m.skin == Some(0)) {
+            self.set_vertex_buffer(0, m.vertex_buffer.slice(..));
+            self.set_bind_group(1, &model.model.material[m.material].bind_group, &[]);
+            self.set_index_buffer(m.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+
+            self.draw_indexed(0..m.index_length, 0, 0..instances);
+        }
+    }
+
+    fn draw_skinned_geometry(
+        &mut self,
+        model: &'a SkinnedModel,
+        instance_buffer: &'a wgpu::Buffer,
+        instances: u32,
+    ) {
+        self.set_vertex_buffer(1, instance_buffer.slice(..));
+        self.set_bind_group(1, model.player.bind_group(), &[]);
+        for m in model.model.mesh.iter().filter(|m| m.skin == Some(0)) {
+            self.set_vertex_buffer(0, m.vertex_buffer.slice(..));
+            self.set_index_buffer(m.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+
+            self.draw_indexed(0..m.index_length, 0, 0..instances);
+        }
+    }
+}
+
 pub trait DrawLight<'a> {
     fn draw_light(&mut self, light_info: &'a ModelRenderInfo, light: &'a wgpu::BindGroup);
 }
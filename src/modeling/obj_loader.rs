@@ -0,0 +1,460 @@
+use crate::modeling::gltf_loader::{MaterialData, MeshData};
+use crate::modeling::vertex_index::Vertex;
+use nalgebra::Vector3;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use tobj::LoadOptions;
+
+/// How normals are generated for meshes that don't have any.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NormalGeneration {
+    /// Average the face normals around every shared vertex.
+    Smooth,
+    /// Give every triangle its own vertices and face normal.
+    Flat,
+}
+
+/// Errors for OBJ files that are actually broken, as opposed to just
+/// missing optional data.
+#[derive(Debug)]
+pub enum ObjError {
+    Parse {
+        path: PathBuf,
+        error: tobj::LoadError,
+    },
+    PositionCount {
+        mesh: String,
+        len: usize,
+    },
+    NormalCount {
+        mesh: String,
+        expected: usize,
+        found: usize,
+    },
+    TexCordCount {
+        mesh: String,
+        expected: usize,
+        found: usize,
+    },
+    IndexOutOfRange {
+        mesh: String,
+        index: u32,
+        vertices: usize,
+    },
+    NotTriangulated {
+        mesh: String,
+        len: usize,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Parse { path, error } => write!(f, "Failed to parse {:?}: {}", path, error),
+            ObjError::PositionCount { mesh, len } => write!(
+                f,
+                "Mesh {:?} has {} position components, which isn't a multiple of 3",
+                mesh, len
+            ),
+            ObjError::NormalCount {
+                mesh,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Mesh {:?} has {} normal components, expected {}",
+                mesh, found, expected
+            ),
+            ObjError::TexCordCount {
+                mesh,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Mesh {:?} has {} texture coordinate components, expected {}",
+                mesh, found, expected
+            ),
+            ObjError::IndexOutOfRange {
+                mesh,
+                index,
+                vertices,
+            } => write!(
+                f,
+                "Mesh {:?} references vertex {} but only has {} vertices",
+                mesh, index, vertices
+            ),
+            ObjError::NotTriangulated { mesh, len } => write!(
+                f,
+                "Mesh {:?} has {} indices, which isn't a multiple of 3",
+                mesh, len
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+/// CPU side of a loaded `.obj` file.
+pub struct ObjData {
+    pub meshes: Vec<MeshData>,
+    pub materials: Vec<MaterialData>,
+}
+
+impl ObjData {
+    /// Loads an OBJ file. Missing normals are generated, missing texture
+    /// coordinates default to zero and a missing or broken `.mtl` file falls
+    /// back to a default material. Textures that are missing or can't be
+    /// decoded are left out. Only corrupt geometry is an error.
+    pub fn load<P: AsRef<Path>>(path: P, normals: NormalGeneration) -> Result<Self, ObjError> {
+        let path = path.as_ref();
+        let (obj_models, obj_materials) = tobj::load_obj(
+            path,
+            &LoadOptions {
+                triangulate: true,
+                single_index: true,
+                ..Default::default()
+            },
+        )
+        .map_err(|error| ObjError::Parse {
+            path: path.to_path_buf(),
+            error,
+        })?;
+
+        // We're assuming that the texture files are stored with the obj file
+        let containing_folder = path.parent().unwrap_or_else(|| Path::new(""));
+
+        let obj_materials = obj_materials.unwrap_or_else(|e| {
+            log::warn!("{:?} has no usable materials ({}), using default", path, e);
+            Vec::new()
+        });
+        let mut materials = Vec::new();
+        for mat in obj_materials {
+            // The base color is decoded when it's uploaded, and only if it
+            // isn't cached yet. Normal maps aren't shared.
+            let base_color_path = find_texture(containing_folder, &mat.diffuse_texture)
+                .or_else(|| find_texture(containing_folder, "default.png"));
+            let normal = find_texture(containing_folder, &mat.normal_texture)
+                .and_then(|path| decode_texture(&path));
+            materials.push(MaterialData {
+                name: mat.name,
+                base_color_factor: [mat.diffuse[0], mat.diffuse[1], mat.diffuse[2], mat.dissolve],
+                base_color: None,
+                base_color_path,
+                normal,
+            });
+        }
+        let default_material = materials.len();
+        materials.push(MaterialData {
+            name: String::from("default"),
+            base_color_factor: [1., 1., 1., 1.],
+            base_color: None,
+            base_color_path: find_texture(containing_folder, "default.png"),
+            normal: None,
+        });
+
+        let mut meshes = Vec::new();
+        for m in obj_models {
+            let material = m
+                .mesh
+                .material_id
+                .filter(|id| *id < default_material)
+                .unwrap_or(default_material);
+            let (vertices, indices) = build_vertices(&m.name, &m.mesh, normals)?;
+            meshes.push(MeshData {
+                name: m.name,
+                vertices,
+                indices,
+                material,
+                skin: None,
+            });
+        }
+
+        Ok(Self { meshes, materials })
+    }
+}
+
+/// Path of `file` in `folder`. An empty name means there is no texture, one
+/// that doesn't exist or isn't an image is ignored with a warning. Only the
+/// header is read, the image is decoded when it's needed.
+fn find_texture(folder: &Path, file: &str) -> Option<PathBuf> {
+    if file.is_empty() {
+        return None;
+    }
+    let path = folder.join(file);
+    if !path.is_file() {
+        log::warn!("Texture {:?} doesn't exist, ignoring it", path);
+        return None;
+    }
+    match image::image_dimensions(&path) {
+        Ok(_) => Some(path),
+        Err(e) => {
+            log::warn!("Texture {:?} can't be decoded ({}), ignoring it", path, e);
+            None
+        }
+    }
+}
+
+fn decode_texture(path: &Path) -> Option<image::RgbaImage> {
+    match image::open(path) {
+        Ok(img) => Some(img.to_rgba8()),
+        Err(e) => {
+            log::warn!("Texture {:?} can't be decoded ({}), ignoring it", path, e);
+            None
+        }
+    }
+}
+
+fn build_vertices(
+    name: &str,
+    mesh: &tobj::Mesh,
+    normals: NormalGeneration,
+) -> Result<(Vec<Vertex>, Vec<u32>), ObjError> {
+    let positions = &mesh.positions;
+    if positions.len() % 3 != 0 {
+        return Err(ObjError::PositionCount {
+            mesh: String::from(name),
+            len: positions.len(),
+        });
+    }
+    let vertex_count = positions.len() / 3;
+    if !mesh.normals.is_empty() && mesh.normals.len() != vertex_count * 3 {
+        return Err(ObjError::NormalCount {
+            mesh: String::from(name),
+            expected: vertex_count * 3,
+            found: mesh.normals.len(),
+        });
+    }
+    if !mesh.texcoords.is_empty() && mesh.texcoords.len() != vertex_count * 2 {
+        return Err(ObjError::TexCordCount {
+            mesh: String::from(name),
+            expected: vertex_count * 2,
+            found: mesh.texcoords.len(),
+        });
+    }
+    if mesh.indices.len() % 3 != 0 {
+        return Err(ObjError::NotTriangulated {
+            mesh: String::from(name),
+            len: mesh.indices.len(),
+        });
+    }
+    if let Some(index) = mesh.indices.iter().find(|i| **i as usize >= vertex_count) {
+        return Err(ObjError::IndexOutOfRange {
+            mesh: String::from(name),
+            index: *index,
+            vertices: vertex_count,
+        });
+    }
+
+    let mut vertices = (0..vertex_count)
+        .map(|i| Vertex {
+            position: [positions[i * 3], positions[i * 3 + 1], positions[i * 3 + 2]],
+            tex_cords: if mesh.texcoords.is_empty() {
+                [0., 0.]
+            } else {
+                [mesh.texcoords[i * 2], mesh.texcoords[i * 2 + 1]]
+            },
+            normal: if mesh.normals.is_empty() {
+                [0., 0., 0.]
+            } else {
+                [
+                    mesh.normals[i * 3],
+                    mesh.normals[i * 3 + 1],
+                    mesh.normals[i * 3 + 2],
+                ]
+            },
+        })
+        .collect::<Vec<_>>();
+    let mut indices = mesh.indices.clone();
+
+    if mesh.normals.is_empty() {
+        match normals {
+            NormalGeneration::Smooth => generate_smooth_normals(&mut vertices, &indices),
+            NormalGeneration::Flat => {
+                let (flat_vertices, flat_indices) = generate_flat_normals(&vertices, &indices);
+                vertices = flat_vertices;
+                indices = flat_indices;
+            }
+        }
+    }
+
+    Ok((vertices, indices))
+}
+
+fn face_normal(a: &Vertex, b: &Vertex, c: &Vertex) -> Vector3<f32> {
+    let a = Vector3::from(a.position);
+    let b = Vector3::from(b.position);
+    let c = Vector3::from(c.position);
+    (b - a).cross(&(c - a))
+}
+
+/// Sums the (area weighted) face normals into every vertex of the face.
+pub fn generate_smooth_normals(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut sums = vec![Vector3::zeros(); vertices.len()];
+    for face in indices.chunks(3) {
+        let (a, b, c) = (face[0] as usize, face[1] as usize, face[2] as usize);
+        let normal = face_normal(&vertices[a], &vertices[b], &vertices[c]);
+        sums[a] += normal;
+        sums[b] += normal;
+        sums[c] += normal;
+    }
+    for (vertex, sum) in vertices.iter_mut().zip(sums.iter()) {
+        vertex.normal = sum
+            .try_normalize(f32::EPSILON)
+            .unwrap_or_else(Vector3::y)
+            .into();
+    }
+}
+
+/// Splits every triangle into its own three vertices with the face normal.
+pub fn generate_flat_normals(vertices: &[Vertex], indices: &[u32]) -> (Vec<Vertex>, Vec<u32>) {
+    let mut flat_vertices = Vec::with_capacity(indices.len());
+    for face in indices.chunks(3) {
+        let (a, b, c) = (
+            vertices[face[0] as usize],
+            vertices[face[1] as usize],
+            vertices[face[2] as usize],
+        );
+        let normal: [f32; 3] = face_normal(&a, &b, &c)
+            .try_normalize(f32::EPSILON)
+            .unwrap_or_else(Vector3::y)
+            .into();
+        for mut v in vec![a, b, c] {
+            v.normal = normal;
+            flat_vertices.push(v);
+        }
+    }
+    let flat_indices = (0..flat_vertices.len() as u32).collect();
+    (flat_vertices, flat_indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/obj")
+            .join(name)
+    }
+
+    fn load(name: &str, normals: NormalGeneration) -> ObjData {
+        ObjData::load(fixture(name), normals).unwrap()
+    }
+
+    /// The material of the only mesh, which has to be the default one.
+    fn assert_default_material(data: &ObjData) {
+        let material = &data.materials[data.meshes[0].material];
+        assert_eq!(material.name, "default");
+        assert_eq!(data.meshes[0].material, data.materials.len() - 1);
+        // There's no `default.png` next to the fixtures.
+        assert_eq!(material.base_color_path, None);
+    }
+
+    #[test]
+    fn corrupt_geometry_is_an_error() {
+        match ObjData::load(fixture("bad_position.obj"), NormalGeneration::Smooth) {
+            Err(ObjError::Parse { error, .. }) => {
+                assert_eq!(error, tobj::LoadError::PositionParseError)
+            }
+            result => panic!("expected a parse error, got {:?}", result.err()),
+        }
+        // tobj already catches faces that point past the positions.
+        match ObjData::load(fixture("bad_index.obj"), NormalGeneration::Smooth) {
+            Err(ObjError::Parse { error, .. }) => {
+                assert_eq!(error, tobj::LoadError::FaceVertexOutOfBounds)
+            }
+            result => panic!("expected a parse error, got {:?}", result.err()),
+        }
+        assert!(ObjData::load(fixture("does_not_exist.obj"), NormalGeneration::Smooth).is_err());
+    }
+
+    #[test]
+    fn index_out_of_range_is_an_error() {
+        let mesh = tobj::Mesh {
+            positions: vec![0., 0., 0., 1., 0., 0., 0., 1., 0.],
+            indices: vec![0, 1, 7],
+            ..Default::default()
+        };
+        match build_vertices("broken", &mesh, NormalGeneration::Smooth) {
+            Err(ObjError::IndexOutOfRange {
+                index, vertices, ..
+            }) => assert_eq!((index, vertices), (7, 3)),
+            result => panic!("expected an index error, got {:?}", result.err()),
+        }
+        let mesh = tobj::Mesh {
+            positions: vec![0., 0., 0., 1.],
+            ..Default::default()
+        };
+        assert!(matches!(
+            build_vertices("broken", &mesh, NormalGeneration::Smooth),
+            Err(ObjError::PositionCount { len: 4, .. })
+        ));
+    }
+
+    #[test]
+    fn generates_smooth_normals() {
+        let data = load("no_normals.obj", NormalGeneration::Smooth);
+        let mesh = &data.meshes[0];
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.indices.len(), 6);
+        for vertex in mesh.vertices.iter() {
+            assert_eq!(vertex.normal, [0., 1., 0.]);
+        }
+        // The texture coordinates are still read.
+        assert!(mesh.vertices.iter().any(|v| v.tex_cords == [1., 1.]));
+    }
+
+    #[test]
+    fn generates_flat_normals() {
+        let data = load("no_normals.obj", NormalGeneration::Flat);
+        let mesh = &data.meshes[0];
+        // Every triangle gets its own vertices.
+        assert_eq!(mesh.vertices.len(), 6);
+        assert_eq!(mesh.indices, (0..6).collect::<Vec<_>>());
+        for vertex in mesh.vertices.iter() {
+            assert_eq!(vertex.normal, [0., 1., 0.]);
+        }
+    }
+
+    #[test]
+    fn defaults_missing_tex_cords() {
+        let data = load("no_texcoords.obj", NormalGeneration::Smooth);
+        let mesh = &data.meshes[0];
+        assert_eq!(mesh.vertices.len(), 4);
+        for vertex in mesh.vertices.iter() {
+            assert_eq!(vertex.tex_cords, [0., 0.]);
+            assert_eq!(vertex.normal, [0., 1., 0.]);
+        }
+        let material = &data.materials[mesh.material];
+        assert_eq!(material.name, "Quad");
+        assert_eq!(material.base_color_factor, [0.8, 0.2, 0.2, 1.]);
+    }
+
+    #[test]
+    fn falls_back_to_the_default_material() {
+        let data = load("no_material.obj", NormalGeneration::Smooth);
+        assert_eq!(data.materials.len(), 1);
+        assert_default_material(&data);
+
+        // A missing library is the same as none at all.
+        let data = load("no_mtl.obj", NormalGeneration::Smooth);
+        assert_eq!(data.materials.len(), 1);
+        assert_default_material(&data);
+    }
+
+    #[test]
+    fn ignores_missing_and_corrupt_textures() {
+        let data = load("missing_texture.obj", NormalGeneration::Smooth);
+        let material = &data.materials[data.meshes[0].material];
+        assert_eq!(material.name, "Gone");
+        assert_eq!(material.base_color_factor, [0.5, 0.5, 0.5, 1.]);
+        assert!(material.base_color.is_none());
+        assert_eq!(material.base_color_path, None);
+
+        let data = load("corrupt_texture.obj", NormalGeneration::Smooth);
+        let material = &data.materials[data.meshes[0].material];
+        assert_eq!(material.name, "Corrupt");
+        assert!(material.base_color.is_none());
+        assert_eq!(material.base_color_path, None);
+    }
+}
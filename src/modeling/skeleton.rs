@@ -0,0 +1,238 @@
+use crate::modeling::vertex_index::VertexLayout;
+use nalgebra::{Matrix4, Translation3, UnitQuaternion, Vector3};
+use wgpu::util::DeviceExt;
+
+/// Maximum number of joints that influence a single vertex.
+pub const MAX_JOINT_INFLUENCES: usize = 4;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+pub struct SkinnedVertex {
+    pub position: [f32; 3],
+    pub tex_cords: [f32; 2],
+    pub normal: [f32; 3],
+    // Locations 3-10 are taken by the instance buffer.
+    #[vertex(location = 11)]
+    pub joints: [u32; MAX_JOINT_INFLUENCES],
+    pub weights: [f32; MAX_JOINT_INFLUENCES],
+}
+
+/// Local transform of a joint relative to its parent.
+#[derive(Debug, Clone, Copy)]
+pub struct Transform {
+    pub translation: Vector3<f32>,
+    pub rotation: UnitQuaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Self {
+            translation: Vector3::zeros(),
+            rotation: UnitQuaternion::identity(),
+            scale: Vector3::new(1., 1., 1.),
+        }
+    }
+
+    pub fn to_matrix(&self) -> Matrix4<f32> {
+        Translation3::from(self.translation).to_homogeneous()
+            * self.rotation.to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(&self.scale)
+    }
+}
+
+pub struct Joint {
+    pub name: String,
+    pub parent: Option<usize>,
+    pub inverse_bind_matrix: Matrix4<f32>,
+    pub rest_pose: Transform,
+}
+
+/// Joint hierarchy of a skinned mesh. Parents always come before their
+/// children so a pose can be evaluated in a single pass.
+pub struct Skeleton {
+    pub joints: Vec<Joint>,
+}
+
+impl Skeleton {
+    /// Builds a skeleton from joints in any order, sorting them so that every
+    /// parent precedes its children. Returns the new joint order as
+    /// `old index -> new index`.
+    pub fn new(joints: Vec<Joint>) -> (Self, Vec<usize>) {
+        let mut order = Vec::with_capacity(joints.len());
+        let mut visited = vec![false; joints.len()];
+        fn visit(i: usize, joints: &[Joint], visited: &mut [bool], order: &mut Vec<usize>) {
+            if visited[i] {
+                return;
+            }
+            visited[i] = true;
+            if let Some(parent) = joints[i].parent {
+                visit(parent, joints, visited, order);
+            }
+            order.push(i);
+        }
+        for i in 0..joints.len() {
+            visit(i, &joints, &mut visited, &mut order);
+        }
+
+        let mut remap = vec![0; joints.len()];
+        for (new, old) in order.iter().enumerate() {
+            remap[*old] = new;
+        }
+        let mut slots: Vec<Option<Joint>> = joints.into_iter().map(Some).collect();
+        let joints = order
+            .iter()
+            .map(|old| {
+                let mut joint = slots[*old].take().unwrap();
+                joint.parent = joint.parent.map(|p| remap[p]);
+                joint
+            })
+            .collect();
+
+        (Self { joints }, remap)
+    }
+
+    pub fn rest_pose(&self) -> Vec<Transform> {
+        self.joints.iter().map(|j| j.rest_pose).collect()
+    }
+
+    /// Computes the skinning matrix of every joint for the given local pose.
+    pub fn joint_matrices(&self, pose: &[Transform]) -> Vec<Matrix4<f32>> {
+        let mut global: Vec<Matrix4<f32>> = Vec::with_capacity(self.joints.len());
+        for (i, joint) in self.joints.iter().enumerate() {
+            let local = pose.get(i).unwrap_or(&joint.rest_pose).to_matrix();
+            let matrix = match joint.parent {
+                Some(parent) => global[parent] * local,
+                None => local,
+            };
+            global.push(matrix);
+        }
+        global
+            .iter()
+            .zip(self.joints.iter())
+            .map(|(g, j)| g * j.inverse_bind_matrix)
+            .collect()
+    }
+}
+
+/// GPU storage buffer that holds the joint matrices of one skinned model.
+pub struct JointBuffer {
+    pub buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+    pub joint_count: usize,
+}
+
+impl JointBuffer {
+    pub const BIND_GROUP_LAYOUT_ENTRIES: [wgpu::BindGroupLayoutEntry; 1] =
+        [wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStage::VERTEX,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }];
+
+    pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("joint_bind_group_layout"),
+            entries: &Self::BIND_GROUP_LAYOUT_ENTRIES,
+        })
+    }
+
+    pub fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, skeleton: &Skeleton) -> Self {
+        let matrices = to_raw(&skeleton.joint_matrices(&skeleton.rest_pose()));
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Joint Matrix Buffer"),
+            contents: bytemuck::cast_slice(&matrices),
+            usage: wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("joint_bind_group"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+        Self {
+            buffer,
+            bind_group,
+            joint_count: skeleton.joints.len(),
+        }
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, matrices: &[Matrix4<f32>]) {
+        let matrices = to_raw(&matrices[..matrices.len().min(self.joint_count)]);
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&matrices));
+    }
+}
+
+fn to_raw(matrices: &[Matrix4<f32>]) -> Vec<[[f32; 4]; 4]> {
+    matrices.iter().map(|m| (*m).into()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn joint(name: &str, parent: Option<usize>, translation: Vector3<f32>) -> Joint {
+        Joint {
+            name: String::from(name),
+            parent,
+            inverse_bind_matrix: Matrix4::identity(),
+            rest_pose: Transform {
+                translation,
+                rotation: UnitQuaternion::from_axis_angle(&Vector3::y_axis(), 0.3),
+                scale: Vector3::new(1., 2., 1.),
+            },
+        }
+    }
+
+    #[test]
+    fn parents_come_before_their_children() {
+        let (skeleton, remap) = Skeleton::new(vec![
+            joint("hand", Some(2), Vector3::x()),
+            joint("arm", Some(3), Vector3::x()),
+            joint("forearm", Some(1), Vector3::x()),
+            joint("body", None, Vector3::zeros()),
+        ]);
+        let names = skeleton
+            .joints
+            .iter()
+            .map(|j| j.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["body", "arm", "forearm", "hand"]);
+        assert_eq!(remap, vec![3, 1, 2, 0]);
+        for (i, joint) in skeleton.joints.iter().enumerate() {
+            if let Some(parent) = joint.parent {
+                assert!(parent < i);
+            }
+        }
+        assert_eq!(skeleton.joints[3].parent, Some(2));
+    }
+
+    #[test]
+    fn bind_pose_has_identity_joint_matrices() {
+        let (mut skeleton, _) = Skeleton::new(vec![
+            joint("root", None, Vector3::new(0., 1., 0.)),
+            joint("child", Some(0), Vector3::new(0., 2., 0.)),
+            joint("grandchild", Some(1), Vector3::new(1., 0., 0.)),
+        ]);
+        // Joints are bound in their rest pose.
+        let globals = skeleton.joint_matrices(&skeleton.rest_pose());
+        for (joint, global) in skeleton.joints.iter_mut().zip(globals.iter()) {
+            joint.inverse_bind_matrix = global.try_inverse().unwrap();
+        }
+
+        for matrix in skeleton.joint_matrices(&skeleton.rest_pose()) {
+            assert!((matrix - Matrix4::identity()).abs().max() < 1e-5);
+        }
+        // Joints missing from the pose are in their rest pose as well.
+        for matrix in skeleton.joint_matrices(&[]) {
+            assert!((matrix - Matrix4::identity()).abs().max() < 1e-5);
+        }
+    }
+}
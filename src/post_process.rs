@@ -0,0 +1,844 @@
+//! Post-processing. The scene is drawn into an HDR target ([`HDR_FORMAT`]),
+//! which a chain of fullscreen passes turns into the image on screen. Each
+//! [`Effect`] adds one or more passes to the render graph, the last one
+//! writes the swap chain image.
+//!
+//! The passes draw with `blit/blit.vert`, their fragment shaders are in
+//! `shaders/post/`. The parameters of all effects are in one uniform,
+//! written every frame, so changing them doesn't touch the graph. Enabling
+//! or disabling an effect does, see [`PostProcess::add_passes`].
+
+use crate::hot_reload;
+use crate::profiler::GpuTimer;
+use crate::rendering::preprocessor::Preprocessor;
+use crate::rendering::reflection::PipelineInterface;
+use crate::rendering::render_graph::{RenderGraph, ResourceId, TextureDesc, TextureSize};
+use crate::rendering::shader::ShaderCode;
+use crate::texture::Texture;
+use anyhow::{ensure, Context, Result};
+use image::RgbaImage;
+use std::collections::HashMap;
+use std::fmt;
+use std::num::NonZeroU32;
+use std::path::Path;
+use std::str::FromStr;
+use wgpu::util::DeviceExt;
+
+/// Format the scene is drawn in, and of the textures between the passes.
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Entries per side of the color grading LUT, `LUT_SIZE` in
+/// `color_grading.frag`.
+const LUT_SIZE: u32 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Effect {
+    /// Makes bright parts glow into their surroundings.
+    Bloom,
+    /// Maps the HDR colors into the range the screen can show.
+    Tonemap,
+    /// Looks the colors up in a 3D LUT.
+    ColorGrading,
+    Gamma,
+    /// Smooths edges.
+    Fxaa,
+}
+
+impl Effect {
+    /// In the order they run.
+    pub const ALL: [Effect; 5] = [
+        Effect::Bloom,
+        Effect::Tonemap,
+        Effect::ColorGrading,
+        Effect::Gamma,
+        Effect::Fxaa,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Effect::Bloom => "bloom",
+            Effect::Tonemap => "tonemap",
+            Effect::ColorGrading => "color-grading",
+            Effect::Gamma => "gamma",
+            Effect::Fxaa => "fxaa",
+        }
+    }
+}
+
+impl FromStr for Effect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|e| e.name() == s)
+            .ok_or_else(|| {
+                let names = Self::ALL.iter().map(|e| e.name()).collect::<Vec<_>>();
+                format!("expected one of {}", names.join(", "))
+            })
+    }
+}
+
+/// The order matches the `TONEMAPPER_` defines in `post.glsl`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tonemapper {
+    Aces,
+    Reinhard,
+}
+
+impl Tonemapper {
+    pub fn name(self) -> &'static str {
+        match self {
+            Tonemapper::Aces => "aces",
+            Tonemapper::Reinhard => "reinhard",
+        }
+    }
+}
+
+impl FromStr for Tonemapper {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "aces" => Ok(Tonemapper::Aces),
+            "reinhard" => Ok(Tonemapper::Reinhard),
+            _ => Err(String::from("expected aces or reinhard")),
+        }
+    }
+}
+
+/// Parameters of the effects, they can be changed at any time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PostSettings {
+    /// Multiplies the scene color before tonemapping.
+    pub exposure: f32,
+    pub tonemapper: Tonemapper,
+    /// Applied on top of the sRGB encoding of the swap chain, 1 keeps the
+    /// image as it is.
+    pub gamma: f32,
+    /// Brightness above which pixels bloom.
+    pub bloom_threshold: f32,
+    pub bloom_intensity: f32,
+    /// How much of the LUT's color is used, from 0 to 1.
+    pub lut_strength: f32,
+    /// Contrast, relative to the brightest pixel around, below which FXAA
+    /// leaves a pixel alone.
+    pub fxaa_edge_threshold: f32,
+    /// How far FXAA blends along an edge, in pixels.
+    pub fxaa_span_max: f32,
+}
+
+impl Default for PostSettings {
+    fn default() -> Self {
+        Self {
+            exposure: 1.,
+            tonemapper: Tonemapper::Aces,
+            gamma: 1.,
+            bloom_threshold: 1.,
+            bloom_intensity: 0.3,
+            lut_strength: 1.,
+            fxaa_edge_threshold: 0.125,
+            fxaa_span_max: 8.,
+        }
+    }
+}
+
+/// `PostParams` in `post.glsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    exposure: f32,
+    tonemapper: u32,
+    gamma: f32,
+    bloom_threshold: f32,
+    bloom_intensity: f32,
+    lut_strength: f32,
+    fxaa_edge_threshold: f32,
+    fxaa_span_max: f32,
+}
+
+impl From<PostSettings> for Params {
+    fn from(s: PostSettings) -> Self {
+        Self {
+            exposure: s.exposure,
+            tonemapper: s.tonemapper as u32,
+            gamma: s.gamma,
+            bloom_threshold: s.bloom_threshold,
+            bloom_intensity: s.bloom_intensity,
+            lut_strength: s.lut_strength,
+            fxaa_edge_threshold: s.fxaa_edge_threshold,
+            fxaa_span_max: s.fxaa_span_max,
+        }
+    }
+}
+
+/// Set 1 of every pass.
+const PARAMS_LAYOUT_ENTRIES: [wgpu::BindGroupLayoutEntry; 1] = [wgpu::BindGroupLayoutEntry {
+    binding: 0,
+    visibility: wgpu::ShaderStage::FRAGMENT,
+    ty: wgpu::BindingType::Buffer {
+        ty: wgpu::BufferBindingType::Uniform,
+        has_dynamic_offset: false,
+        min_binding_size: None,
+    },
+    count: None,
+}];
+
+const fn texture_entry(
+    binding: u32,
+    view_dimension: wgpu::TextureViewDimension,
+) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStage::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        },
+        count: None,
+    }
+}
+
+/// What set 0 of a pass holds. It always starts like the set of
+/// `blit/blit.frag`, the input and the sampler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Inputs {
+    One,
+    /// A second 2D texture at binding 2.
+    Two,
+    /// The LUT at binding 2.
+    Lut,
+}
+
+impl Inputs {
+    const ALL: [Inputs; 3] = [Inputs::One, Inputs::Two, Inputs::Lut];
+
+    fn layout_entries(self) -> &'static [wgpu::BindGroupLayoutEntry] {
+        const TWO: [wgpu::BindGroupLayoutEntry; 3] = [
+            Texture::BIND_GROUP_LAYOUT_ENTRIES[0],
+            Texture::BIND_GROUP_LAYOUT_ENTRIES[1],
+            texture_entry(2, wgpu::TextureViewDimension::D2),
+        ];
+        const LUT: [wgpu::BindGroupLayoutEntry; 3] = [
+            Texture::BIND_GROUP_LAYOUT_ENTRIES[0],
+            Texture::BIND_GROUP_LAYOUT_ENTRIES[1],
+            texture_entry(2, wgpu::TextureViewDimension::D3),
+        ];
+        match self {
+            Inputs::One => &Texture::BIND_GROUP_LAYOUT_ENTRIES,
+            Inputs::Two => &TWO,
+            Inputs::Lut => &LUT,
+        }
+    }
+}
+
+/// The fragment shader of a pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Shader {
+    Copy,
+    BloomBright,
+    BlurHorizontal,
+    BlurVertical,
+    BloomComposite,
+    Tonemap,
+    ColorGrading,
+    Gamma,
+    Fxaa,
+}
+
+impl Shader {
+    const ALL: [Shader; 9] = [
+        Shader::Copy,
+        Shader::BloomBright,
+        Shader::BlurHorizontal,
+        Shader::BlurVertical,
+        Shader::BloomComposite,
+        Shader::Tonemap,
+        Shader::ColorGrading,
+        Shader::Gamma,
+        Shader::Fxaa,
+    ];
+
+    fn inputs(self) -> Inputs {
+        match self {
+            Shader::BloomComposite => Inputs::Two,
+            Shader::ColorGrading => Inputs::Lut,
+            _ => Inputs::One,
+        }
+    }
+
+    fn code(self, dev: bool) -> Result<ShaderCode> {
+        macro_rules! spirv {
+            ($path:literal) => {
+                include_bytes!(concat!(env!("OUT_DIR"), "/shaders/", $path, ".spv"))
+            };
+        }
+        let plain = Preprocessor::new();
+        let (name, preprocessor, bundled): (_, _, &'static [u8]) = match self {
+            Shader::Copy => ("blit/blit.frag", plain, spirv!("blit/blit.frag")),
+            Shader::BloomBright => (
+                "post/bloom_bright.frag",
+                plain,
+                spirv!("post/bloom_bright.frag"),
+            ),
+            Shader::BlurHorizontal => ("post/blur.frag", plain, spirv!("post/blur.frag")),
+            // `build.rs` compiles this variant as well.
+            Shader::BlurVertical => (
+                "post/blur.frag",
+                Preprocessor::new().define("BLUR_VERTICAL", ""),
+                spirv!("post/blur.vertical.frag"),
+            ),
+            Shader::BloomComposite => (
+                "post/bloom_composite.frag",
+                plain,
+                spirv!("post/bloom_composite.frag"),
+            ),
+            Shader::Tonemap => ("post/tonemap.frag", plain, spirv!("post/tonemap.frag")),
+            Shader::ColorGrading => (
+                "post/color_grading.frag",
+                plain,
+                spirv!("post/color_grading.frag"),
+            ),
+            Shader::Gamma => ("post/gamma.frag", plain, spirv!("post/gamma.frag")),
+            Shader::Fxaa => ("post/fxaa.frag", plain, spirv!("post/fxaa.frag")),
+        };
+        hot_reload::shader_code(dev, name, &preprocessor, bundled)
+    }
+}
+
+/// What a pass of [`PostProcess::add_passes`] needs from the graph's target.
+pub struct PostContext<'a> {
+    pub post: &'a mut PostProcess,
+    pub device: &'a wgpu::Device,
+    pub gpu_timer: &'a mut GpuTimer,
+}
+
+/// Where a pass reads from, while the chain is put together.
+#[derive(Clone, Copy)]
+enum Source {
+    /// The texture the chain starts with.
+    Input,
+    /// The output of an earlier step.
+    Step(usize),
+}
+
+struct Step {
+    name: &'static str,
+    shader: Shader,
+    inputs: Vec<Source>,
+    size: TextureSize,
+}
+
+/// The effects, their parameters and the GPU objects they draw with.
+/// Pipelines are created once per shader and target format.
+pub struct PostProcess {
+    /// In the order of [`Effect::ALL`].
+    effects: Vec<Effect>,
+    pub settings: PostSettings,
+    vert_shader: wgpu::ShaderModule,
+    frag_shaders: HashMap<Shader, wgpu::ShaderModule>,
+    /// A bind group layout and a pipeline layout for each of
+    /// [`Inputs::ALL`].
+    layouts: Vec<(wgpu::BindGroupLayout, wgpu::PipelineLayout)>,
+    params_buffer: wgpu::Buffer,
+    params_bind_group: wgpu::BindGroup,
+    sampler: wgpu::Sampler,
+    lut: wgpu::TextureView,
+    pipelines: HashMap<(Shader, wgpu::TextureFormat), wgpu::RenderPipeline>,
+}
+
+impl PostProcess {
+    /// Bloom, tonemapping and FXAA are enabled, with the
+    /// [default](PostSettings::default) settings. The LUT keeps colors as
+    /// they are until one is loaded.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, dev: bool) -> Result<Self> {
+        let (vert_shader, frag_shaders) = load_shaders(device, dev)?;
+        let params_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Post-processing parameter layout"),
+            entries: &PARAMS_LAYOUT_ENTRIES,
+        });
+        let layouts = Inputs::ALL
+            .iter()
+            .map(|inputs| {
+                let input_layout =
+                    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                        label: Some(&format!("Post-processing {:?} layout", inputs)),
+                        entries: inputs.layout_entries(),
+                    });
+                let pipeline_layout =
+                    device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                        label: Some(&format!("Post-processing {:?} pipeline layout", inputs)),
+                        bind_group_layouts: &[&input_layout, &params_layout],
+                        push_constant_ranges: &[],
+                    });
+                (input_layout, pipeline_layout)
+            })
+            .collect();
+
+        let settings = PostSettings::default();
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Post-processing parameters"),
+            contents: bytemuck::cast_slice(&[Params::from(settings)]),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+        let params_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Post-processing parameters"),
+            layout: &params_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: params_buffer.as_entire_binding(),
+            }],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Post-processing sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Ok(Self {
+            effects: vec![Effect::Bloom, Effect::Tonemap, Effect::Fxaa],
+            settings,
+            vert_shader,
+            frag_shaders,
+            layouts,
+            params_buffer,
+            params_bind_group,
+            sampler,
+            lut: create_lut(device, queue, &identity_lut()),
+            pipelines: HashMap::new(),
+        })
+    }
+
+    pub fn effects(&self) -> &[Effect] {
+        &self.effects
+    }
+
+    /// Returns whether anything changed. The graph has to be built again
+    /// when it did.
+    pub fn set_enabled(&mut self, effect: Effect, enabled: bool) -> bool {
+        if self.effects.contains(&effect) == enabled {
+            return false;
+        }
+        if enabled {
+            self.effects.push(effect);
+        } else {
+            self.effects.retain(|&e| e != effect);
+        }
+        self.effects
+            .sort_by_key(|e| Effect::ALL.iter().position(|a| a == e));
+        true
+    }
+
+    /// Loads a LUT from a strip of `LUT_SIZE` squares, as most tools export
+    /// them: red goes right in each square, green down and blue from square
+    /// to square.
+    pub fn load_lut<P: AsRef<Path>>(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: P,
+    ) -> Result<()> {
+        let path = path.as_ref();
+        let image = image::open(path)
+            .with_context(|| format!("Failed to load the LUT {:?}", path))?
+            .to_rgba8();
+        self.lut = create_lut(device, queue, &lut_from_strip(&image)?);
+        Ok(())
+    }
+
+    /// Compiles the shaders again, in development mode from the source tree.
+    pub fn reload(&mut self, device: &wgpu::Device, dev: bool) -> Result<()> {
+        let (vert_shader, frag_shaders) = load_shaders(device, dev)?;
+        self.vert_shader = vert_shader;
+        self.frag_shaders = frag_shaders;
+        self.pipelines.clear();
+        Ok(())
+    }
+
+    /// Uploads the settings, call it once per frame before the passes run.
+    pub fn prepare(&self, queue: &wgpu::Queue) {
+        queue.write_buffer(
+            &self.params_buffer,
+            0,
+            bytemuck::cast_slice(&[Params::from(self.settings)]),
+        );
+    }
+
+    /// Adds the passes of the enabled effects, from `input` to `output`. The
+    /// textures between them are transients in [`HDR_FORMAT`], `output` has
+    /// `output_format`. Without any effect `input` is only copied.
+    /// `context` picks what the passes need out of the graph's target.
+    pub fn add_passes<T: 'static>(
+        &self,
+        graph: &mut RenderGraph<T>,
+        input: ResourceId,
+        output: ResourceId,
+        output_format: wgpu::TextureFormat,
+        context: fn(&mut T) -> PostContext<'_>,
+    ) {
+        let mut steps = Vec::new();
+        let mut current = Source::Input;
+        for &effect in &self.effects {
+            let window = TextureSize::Window;
+            current = match effect {
+                Effect::Bloom => {
+                    let half = TextureSize::Scaled(0.5);
+                    let bright = Step::push(
+                        &mut steps,
+                        "bloom bright",
+                        Shader::BloomBright,
+                        &[current],
+                        half,
+                    );
+                    let blurred = Step::push(
+                        &mut steps,
+                        "bloom blur horizontal",
+                        Shader::BlurHorizontal,
+                        &[bright],
+                        half,
+                    );
+                    let blurred = Step::push(
+                        &mut steps,
+                        "bloom blur vertical",
+                        Shader::BlurVertical,
+                        &[blurred],
+                        half,
+                    );
+                    Step::push(
+                        &mut steps,
+                        "bloom",
+                        Shader::BloomComposite,
+                        &[current, blurred],
+                        window,
+                    )
+                }
+                Effect::Tonemap => {
+                    Step::push(&mut steps, "tonemap", Shader::Tonemap, &[current], window)
+                }
+                Effect::ColorGrading => Step::push(
+                    &mut steps,
+                    "color grading",
+                    Shader::ColorGrading,
+                    &[current],
+                    window,
+                ),
+                Effect::Gamma => Step::push(&mut steps, "gamma", Shader::Gamma, &[current], window),
+                Effect::Fxaa => Step::push(&mut steps, "fxaa", Shader::Fxaa, &[current], window),
+            };
+        }
+        if steps.is_empty() {
+            Step::push(
+                &mut steps,
+                "post copy",
+                Shader::Copy,
+                &[current],
+                TextureSize::Window,
+            );
+        }
+
+        let last = steps.len() - 1;
+        let mut outputs = Vec::with_capacity(steps.len());
+        for (i, step) in steps.into_iter().enumerate() {
+            let (target, format) = if i == last {
+                (output, output_format)
+            } else {
+                let desc = TextureDesc {
+                    size: step.size,
+                    ..TextureDesc::new(step.name, HDR_FORMAT)
+                };
+                (graph.add_texture(desc), HDR_FORMAT)
+            };
+            outputs.push(target);
+            let reads = step
+                .inputs
+                .iter()
+                .map(|source| match *source {
+                    Source::Input => input,
+                    Source::Step(j) => outputs[j],
+                })
+                .collect::<Vec<_>>();
+            let (name, shader) = (step.name, step.shader);
+            let inputs = reads.clone();
+            graph.add_pass(
+                name,
+                &reads,
+                &[target],
+                move |t: &mut T, encoder, resources| {
+                    let views = inputs
+                        .iter()
+                        .map(|&id| resources.view(id))
+                        .collect::<Vec<_>>();
+                    let PostContext {
+                        post,
+                        device,
+                        gpu_timer,
+                    } = context(t);
+                    gpu_timer.begin(encoder, name);
+                    post.draw(
+                        device,
+                        encoder,
+                        shader,
+                        &views,
+                        resources.view(target),
+                        format,
+                    );
+                    gpu_timer.end(encoder);
+                },
+            );
+        }
+    }
+
+    /// Draws `shader` over all of `target`.
+    fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        shader: Shader,
+        inputs: &[&wgpu::TextureView],
+        target: &wgpu::TextureView,
+        format: wgpu::TextureFormat,
+    ) {
+        let key = (shader, format);
+        if !self.pipelines.contains_key(&key) {
+            let pipeline = self.create_pipeline(device, shader, format);
+            self.pipelines.insert(key, pipeline);
+        }
+        let pipeline = &self.pipelines[&key];
+
+        let mut entries = vec![
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(inputs[0]),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+        ];
+        let second = match shader.inputs() {
+            Inputs::One => None,
+            Inputs::Two => Some(inputs[1]),
+            Inputs::Lut => Some(&self.lut),
+        };
+        if let Some(view) = second {
+            entries.push(wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(view),
+            });
+        }
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.layouts[shader.inputs() as usize].0,
+            entries: &entries,
+        });
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Post-processing pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.set_bind_group(1, &self.params_bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+
+    fn create_pipeline(
+        &self,
+        device: &wgpu::Device,
+        shader: Shader,
+        format: wgpu::TextureFormat,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&format!(
+                "Post-processing pipeline {:?} {:?}",
+                shader, format
+            )),
+            layout: Some(&self.layouts[shader.inputs() as usize].1),
+            vertex: wgpu::VertexState {
+                module: &self.vert_shader,
+                entry_point: "main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &self.frag_shaders[&shader],
+                entry_point: "main",
+                targets: &[format.into()],
+            }),
+        })
+    }
+}
+
+/// Lists the enabled effects and the settings.
+impl fmt::Display for PostProcess {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let effects = self.effects.iter().map(|e| e.name()).collect::<Vec<_>>();
+        let s = &self.settings;
+        writeln!(
+            f,
+            "Effects: {}",
+            if effects.is_empty() {
+                String::from("none")
+            } else {
+                effects.join(", ")
+            }
+        )?;
+        writeln!(
+            f,
+            "Exposure {}, tonemapper {}, gamma {}",
+            s.exposure,
+            s.tonemapper.name(),
+            s.gamma
+        )?;
+        writeln!(
+            f,
+            "Bloom threshold {}, intensity {}",
+            s.bloom_threshold, s.bloom_intensity
+        )?;
+        writeln!(f, "Grading strength {}", s.lut_strength)?;
+        write!(
+            f,
+            "FXAA edge threshold {}, span {}",
+            s.fxaa_edge_threshold, s.fxaa_span_max
+        )
+    }
+}
+
+impl Step {
+    fn push(
+        steps: &mut Vec<Step>,
+        name: &'static str,
+        shader: Shader,
+        inputs: &[Source],
+        size: TextureSize,
+    ) -> Source {
+        steps.push(Step {
+            name,
+            shader,
+            inputs: inputs.to_vec(),
+            size,
+        });
+        Source::Step(steps.len() - 1)
+    }
+}
+
+/// The vertex shader and the fragment shader of each [`Shader`], checked
+/// against the layouts they're used with.
+fn load_shaders(
+    device: &wgpu::Device,
+    dev: bool,
+) -> Result<(wgpu::ShaderModule, HashMap<Shader, wgpu::ShaderModule>)> {
+    let vert = hot_reload::shader_code(
+        dev,
+        "blit/blit.vert",
+        &Preprocessor::new(),
+        include_bytes!(concat!(env!("OUT_DIR"), "/shaders/blit/blit.vert.spv")),
+    )?;
+    let vert_interface = vert.reflect()?;
+    let mut frag_shaders = HashMap::new();
+    for &shader in Shader::ALL.iter() {
+        let frag = shader.code(dev)?;
+        PipelineInterface::new(&[&vert_interface, &frag.reflect()?])?
+            .validate(
+                &[shader.inputs().layout_entries(), &PARAMS_LAYOUT_ENTRIES],
+                &[],
+            )
+            .with_context(|| {
+                format!(
+                    "Layouts of the {:?} post-processing pass don't match its shaders",
+                    shader
+                )
+            })?;
+        let module = frag.create_module(device, &format!("{:?} post-processing shader", shader));
+        frag_shaders.insert(shader, module);
+    }
+    let vert = vert.create_module(device, "post-processing vertex shader");
+    Ok((vert, frag_shaders))
+}
+
+/// A LUT that maps every color to itself, in the layout of the 3D texture.
+fn identity_lut() -> Vec<u8> {
+    let value = |i: u32| (i * 255 / (LUT_SIZE - 1)) as u8;
+    let mut data = Vec::with_capacity((LUT_SIZE * LUT_SIZE * LUT_SIZE * 4) as usize);
+    for b in 0..LUT_SIZE {
+        for g in 0..LUT_SIZE {
+            for r in 0..LUT_SIZE {
+                data.extend_from_slice(&[value(r), value(g), value(b), 255]);
+            }
+        }
+    }
+    data
+}
+
+/// Reorders a strip of squares, see [`PostProcess::load_lut`], into the
+/// layout of the 3D texture.
+fn lut_from_strip(image: &RgbaImage) -> Result<Vec<u8>> {
+    ensure!(
+        image.dimensions() == (LUT_SIZE * LUT_SIZE, LUT_SIZE),
+        "A LUT has to be {}x{} pixels, not {}x{}",
+        LUT_SIZE * LUT_SIZE,
+        LUT_SIZE,
+        image.width(),
+        image.height()
+    );
+    let mut data = Vec::with_capacity((LUT_SIZE * LUT_SIZE * LUT_SIZE * 4) as usize);
+    for b in 0..LUT_SIZE {
+        for g in 0..LUT_SIZE {
+            for r in 0..LUT_SIZE {
+                data.extend_from_slice(&image.get_pixel(b * LUT_SIZE + r, g).0);
+            }
+        }
+    }
+    Ok(data)
+}
+
+fn create_lut(device: &wgpu::Device, queue: &wgpu::Queue, data: &[u8]) -> wgpu::TextureView {
+    let size = wgpu::Extent3d {
+        width: LUT_SIZE,
+        height: LUT_SIZE,
+        depth_or_array_layers: LUT_SIZE,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Color grading LUT"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D3,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+    });
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture: &texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+        },
+        data,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: NonZeroU32::new(LUT_SIZE * 4),
+            rows_per_image: NonZeroU32::new(LUT_SIZE),
+        },
+        size,
+    );
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
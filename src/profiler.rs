@@ -0,0 +1,452 @@
+//! Frame profiler. [`Profiler`] times nested CPU scopes with [`Instant`] and
+//! needs no GPU. [`GpuTimer`] times passes with timestamp queries, on adapters
+//! with `TIMESTAMP_QUERY`. Its results arrive a few frames late, when the
+//! GPU is done with them, and are handed to the profiler.
+//!
+//! A [`Capture`] of a few frames can be saved as Chrome trace JSON, which
+//! `chrome://tracing` and <https://ui.perfetto.dev> open.
+
+use anyhow::{Context, Result};
+use futures::FutureExt;
+use std::fmt::Write;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+/// Frames whose timestamps can be in flight at once.
+const GPU_FRAMES: usize = 3;
+/// GPU scopes per frame, each takes two queries. Later ones aren't timed.
+const MAX_GPU_SCOPES: usize = 32;
+
+/// A timed scope. `start` is the time since the profiler was created.
+#[derive(Debug, Clone)]
+pub struct Scope {
+    pub name: &'static str,
+    /// How many scopes it's nested in.
+    pub depth: usize,
+    pub start: Duration,
+    pub duration: Duration,
+}
+
+/// Records the CPU scopes of each frame, and keeps the last finished one.
+pub struct Profiler {
+    epoch: Instant,
+    /// The frame being recorded.
+    scopes: Vec<Scope>,
+    /// Indices of the scopes in `scopes` that haven't ended yet.
+    open: Vec<usize>,
+    last_frame: Vec<Scope>,
+    gpu_frame: Vec<Scope>,
+    capture: Option<Capture>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self {
+            epoch: Instant::now(),
+            scopes: Vec::new(),
+            open: Vec::new(),
+            last_frame: Vec::new(),
+            gpu_frame: Vec::new(),
+            capture: None,
+        }
+    }
+
+    /// Time since the profiler was created, what [`Scope::start`] counts from.
+    pub fn now(&self) -> Duration {
+        self.epoch.elapsed()
+    }
+
+    /// Starts a scope inside the ones that are open.
+    pub fn begin(&mut self, name: &'static str) {
+        self.open.push(self.scopes.len());
+        self.scopes.push(Scope {
+            name,
+            depth: self.open.len() - 1,
+            start: self.now(),
+            duration: Duration::default(),
+        });
+    }
+
+    /// Ends the scope that began last.
+    pub fn end(&mut self) {
+        let index = self.open.pop().expect("Profiler::end without a begin");
+        let now = self.now();
+        let scope = &mut self.scopes[index];
+        scope.duration = now - scope.start;
+    }
+
+    /// Finishes the frame. Scopes that begin after this belong to the next
+    /// one, so work between frames, like input, is counted in the next frame.
+    pub fn end_frame(&mut self) {
+        debug_assert!(
+            self.open.is_empty(),
+            "Scopes {:?} didn't end before the frame",
+            self.open
+                .iter()
+                .map(|&i| self.scopes[i].name)
+                .collect::<Vec<_>>()
+        );
+        self.open.clear();
+        self.last_frame = std::mem::take(&mut self.scopes);
+        if let Some(capture) = &mut self.capture {
+            if capture.frames_left > 0 {
+                capture.cpu.extend(self.last_frame.iter().cloned());
+                capture.frames_left -= 1;
+            }
+        }
+    }
+
+    /// The CPU scopes of the last finished frame, in the order they began.
+    pub fn last_frame(&self) -> &[Scope] {
+        &self.last_frame
+    }
+
+    /// Takes the GPU scopes of a frame from [`GpuTimer::poll`].
+    pub fn record_gpu(&mut self, scopes: Vec<Scope>) {
+        if let Some(capture) = &mut self.capture {
+            if capture.frames_left > 0 {
+                capture.gpu.extend(scopes.iter().cloned());
+            }
+        }
+        self.gpu_frame = scopes;
+    }
+
+    /// The GPU scopes of the latest frame the GPU finished.
+    pub fn gpu_frame(&self) -> &[Scope] {
+        &self.gpu_frame
+    }
+
+    /// Records the next `frames` frames into a [`Capture`], replacing one
+    /// that's in progress.
+    pub fn start_capture(&mut self, frames: usize) {
+        self.capture = Some(Capture {
+            cpu: Vec::new(),
+            gpu: Vec::new(),
+            frames_left: frames,
+        });
+    }
+
+    /// The capture, once all its frames are recorded.
+    pub fn take_capture(&mut self) -> Option<Capture> {
+        match &self.capture {
+            Some(capture) if capture.frames_left == 0 => self.capture.take(),
+            _ => None,
+        }
+    }
+}
+
+/// The scopes of a few frames. GPU scopes of the last frames can be missing,
+/// because the GPU wasn't done with them when the capture ended.
+#[derive(Debug, Clone)]
+pub struct Capture {
+    pub cpu: Vec<Scope>,
+    pub gpu: Vec<Scope>,
+    frames_left: usize,
+}
+
+impl Capture {
+    /// The scopes as complete events of the Chrome trace event format, with
+    /// the CPU and the GPU as two threads.
+    pub fn to_chrome_trace(&self) -> String {
+        let mut events = vec![
+            r#"{"name":"thread_name","ph":"M","pid":0,"tid":0,"args":{"name":"CPU"}}"#.to_string(),
+            r#"{"name":"thread_name","ph":"M","pid":0,"tid":1,"args":{"name":"GPU"}}"#.to_string(),
+        ];
+        for (tid, scopes) in [&self.cpu, &self.gpu].iter().enumerate() {
+            for scope in scopes.iter() {
+                let mut event = String::from(r#"{"name":"#);
+                write_json_string(&mut event, scope.name);
+                write!(
+                    event,
+                    r#","ph":"X","ts":{:.3},"dur":{:.3},"pid":0,"tid":{}}}"#,
+                    scope.start.as_secs_f64() * 1e6,
+                    scope.duration.as_secs_f64() * 1e6,
+                    tid
+                )
+                .unwrap();
+                events.push(event);
+            }
+        }
+        format!(
+            "{{\"traceEvents\":[\n{}\n],\"displayTimeUnit\":\"ms\"}}\n",
+            events.join(",\n")
+        )
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_chrome_trace())
+            .with_context(|| format!("Failed to write the capture to {:?}", path))
+    }
+}
+
+fn write_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+type MapFuture = Pin<Box<dyn Future<Output = Result<(), wgpu::BufferAsyncError>> + Send>>;
+
+/// The queries of one frame and the buffer they're read back with.
+struct GpuFrame {
+    query_set: wgpu::QuerySet,
+    buffer: wgpu::Buffer,
+    /// Name and depth of each scope, its queries are `2 * i` and `2 * i + 1`.
+    scopes: Vec<(&'static str, usize)>,
+    /// When the frame was submitted, the GPU scopes start there.
+    submitted: Duration,
+    /// Set while the buffer is being mapped, the frame can't be reused until
+    /// it's read.
+    mapping: Option<MapFuture>,
+}
+
+/// Times GPU work with timestamps written between passes. Without
+/// `TIMESTAMP_QUERY` it's [`disabled`](Self::disabled) and does nothing.
+pub struct GpuTimer {
+    frames: Vec<GpuFrame>,
+    current: usize,
+    /// Scopes that haven't ended, `None` for the ones that aren't timed.
+    open: Vec<Option<usize>>,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+}
+
+impl GpuTimer {
+    /// The device needs `TIMESTAMP_QUERY`.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let frames = (0..GPU_FRAMES)
+            .map(|_| GpuFrame {
+                query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                    ty: wgpu::QueryType::Timestamp,
+                    count: MAX_GPU_SCOPES as u32 * 2,
+                }),
+                buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Timestamp buffer"),
+                    size: (MAX_GPU_SCOPES * 2 * wgpu::QUERY_SIZE as usize) as wgpu::BufferAddress,
+                    usage: wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ,
+                    mapped_at_creation: false,
+                }),
+                scopes: Vec::new(),
+                submitted: Duration::default(),
+                mapping: None,
+            })
+            .collect();
+        Self {
+            frames,
+            current: 0,
+            open: Vec::new(),
+            period: queue.get_timestamp_period(),
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self {
+            frames: Vec::new(),
+            current: 0,
+            open: Vec::new(),
+            period: 0.,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.frames.is_empty()
+    }
+
+    /// The frame that's recorded into, if it isn't still being read back.
+    fn recording(&mut self) -> Option<&mut GpuFrame> {
+        self.frames
+            .get_mut(self.current)
+            .filter(|frame| frame.mapping.is_none())
+    }
+
+    /// Writes the start timestamp of a scope. Passes can't be open, so it's
+    /// called between them.
+    pub fn begin(&mut self, encoder: &mut wgpu::CommandEncoder, name: &'static str) {
+        let depth = self.open.len();
+        let index = match self.recording() {
+            Some(frame) if frame.scopes.len() < MAX_GPU_SCOPES => {
+                let index = frame.scopes.len();
+                encoder.write_timestamp(&frame.query_set, index as u32 * 2);
+                frame.scopes.push((name, depth));
+                Some(index)
+            }
+            _ => None,
+        };
+        self.open.push(index);
+    }
+
+    pub fn end(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let index = self.open.pop().expect("GpuTimer::end without a begin");
+        if let (Some(index), Some(frame)) = (index, self.recording()) {
+            encoder.write_timestamp(&frame.query_set, index as u32 * 2 + 1);
+        }
+    }
+
+    /// Copies the timestamps of the frame to its buffer, before the encoder
+    /// is finished.
+    pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if let Some(frame) = self.recording() {
+            if !frame.scopes.is_empty() {
+                let queries = frame.scopes.len() as u32 * 2;
+                encoder.resolve_query_set(&frame.query_set, 0..queries, &frame.buffer, 0);
+            }
+        }
+    }
+
+    /// Starts reading the timestamps back after the frame was submitted at
+    /// `submitted`, see [`Profiler::now`].
+    pub fn finish(&mut self, submitted: Duration) {
+        debug_assert!(self.open.is_empty(), "GPU scopes didn't end");
+        self.open.clear();
+        if let Some(frame) = self.recording() {
+            if !frame.scopes.is_empty() {
+                frame.submitted = submitted;
+                frame.mapping = Some(Box::pin(
+                    frame.buffer.slice(..).map_async(wgpu::MapMode::Read),
+                ));
+            }
+        }
+        if self.enabled() {
+            self.current = (self.current + 1) % GPU_FRAMES;
+        }
+    }
+
+    /// The scopes of the latest frame that was read back since the last
+    /// call. The device has to be polled for the reads to finish.
+    pub fn poll(&mut self) -> Option<Vec<Scope>> {
+        let period = self.period as f64;
+        let mut latest = None;
+        // Oldest first, the frame after the current one is the oldest.
+        for i in 1..=self.frames.len() {
+            let frame = &mut self.frames[(self.current + i) % self.frames.len()];
+            let result = match frame.mapping.as_mut().and_then(|m| m.now_or_never()) {
+                Some(result) => result,
+                None => continue,
+            };
+            frame.mapping = None;
+            let scopes = std::mem::take(&mut frame.scopes);
+            if let Err(e) = result {
+                log::warn!("Failed to read the GPU timestamps: {:?}", e);
+                continue;
+            }
+            let data = frame.buffer.slice(..).get_mapped_range();
+            let ticks: &[u64] = bytemuck::cast_slice(&data);
+            let first = ticks[0];
+            let time = |ticks: u64| Duration::from_nanos((ticks as f64 * period) as u64);
+            latest = Some(
+                scopes
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &(name, depth))| {
+                        let (start, end) = (ticks[i * 2], ticks[i * 2 + 1]);
+                        Scope {
+                            name,
+                            depth,
+                            start: frame.submitted + time(start.saturating_sub(first)),
+                            duration: time(end.saturating_sub(start)),
+                        }
+                    })
+                    .collect(),
+            );
+            drop(data);
+            frame.buffer.unmap();
+        }
+        latest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nests_scopes() {
+        let mut profiler = Profiler::new();
+        profiler.begin("update");
+        profiler.begin("meshing");
+        profiler.end();
+        profiler.end();
+        profiler.begin("render");
+        profiler.end();
+        profiler.end_frame();
+
+        let frame = profiler.last_frame();
+        let names = frame.iter().map(|s| (s.name, s.depth)).collect::<Vec<_>>();
+        assert_eq!(names, [("update", 0), ("meshing", 1), ("render", 0)]);
+        assert!(frame[1].start >= frame[0].start);
+        assert!(frame[1].start + frame[1].duration <= frame[0].start + frame[0].duration);
+        assert!(frame[2].start >= frame[0].start + frame[0].duration);
+    }
+
+    #[test]
+    fn scopes_after_a_frame_go_to_the_next() {
+        let mut profiler = Profiler::new();
+        profiler.begin("update");
+        profiler.end();
+        profiler.end_frame();
+        profiler.begin("input");
+        profiler.end();
+
+        assert_eq!(profiler.last_frame().len(), 1);
+        profiler.end_frame();
+        assert_eq!(profiler.last_frame()[0].name, "input");
+    }
+
+    #[test]
+    fn captures_frames() {
+        let mut profiler = Profiler::new();
+        profiler.start_capture(2);
+        for _ in 0..2 {
+            assert!(profiler.take_capture().is_none());
+            profiler.begin("update");
+            profiler.end();
+            profiler.end_frame();
+        }
+        profiler.begin("update");
+        profiler.end();
+        profiler.end_frame();
+        profiler.record_gpu(vec![Scope {
+            name: "main \"pass\"",
+            depth: 0,
+            start: Duration::from_micros(1500),
+            duration: Duration::from_micros(250),
+        }]);
+
+        let capture = profiler.take_capture().unwrap();
+        assert_eq!(capture.cpu.len(), 2);
+        assert!(profiler.take_capture().is_none());
+        let trace = capture.to_chrome_trace();
+        assert!(trace.starts_with("{\"traceEvents\":["));
+        assert_eq!(trace.matches(r#""name":"update","ph":"X""#).count(), 2);
+        // Not part of the capture anymore.
+        assert!(!trace.contains("main"));
+    }
+
+    #[test]
+    fn writes_trace_events() {
+        let capture = Capture {
+            cpu: Vec::new(),
+            gpu: vec![Scope {
+                name: "main \"pass\"",
+                depth: 0,
+                start: Duration::from_micros(1500),
+                duration: Duration::from_micros(250),
+            }],
+            frames_left: 0,
+        };
+        let trace = capture.to_chrome_trace();
+        assert!(trace.contains(
+            r#"{"name":"main \"pass\"","ph":"X","ts":1500.000,"dur":250.000,"pid":0,"tid":1}"#
+        ));
+    }
+}
@@ -1,10 +1,11 @@
-use crate::cube::CubeType;
+use crate::modeling::vertex_index::VertexLayout;
 use nalgebra::{Matrix3, Rotation3, Translation3};
 
 pub struct Quad {
     pub position: Translation3<f32>,
     pub rotation: Rotation3<f32>,
-    pub offset: u32,
+    /// Atlas rect of the face's texture, see `AtlasRect::uv`.
+    pub uv_rect: [f32; 4],
     pub direction: QuadDirection,
 }
 
@@ -12,13 +13,13 @@ impl Quad {
     pub fn new(
         position: [f32; 3],
         rotation: Rotation3<f32>,
-        offset: u32,
+        uv_rect: [f32; 4],
         direction: QuadDirection,
     ) -> Self {
         Quad {
             position: Translation3::new(position[0], position[1], position[2]),
             rotation,
-            offset,
+            uv_rect,
             direction,
         }
     }
@@ -26,20 +27,18 @@ impl Quad {
         let matrix: [[f32; 4]; 4] =
             (self.position.to_homogeneous() * self.rotation.matrix().to_homogeneous()).into();
         let n_matrix: [[f32; 3]; 3] = Matrix3::from(self.rotation).into();
-        let offset_x_y = match self.direction {
-            QuadDirection::SIDE => (0., 0.),
-            QuadDirection::DOWN => (0., 1.),
-            QuadDirection::UP => (1., 0.),
-        };
-        let offset = self.offset;
         QuadRaw {
             matrix,
             n_matrix,
-            offset,
+            // All block textures are in the atlas, the first texture of the
+            // chunk texture array.
+            offset: 0,
+            uv_rect: self.uv_rect,
         }
     }
 }
 
+#[derive(Copy, Clone)]
 pub enum QuadDirection {
     UP = 2,
     DOWN = 1,
@@ -47,60 +46,11 @@ pub enum QuadDirection {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable, VertexLayout)]
+#[vertex(step_mode = "instance", location = 3)]
 pub struct QuadRaw {
     pub matrix: [[f32; 4]; 4],
     pub n_matrix: [[f32; 3]; 3],
-    pub offset: u32, // Every map must have same width and height with same amount of rows and columns.
-}
-
-impl QuadRaw {
-    pub fn init_buffer_layout<'a>() -> wgpu::VertexBufferLayout<'a> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<QuadRaw>() as wgpu::BufferAddress,
-            step_mode: wgpu::InputStepMode::Instance,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x4,
-                    offset: 0,
-                    shader_location: 3,
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x4,
-                    offset: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
-                    shader_location: 4,
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x4,
-                    offset: std::mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
-                    shader_location: 5,
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x4,
-                    offset: std::mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
-                    shader_location: 6,
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x3,
-                    offset: std::mem::size_of::<[f32; 16]>() as wgpu::BufferAddress,
-                    shader_location: 7,
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x3,
-                    offset: std::mem::size_of::<[f32; 19]>() as wgpu::BufferAddress,
-                    shader_location: 8,
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x3,
-                    offset: std::mem::size_of::<[f32; 22]>() as wgpu::BufferAddress,
-                    shader_location: 9,
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Uint32,
-                    offset: std::mem::size_of::<[f32; 25]>() as wgpu::BufferAddress,
-                    shader_location: 10,
-                },
-            ],
-        }
-    }
+    pub offset: u32, // Index into the chunk texture array.
+    pub uv_rect: [f32; 4],
 }
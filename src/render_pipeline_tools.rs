@@ -1,3 +1,94 @@
+/// Topology, rasterizer, depth and blend state that differ between variants
+/// of a pipeline built from the same shaders. The default draws filled, back
+/// face culled triangles, keeping the closest one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PipelineVariant {
+    pub topology: wgpu::PrimitiveTopology,
+    /// Anything but `Fill` needs `NON_FILL_POLYGON_MODE`.
+    pub polygon_mode: wgpu::PolygonMode,
+    pub cull_mode: Option<wgpu::Face>,
+    pub depth_write_enabled: bool,
+    pub depth_compare: wgpu::CompareFunction,
+    pub depth_bias: wgpu::DepthBiasState,
+    /// `None` writes the fragment's color as it is, which formats that can't
+    /// be blended, like `Rgba32Float`, need.
+    pub blend: Option<wgpu::BlendState>,
+}
+
+impl Default for PipelineVariant {
+    fn default() -> Self {
+        Self {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            cull_mode: Some(wgpu::Face::Back),
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            depth_bias: wgpu::DepthBiasState::default(),
+            blend: None,
+        }
+    }
+}
+
+impl PipelineVariant {
+    /// Triangle edges, drawn over what the default variant drew. The lines
+    /// are pulled towards the camera so the faces underneath don't hide them.
+    pub fn wireframe() -> Self {
+        Self {
+            polygon_mode: wgpu::PolygonMode::Line,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            depth_bias: wgpu::DepthBiasState {
+                constant: -2,
+                slope_scale: -1.,
+                clamp: 0.,
+            },
+            ..Self::default()
+        }
+    }
+
+    /// Debug lines, which are tested against the depth of the scene but don't
+    /// write to it.
+    pub fn lines() -> Self {
+        Self {
+            topology: wgpu::PrimitiveTopology::LineList,
+            cull_mode: None,
+            depth_write_enabled: false,
+            ..Self::default()
+        }
+    }
+
+    /// Drawn first, behind everything else. It neither tests nor writes
+    /// depth, and has no back faces to cull.
+    pub fn background() -> Self {
+        Self {
+            cull_mode: None,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Always,
+            ..Self::default()
+        }
+    }
+
+    /// Adds up every fragment, hidden or not, instead of keeping the closest.
+    pub fn overdraw() -> Self {
+        let add = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+        Self {
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Always,
+            blend: Some(wgpu::BlendState {
+                color: add,
+                alpha: add,
+            }),
+            ..Self::default()
+        }
+    }
+}
+
+/// `sample_count` has to match the attachments of the render pass the
+/// pipeline is used in.
 pub fn new_render_pipeline(
     name: &str,
     device: &wgpu::Device,
@@ -6,7 +97,9 @@ pub fn new_render_pipeline(
     frag_shader: &wgpu::ShaderModule,
     color_format: wgpu::TextureFormat,
     depth_format: wgpu::TextureFormat,
+    sample_count: u32,
     vert_layout: &[wgpu::VertexBufferLayout],
+    variant: PipelineVariant,
 ) -> wgpu::RenderPipeline {
     let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: Some(&format!("{} pipeline layout.", name)),
@@ -23,23 +116,23 @@ pub fn new_render_pipeline(
             buffers: vert_layout,
         },
         primitive: wgpu::PrimitiveState {
-            topology: wgpu::PrimitiveTopology::TriangleList,
+            topology: variant.topology,
             strip_index_format: None,
             front_face: wgpu::FrontFace::Ccw,
-            cull_mode: Some(wgpu::Face::Back),
+            cull_mode: variant.cull_mode,
             clamp_depth: false,
-            polygon_mode: wgpu::PolygonMode::Fill,
+            polygon_mode: variant.polygon_mode,
             conservative: false,
         },
         depth_stencil: Some(wgpu::DepthStencilState {
             format: depth_format,
-            depth_write_enabled: true,
-            depth_compare: wgpu::CompareFunction::Less,
+            depth_write_enabled: variant.depth_write_enabled,
+            depth_compare: variant.depth_compare,
             stencil: wgpu::StencilState::default(),
-            bias: wgpu::DepthBiasState::default(),
+            bias: variant.depth_bias,
         }),
         multisample: wgpu::MultisampleState {
-            count: 1,
+            count: sample_count,
             mask: !0,
             alpha_to_coverage_enabled: false,
         },
@@ -48,7 +141,7 @@ pub fn new_render_pipeline(
             entry_point: "main",
             targets: &[wgpu::ColorTargetState {
                 format: color_format,
-                blend: Some(wgpu::BlendState::REPLACE),
+                blend: variant.blend,
                 write_mask: wgpu::ColorWrite::ALL,
             }],
         }),
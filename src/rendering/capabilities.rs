@@ -0,0 +1,145 @@
+//! What the adapter can do, and which rendering paths we pick because of it.
+//! `--capabilities` prints the [`Capabilities`] report, which is handy for
+//! bug reports.
+
+use crate::texture::TextureArrayKind;
+use anyhow::{Context, Result};
+use std::fmt;
+
+/// Sample counts every adapter supports. wgpu can't tell us which other
+/// counts a format supports, so we stay with the ones WebGPU guarantees.
+pub const MSAA_SAMPLE_COUNTS: [u32; 2] = [1, 4];
+
+/// Sample count the scene is drawn with unless `--msaa` asks for another.
+pub const DEFAULT_MSAA_SAMPLES: u32 = 4;
+
+#[derive(Debug, Clone)]
+pub struct Capabilities {
+    pub info: wgpu::AdapterInfo,
+    pub features: wgpu::Features,
+    pub limits: wgpu::Limits,
+    pub texture_array_kind: TextureArrayKind,
+    /// Whether `PolygonMode::Line` can be used.
+    pub wireframe: bool,
+    /// Whether BC compressed KTX2 and DDS textures can be loaded.
+    pub texture_compression_bc: bool,
+    /// Whether GPU passes can be timed with timestamp queries.
+    pub timestamp_query: bool,
+}
+
+impl Capabilities {
+    pub fn probe(adapter: &wgpu::Adapter) -> Self {
+        let features = adapter.features();
+        Self {
+            info: adapter.get_info(),
+            features,
+            limits: adapter.limits(),
+            texture_array_kind: TextureArrayKind::for_features(features),
+            wireframe: features.contains(wgpu::Features::NON_FILL_POLYGON_MODE),
+            texture_compression_bc: features.contains(wgpu::Features::TEXTURE_COMPRESSION_BC),
+            timestamp_query: features.contains(wgpu::Features::TIMESTAMP_QUERY),
+        }
+    }
+
+    /// Asks for an adapter that can draw to `surface`, or any adapter if
+    /// there's no surface.
+    pub async fn request_adapter(
+        instance: &wgpu::Instance,
+        surface: Option<&wgpu::Surface>,
+    ) -> Result<wgpu::Adapter> {
+        instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                compatible_surface: surface,
+            })
+            .await
+            .context("No graphics adapter was found, is a Vulkan, Metal or DX12 driver installed?")
+    }
+
+    /// Creates the device with the features of the paths we picked.
+    pub async fn request_device(
+        &self,
+        adapter: &wgpu::Adapter,
+    ) -> Result<(wgpu::Device, wgpu::Queue)> {
+        adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: Some("Device & Queue"),
+                    features: self.required_features(),
+                    limits: self.required_limits(),
+                },
+                None,
+            )
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to create a device on {} ({:?})",
+                    self.info.name, self.info.backend
+                )
+            })
+    }
+
+    pub fn required_features(&self) -> wgpu::Features {
+        let mut features = self.texture_array_kind.required_features();
+        if self.wireframe {
+            features |= wgpu::Features::NON_FILL_POLYGON_MODE;
+        }
+        if self.texture_compression_bc {
+            features |= wgpu::Features::TEXTURE_COMPRESSION_BC;
+        }
+        if self.timestamp_query {
+            features |= wgpu::Features::TIMESTAMP_QUERY;
+        }
+        features
+    }
+
+    /// The default limits, except for the number of textures per stage,
+    /// which some adapters have fewer than 16 of. We only need one.
+    pub fn required_limits(&self) -> wgpu::Limits {
+        let defaults = wgpu::Limits::default();
+        wgpu::Limits {
+            max_sampled_textures_per_shader_stage: defaults
+                .max_sampled_textures_per_shader_stage
+                .min(self.limits.max_sampled_textures_per_shader_stage),
+            ..defaults
+        }
+    }
+
+    /// The largest supported sample count that isn't above `requested`.
+    pub fn msaa_samples(&self, requested: u32) -> u32 {
+        MSAA_SAMPLE_COUNTS
+            .iter()
+            .copied()
+            .filter(|&count| count <= requested)
+            .max()
+            .unwrap_or(1)
+    }
+}
+
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Adapter: {}", self.info.name)?;
+        writeln!(f, "  backend: {:?}", self.info.backend)?;
+        writeln!(f, "  device type: {:?}", self.info.device_type)?;
+        writeln!(
+            f,
+            "  vendor: {:#06x}, device: {:#06x}",
+            self.info.vendor, self.info.device
+        )?;
+        writeln!(f, "Features: {:?}", self.features)?;
+        writeln!(f, "Limits: {:#?}", self.limits)?;
+        writeln!(f, "Rendering paths:")?;
+        writeln!(f, "  chunk textures: {:?}", self.texture_array_kind)?;
+        writeln!(f, "  wireframe: {}", self.wireframe)?;
+        writeln!(f, "  BC textures: {}", self.texture_compression_bc)?;
+        writeln!(f, "  GPU timings: {}", self.timestamp_query)?;
+        writeln!(f, "  MSAA sample counts: {:?}", MSAA_SAMPLE_COUNTS)
+    }
+}
+
+/// Probes the default adapter without opening a window.
+pub async fn report() -> Result<String> {
+    let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
+    let adapter = Capabilities::request_adapter(&instance, None).await?;
+    Ok(Capabilities::probe(&adapter).to_string())
+}
@@ -1,3 +1,6 @@
+use crate::rendering::capabilities::{Capabilities, DEFAULT_MSAA_SAMPLES};
+use anyhow::{Context, Result};
+
 pub struct Graphics {
     pub size: winit::dpi::PhysicalSize<u32>,
     pub surface: wgpu::Surface,
@@ -5,38 +8,23 @@ pub struct Graphics {
     pub queue: wgpu::Queue,
     pub sc_desc: wgpu::SwapChainDescriptor,
     pub swap_chain: wgpu::SwapChain,
+    pub capabilities: Capabilities,
+    /// Samples per pixel of the render passes and the pipelines drawn in
+    /// them.
+    pub msaa_samples: u32,
 }
 
 impl Graphics {
-    pub fn new(window: &winit::window::Window) -> Self {
+    pub async fn new(window: &winit::window::Window) -> Result<Self> {
         let size = window.inner_size();
         let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
         let surface = unsafe { instance.create_surface(&window) };
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::HighPerformance,
-                compatible_surface: Some(&surface),
-            })
-            .await
-            .unwrap();
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: Some("Device & Queue"),
-                    features: wgpu::Features::SAMPLED_TEXTURE_ARRAY_NON_UNIFORM_INDEXING
-                        | wgpu::Features::SAMPLED_TEXTURE_BINDING_ARRAY
-                        | wgpu::Features::NON_FILL_POLYGON_MODE
-                        | wgpu::Features::UNSIZED_BINDING_ARRAY,
-                    limits: wgpu::Limits {
-                        max_sampled_textures_per_shader_stage: 0,
-                        ..Default::default()
-                    },
-                },
-                None,
-            )
-            .await
-            .unwrap();
-        let sc_format = adapter.get_swap_chain_preferred_format(&surface).unwrap();
+        let adapter = Capabilities::request_adapter(&instance, Some(&surface)).await?;
+        let capabilities = Capabilities::probe(&adapter);
+        let (device, queue) = capabilities.request_device(&adapter).await?;
+        let sc_format = adapter
+            .get_swap_chain_preferred_format(&surface)
+            .context("The adapter can't draw to the window")?;
         let sc_desc = wgpu::SwapChainDescriptor {
             usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
             format: sc_format,
@@ -45,14 +33,17 @@ impl Graphics {
             present_mode: wgpu::PresentMode::Fifo,
         };
         let swap_chain = device.create_swap_chain(&surface, &sc_desc);
+        let msaa_samples = capabilities.msaa_samples(DEFAULT_MSAA_SAMPLES);
 
-        Self {
+        Ok(Self {
             size,
             surface,
             device,
             queue,
             sc_desc,
             swap_chain,
-        }
+            capabilities,
+            msaa_samples,
+        })
     }
 }
@@ -1,5 +1,10 @@
+pub mod capabilities;
 pub mod graphics;
 pub mod object;
 pub mod pipeline;
+pub mod preprocessor;
+pub mod reflection;
+pub mod render_graph;
 pub mod renderer;
+pub mod shader;
 pub mod uniforms;
\ No newline at end of file
@@ -1,7 +1,11 @@
 use std::num::NonZeroU32;
 use std::path::Path;
+use anyhow::Result;
+use crate::render_pipeline_tools::PipelineVariant;
 use crate::rendering::object::Object;
 use crate::rendering::graphics::Graphics;
+use crate::rendering::preprocessor::Preprocessor;
+use crate::rendering::shader::load_shader;
 
 pub struct Pipeline {
     pub render_pipeline: wgpu::RenderPipeline,
@@ -9,6 +13,11 @@ pub struct Pipeline {
 }
 
 impl Pipeline {
+    /// Creates a pipeline from shaders that are loaded at runtime. See
+    /// [`ShaderCode::load`](crate::rendering::shader::ShaderCode::load) for
+    /// the supported shader files. `variant` picks the rasterizer, depth and
+    /// blend state, use `PipelineVariant::default()` for ordinary geometry.
+    /// The pipeline uses the sample count of `graphics`.
     pub fn new<P: AsRef<Path>>(
         label: &str,
         graphics: &Graphics,
@@ -17,21 +26,11 @@ impl Pipeline {
         depth_format: Option<wgpu::TextureFormat>,
         bind_group_layouts: &[&wgpu::BindGroupLayout],
         vertex_buffer_layouts: &[wgpu::VertexBufferLayout],
-    ) -> Self {
-        let vertex_shader = graphics
-            .device
-            .create_shader_module(&wgpu::ShaderModuleDescriptor {
-                label: Some(&format!("{} vertex shader", label)),
-                source: wgpu::util::make_spirv(include_bytes!(vertex_path)),
-                flags: wgpu::ShaderFlags::all(),
-            });
-        let fragment_shader = graphics
-            .device
-            .create_shader_module(&wgpu::ShaderModuleDescriptor {
-                label: Some(&format!("{} fragment shader", label)),
-                source: wgpu::util::make_spirv(include_bytes!(fragment_path)),
-                flags: wgpu::ShaderFlags::empty(),
-            });
+        variant: PipelineVariant,
+    ) -> Result<Self> {
+        let preprocessor = Preprocessor::new();
+        let vertex_shader = load_shader(&graphics.device, vertex_path, &preprocessor)?;
+        let fragment_shader = load_shader(&graphics.device, fragment_path, &preprocessor)?;
         let layout = graphics
             .device
             .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -51,23 +50,23 @@ impl Pipeline {
                         buffers: vertex_buffer_layouts,
                     },
                     primitive: wgpu::PrimitiveState {
-                        topology: wgpu::PrimitiveTopology::TriangleList,
+                        topology: variant.topology,
                         strip_index_format: None,
                         front_face: wgpu::FrontFace::Ccw,
-                        cull_mode: Some(wgpu::Face::Back),
+                        cull_mode: variant.cull_mode,
                         clamp_depth: false,
-                        polygon_mode: wgpu::PolygonMode::Fill,
+                        polygon_mode: variant.polygon_mode,
                         conservative: false,
                     },
                     depth_stencil: depth_format.map(|f| wgpu::DepthStencilState {
                         format: f,
-                        depth_write_enabled: true,
-                        depth_compare: wgpu::CompareFunction::Less,
+                        depth_write_enabled: variant.depth_write_enabled,
+                        depth_compare: variant.depth_compare,
                         stencil: wgpu::StencilState::default(),
-                        bias: wgpu::DepthBiasState::default(),
+                        bias: variant.depth_bias,
                     }),
                     multisample: wgpu::MultisampleState {
-                        count: 1,
+                        count: graphics.msaa_samples,
                         mask: !0,
                         alpha_to_coverage_enabled: false,
                     },
@@ -76,12 +75,15 @@ impl Pipeline {
                         entry_point: "main",
                         targets: &[wgpu::ColorTargetState {
                             format: graphics.sc_desc.format,
-                            blend: Some(wgpu::BlendState::REPLACE),
+                            blend: variant.blend,
                             write_mask: wgpu::ColorWrite::ALL,
                         }],
                     }),
                 });
-        Self { render_pipeline }
+        Ok(Self {
+            render_pipeline,
+            objects: Vec::new(),
+        })
     }
 
     pub fn add_object(&mut self, object: Object) {
@@ -0,0 +1,522 @@
+//! A small text preprocessor for shader sources. It's used by `build.rs` as
+//! well, so it must only depend on `std`.
+//!
+//! Supported directives:
+//! - `#include "file"`, searched next to the including file first and then in
+//!   the include directories. Every file is included at most once, and a
+//!   file including itself (directly or not) is an error.
+//! - `#define NAME [value]`, replaces `NAME` with `value` in all following
+//!   lines. Function-like macros aren't supported.
+//! - `#undef NAME`
+//! - `#ifdef NAME`, `#ifndef NAME`, `#else` and `#endif`.
+//!
+//! Every other directive (`#version`, `#extension`, ...) is left for the
+//! shader compiler.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum PreprocessError {
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    IncludeNotFound {
+        file: PathBuf,
+        line: usize,
+        include: String,
+    },
+    IncludeCycle {
+        file: PathBuf,
+        line: usize,
+        include: PathBuf,
+    },
+    Syntax {
+        file: PathBuf,
+        line: usize,
+        message: String,
+    },
+    UnterminatedIf {
+        file: PathBuf,
+        line: usize,
+    },
+}
+
+impl fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PreprocessError::Io { path, error } => {
+                write!(f, "Failed to read {:?}: {}", path, error)
+            }
+            PreprocessError::IncludeNotFound {
+                file,
+                line,
+                include,
+            } => write!(
+                f,
+                "{}:{}: included file {:?} not found",
+                file.display(),
+                line,
+                include
+            ),
+            PreprocessError::IncludeCycle {
+                file,
+                line,
+                include,
+            } => write!(
+                f,
+                "{}:{}: {} includes itself",
+                file.display(),
+                line,
+                include.display()
+            ),
+            PreprocessError::Syntax {
+                file,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", file.display(), line, message),
+            PreprocessError::UnterminatedIf { file, line } => write!(
+                f,
+                "{}:{}: #ifdef/#ifndef without a matching #endif",
+                file.display(),
+                line
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PreprocessError {}
+
+/// Output of the preprocessor along with where every line came from.
+pub struct Preprocessed {
+    pub source: String,
+    /// Files that were read, the main file first.
+    pub files: Vec<PathBuf>,
+    /// Index into `files` and line number of every output line.
+    lines: Vec<(usize, usize)>,
+}
+
+impl Preprocessed {
+    /// File and line a (1 based) line of the output came from.
+    pub fn origin(&self, line: usize) -> Option<(&Path, usize)> {
+        let (file, line) = *self.lines.get(line.checked_sub(1)?)?;
+        Some((&self.files[file], line))
+    }
+
+    /// Rewrites `name:line:` locations in compiler messages so they point to
+    /// the original file and line instead of the preprocessed output.
+    pub fn remap_diagnostics(&self, name: &str, message: &str) -> String {
+        let prefix = format!("{}:", name);
+        message
+            .lines()
+            .map(|l| {
+                let rest = match l.strip_prefix(&prefix) {
+                    Some(rest) => rest,
+                    None => return String::from(l),
+                };
+                let (number, rest) = rest.split_at(rest.find(':').unwrap_or(0));
+                match number.parse().ok().and_then(|n| self.origin(n)) {
+                    Some((file, line)) => format!("{}:{}{}", file.display(), line, rest),
+                    None => String::from(l),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct Preprocessor {
+    defines: HashMap<String, String>,
+    include_dirs: Vec<PathBuf>,
+}
+
+/// State of one `#ifdef`/`#ifndef` block.
+struct Condition {
+    /// Whether the lines of the current branch are kept.
+    active: bool,
+    /// Whether the lines around the whole block are kept.
+    parent_active: bool,
+    seen_else: bool,
+    line: usize,
+}
+
+impl Preprocessor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Defines `name` before the first line of the shader, as if it started
+    /// with `#define name value`.
+    pub fn define(mut self, name: &str, value: &str) -> Self {
+        self.defines.insert(String::from(name), String::from(value));
+        self
+    }
+
+    pub fn include_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.include_dirs.push(dir.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn process_file<P: AsRef<Path>>(&self, path: P) -> Result<Preprocessed, PreprocessError> {
+        let path = path.as_ref();
+        let source = read(path)?;
+        self.process_str(path, &source)
+    }
+
+    /// Processes `source` as if it was read from `path`, which is only used
+    /// to resolve includes and in error messages.
+    pub fn process_str<P: AsRef<Path>>(
+        &self,
+        path: P,
+        source: &str,
+    ) -> Result<Preprocessed, PreprocessError> {
+        let mut state = State {
+            defines: self.defines.clone(),
+            output: Preprocessed {
+                source: String::new(),
+                files: Vec::new(),
+                lines: Vec::new(),
+            },
+            included: HashSet::new(),
+            stack: Vec::new(),
+        };
+        self.process(&mut state, path.as_ref(), source)?;
+        Ok(state.output)
+    }
+
+    fn process(&self, state: &mut State, path: &Path, source: &str) -> Result<(), PreprocessError> {
+        let file = state.output.files.len();
+        state.output.files.push(path.to_path_buf());
+        let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        state.included.insert(canonical.clone());
+        state.stack.push(canonical);
+
+        let syntax = |line: usize, message: &str| PreprocessError::Syntax {
+            file: path.to_path_buf(),
+            line,
+            message: String::from(message),
+        };
+        let mut conditions: Vec<Condition> = Vec::new();
+        for (i, text) in source.lines().enumerate() {
+            let line = i + 1;
+            let active = conditions.last().map_or(true, |c| c.active);
+            let (directive, args) = match parse_directive(text) {
+                Some(d) => d,
+                None => {
+                    if active {
+                        state.push_line(&substitute(text, &state.defines), file, line);
+                    }
+                    continue;
+                }
+            };
+
+            match directive {
+                "ifdef" | "ifndef" => {
+                    let name = single_name(args).ok_or_else(|| syntax(line, "expected a name"))?;
+                    let defined = state.defines.contains_key(name);
+                    conditions.push(Condition {
+                        active: active && (defined == (directive == "ifdef")),
+                        parent_active: active,
+                        seen_else: false,
+                        line,
+                    });
+                }
+                "else" => {
+                    let condition = conditions
+                        .last_mut()
+                        .filter(|c| !c.seen_else)
+                        .ok_or_else(|| syntax(line, "#else without #ifdef/#ifndef"))?;
+                    condition.active = condition.parent_active && !condition.active;
+                    condition.seen_else = true;
+                }
+                "endif" => {
+                    conditions
+                        .pop()
+                        .ok_or_else(|| syntax(line, "#endif without #ifdef/#ifndef"))?;
+                }
+                _ if !active => {}
+                "define" => {
+                    let mut parts = args.splitn(2, char::is_whitespace);
+                    let name = parts
+                        .next()
+                        .filter(|n| is_identifier(n))
+                        .ok_or_else(|| syntax(line, "expected a name"))?;
+                    let value = parts.next().unwrap_or("").trim();
+                    state
+                        .defines
+                        .insert(String::from(name), String::from(value));
+                }
+                "undef" => {
+                    let name = single_name(args).ok_or_else(|| syntax(line, "expected a name"))?;
+                    state.defines.remove(name);
+                }
+                "include" => {
+                    let include = args
+                        .strip_prefix('"')
+                        .and_then(|a| a.strip_suffix('"'))
+                        .ok_or_else(|| syntax(line, "expected #include \"file\""))?;
+                    let include_path = self.resolve(path, include).ok_or_else(|| {
+                        PreprocessError::IncludeNotFound {
+                            file: path.to_path_buf(),
+                            line,
+                            include: String::from(include),
+                        }
+                    })?;
+                    let canonical =
+                        fs::canonicalize(&include_path).unwrap_or_else(|_| include_path.clone());
+                    if state.stack.contains(&canonical) {
+                        return Err(PreprocessError::IncludeCycle {
+                            file: path.to_path_buf(),
+                            line,
+                            include: include_path,
+                        });
+                    }
+                    if !state.included.contains(&canonical) {
+                        let source = read(&include_path)?;
+                        self.process(state, &include_path, &source)?;
+                    }
+                }
+                _ => state.push_line(text, file, line),
+            }
+        }
+
+        if let Some(c) = conditions.last() {
+            return Err(PreprocessError::UnterminatedIf {
+                file: path.to_path_buf(),
+                line: c.line,
+            });
+        }
+        state.stack.pop();
+        Ok(())
+    }
+
+    fn resolve(&self, from: &Path, include: &str) -> Option<PathBuf> {
+        let folder = from.parent().unwrap_or_else(|| Path::new(""));
+        std::iter::once(folder)
+            .chain(self.include_dirs.iter().map(PathBuf::as_path))
+            .map(|dir| dir.join(include))
+            .find(|path| path.is_file())
+    }
+}
+
+struct State {
+    defines: HashMap<String, String>,
+    output: Preprocessed,
+    included: HashSet<PathBuf>,
+    /// Files that are being processed, to find include cycles.
+    stack: Vec<PathBuf>,
+}
+
+impl State {
+    fn push_line(&mut self, text: &str, file: usize, line: usize) {
+        self.output.source.push_str(text);
+        self.output.source.push('\n');
+        self.output.lines.push((file, line));
+    }
+}
+
+fn read(path: &Path) -> Result<String, PreprocessError> {
+    fs::read_to_string(path).map_err(|error| PreprocessError::Io {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+/// Splits `#name args` into its name and arguments.
+fn parse_directive(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim_start().strip_prefix('#')?.trim_start();
+    let end = line
+        .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .unwrap_or_else(|| line.len());
+    let args = line[end..].trim();
+    // Trailing `//` comments aren't part of the arguments.
+    let args = args.find("//").map_or(args, |i| args[..i].trim_end());
+    Some((&line[..end], args))
+}
+
+fn single_name(args: &str) -> Option<&str> {
+    Some(args).filter(|a| is_identifier(a))
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Replaces every identifier that has a definition with its value.
+fn substitute(line: &str, defines: &HashMap<String, String>) -> String {
+    if defines.is_empty() {
+        return String::from(line);
+    }
+    let mut result = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        // Don't split numbers like `1e5` or `0x1F` into identifiers.
+        let prefix_ends_in_digit = rest[..start]
+            .chars()
+            .last()
+            .map_or(false, |c| c.is_ascii_alphanumeric());
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or_else(|| rest.len());
+        let word = &rest[..end];
+        match defines.get(word) {
+            Some(value) if !prefix_ends_in_digit => result.push_str(value),
+            _ => result.push_str(word),
+        }
+        rest = &rest[end..];
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixtures() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/shaders")
+    }
+
+    fn process(preprocessor: &Preprocessor, source: &str) -> Result<String, PreprocessError> {
+        preprocessor
+            .process_str(fixtures().join("test.glsl"), source)
+            .map(|output| output.source)
+    }
+
+    #[test]
+    fn includes_nested_files_once() {
+        let dir = fixtures();
+        let output = Preprocessor::new()
+            .include_dir(dir.join("include"))
+            .process_file(dir.join("main.glsl"))
+            .unwrap();
+        assert_eq!(
+            output.source,
+            "float c;\nfloat b;\nfloat a;\nvoid main() {}\n"
+        );
+        assert_eq!(
+            output.files,
+            vec![
+                dir.join("main.glsl"),
+                dir.join("a.glsl"),
+                dir.join("b.glsl"),
+                dir.join("include/c.glsl"),
+            ]
+        );
+        assert_eq!(output.origin(2), Some((dir.join("b.glsl").as_path(), 2)));
+        assert_eq!(output.origin(4), Some((dir.join("main.glsl").as_path(), 3)));
+        assert_eq!(output.origin(5), None);
+    }
+
+    #[test]
+    fn missing_includes_are_an_error() {
+        let error = Preprocessor::new()
+            .process_file(fixtures().join("main.glsl"))
+            .err()
+            .unwrap();
+        assert!(matches!(
+            error,
+            PreprocessError::IncludeNotFound { line: 1, ref include, .. } if include == "c.glsl"
+        ));
+    }
+
+    #[test]
+    fn include_cycles_are_an_error() {
+        let dir = fixtures();
+        let error = Preprocessor::new()
+            .process_file(dir.join("cycle_a.glsl"))
+            .err()
+            .unwrap();
+        match error {
+            PreprocessError::IncludeCycle {
+                file,
+                line,
+                include,
+            } => {
+                assert_eq!(file, dir.join("cycle_b.glsl"));
+                assert_eq!(line, 1);
+                assert_eq!(include, dir.join("cycle_a.glsl"));
+            }
+            e => panic!("expected an include cycle, got {}", e),
+        }
+    }
+
+    #[test]
+    fn nested_conditions() {
+        let source = "\
+#ifdef A
+a
+#ifndef B
+not b
+#else
+b
+#endif
+#else
+not a
+#ifdef B
+hidden
+#endif
+#endif
+end";
+        let none = Preprocessor::new();
+        assert_eq!(process(&none, source).unwrap(), "not a\nend\n");
+        let a = Preprocessor::new().define("A", "");
+        assert_eq!(process(&a, source).unwrap(), "a\nnot b\nend\n");
+        let ab = a.define("B", "");
+        assert_eq!(process(&ab, source).unwrap(), "a\nb\nend\n");
+    }
+
+    #[test]
+    fn unbalanced_conditions_are_an_error() {
+        let preprocessor = Preprocessor::new();
+        assert!(matches!(
+            process(&preprocessor, "#ifdef A\n#ifdef B\n#endif\n"),
+            Err(PreprocessError::UnterminatedIf { line: 1, .. })
+        ));
+        assert!(matches!(
+            process(&preprocessor, "a\n#endif\n"),
+            Err(PreprocessError::Syntax { line: 2, .. })
+        ));
+        assert!(matches!(
+            process(&preprocessor, "#else\n"),
+            Err(PreprocessError::Syntax { line: 1, .. })
+        ));
+        assert!(matches!(
+            process(&preprocessor, "#ifdef A\n#else\n#else\n#endif\n"),
+            Err(PreprocessError::Syntax { line: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn substitutes_defines() {
+        let preprocessor = Preprocessor::new().define("SET", "2");
+        let source = "\
+#define SIZE 16 // texels
+#define EMPTY
+layout(set = SET) uniform u;
+float x[SIZE] = SIZE_2 + 1e5 + 0xSIZE EMPTY;
+#undef SIZE
+SIZE";
+        assert_eq!(
+            process(&preprocessor, source).unwrap(),
+            "layout(set = 2) uniform u;\nfloat x[16] = SIZE_2 + 1e5 + 0xSIZE ;\nSIZE\n"
+        );
+    }
+
+    #[test]
+    fn keeps_unknown_directives() {
+        let preprocessor = Preprocessor::new().define("VERSION", "450");
+        assert_eq!(
+            process(&preprocessor, "#version 450\n#extension GL_foo : enable\n").unwrap(),
+            "#version 450\n#extension GL_foo : enable\n"
+        );
+    }
+}
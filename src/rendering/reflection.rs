@@ -0,0 +1,948 @@
+//! Reflection of the resources and vertex inputs a shader uses, so that the
+//! hand-written bind group and vertex buffer layouts can be checked against
+//! the shaders, or generated from them.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::num::NonZeroU32;
+use std::ops::Range;
+
+#[derive(Debug)]
+pub enum ReflectionError {
+    Parse(String),
+    Unsupported {
+        name: String,
+        reason: &'static str,
+    },
+    /// Two stages use the same binding for different resources.
+    Conflict {
+        group: u32,
+        binding: u32,
+        name: String,
+    },
+    MissingBinding {
+        group: u32,
+        binding: u32,
+        name: String,
+    },
+    BindingType {
+        group: u32,
+        binding: u32,
+        name: String,
+        shader: wgpu::BindingType,
+        layout: wgpu::BindingType,
+    },
+    BindingCount {
+        group: u32,
+        binding: u32,
+        name: String,
+        shader: BindingCount,
+        layout: Option<NonZeroU32>,
+    },
+    Visibility {
+        group: u32,
+        binding: u32,
+        name: String,
+        stage: wgpu::ShaderStage,
+    },
+    MissingLocation {
+        location: u32,
+        name: String,
+    },
+    LocationFormat {
+        location: u32,
+        name: String,
+        shader: wgpu::VertexFormat,
+        layout: wgpu::VertexFormat,
+    },
+}
+
+impl fmt::Display for ReflectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReflectionError::Parse(message) => write!(f, "Failed to parse shader: {}", message),
+            ReflectionError::Unsupported { name, reason } => {
+                write!(f, "Can't reflect {:?}: {}", name, reason)
+            }
+            ReflectionError::Conflict {
+                group,
+                binding,
+                name,
+            } => write!(
+                f,
+                "{:?} (set = {}, binding = {}) has a different type in another stage",
+                name, group, binding
+            ),
+            ReflectionError::MissingBinding {
+                group,
+                binding,
+                name,
+            } => write!(
+                f,
+                "{:?} (set = {}, binding = {}) isn't in the bind group layout",
+                name, group, binding
+            ),
+            ReflectionError::BindingType {
+                group,
+                binding,
+                name,
+                shader,
+                layout,
+            } => write!(
+                f,
+                "{:?} (set = {}, binding = {}) is {:?} in the shader, but {:?} in the layout",
+                name, group, binding, shader, layout
+            ),
+            ReflectionError::BindingCount {
+                group,
+                binding,
+                name,
+                shader,
+                layout,
+            } => write!(
+                f,
+                "{:?} (set = {}, binding = {}) has count {:?} in the shader, but {:?} in the layout",
+                name, group, binding, shader, layout
+            ),
+            ReflectionError::Visibility {
+                group,
+                binding,
+                name,
+                stage,
+            } => write!(
+                f,
+                "{:?} (set = {}, binding = {}) is used in {:?}, but not visible there",
+                name, group, binding, stage
+            ),
+            ReflectionError::MissingLocation { location, name } => write!(
+                f,
+                "Vertex input {:?} (location = {}) isn't in any vertex buffer",
+                name, location
+            ),
+            ReflectionError::LocationFormat {
+                location,
+                name,
+                shader,
+                layout,
+            } => write!(
+                f,
+                "Vertex input {:?} (location = {}) is {:?} in the shader, but {:?} in the vertex buffer",
+                name, location, shader, layout
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReflectionError {}
+
+/// Number of resources behind a single binding.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BindingCount {
+    Single,
+    Array(NonZeroU32),
+    /// Array without a size in the shader, like `texture2D textures[]`.
+    RuntimeArray,
+}
+
+impl BindingCount {
+    fn matches(&self, count: Option<NonZeroU32>) -> bool {
+        match (self, count) {
+            (BindingCount::Single, None) => true,
+            (BindingCount::Array(n), Some(count)) => *n == count,
+            (BindingCount::RuntimeArray, Some(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReflectedBinding {
+    pub group: u32,
+    pub binding: u32,
+    pub name: String,
+    pub ty: wgpu::BindingType,
+    pub count: BindingCount,
+    pub visibility: wgpu::ShaderStage,
+}
+
+#[derive(Debug, Clone)]
+pub struct VertexInput {
+    pub location: u32,
+    pub name: String,
+    pub format: wgpu::VertexFormat,
+}
+
+/// Resources and vertex inputs of a single shader module.
+#[derive(Debug, Clone)]
+pub struct ShaderInterface {
+    pub stage: wgpu::ShaderStage,
+    pub bindings: Vec<ReflectedBinding>,
+    pub vertex_inputs: Vec<VertexInput>,
+}
+
+impl ShaderInterface {
+    pub fn from_spirv(bytes: &[u8]) -> Result<Self, ReflectionError> {
+        let module = naga::front::spv::parse_u8_slice(bytes, &Default::default())
+            .map_err(|e| ReflectionError::Parse(format!("{:?}", e)))?;
+        Self::from_module(&module)
+    }
+
+    pub fn from_wgsl(source: &str) -> Result<Self, ReflectionError> {
+        let module = naga::front::wgsl::parse_str(source)
+            .map_err(|e| ReflectionError::Parse(e.emit_to_string(source)))?;
+        Self::from_module(&module)
+    }
+
+    /// Reflects the first entry point of `module`.
+    pub fn from_module(module: &naga::Module) -> Result<Self, ReflectionError> {
+        let entry_point = module
+            .entry_points
+            .first()
+            .ok_or_else(|| ReflectionError::Parse(String::from("no entry point")))?;
+        let stage = match entry_point.stage {
+            naga::ShaderStage::Vertex => wgpu::ShaderStage::VERTEX,
+            naga::ShaderStage::Fragment => wgpu::ShaderStage::FRAGMENT,
+            naga::ShaderStage::Compute => wgpu::ShaderStage::COMPUTE,
+        };
+
+        // Resources that are declared but never touched don't need a binding.
+        // The frontends add an expression for every global to every function,
+        // so only the validator's analysis knows what the entry point uses. If
+        // it rejects the module, every declared resource needs a binding.
+        let info = naga::valid::Validator::new(
+            naga::valid::ValidationFlags::empty(),
+            naga::valid::Capabilities::all(),
+        )
+        .validate(module)
+        .ok();
+        let used = |handle: naga::Handle<naga::GlobalVariable>| {
+            info.as_ref()
+                .map_or(true, |info| !info.get_entry_point(0)[handle].is_empty())
+        };
+
+        let mut bindings = Vec::new();
+        for (handle, var) in module.global_variables.iter() {
+            let binding = match &var.binding {
+                Some(binding) if used(handle) => binding,
+                _ => continue,
+            };
+            let ty = &module.types[var.ty];
+            let name = var
+                .name
+                .clone()
+                .filter(|n| !n.is_empty())
+                .or_else(|| ty.name.clone())
+                .unwrap_or_else(|| format!("set {} binding {}", binding.group, binding.binding));
+            let (ty, count) = binding_type(module, var, &name)?;
+            bindings.push(ReflectedBinding {
+                group: binding.group,
+                binding: binding.binding,
+                name,
+                ty,
+                count,
+                visibility: stage,
+            });
+        }
+
+        let mut vertex_inputs = Vec::new();
+        if stage == wgpu::ShaderStage::VERTEX {
+            for arg in entry_point.function.arguments.iter() {
+                let name = arg.name.clone().unwrap_or_default();
+                match (&arg.binding, &module.types[arg.ty].inner) {
+                    (Some(binding), inner) => {
+                        if let Some(input) = vertex_input(binding, inner, &name)? {
+                            vertex_inputs.push(input);
+                        }
+                    }
+                    (None, naga::TypeInner::Struct { members, .. }) => {
+                        for member in members {
+                            let name = member.name.clone().unwrap_or_default();
+                            if let Some(binding) = &member.binding {
+                                let inner = &module.types[member.ty].inner;
+                                if let Some(input) = vertex_input(binding, inner, &name)? {
+                                    vertex_inputs.push(input);
+                                }
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            vertex_inputs.sort_by_key(|i| i.location);
+        }
+
+        Ok(Self {
+            stage,
+            bindings,
+            vertex_inputs,
+        })
+    }
+}
+
+/// Combined interface of all the shaders of one pipeline.
+#[derive(Debug, Clone)]
+pub struct PipelineInterface {
+    bindings: BTreeMap<(u32, u32), ReflectedBinding>,
+    pub vertex_inputs: Vec<VertexInput>,
+}
+
+impl PipelineInterface {
+    pub fn new(shaders: &[&ShaderInterface]) -> Result<Self, ReflectionError> {
+        let mut bindings = BTreeMap::<(u32, u32), ReflectedBinding>::new();
+        let mut vertex_inputs = Vec::new();
+        for shader in shaders {
+            for b in shader.bindings.iter() {
+                match bindings.get_mut(&(b.group, b.binding)) {
+                    Some(existing) if existing.ty == b.ty && existing.count == b.count => {
+                        existing.visibility |= b.visibility;
+                    }
+                    Some(_) => {
+                        return Err(ReflectionError::Conflict {
+                            group: b.group,
+                            binding: b.binding,
+                            name: b.name.clone(),
+                        })
+                    }
+                    None => {
+                        bindings.insert((b.group, b.binding), b.clone());
+                    }
+                }
+            }
+            vertex_inputs.extend(shader.vertex_inputs.iter().cloned());
+        }
+        Ok(Self {
+            bindings,
+            vertex_inputs,
+        })
+    }
+
+    /// Number of bind groups, including unused ones in between.
+    pub fn group_count(&self) -> u32 {
+        self.bindings.keys().map(|(g, _)| g + 1).max().unwrap_or(0)
+    }
+
+    /// Checks that `entries` has everything the shaders use from `group`.
+    /// Entries the shaders don't use are fine.
+    pub fn validate_bind_group(
+        &self,
+        group: u32,
+        entries: &[wgpu::BindGroupLayoutEntry],
+    ) -> Result<(), ReflectionError> {
+        for b in self.bindings.values().filter(|b| b.group == group) {
+            let entry = entries
+                .iter()
+                .find(|e| e.binding == b.binding)
+                .ok_or_else(|| ReflectionError::MissingBinding {
+                    group,
+                    binding: b.binding,
+                    name: b.name.clone(),
+                })?;
+            if !binding_types_match(&b.ty, &entry.ty) {
+                return Err(ReflectionError::BindingType {
+                    group,
+                    binding: b.binding,
+                    name: b.name.clone(),
+                    shader: b.ty,
+                    layout: entry.ty,
+                });
+            }
+            if !b.count.matches(entry.count) {
+                return Err(ReflectionError::BindingCount {
+                    group,
+                    binding: b.binding,
+                    name: b.name.clone(),
+                    shader: b.count,
+                    layout: entry.count,
+                });
+            }
+            if !entry.visibility.contains(b.visibility) {
+                return Err(ReflectionError::Visibility {
+                    group,
+                    binding: b.binding,
+                    name: b.name.clone(),
+                    stage: b.visibility - entry.visibility,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that every vertex input is fed by an attribute of a matching
+    /// format. Attributes the shader doesn't read are fine.
+    pub fn validate_vertex_buffers(
+        &self,
+        buffers: &[wgpu::VertexBufferLayout],
+    ) -> Result<(), ReflectionError> {
+        for input in self.vertex_inputs.iter() {
+            let attribute = buffers
+                .iter()
+                .flat_map(|b| b.attributes.iter())
+                .find(|a| a.shader_location == input.location)
+                .ok_or_else(|| ReflectionError::MissingLocation {
+                    location: input.location,
+                    name: input.name.clone(),
+                })?;
+            if format_class(attribute.format) != format_class(input.format) {
+                return Err(ReflectionError::LocationFormat {
+                    location: input.location,
+                    name: input.name.clone(),
+                    shader: input.format,
+                    layout: attribute.format,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Validates a whole pipeline: `groups[i]` are the layout entries of
+    /// bind group `i`.
+    pub fn validate(
+        &self,
+        groups: &[&[wgpu::BindGroupLayoutEntry]],
+        buffers: &[wgpu::VertexBufferLayout],
+    ) -> Result<(), ReflectionError> {
+        for group in 0..self.group_count() {
+            let entries = groups.get(group as usize).copied().unwrap_or(&[]);
+            self.validate_bind_group(group, entries)?;
+        }
+        self.validate_vertex_buffers(buffers)
+    }
+
+    /// Generates the layout entries of `group`, visible to the stages that
+    /// use them.
+    pub fn bind_group_layout_entries(
+        &self,
+        group: u32,
+    ) -> Result<Vec<wgpu::BindGroupLayoutEntry>, ReflectionError> {
+        self.bindings
+            .values()
+            .filter(|b| b.group == group)
+            .map(|b| {
+                let count =
+                    match b.count {
+                        BindingCount::Single => None,
+                        BindingCount::Array(n) => Some(n),
+                        BindingCount::RuntimeArray => return Err(ReflectionError::Unsupported {
+                            name: b.name.clone(),
+                            reason:
+                                "the size of runtime arrays must come from a hand-written layout",
+                        }),
+                    };
+                Ok(wgpu::BindGroupLayoutEntry {
+                    binding: b.binding,
+                    visibility: b.visibility,
+                    ty: b.ty,
+                    count,
+                })
+            })
+            .collect()
+    }
+
+    /// Generates tightly packed attributes for the vertex inputs in
+    /// `locations`, e.g. `0..3` for the per-vertex data of one buffer.
+    pub fn vertex_attributes(&self, locations: Range<u32>) -> Vec<wgpu::VertexAttribute> {
+        let mut offset = 0;
+        self.vertex_inputs
+            .iter()
+            .filter(|i| locations.contains(&i.location))
+            .map(|i| {
+                let attribute = wgpu::VertexAttribute {
+                    format: i.format,
+                    offset,
+                    shader_location: i.location,
+                };
+                offset += i.format.size();
+                attribute
+            })
+            .collect()
+    }
+}
+
+fn binding_type(
+    module: &naga::Module,
+    var: &naga::GlobalVariable,
+    name: &str,
+) -> Result<(wgpu::BindingType, BindingCount), ReflectionError> {
+    let unsupported = |reason| ReflectionError::Unsupported {
+        name: String::from(name),
+        reason,
+    };
+    let mut inner = &module.types[var.ty].inner;
+    let mut count = BindingCount::Single;
+    if let naga::TypeInner::Array { base, size, .. } = inner {
+        // Arrays of buffers are blocks with an array inside, only arrays of
+        // textures and samplers are binding arrays.
+        if var.class == naga::StorageClass::Handle {
+            count = match size {
+                naga::ArraySize::Dynamic => BindingCount::RuntimeArray,
+                naga::ArraySize::Constant(c) => match &module.constants[*c].inner {
+                    naga::ConstantInner::Scalar {
+                        value: naga::ScalarValue::Uint(n),
+                        ..
+                    } => BindingCount::Array(
+                        NonZeroU32::new(*n as u32).ok_or_else(|| unsupported("empty array"))?,
+                    ),
+                    naga::ConstantInner::Scalar {
+                        value: naga::ScalarValue::Sint(n),
+                        ..
+                    } => BindingCount::Array(
+                        NonZeroU32::new(*n as u32).ok_or_else(|| unsupported("empty array"))?,
+                    ),
+                    _ => return Err(unsupported("array size isn't an integer")),
+                },
+            };
+            inner = &module.types[*base].inner;
+        }
+    }
+
+    let ty = match (var.class, inner) {
+        (naga::StorageClass::Uniform, _) => wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        (naga::StorageClass::Storage, _) => wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage {
+                read_only: !var.storage_access.contains(naga::StorageAccess::STORE),
+            },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        (naga::StorageClass::Handle, naga::TypeInner::Sampler { comparison }) => {
+            wgpu::BindingType::Sampler {
+                filtering: true,
+                comparison: *comparison,
+            }
+        }
+        (
+            naga::StorageClass::Handle,
+            naga::TypeInner::Image {
+                dim,
+                arrayed,
+                class,
+            },
+        ) => {
+            let view_dimension = match (dim, arrayed) {
+                (naga::ImageDimension::D1, false) => wgpu::TextureViewDimension::D1,
+                (naga::ImageDimension::D2, false) => wgpu::TextureViewDimension::D2,
+                (naga::ImageDimension::D2, true) => wgpu::TextureViewDimension::D2Array,
+                (naga::ImageDimension::D3, false) => wgpu::TextureViewDimension::D3,
+                (naga::ImageDimension::Cube, false) => wgpu::TextureViewDimension::Cube,
+                (naga::ImageDimension::Cube, true) => wgpu::TextureViewDimension::CubeArray,
+                _ => return Err(unsupported("image dimension has no wgpu equivalent")),
+            };
+            let (sample_type, multisampled) = match class {
+                naga::ImageClass::Sampled { kind, multi } => (
+                    match kind {
+                        naga::ScalarKind::Float => {
+                            wgpu::TextureSampleType::Float { filterable: true }
+                        }
+                        naga::ScalarKind::Sint => wgpu::TextureSampleType::Sint,
+                        naga::ScalarKind::Uint => wgpu::TextureSampleType::Uint,
+                        naga::ScalarKind::Bool => return Err(unsupported("bool texture")),
+                    },
+                    *multi,
+                ),
+                naga::ImageClass::Depth => (wgpu::TextureSampleType::Depth, false),
+                naga::ImageClass::Storage(_) => return Err(unsupported("storage texture")),
+            };
+            wgpu::BindingType::Texture {
+                sample_type,
+                view_dimension,
+                multisampled,
+            }
+        }
+        _ => return Err(unsupported("not a buffer, texture or sampler")),
+    };
+    Ok((ty, count))
+}
+
+fn vertex_input(
+    binding: &naga::Binding,
+    inner: &naga::TypeInner,
+    name: &str,
+) -> Result<Option<VertexInput>, ReflectionError> {
+    let location = match binding {
+        naga::Binding::Location { location, .. } => *location,
+        naga::Binding::BuiltIn(_) => return Ok(None),
+    };
+    let (kind, components) = match inner {
+        naga::TypeInner::Scalar { kind, .. } => (*kind, 1),
+        naga::TypeInner::Vector { size, kind, .. } => (*kind, *size as u32),
+        _ => {
+            return Err(ReflectionError::Unsupported {
+                name: String::from(name),
+                reason: "vertex inputs must be scalars or vectors",
+            })
+        }
+    };
+    use wgpu::VertexFormat::*;
+    let format = match (kind, components) {
+        (naga::ScalarKind::Float, 1) => Float32,
+        (naga::ScalarKind::Float, 2) => Float32x2,
+        (naga::ScalarKind::Float, 3) => Float32x3,
+        (naga::ScalarKind::Float, 4) => Float32x4,
+        (naga::ScalarKind::Uint, 1) => Uint32,
+        (naga::ScalarKind::Uint, 2) => Uint32x2,
+        (naga::ScalarKind::Uint, 3) => Uint32x3,
+        (naga::ScalarKind::Uint, 4) => Uint32x4,
+        (naga::ScalarKind::Sint, 1) => Sint32,
+        (naga::ScalarKind::Sint, 2) => Sint32x2,
+        (naga::ScalarKind::Sint, 3) => Sint32x3,
+        (naga::ScalarKind::Sint, 4) => Sint32x4,
+        _ => {
+            return Err(ReflectionError::Unsupported {
+                name: String::from(name),
+                reason: "vertex input type has no vertex format",
+            })
+        }
+    };
+    Ok(Some(VertexInput {
+        location,
+        name: String::from(name),
+        format,
+    }))
+}
+
+/// Scalar kind and number of components the shader sees for a format.
+fn format_class(format: wgpu::VertexFormat) -> (naga::ScalarKind, u32) {
+    use naga::ScalarKind::*;
+    use wgpu::VertexFormat::*;
+    match format {
+        Uint8x2 | Uint16x2 | Uint32x2 => (Uint, 2),
+        Uint8x4 | Uint16x4 | Uint32x4 => (Uint, 4),
+        Uint32 => (Uint, 1),
+        Uint32x3 => (Uint, 3),
+        Sint8x2 | Sint16x2 | Sint32x2 => (Sint, 2),
+        Sint8x4 | Sint16x4 | Sint32x4 => (Sint, 4),
+        Sint32 => (Sint, 1),
+        Sint32x3 => (Sint, 3),
+        Unorm8x2 | Snorm8x2 | Unorm16x2 | Snorm16x2 | Float16x2 | Float32x2 => (Float, 2),
+        Unorm8x4 | Snorm8x4 | Unorm16x4 | Snorm16x4 | Float16x4 | Float32x4 => (Float, 4),
+        Float32 | Float64 => (Float, 1),
+        Float32x3 | Float64x3 => (Float, 3),
+        Float64x2 => (Float, 2),
+        Float64x4 => (Float, 4),
+    }
+}
+
+/// Compares binding types, ignoring the details the shader can't know about
+/// like filtering or dynamic offsets.
+fn binding_types_match(shader: &wgpu::BindingType, layout: &wgpu::BindingType) -> bool {
+    use wgpu::BindingType::*;
+    match (shader, layout) {
+        (Buffer { ty: a, .. }, Buffer { ty: b, .. }) => a == b,
+        (Sampler { comparison: a, .. }, Sampler { comparison: b, .. }) => a == b,
+        (
+            Texture {
+                sample_type: a_sample,
+                view_dimension: a_dim,
+                multisampled: a_multi,
+            },
+            Texture {
+                sample_type: b_sample,
+                view_dimension: b_dim,
+                multisampled: b_multi,
+            },
+        ) => {
+            let same_sample_type = match (a_sample, b_sample) {
+                (wgpu::TextureSampleType::Float { .. }, wgpu::TextureSampleType::Float { .. }) => {
+                    true
+                }
+                (a, b) => a == b,
+            };
+            same_sample_type && a_dim == b_dim && a_multi == b_multi
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VERTEX: &str = r#"
+[[block]]
+struct Camera {
+    view_proj: mat4x4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> camera: Camera;
+
+[[stage(vertex)]]
+fn main(
+    [[location(0)]] position: vec3<f32>,
+    [[location(1)]] layer: u32,
+) -> [[builtin(position)]] vec4<f32> {
+    return camera.view_proj * vec4<f32>(position, f32(layer));
+}
+"#;
+
+    const FRAGMENT: &str = r#"
+[[group(1), binding(0)]]
+var t_diffuse: texture_2d<f32>;
+[[group(1), binding(1)]]
+var s_diffuse: sampler;
+[[group(1), binding(2)]]
+var t_unused: texture_2d<f32>;
+
+[[stage(fragment)]]
+fn main() -> [[location(0)]] vec4<f32> {
+    return textureSample(t_diffuse, s_diffuse, vec2<f32>(0.5, 0.5));
+}
+"#;
+
+    const CAMERA: [wgpu::BindGroupLayoutEntry; 1] = [wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStage::VERTEX,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }];
+
+    const TEXTURE: [wgpu::BindGroupLayoutEntry; 2] = [
+        wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::Sampler {
+                filtering: true,
+                comparison: false,
+            },
+            count: None,
+        },
+    ];
+
+    fn interface() -> PipelineInterface {
+        let vert = ShaderInterface::from_wgsl(VERTEX).unwrap();
+        let frag = ShaderInterface::from_wgsl(FRAGMENT).unwrap();
+        PipelineInterface::new(&[&vert, &frag]).unwrap()
+    }
+
+    fn attributes(formats: &[(u32, wgpu::VertexFormat)]) -> Vec<wgpu::VertexAttribute> {
+        formats
+            .iter()
+            .map(|&(shader_location, format)| wgpu::VertexAttribute {
+                format,
+                offset: 0,
+                shader_location,
+            })
+            .collect()
+    }
+
+    fn buffer(attributes: &[wgpu::VertexAttribute]) -> wgpu::VertexBufferLayout<'_> {
+        wgpu::VertexBufferLayout {
+            array_stride: 16,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes,
+        }
+    }
+
+    #[test]
+    fn reflects_used_bindings_and_inputs() {
+        let interface = interface();
+        assert_eq!(interface.group_count(), 2);
+        let locations = interface
+            .vertex_inputs
+            .iter()
+            .map(|i| (i.location, i.name.as_str(), i.format))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            locations,
+            vec![
+                (0, "position", wgpu::VertexFormat::Float32x3),
+                (1, "layer", wgpu::VertexFormat::Uint32),
+            ]
+        );
+
+        let vertex = attributes(&[
+            (0, wgpu::VertexFormat::Float32x3),
+            (1, wgpu::VertexFormat::Uint32),
+            // Not read by the shader.
+            (2, wgpu::VertexFormat::Float32x2),
+        ]);
+        // `t_unused` isn't in the layout, but the shader never samples it.
+        interface
+            .validate(&[&CAMERA, &TEXTURE], &[buffer(&vertex)])
+            .unwrap();
+    }
+
+    #[test]
+    fn missing_binding() {
+        let vertex = attributes(&[
+            (0, wgpu::VertexFormat::Float32x3),
+            (1, wgpu::VertexFormat::Uint32),
+        ]);
+        let error = interface()
+            .validate(&[&CAMERA, &TEXTURE[..1]], &[buffer(&vertex)])
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            ReflectionError::MissingBinding { group: 1, binding: 1, ref name } if name == "s_diffuse"
+        ));
+        let error = interface()
+            .validate(&[&CAMERA], &[buffer(&vertex)])
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            ReflectionError::MissingBinding {
+                group: 1,
+                binding: 0,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn wrong_binding_type() {
+        let mut texture = TEXTURE;
+        texture[0].ty = wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Uint,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        };
+        let error = interface().validate_bind_group(1, &texture).unwrap_err();
+        assert!(matches!(
+            error,
+            ReflectionError::BindingType { group: 1, binding: 0, ref name, .. } if name == "t_diffuse"
+        ));
+
+        let mut camera = CAMERA;
+        camera[0].ty = TEXTURE[1].ty;
+        let error = interface().validate_bind_group(0, &camera).unwrap_err();
+        assert!(matches!(
+            error,
+            ReflectionError::BindingType { group: 0, binding: 0, ref name, .. } if name == "camera"
+        ));
+    }
+
+    #[test]
+    fn binding_not_visible_to_its_stage() {
+        let mut camera = CAMERA;
+        camera[0].visibility = wgpu::ShaderStage::FRAGMENT;
+        let error = interface().validate_bind_group(0, &camera).unwrap_err();
+        assert!(matches!(
+            error,
+            ReflectionError::Visibility {
+                stage: wgpu::ShaderStage::VERTEX,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn mismatched_vertex_format() {
+        // Normalized formats are floats in the shader.
+        let vertex = attributes(&[
+            (0, wgpu::VertexFormat::Float32x3),
+            (1, wgpu::VertexFormat::Unorm8x4),
+        ]);
+        let error = interface()
+            .validate_vertex_buffers(&[buffer(&vertex)])
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            ReflectionError::LocationFormat {
+                location: 1,
+                shader: wgpu::VertexFormat::Uint32,
+                layout: wgpu::VertexFormat::Unorm8x4,
+                ..
+            }
+        ));
+
+        let vertex = attributes(&[
+            (0, wgpu::VertexFormat::Float32x2),
+            (1, wgpu::VertexFormat::Uint32),
+        ]);
+        assert!(matches!(
+            interface().validate_vertex_buffers(&[buffer(&vertex)]),
+            Err(ReflectionError::LocationFormat { location: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn missing_location() {
+        // The attributes may be split over several buffers.
+        let position = attributes(&[(0, wgpu::VertexFormat::Float32x3)]);
+        let layer = attributes(&[(1, wgpu::VertexFormat::Uint32)]);
+        interface()
+            .validate_vertex_buffers(&[buffer(&position), buffer(&layer)])
+            .unwrap();
+        let error = interface()
+            .validate_vertex_buffers(&[buffer(&position)])
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            ReflectionError::MissingLocation { location: 1, ref name } if name == "layer"
+        ));
+    }
+
+    #[test]
+    fn generates_the_layouts() {
+        let interface = interface();
+        assert_eq!(interface.bind_group_layout_entries(0).unwrap(), CAMERA);
+        // `t_unused` isn't part of the layout.
+        assert_eq!(interface.bind_group_layout_entries(1).unwrap(), TEXTURE);
+        assert!(interface.bind_group_layout_entries(2).unwrap().is_empty());
+
+        let vertex = attributes(&[
+            (0, wgpu::VertexFormat::Float32x3),
+            (1, wgpu::VertexFormat::Uint32),
+        ]);
+        let generated = interface.vertex_attributes(0..2);
+        assert_eq!(generated[0], vertex[0]);
+        assert_eq!(
+            generated[1],
+            wgpu::VertexAttribute {
+                offset: 12,
+                ..vertex[1]
+            }
+        );
+        assert_eq!(interface.vertex_attributes(1..2)[0], vertex[1]);
+        interface
+            .validate_vertex_buffers(&[buffer(&generated)])
+            .unwrap();
+    }
+
+    #[test]
+    fn stages_must_agree_on_shared_bindings() {
+        let vert = ShaderInterface::from_wgsl(VERTEX).unwrap();
+        let frag = ShaderInterface::from_wgsl(
+            r#"
+[[group(0), binding(0)]]
+var s_camera: sampler;
+[[group(1), binding(0)]]
+var t_diffuse: texture_2d<f32>;
+
+[[stage(fragment)]]
+fn main() -> [[location(0)]] vec4<f32> {
+    return textureSample(t_diffuse, s_camera, vec2<f32>(0.5, 0.5));
+}
+"#,
+        );
+        let frag = match frag {
+            Ok(frag) => frag,
+            Err(e) => panic!("{}", e),
+        };
+        assert!(matches!(
+            PipelineInterface::new(&[&vert, &frag]),
+            Err(ReflectionError::Conflict {
+                group: 0,
+                binding: 0,
+                ..
+            })
+        ));
+    }
+}
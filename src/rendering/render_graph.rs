@@ -0,0 +1,511 @@
+//! Render graph. Passes declare the textures they read and write, and
+//! [`RenderGraph::compile`] works out the order they run in from that. Passes
+//! whose output nobody uses are left out, and transient textures that are
+//! never in use at the same time share their memory.
+//!
+//! Transient textures are owned by the graph and sized relative to the
+//! window, [`RenderGraph::resize`] recreates them. Imported textures, like the
+//! swap chain image, are handed to [`RenderGraph::execute`] every frame.
+//!
+//! Compiling doesn't touch the GPU, so the ordering can be tested without one.
+
+use anyhow::{bail, Result};
+use std::collections::BTreeSet;
+
+/// A texture of the graph, returned when it's added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ResourceId(usize);
+
+/// Size of a transient texture.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextureSize {
+    Window,
+    /// A fraction of the window size, at least one pixel.
+    Scaled(f32),
+    Fixed(u32, u32),
+}
+
+impl TextureSize {
+    pub fn extent(self, (width, height): (u32, u32)) -> (u32, u32) {
+        match self {
+            TextureSize::Window => (width, height),
+            TextureSize::Scaled(scale) => (
+                ((width as f32 * scale) as u32).max(1),
+                ((height as f32 * scale) as u32).max(1),
+            ),
+            TextureSize::Fixed(width, height) => (width, height),
+        }
+    }
+}
+
+/// A transient texture. Its usage comes from how the passes use it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextureDesc {
+    pub label: &'static str,
+    pub format: wgpu::TextureFormat,
+    pub size: TextureSize,
+    pub sample_count: u32,
+}
+
+impl TextureDesc {
+    /// A window sized texture with one sample.
+    pub fn new(label: &'static str, format: wgpu::TextureFormat) -> Self {
+        Self {
+            label,
+            format,
+            size: TextureSize::Window,
+            sample_count: 1,
+        }
+    }
+
+    /// Whether both can be the same physical texture, the label doesn't
+    /// matter.
+    fn can_alias(&self, other: &TextureDesc) -> bool {
+        self.format == other.format
+            && self.size == other.size
+            && self.sample_count == other.sample_count
+    }
+}
+
+enum Resource {
+    Transient(TextureDesc),
+    /// Index into the views given to [`RenderGraph::execute`].
+    Imported(&'static str, usize),
+}
+
+/// Records the commands of a pass. It begins its own render passes, with
+/// the views from [`PassResources`].
+type PassFn<T> = Box<dyn Fn(&mut T, &mut wgpu::CommandEncoder, &PassResources)>;
+
+struct Pass<T> {
+    name: &'static str,
+    reads: Vec<ResourceId>,
+    writes: Vec<ResourceId>,
+    run: PassFn<T>,
+}
+
+/// The result of [`RenderGraph::compile`].
+struct Schedule {
+    /// Indices of the passes that run, in order.
+    order: Vec<usize>,
+    /// The physical texture of each resource, `None` for imported and unused
+    /// ones.
+    physical: Vec<Option<usize>>,
+    textures: Vec<(TextureDesc, wgpu::TextureUsage)>,
+}
+
+/// Passes that run on a `T`, usually the renderer that owns the pipelines
+/// and buffers they draw with.
+pub struct RenderGraph<T> {
+    resources: Vec<Resource>,
+    imports: usize,
+    passes: Vec<Pass<T>>,
+    schedule: Option<Schedule>,
+    /// The physical textures of the schedule, created by `resize`.
+    textures: Vec<(wgpu::Texture, wgpu::TextureView)>,
+}
+
+impl<T> Default for RenderGraph<T> {
+    fn default() -> Self {
+        Self {
+            resources: Vec::new(),
+            imports: 0,
+            passes: Vec::new(),
+            schedule: None,
+            textures: Vec::new(),
+        }
+    }
+}
+
+impl<T> RenderGraph<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a texture that only lives during the frame.
+    pub fn add_texture(&mut self, desc: TextureDesc) -> ResourceId {
+        self.resources.push(Resource::Transient(desc));
+        ResourceId(self.resources.len() - 1)
+    }
+
+    /// Adds a texture that's given to every [`execute`](Self::execute), in
+    /// the order they were imported. Passes writing to it always run.
+    pub fn import(&mut self, name: &'static str) -> ResourceId {
+        self.resources.push(Resource::Imported(name, self.imports));
+        self.imports += 1;
+        ResourceId(self.resources.len() - 1)
+    }
+
+    /// Adds a pass. Passes that write the same texture run in the order
+    /// they're added, and before any pass that only reads it.
+    pub fn add_pass<F>(
+        &mut self,
+        name: &'static str,
+        reads: &[ResourceId],
+        writes: &[ResourceId],
+        run: F,
+    ) where
+        F: Fn(&mut T, &mut wgpu::CommandEncoder, &PassResources) + 'static,
+    {
+        self.passes.push(Pass {
+            name,
+            reads: reads.to_vec(),
+            writes: writes.to_vec(),
+            run: Box::new(run),
+        });
+        self.schedule = None;
+    }
+
+    fn resource_name(&self, id: ResourceId) -> &'static str {
+        match self.resources[id.0] {
+            Resource::Transient(desc) => desc.label,
+            Resource::Imported(name, _) => name,
+        }
+    }
+
+    /// Orders the passes, leaves out the ones nothing depends on and assigns
+    /// the transient textures to physical ones. Fails if a pass reads a
+    /// texture nobody writes, or passes depend on each other in a cycle.
+    /// [`resize`](Self::resize) has to be called after it.
+    pub fn compile(&mut self) -> Result<()> {
+        let mut writers = vec![Vec::new(); self.resources.len()];
+        for (i, pass) in self.passes.iter().enumerate() {
+            for id in &pass.writes {
+                writers[id.0].push(i);
+            }
+        }
+        for pass in &self.passes {
+            for &id in &pass.reads {
+                let transient = matches!(self.resources[id.0], Resource::Transient(_));
+                if transient && writers[id.0].is_empty() {
+                    bail!(
+                        "The {} pass reads {}, which no pass writes",
+                        pass.name,
+                        self.resource_name(id)
+                    );
+                }
+            }
+        }
+
+        // Passes that write an imported texture are needed, and so is every
+        // pass that writes something a needed pass uses.
+        let mut needed = vec![false; self.passes.len()];
+        let mut stack = self
+            .passes
+            .iter()
+            .enumerate()
+            .filter(|(_, pass)| {
+                pass.writes
+                    .iter()
+                    .any(|id| matches!(self.resources[id.0], Resource::Imported(..)))
+            })
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        while let Some(i) = stack.pop() {
+            if needed[i] {
+                continue;
+            }
+            needed[i] = true;
+            let pass = &self.passes[i];
+            for id in pass.reads.iter().chain(&pass.writes) {
+                stack.extend(writers[id.0].iter().filter(|&&w| !needed[w]));
+            }
+        }
+
+        // Writers of a texture run one after another, readers after all of
+        // them.
+        let mut dependencies = vec![BTreeSet::new(); self.passes.len()];
+        for (id, writers) in writers.iter().enumerate() {
+            let writers = writers
+                .iter()
+                .copied()
+                .filter(|&w| needed[w])
+                .collect::<Vec<_>>();
+            for pair in writers.windows(2) {
+                dependencies[pair[1]].insert(pair[0]);
+            }
+            for (i, pass) in self.passes.iter().enumerate() {
+                let reads = pass.reads.iter().any(|r| r.0 == id);
+                if needed[i] && reads && !writers.contains(&i) {
+                    dependencies[i].extend(writers.iter().copied());
+                }
+            }
+        }
+        let mut order = Vec::new();
+        let mut done = vec![false; self.passes.len()];
+        while order.len() < needed.iter().filter(|&&n| n).count() {
+            // The first pass that's ready, to keep the order they were added
+            // in where it doesn't matter.
+            let next = (0..self.passes.len())
+                .find(|&i| needed[i] && !done[i] && dependencies[i].iter().all(|&d| done[d]));
+            match next {
+                Some(i) => {
+                    done[i] = true;
+                    order.push(i);
+                }
+                None => {
+                    let cycle = (0..self.passes.len())
+                        .filter(|&i| needed[i] && !done[i])
+                        .map(|i| self.passes[i].name)
+                        .collect::<Vec<_>>();
+                    bail!("The passes {} depend on each other", cycle.join(", "));
+                }
+            }
+        }
+
+        // When each transient is first and last used, and how.
+        let mut uses = vec![None; self.resources.len()];
+        for (position, &i) in order.iter().enumerate() {
+            let pass = &self.passes[i];
+            let accesses = pass
+                .reads
+                .iter()
+                .map(|id| (id, wgpu::TextureUsage::SAMPLED))
+                .chain(
+                    pass.writes
+                        .iter()
+                        .map(|id| (id, wgpu::TextureUsage::RENDER_ATTACHMENT)),
+                );
+            for (id, usage) in accesses {
+                let (first, last, all_usage) =
+                    uses[id.0].get_or_insert((position, position, wgpu::TextureUsage::empty()));
+                *first = position.min(*first);
+                *last = position.max(*last);
+                *all_usage |= usage;
+            }
+        }
+        let mut physical = vec![None; self.resources.len()];
+        let mut textures: Vec<(TextureDesc, wgpu::TextureUsage)> = Vec::new();
+        // The last position each physical texture is used at.
+        let mut busy_until: Vec<usize> = Vec::new();
+        let mut transients = self
+            .resources
+            .iter()
+            .enumerate()
+            .filter_map(|(id, resource)| match (resource, uses[id]) {
+                (Resource::Transient(desc), Some(use_)) => Some((id, *desc, use_)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        transients.sort_by_key(|&(_, _, (first, _, _))| first);
+        for (id, desc, (first, last, usage)) in transients {
+            let free =
+                textures
+                    .iter()
+                    .zip(&busy_until)
+                    .position(|(&(other, other_usage), &until)| {
+                        other.can_alias(&desc) && other_usage == usage && until < first
+                    });
+            let index = match free {
+                Some(index) => index,
+                None => {
+                    textures.push((desc, usage));
+                    busy_until.push(0);
+                    textures.len() - 1
+                }
+            };
+            busy_until[index] = last;
+            physical[id] = Some(index);
+        }
+
+        self.schedule = Some(Schedule {
+            order,
+            physical,
+            textures,
+        });
+        self.textures.clear();
+        Ok(())
+    }
+
+    fn schedule(&self) -> &Schedule {
+        self.schedule
+            .as_ref()
+            .expect("The render graph wasn't compiled")
+    }
+
+    /// Names of the passes that run, in order.
+    pub fn pass_order(&self) -> Vec<&'static str> {
+        self.schedule()
+            .order
+            .iter()
+            .map(|&i| self.passes[i].name)
+            .collect()
+    }
+
+    /// How many textures the transients need after aliasing.
+    pub fn physical_texture_count(&self) -> usize {
+        self.schedule().textures.len()
+    }
+
+    /// (Re)creates the transient textures for a window of `width` by
+    /// `height`.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        let textures = self
+            .schedule()
+            .textures
+            .iter()
+            .map(|(desc, usage)| {
+                let (width, height) = desc.size.extent((width, height));
+                let texture = device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some(desc.label),
+                    size: wgpu::Extent3d {
+                        width,
+                        height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: desc.sample_count,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: desc.format,
+                    usage: *usage,
+                });
+                let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+                (texture, view)
+            })
+            .collect();
+        self.textures = textures;
+    }
+
+    /// Records the passes in order. `imports` are the views of the imported
+    /// textures, in the order they were imported.
+    pub fn execute(
+        &self,
+        target: &mut T,
+        encoder: &mut wgpu::CommandEncoder,
+        imports: &[&wgpu::TextureView],
+    ) {
+        assert_eq!(imports.len(), self.imports, "Wrong number of imports");
+        let schedule = self.schedule();
+        assert_eq!(
+            self.textures.len(),
+            schedule.textures.len(),
+            "The render graph wasn't resized after compiling"
+        );
+        let resources = PassResources {
+            resources: &self.resources,
+            physical: &schedule.physical,
+            textures: &self.textures,
+            imports,
+        };
+        for &i in &schedule.order {
+            (self.passes[i].run)(target, encoder, &resources);
+        }
+    }
+}
+
+/// The views of the graph's textures, for the passes.
+pub struct PassResources<'a> {
+    resources: &'a [Resource],
+    physical: &'a [Option<usize>],
+    textures: &'a [(wgpu::Texture, wgpu::TextureView)],
+    imports: &'a [&'a wgpu::TextureView],
+}
+
+impl<'a> PassResources<'a> {
+    pub fn view(&self, id: ResourceId) -> &'a wgpu::TextureView {
+        match self.resources[id.0] {
+            Resource::Transient(desc) => {
+                let index = self.physical[id.0]
+                    .unwrap_or_else(|| panic!("{} isn't used by the passes that run", desc.label));
+                &self.textures[index].1
+            }
+            Resource::Imported(_, index) => self.imports[index],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLOR: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+    fn pass(
+        graph: &mut RenderGraph<()>,
+        name: &'static str,
+        reads: &[ResourceId],
+        writes: &[ResourceId],
+    ) {
+        graph.add_pass(name, reads, writes, |_, _, _| {});
+    }
+
+    #[test]
+    fn orders_readers_after_writers() {
+        let mut graph = RenderGraph::new();
+        let backbuffer = graph.import("swap chain");
+        let hdr = graph.add_texture(TextureDesc::new("hdr", COLOR));
+        pass(&mut graph, "tonemap", &[hdr], &[backbuffer]);
+        pass(&mut graph, "main", &[], &[hdr]);
+        pass(&mut graph, "translucent", &[], &[hdr]);
+        pass(&mut graph, "overlay", &[], &[backbuffer]);
+        graph.compile().unwrap();
+
+        assert_eq!(
+            graph.pass_order(),
+            ["main", "translucent", "tonemap", "overlay"]
+        );
+    }
+
+    #[test]
+    fn leaves_out_unused_passes() {
+        let mut graph = RenderGraph::new();
+        let backbuffer = graph.import("swap chain");
+        let unused = graph.add_texture(TextureDesc::new("unused", COLOR));
+        pass(&mut graph, "main", &[], &[backbuffer]);
+        pass(&mut graph, "nobody reads this", &[], &[unused]);
+        graph.compile().unwrap();
+
+        assert_eq!(graph.pass_order(), ["main"]);
+        assert_eq!(graph.physical_texture_count(), 0);
+    }
+
+    #[test]
+    fn aliases_textures_that_are_not_used_together() {
+        let mut graph = RenderGraph::new();
+        let backbuffer = graph.import("swap chain");
+        let a = graph.add_texture(TextureDesc::new("a", COLOR));
+        let b = graph.add_texture(TextureDesc::new("b", COLOR));
+        let c = graph.add_texture(TextureDesc::new("c", COLOR));
+        let half = graph.add_texture(TextureDesc {
+            size: TextureSize::Scaled(0.5),
+            ..TextureDesc::new("half", COLOR)
+        });
+        pass(&mut graph, "1", &[], &[a]);
+        pass(&mut graph, "2", &[a], &[b]);
+        // `a` is free again, `c` can use its texture.
+        pass(&mut graph, "3", &[b], &[c]);
+        pass(&mut graph, "4", &[c], &[half]);
+        pass(&mut graph, "5", &[half], &[backbuffer]);
+        graph.compile().unwrap();
+
+        assert_eq!(graph.pass_order(), ["1", "2", "3", "4", "5"]);
+        assert_eq!(graph.physical_texture_count(), 3);
+    }
+
+    #[test]
+    fn reports_cycles_and_missing_writers() {
+        let mut graph = RenderGraph::new();
+        let backbuffer = graph.import("swap chain");
+        let a = graph.add_texture(TextureDesc::new("a", COLOR));
+        let b = graph.add_texture(TextureDesc::new("b", COLOR));
+        pass(&mut graph, "x", &[b], &[a, backbuffer]);
+        pass(&mut graph, "y", &[a], &[b]);
+        let error = graph.compile().unwrap_err().to_string();
+        assert_eq!(error, "The passes x, y depend on each other");
+
+        let mut graph = RenderGraph::new();
+        let backbuffer = graph.import("swap chain");
+        let a = graph.add_texture(TextureDesc::new("a", COLOR));
+        pass(&mut graph, "x", &[a], &[backbuffer]);
+        let error = graph.compile().unwrap_err().to_string();
+        assert_eq!(error, "The x pass reads a, which no pass writes");
+    }
+
+    #[test]
+    fn sizes_relative_to_the_window() {
+        assert_eq!(TextureSize::Window.extent((800, 600)), (800, 600));
+        assert_eq!(TextureSize::Scaled(0.5).extent((801, 600)), (400, 300));
+        assert_eq!(TextureSize::Scaled(0.5).extent((1, 1)), (1, 1));
+        assert_eq!(TextureSize::Fixed(64, 32).extent((800, 600)), (64, 32));
+    }
+}
@@ -2,7 +2,9 @@ use crate::texture::Texture;
 use crate::rendering::graphics::Graphics;
 use crate::rendering::uniforms;
 use crate::rendering::pipeline::Pipeline;
+use crate::render_pipeline_tools::PipelineVariant;
 use crate::rendering::object::DrawObject;
+use crate::rendering::render_graph::{RenderGraph, TextureDesc};
 use crate::modeling::vertex_index::{Vertex, VertexLayout};
 use crate::modeling::instance::InstanceRaw;
 
@@ -10,68 +12,93 @@ pub const SAMPLED_TEXTURES_COUNT: u32 = 2;
 
 pub struct Renderer {
     pipelines: Vec<Pipeline>,
-    depth_texture: Texture,
+    graph: RenderGraph<Vec<Pipeline>>,
 }
 
 impl Renderer {
     pub fn new(graphics: &Graphics) -> Self {
-        let depth_texture =
-            Texture::create_depth_texture(&graphics.device, &graphics.sc_desc, "depth texture");
+        let samples = graphics.msaa_samples;
+        let mut graph = RenderGraph::new();
+        let frame = graph.import("swap chain");
+        let depth = graph.add_texture(TextureDesc {
+            sample_count: samples,
+            ..TextureDesc::new("depth texture", Texture::DEPTH_FORMAT)
+        });
+        if samples > 1 {
+            let color = graph.add_texture(TextureDesc {
+                sample_count: samples,
+                ..TextureDesc::new("multisampled color", graphics.sc_desc.format)
+            });
+            graph.add_pass(
+                "main",
+                &[],
+                &[color, depth, frame],
+                move |pipelines: &mut Vec<Pipeline>, encoder, resources| {
+                    main_pass(
+                        pipelines,
+                        encoder,
+                        resources.view(color),
+                        Some(resources.view(frame)),
+                        resources.view(depth),
+                    )
+                },
+            );
+        } else {
+            graph.add_pass(
+                "main",
+                &[],
+                &[frame, depth],
+                move |pipelines: &mut Vec<Pipeline>, encoder, resources| {
+                    main_pass(
+                        pipelines,
+                        encoder,
+                        resources.view(frame),
+                        None,
+                        resources.view(depth),
+                    )
+                },
+            );
+        }
+        graph
+            .compile()
+            .expect("The main pass writes to the swap chain");
+        graph.resize(
+            &graphics.device,
+            graphics.sc_desc.width,
+            graphics.sc_desc.height,
+        );
         Self {
             pipelines: Vec::new(),
-            depth_texture,
+            graph,
         }
     }
 
-    pub fn render(&self, graphics: &Graphics) -> Result<(), wgpu::SwapChainError> {
+    pub fn resize(&mut self, graphics: &Graphics) {
+        self.graph.resize(
+            &graphics.device,
+            graphics.sc_desc.width,
+            graphics.sc_desc.height,
+        );
+    }
+
+    pub fn render(&mut self, graphics: &Graphics) -> Result<(), wgpu::SwapChainError> {
         let frame = graphics.swap_chain.get_current_frame()?.output;
         let mut encoder = graphics
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("render command encoder"),
             });
-        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("main render pass"),
-            color_attachments: &[wgpu::RenderPassColorAttachment {
-                view: &frame.view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color {
-                        r: 0.1,
-                        g: 0.2,
-                        b: 0.3,
-                        a: 1.0,
-                    }),
-                    store: true,
-                },
-            }],
-            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                view: &self.depth_texture.view,
-                depth_ops: Some(wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(1.0),
-                    store: true,
-                }),
-                stencil_ops: None,
-            }),
-        });
-
-        for p in self.pipelines {
-            render_pass.set_pipeline(&p.render_pipeline);
-            for o in p.objects {
-                use crate::object::DrawObject;
-                render_pass.draw_object(&o);
-            }
-        }
-
-        drop(render_pass);
+        self.graph
+            .execute(&mut self.pipelines, &mut encoder, &[&frame.view]);
+        graphics.queue.submit(Some(encoder.finish()));
 
         Ok(())
     }
 
-    fn add_primary_pipeline(&mut self, graphics: &Graphics) {
-        let shader_dir = std::path::Path::new(env!("OUT_DIR")).join("src/shaders");
-        let v_path = shader_dir.join("shader.vert");
-        let f_path = shader_dir.join("shader.frag");
+    fn add_primary_pipeline(&mut self, graphics: &Graphics) -> anyhow::Result<()> {
+        let shader_dir = std::path::Path::new(env!("OUT_DIR")).join("shaders");
+        let v_path = shader_dir.join("shader.vert.spv");
+        let f_path = shader_dir.join("shader.frag.spv");
 
         let bind_group_layout_desc = vec![
             uniforms::MATRIX_UNIFORM_LAYOUT_DESC,
@@ -91,11 +118,53 @@ impl Renderer {
             Some(Texture::DEPTH_FORMAT),
             bind_group_layouts,
             vertex_buffer_layouts,
-        );
+            PipelineVariant::default(),
+        )?;
         self.pipelines.push(main_pipeline);
+        Ok(())
     }
 
     pub fn add_custom_pipeline(&mut self, pipeline: Pipeline) {
         self.pipelines.push(pipeline);
     }
 }
+
+fn main_pass(
+    pipelines: &[Pipeline],
+    encoder: &mut wgpu::CommandEncoder,
+    target: &wgpu::TextureView,
+    resolve_target: Option<&wgpu::TextureView>,
+    depth: &wgpu::TextureView,
+) {
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("main render pass"),
+        color_attachments: &[wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color {
+                    r: 0.1,
+                    g: 0.2,
+                    b: 0.3,
+                    a: 1.0,
+                }),
+                store: true,
+            },
+        }],
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+            view: depth,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(1.0),
+                store: true,
+            }),
+            stencil_ops: None,
+        }),
+    });
+
+    for p in pipelines {
+        render_pass.set_pipeline(&p.render_pipeline);
+        for o in &p.objects {
+            render_pass.draw_object(o);
+        }
+    }
+}
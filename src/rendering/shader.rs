@@ -0,0 +1,113 @@
+use crate::rendering::preprocessor::Preprocessor;
+use crate::rendering::reflection::ShaderInterface;
+use anyhow::*;
+use std::path::Path;
+
+/// First word of every SPIR-V module.
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+/// Shader code that is ready to be handed to wgpu.
+pub enum ShaderCode {
+    SpirV(Vec<u8>),
+    Wgsl(String),
+}
+
+impl ShaderCode {
+    /// Reads a shader from disk. The language is picked by the extension:
+    /// `.spv` is SPIR-V, `.wgsl` is WGSL and `.vert`, `.frag` and `.comp` are
+    /// GLSL, which gets compiled to SPIR-V. Text sources are run through
+    /// `preprocessor` first.
+    pub fn load<P: AsRef<Path>>(path: P, preprocessor: &Preprocessor) -> Result<Self> {
+        let path = path.as_ref();
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        let kind = match extension {
+            "spv" => {
+                let bytes = std::fs::read(path)
+                    .with_context(|| format!("Failed to read shader {:?}", path))?;
+                validate_spirv(&bytes).with_context(|| format!("{:?} isn't SPIR-V", path))?;
+                return Ok(ShaderCode::SpirV(bytes));
+            }
+            "wgsl" => {
+                let code = preprocessor.process_file(path)?;
+                if let Err(e) = naga::front::wgsl::parse_str(&code.source) {
+                    bail!(
+                        "Failed to parse {:?}:\n{}",
+                        path,
+                        e.emit_to_string(&code.source)
+                    );
+                }
+                return Ok(ShaderCode::Wgsl(code.source));
+            }
+            "vert" => shaderc::ShaderKind::Vertex,
+            "frag" => shaderc::ShaderKind::Fragment,
+            "comp" => shaderc::ShaderKind::Compute,
+            _ => bail!("Unknown shader type {:?}", path),
+        };
+
+        let code = preprocessor.process_file(path)?;
+        let name = path
+            .file_name()
+            .and_then(|f| f.to_str())
+            .unwrap_or("shader");
+        let mut compiler = shaderc::Compiler::new().context("Failed to create shader compiler")?;
+        match compiler.compile_into_spirv(&code.source, kind, name, "main", None) {
+            Ok(artifact) => {
+                if artifact.get_num_warnings() > 0 {
+                    log::warn!(
+                        "{}",
+                        code.remap_diagnostics(name, &artifact.get_warning_messages())
+                    );
+                }
+                Ok(ShaderCode::SpirV(artifact.as_binary_u8().to_vec()))
+            }
+            Err(shaderc::Error::CompilationError(_, message)) => bail!(
+                "Failed to compile {:?}:\n{}",
+                path,
+                code.remap_diagnostics(name, &message)
+            ),
+            Err(e) => Err(e).with_context(|| format!("Failed to compile {:?}", path)),
+        }
+    }
+
+    /// Resources and vertex inputs the shader uses.
+    pub fn reflect(&self) -> Result<ShaderInterface> {
+        let interface = match self {
+            ShaderCode::SpirV(bytes) => ShaderInterface::from_spirv(bytes)?,
+            ShaderCode::Wgsl(code) => ShaderInterface::from_wgsl(code)?,
+        };
+        Ok(interface)
+    }
+
+    pub fn create_module(&self, device: &wgpu::Device, label: &str) -> wgpu::ShaderModule {
+        let source = match self {
+            ShaderCode::SpirV(bytes) => wgpu::util::make_spirv(bytes),
+            ShaderCode::Wgsl(code) => wgpu::ShaderSource::Wgsl(code.as_str().into()),
+        };
+        device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some(label),
+            source,
+            flags: wgpu::ShaderFlags::VALIDATION,
+        })
+    }
+}
+
+/// Loads a shader with [`ShaderCode::load`] and creates its module.
+pub fn load_shader<P: AsRef<Path>>(
+    device: &wgpu::Device,
+    path: P,
+    preprocessor: &Preprocessor,
+) -> Result<wgpu::ShaderModule> {
+    let code = ShaderCode::load(path.as_ref(), preprocessor)?;
+    Ok(code.create_module(device, &path.as_ref().to_string_lossy()))
+}
+
+fn validate_spirv(bytes: &[u8]) -> Result<()> {
+    if bytes.len() < 4 || bytes.len() % 4 != 0 {
+        bail!("Size of {} bytes isn't a multiple of 4", bytes.len());
+    }
+    let magic = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    if magic != SPIRV_MAGIC {
+        bail!("Wrong magic number {:#010x}", magic);
+    }
+    Ok(())
+}
@@ -1,20 +1,12 @@
 use crate::renderer;
 use std::num::NonZeroU32;
 use crate::rendering::renderer;
+use crate::uniform_matrix::MatrixUniform;
 
 pub const MATRIX_UNIFORM_LAYOUT_DESC: &'a wgpu::BindGroupLayoutDescriptor<'a> =
     &wgpu::BindGroupLayoutDescriptor {
         label: Some("MATRIX_UNIFORM_LAYOUT_DESC"),
-        entries: &[wgpu::BindGroupLayoutEntry {
-            binding: 0,
-            visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
-            ty: wgpu::BindingType::Buffer {
-                ty: wgpu::BufferBindingType::Uniform,
-                has_dynamic_offset: false,
-                min_binding_size: None,
-            },
-            count: None,
-        }],
+        entries: &MatrixUniform::BIND_GROUP_LAYOUT_ENTRIES,
     };
 
 pub const SAMPLED_TEXTURE_AND_SAMPLER_LAYOUT_DESC: &'a wgpu::BindGroupLayoutDescriptor<'a> =
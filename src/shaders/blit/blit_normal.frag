@@ -0,0 +1,16 @@
+#version 450
+// Like blit.frag, but for normal maps. The average of several unit normals is
+// shorter than one, so it's normalised again.
+layout(set = 0, binding = 0) uniform texture2D u_texture;
+layout(set = 0, binding = 1) uniform sampler u_sampler;
+
+layout(location = 0) in vec2 v_uv;
+layout(location = 0) out vec4 out_color;
+
+void main() {
+  vec4 texel = texture(sampler2D(u_texture, u_sampler), v_uv);
+  vec3 normal = texel.xyz * 2.0 - 1.0;
+  // Opposite normals can cancel out, then fall back to one pointing straight up.
+  normal = dot(normal, normal) > 1e-6 ? normalize(normal) : vec3(0.0, 0.0, 1.0);
+  out_color = vec4(normal * 0.5 + 0.5, texel.a);
+}
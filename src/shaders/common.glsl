@@ -0,0 +1,62 @@
+// Uniform blocks shared between shaders.
+//
+// Define LIGHT_SET before including this file to also declare the light,
+// bound to that descriptor set, and FOG_SET for the fog. The fog is at
+// binding FOG_BINDING of its set, 0 unless it's defined as well.
+
+// Values of debug_view, the same as the order of `DebugView` in debug_view.rs.
+#define DEBUG_VIEW_SHADED 0u
+#define DEBUG_VIEW_WIREFRAME 1u
+#define DEBUG_VIEW_NORMALS 2u
+#define DEBUG_VIEW_TEXTURE_LAYERS 3u
+#define DEBUG_VIEW_CHUNK_BORDERS 4u
+#define DEBUG_VIEW_LIGHTING 5u
+#define DEBUG_VIEW_OVERDRAW 6u
+
+layout(set = 0, binding = 0) uniform matrixUniform {
+    mat4 proj_view_model_matrix;
+    vec3 view_pos;
+    // Fills the last 4 bytes of view_pos, see `uniform_matrix::Data`.
+    uint debug_view;
+};
+
+#ifdef LIGHT_SET
+layout(set = LIGHT_SET, binding = 0) uniform Light {
+    vec3 light_pos;
+    float light_ambient;
+    vec3 light_color;
+};
+#endif
+
+#ifdef FOG_SET
+// Values of fog_mode, the same as the order of `FogMode` in fog.rs.
+#define FOG_OFF 0u
+#define FOG_LINEAR 1u
+#define FOG_EXPONENTIAL 2u
+
+#ifndef FOG_BINDING
+#define FOG_BINDING 0
+#endif
+
+layout(set = FOG_SET, binding = FOG_BINDING) uniform Fog {
+    vec3 fog_color;
+    uint fog_mode;
+    float fog_start;
+    float fog_end;
+    float fog_density;
+};
+
+// Blends color toward the fog color, by how far away it is.
+vec3 apply_fog(vec3 color, float distance) {
+    float amount = 0.0;
+    switch (fog_mode) {
+    case FOG_LINEAR:
+        amount = clamp((distance - fog_start) / (fog_end - fog_start), 0.0, 1.0);
+        break;
+    case FOG_EXPONENTIAL:
+        amount = 1.0 - exp(-fog_density * distance);
+        break;
+    }
+    return mix(color, fog_color, amount);
+}
+#endif
@@ -0,0 +1,19 @@
+#version 450 core
+
+// Lines of debug_draw.rs, already in world space, or in device coordinates
+// when SCREEN_SPACE is defined.
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 color;
+
+#include "common.glsl"
+
+layout(location = 0) out vec3 v_color;
+
+void main(void) {
+#ifdef SCREEN_SPACE
+    gl_Position = vec4(position, 1.0);
+#else
+    gl_Position = proj_view_model_matrix * vec4(position, 1.0);
+#endif
+    v_color = color;
+}
@@ -9,21 +9,13 @@ layout(location = 4) in vec4 model_mat2;
 layout(location = 5) in vec4 model_mat3;
 layout(location = 6) in vec4 model_mat4;
 
-layout(set = 0, binding = 0) uniform matrixUniform {
-    mat4 proj_view_model_matrix;
-    vec3 view_pos;
-};
-
-layout(set = 1, binding = 0)
-uniform Light {
-    vec3 u_position;
-    vec3 u_color;
-};
+#define LIGHT_SET 1
+#include "common.glsl"
 
 float scale = 0.25;
 
 void main(void) {
-    vec3 l_pos = scale * pos + u_position;
+    vec3 l_pos = scale * pos + light_pos;
     gl_Position = proj_view_model_matrix * vec4(l_pos, 1.0);
-    v_color = u_color;
+    v_color = light_color;
 }
\ No newline at end of file
@@ -0,0 +1,70 @@
+#version 450 core
+
+// Loaded models, lit and fogged like the chunk.
+
+layout(location = 0) in vec2 frag_tex_cords;
+layout(location = 1) in vec3 v_pos;
+layout(location = 2) in vec3 v_normal;
+
+layout(location = 0) out vec4 outColor;
+
+#define LIGHT_SET 2
+// All four groups are taken, so the fog is bound with the light.
+#define FOG_SET 2
+#define FOG_BINDING 3
+#include "common.glsl"
+
+// The ambient occlusion of the screen, bound with the light like in
+// shader.frag.
+layout(set = LIGHT_SET, binding = 1) uniform texture2D u_ambient_occlusion;
+layout(set = LIGHT_SET, binding = 2) uniform sampler u_ambient_occlusion_sampler;
+
+layout(set = 1, binding = 0) uniform texture2D t_diffuse;
+layout(set = 1, binding = 1) uniform sampler s_diffuse;
+layout(set = 1, binding = 2) uniform texture2D t_normal;
+
+// The models have no tangents, so the tangent frame comes from the screen
+// space derivatives of the position and texture coordinates.
+mat3 tangent_frame(vec3 normal, vec3 pos, vec2 uv) {
+    vec3 dp1 = dFdx(pos);
+    vec3 dp2 = dFdy(pos);
+    vec2 duv1 = dFdx(uv);
+    vec2 duv2 = dFdy(uv);
+
+    vec3 dp2perp = cross(dp2, normal);
+    vec3 dp1perp = cross(normal, dp1);
+    vec3 tangent = dp2perp * duv1.x + dp1perp * duv2.x;
+    vec3 bitangent = dp2perp * duv1.y + dp1perp * duv2.y;
+
+    float scale = inversesqrt(max(max(dot(tangent, tangent), dot(bitangent, bitangent)), 1e-20));
+    // Texture coordinates go down the image, the green channel of a normal
+    // map points up.
+    return mat3(tangent * scale, -bitangent * scale, normal);
+}
+
+float ambient_occlusion() {
+    sampler2D ao = sampler2D(u_ambient_occlusion, u_ambient_occlusion_sampler);
+    return texture(ao, gl_FragCoord.xy / vec2(textureSize(ao, 0))).r;
+}
+
+void main(void) {
+    vec4 object_texture = texture(sampler2D(t_diffuse, s_diffuse), frag_tex_cords);
+
+    vec3 surface_normal = normalize(v_normal);
+    vec3 mapped = texture(sampler2D(t_normal, s_diffuse), frag_tex_cords).xyz * 2.0 - 1.0;
+    vec3 normal = normalize(tangent_frame(surface_normal, v_pos, frag_tex_cords) * mapped);
+    vec3 light_dir = normalize(light_pos - v_pos);
+    float diffuse_strength = max(dot(light_dir, normal), 0.0);
+    vec3 light = light_color * (light_ambient * ambient_occlusion() + diffuse_strength);
+
+    vec3 result = apply_fog(light * object_texture.xyz, distance(view_pos, v_pos));
+    switch (debug_view) {
+    case DEBUG_VIEW_NORMALS:
+        result = normal * 0.5 + 0.5;
+        break;
+    case DEBUG_VIEW_LIGHTING:
+        result = light;
+        break;
+    }
+    outColor = vec4(result, object_texture.a);
+}
@@ -0,0 +1,15 @@
+#version 450
+// Keeps what's brighter than the threshold, at half the resolution.
+#include "post.glsl"
+
+void main() {
+    vec3 color = sample_input(v_uv).rgb;
+    float brightness = max(color.r, max(color.g, color.b));
+    // A soft knee below the threshold, so the bloom fades in instead of
+    // popping up.
+    float knee = bloom_threshold * 0.5;
+    float soft = clamp(brightness - bloom_threshold + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee + 1e-5);
+    float contribution = max(soft, brightness - bloom_threshold) / max(brightness, 1e-5);
+    out_color = vec4(color * contribution, 1.0);
+}
@@ -0,0 +1,10 @@
+#version 450
+// Adds the blurred bright parts to the scene.
+#include "post.glsl"
+
+layout(set = 0, binding = 2) uniform texture2D u_bloom;
+
+void main() {
+    vec3 bloom = texture(sampler2D(u_bloom, u_sampler), v_uv).rgb;
+    out_color = vec4(sample_input(v_uv).rgb + bloom * bloom_intensity, 1.0);
+}
@@ -0,0 +1,22 @@
+#version 450
+// Half of a separable 9 tap gaussian blur, horizontal unless BLUR_VERTICAL is
+// defined. The taps sit between texels, so linear filtering averages two at
+// a time.
+#include "post.glsl"
+
+const float offsets[3] = float[](0.0, 1.3846153846, 3.2307692308);
+const float weights[3] = float[](0.2270270270, 0.3162162162, 0.0702702703);
+
+void main() {
+#ifdef BLUR_VERTICAL
+    vec2 direction = vec2(0.0, input_texel().y);
+#else
+    vec2 direction = vec2(input_texel().x, 0.0);
+#endif
+    vec3 color = sample_input(v_uv).rgb * weights[0];
+    for (int i = 1; i < 3; i++) {
+        color += sample_input(v_uv + direction * offsets[i]).rgb * weights[i];
+        color += sample_input(v_uv - direction * offsets[i]).rgb * weights[i];
+    }
+    out_color = vec4(color, 1.0);
+}
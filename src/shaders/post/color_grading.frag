@@ -0,0 +1,17 @@
+#version 450
+// Looks the color up in a 3D LUT.
+#include "post.glsl"
+
+layout(set = 0, binding = 2) uniform texture3D u_lut;
+
+// LUT_SIZE in post_process.rs.
+#define LUT_SIZE 16.0
+
+void main() {
+    vec4 color = sample_input(v_uv);
+    // Through the texel centers, so the ends of the range aren't blended with
+    // the border.
+    vec3 uvw = clamp(color.rgb, 0.0, 1.0) * ((LUT_SIZE - 1.0) / LUT_SIZE) + 0.5 / LUT_SIZE;
+    vec3 graded = texture(sampler3D(u_lut, u_sampler), uvw).rgb;
+    out_color = vec4(mix(color.rgb, graded, lut_strength), color.a);
+}
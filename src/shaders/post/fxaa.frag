@@ -0,0 +1,51 @@
+#version 450
+// FXAA in the style of the "lite" version of FXAA 3. Blends along edges that
+// stand out from their surroundings, searching up to fxaa_span_max pixels.
+#include "post.glsl"
+
+#define FXAA_REDUCE_MIN (1.0 / 128.0)
+#define FXAA_REDUCE_MUL (1.0 / 8.0)
+
+float luma(vec3 color) {
+    return dot(color, vec3(0.299, 0.587, 0.114));
+}
+
+void main() {
+    vec2 texel = input_texel();
+    vec3 rgb_m = sample_input(v_uv).rgb;
+    float luma_nw = luma(sample_input(v_uv + vec2(-1.0, -1.0) * texel).rgb);
+    float luma_ne = luma(sample_input(v_uv + vec2(1.0, -1.0) * texel).rgb);
+    float luma_sw = luma(sample_input(v_uv + vec2(-1.0, 1.0) * texel).rgb);
+    float luma_se = luma(sample_input(v_uv + vec2(1.0, 1.0) * texel).rgb);
+    float luma_m = luma(rgb_m);
+    float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    if (luma_max - luma_min < max(fxaa_edge_threshold * luma_max, 1.0 / 32.0)) {
+        out_color = vec4(rgb_m, 1.0);
+        return;
+    }
+
+    vec2 direction = vec2(
+        -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+        (luma_nw + luma_sw) - (luma_ne + luma_se));
+    float direction_reduce = max(
+        (luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * FXAA_REDUCE_MUL,
+        FXAA_REDUCE_MIN);
+    float inverse_min = 1.0 / (min(abs(direction.x), abs(direction.y)) + direction_reduce);
+    direction = clamp(direction * inverse_min, -fxaa_span_max, fxaa_span_max) * texel;
+
+    vec3 rgb_a = 0.5 * (
+        sample_input(v_uv + direction * (1.0 / 3.0 - 0.5)).rgb +
+        sample_input(v_uv + direction * (2.0 / 3.0 - 0.5)).rgb);
+    vec3 rgb_b = rgb_a * 0.5 + 0.25 * (
+        sample_input(v_uv - direction * 0.5).rgb +
+        sample_input(v_uv + direction * 0.5).rgb);
+    float luma_b = luma(rgb_b);
+    // The wider blend can reach past the edge, then the narrow one is used.
+    if (luma_b < luma_min || luma_b > luma_max) {
+        out_color = vec4(rgb_a, 1.0);
+    } else {
+        out_color = vec4(rgb_b, 1.0);
+    }
+}
@@ -0,0 +1,9 @@
+#version 450
+// A gamma curve on top of the sRGB encoding of the swap chain, 1 keeps the
+// image as it is.
+#include "post.glsl"
+
+void main() {
+    vec4 color = sample_input(v_uv);
+    out_color = vec4(pow(max(color.rgb, 0.0), vec3(1.0 / gamma)), color.a);
+}
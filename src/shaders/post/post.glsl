@@ -0,0 +1,33 @@
+// Shared by the post-processing passes, which draw a fullscreen triangle with
+// blit/blit.vert. The input is the image so far, the bindings match the ones
+// of blit/blit.frag.
+
+layout(set = 0, binding = 0) uniform texture2D u_input;
+layout(set = 0, binding = 1) uniform sampler u_sampler;
+
+// `Params` in post_process.rs.
+layout(set = 1, binding = 0) uniform PostParams {
+    float exposure;
+    uint tonemapper;
+    float gamma;
+    float bloom_threshold;
+    float bloom_intensity;
+    float lut_strength;
+    float fxaa_edge_threshold;
+    float fxaa_span_max;
+};
+
+// Values of tonemapper, the order of `Tonemapper` in post_process.rs.
+#define TONEMAPPER_ACES 0u
+#define TONEMAPPER_REINHARD 1u
+
+layout(location = 0) in vec2 v_uv;
+layout(location = 0) out vec4 out_color;
+
+vec4 sample_input(vec2 uv) {
+    return texture(sampler2D(u_input, u_sampler), uv);
+}
+
+vec2 input_texel() {
+    return 1.0 / vec2(textureSize(sampler2D(u_input, u_sampler), 0));
+}
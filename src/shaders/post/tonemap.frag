@@ -0,0 +1,30 @@
+#version 450
+// Maps the HDR scene into 0 to 1.
+#include "post.glsl"
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve.
+vec3 aces(vec3 x) {
+    const float a = 2.51;
+    const float b = 0.03;
+    const float c = 2.43;
+    const float d = 0.59;
+    const float e = 0.14;
+    return clamp((x * (a * x + b)) / (x * (c * x + d) + e), 0.0, 1.0);
+}
+
+vec3 reinhard(vec3 x) {
+    return x / (1.0 + x);
+}
+
+void main() {
+    vec3 color = sample_input(v_uv).rgb * exposure;
+    switch (tonemapper) {
+    case TONEMAPPER_ACES:
+        color = aces(color);
+        break;
+    case TONEMAPPER_REINHARD:
+        color = reinhard(color);
+        break;
+    }
+    out_color = vec4(color, 1.0);
+}
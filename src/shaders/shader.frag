@@ -1,6 +1,10 @@
 #version 450 core
 
+// TEXTURE_LAYERS is defined when the adapter doesn't support binding arrays,
+// then the chunk textures are the layers of one texture instead.
+#ifndef TEXTURE_LAYERS
 #extension GL_EXT_nonuniform_qualifier : require
+#endif
 
 layout(location = 0) in vec2 frag_tex_cords;
 layout(location = 1) in vec3 v_pos;
@@ -9,22 +13,52 @@ layout(location = 3) flat in uint v_offset;
 
 layout(location = 0) out vec4 outColor;
 
-layout(set = 0, binding = 0) uniform matrixUniform {
-    mat4 proj_view_model_matrix;
-    vec3 view_pos;
-};
+#define LIGHT_SET 2
+#define FOG_SET 3
+#include "common.glsl"
+
+// The ambient occlusion of the screen, bound with the light. See ssao/.
+layout(set = LIGHT_SET, binding = 1) uniform texture2D u_ambient_occlusion;
+layout(set = LIGHT_SET, binding = 2) uniform sampler u_ambient_occlusion_sampler;
+
 layout(set = 1, binding = 0) uniform sampler u_sampler;
+#ifdef TEXTURE_LAYERS
+layout(set = 1, binding = 1) uniform texture2DArray u_textures;
+#else
 layout(set = 1, binding = 1) uniform texture2D u_textures[];
-layout(set = 2, binding = 0) uniform Light {
-    vec3 light_pos;
-    vec3 light_color;
-};
-float ambient_strenght = 0.05;
+#endif
+
+// 1 where the pixel is fully lit by the ambient light, less in creases and
+// corners. Without SSAO a white pixel is bound, so it's 1 everywhere.
+float ambient_occlusion() {
+    sampler2D ao = sampler2D(u_ambient_occlusion, u_ambient_occlusion_sampler);
+    return texture(ao, gl_FragCoord.xy / vec2(textureSize(ao, 0))).r;
+}
+
+// Size of a chunk in blocks, CHUNK_WIDTH and friends in chunk.rs.
+#define CHUNK_SIZE 16.0
+
+// A color for every texture layer, so neighbouring layers are easy to tell apart.
+vec3 layer_color(uint layer) {
+    return fract(vec3(0.13, 0.57, 0.91) * float(layer + 1) + vec3(0.3, 0.1, 0.7));
+}
+
+// 1 within a pixel or two of a chunk border, 0 elsewhere. Blocks are
+// centered on whole coordinates, so chunks start half a block before them.
+float chunk_border(vec3 pos) {
+    vec3 cell = (pos + 0.5) / CHUNK_SIZE;
+    vec3 to_border = abs(cell - round(cell)) / fwidth(cell);
+    return 1.0 - clamp(min(to_border.x, min(to_border.y, to_border.z)) - 1.0, 0.0, 1.0);
+}
 
 void main(void) {
+#ifdef TEXTURE_LAYERS
+    vec4 object_texture = texture(sampler2DArray(u_textures, u_sampler), vec3(frag_tex_cords, v_offset));
+#else
     vec4 object_texture = texture(sampler2D(u_textures[v_offset], u_sampler), frag_tex_cords);
+#endif
 
-    vec3 ambient_color = light_color * ambient_strenght;
+    vec3 ambient_color = light_color * light_ambient * ambient_occlusion();
 
     vec3 normal = normalize(v_normal);
     vec3 light_dir = normalize(light_pos - v_pos);
@@ -38,6 +72,25 @@ void main(void) {
     float diffuse_strenght = max(dot(light_dir, normal), 0.0);
     vec3 diffuse_color = light_color * diffuse_strenght;
 
-    vec3 result = (ambient_color + diffuse_color + specular_color) * object_texture.xyz;
+    vec3 light = ambient_color + diffuse_color + specular_color;
+    vec3 result = apply_fog(light * object_texture.xyz, distance(view_pos, v_pos));
+    switch (debug_view) {
+    case DEBUG_VIEW_NORMALS:
+        result = normal * 0.5 + 0.5;
+        break;
+    case DEBUG_VIEW_TEXTURE_LAYERS:
+        result = layer_color(v_offset) * (0.5 + 0.5 * dot(object_texture.xyz, vec3(0.3, 0.6, 0.1)));
+        break;
+    case DEBUG_VIEW_CHUNK_BORDERS:
+        result = mix(result, vec3(1.0, 0.1, 0.1), chunk_border(v_pos));
+        break;
+    case DEBUG_VIEW_LIGHTING:
+        result = light;
+        break;
+    case DEBUG_VIEW_OVERDRAW:
+        // Added up by the overdraw pipeline, every layer makes it hotter.
+        result = vec3(0.1, 0.04, 0.01);
+        break;
+    }
     outColor = vec4(result, 0.3);
 }
\ No newline at end of file
@@ -17,11 +17,10 @@ layout(location = 9) in vec3 n_matrix3;
 
 // Texture offset
 layout(location = 10) in uint offset;
+// Atlas rect of the face: top left and bottom right corner
+layout(location = 11) in vec4 uv_rect;
 
-layout(set = 0, binding = 0) uniform matrixUniform {
-    mat4 proj_view_model_matrix;
-    vec3 view_pos;
-};
+#include "common.glsl"
 
 layout(location = 0) out vec2 frag_tex_cords;
 layout(location = 1) out vec3 v_pos;
@@ -33,7 +32,7 @@ void main(void) {
     mat3 n_matrix = mat3(n_matrix1, n_matrix2, n_matrix3);
     vec4 world_position = model_matrix * vec4(pos.xyz, 1.0);
     gl_Position = proj_view_model_matrix * world_position;
-    frag_tex_cords = tex_cords;
+    frag_tex_cords = mix(uv_rect.xy, uv_rect.zw, tex_cords);
     v_pos = world_position.xyz;
     v_normal = n_matrix * normal;
     v_offset = offset;
@@ -0,0 +1,78 @@
+#version 450 core
+
+// The background. Procedural unless SKY_CUBEMAP or SKY_EQUIRECT is defined,
+// then it samples a cubemap or an equirectangular image instead.
+
+layout(location = 0) in vec2 v_ndc;
+layout(location = 0) out vec4 out_color;
+
+// `SkyUniform` in sky.rs.
+layout(set = 0, binding = 0) uniform SkyUniform {
+    mat4 inverse_proj_view;
+    vec3 view_pos;
+    vec3 sun_direction;
+    // Scales the images, so they follow the time of day.
+    float brightness;
+};
+
+#ifdef SKY_CUBEMAP
+layout(set = 1, binding = 0) uniform textureCube u_sky;
+layout(set = 1, binding = 1) uniform sampler u_sampler;
+#endif
+#ifdef SKY_EQUIRECT
+layout(set = 1, binding = 0) uniform texture2D u_sky;
+layout(set = 1, binding = 1) uniform sampler u_sampler;
+#endif
+
+#define PI 3.14159265
+
+// The scattering model of `sky_radiance` in sky.rs, keep them the same.
+const vec3 BETA_RAYLEIGH = vec3(0.08, 0.19, 0.33);
+const float BETA_MIE = 0.05;
+const float MIE_G = 0.76;
+const float SUN_INTENSITY = 3.0;
+const vec3 NIGHT_SKY = vec3(0.002, 0.004, 0.01);
+
+// How much air there is along a direction with height y, most towards the
+// horizon.
+float optical_depth(float y) {
+    return 1.0 / (max(y, 0.0) + 0.15);
+}
+
+vec3 sun_light(vec3 sun) {
+    vec3 extinction = BETA_RAYLEIGH + BETA_MIE;
+    return SUN_INTENSITY * exp(-extinction * optical_depth(sun.y)) * smoothstep(-0.1, 0.05, sun.y);
+}
+
+vec3 sky_radiance(vec3 dir, vec3 sun) {
+    float mu = dot(dir, sun);
+    float rayleigh = 0.75 * (1.0 + mu * mu);
+    float g2 = MIE_G * MIE_G;
+    float mie = (1.0 - g2) / pow(1.0 + g2 - 2.0 * MIE_G * mu, 1.5);
+    vec3 extinction = BETA_RAYLEIGH + BETA_MIE;
+    vec3 scattered = (BETA_RAYLEIGH * rayleigh + BETA_MIE * mie) / extinction
+        * (1.0 - exp(-extinction * optical_depth(dir.y)));
+    vec3 radiance = sun_light(sun) * scattered + NIGHT_SKY;
+    // Darker below the horizon, where the ground would be.
+    return radiance * (0.3 + 0.7 * smoothstep(-0.2, 0.0, dir.y));
+}
+
+void main(void) {
+    vec4 far = inverse_proj_view * vec4(v_ndc, 1.0, 1.0);
+    vec3 dir = normalize(far.xyz / far.w - view_pos);
+
+#ifdef SKY_CUBEMAP
+    vec3 color = texture(samplerCube(u_sky, u_sampler), dir).rgb * brightness;
+#else
+#ifdef SKY_EQUIRECT
+    vec2 uv = vec2(atan(dir.z, dir.x) / (2.0 * PI) + 0.5, acos(clamp(dir.y, -1.0, 1.0)) / PI);
+    vec3 color = texture(sampler2D(u_sky, u_sampler), uv).rgb * brightness;
+#else
+    vec3 color = sky_radiance(dir, sun_direction);
+    // The sun itself, bright enough to bloom.
+    float disk = smoothstep(0.9995, 0.9998, dot(dir, sun_direction));
+    color += sun_light(sun_direction) * disk * 20.0;
+#endif
+#endif
+    out_color = vec4(color, 1.0);
+}
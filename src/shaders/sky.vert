@@ -0,0 +1,11 @@
+#version 450 core
+
+// A fullscreen triangle. The fragment shader turns the position on screen
+// into a view direction.
+layout(location = 0) out vec2 v_ndc;
+
+void main(void) {
+    vec2 uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+    v_ndc = uv * 2.0 - 1.0;
+    gl_Position = vec4(v_ndc, 1.0, 1.0);
+}
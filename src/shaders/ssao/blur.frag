@@ -0,0 +1,24 @@
+#version 450 core
+
+// Averages the occlusion over the NOISE_SIZE by NOISE_SIZE square around each
+// pixel, which hides the pattern of the noise. The bindings match the ones of
+// blit/blit.frag.
+layout(location = 0) in vec2 v_uv;
+layout(location = 0) out vec4 out_occlusion;
+
+layout(set = 0, binding = 0) uniform texture2D u_input;
+layout(set = 0, binding = 1) uniform sampler u_sampler;
+
+// NOISE_SIZE in ssao.rs.
+#define NOISE_SIZE 4
+
+void main(void) {
+    vec2 texel = 1.0 / vec2(textureSize(sampler2D(u_input, u_sampler), 0));
+    float occlusion = 0.0;
+    for (int x = -NOISE_SIZE / 2; x < NOISE_SIZE / 2; x++) {
+        for (int y = -NOISE_SIZE / 2; y < NOISE_SIZE / 2; y++) {
+            occlusion += texture(sampler2D(u_input, u_sampler), v_uv + vec2(x, y) * texel).r;
+        }
+    }
+    out_occlusion = vec4(occlusion / float(NOISE_SIZE * NOISE_SIZE));
+}
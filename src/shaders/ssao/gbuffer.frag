@@ -0,0 +1,15 @@
+#version 450 core
+
+// The G-buffer of the ambient occlusion, drawn with shader.vert before the
+// main pass: the normal of the closest surface, and how far it is from the
+// camera. 0 where nothing was drawn.
+layout(location = 1) in vec3 v_pos;
+layout(location = 2) in vec3 v_normal;
+
+layout(location = 0) out vec4 out_gbuffer;
+
+#include "../common.glsl"
+
+void main(void) {
+    out_gbuffer = vec4(normalize(v_normal), distance(view_pos, v_pos));
+}
@@ -0,0 +1,67 @@
+#version 450 core
+
+// Ambient occlusion from the G-buffer: how many points of a hemisphere above
+// the surface lie behind other surfaces. 1 is not occluded at all. Drawn
+// with blit/blit.vert.
+layout(location = 0) in vec2 v_uv;
+layout(location = 0) out vec4 out_occlusion;
+
+layout(set = 0, binding = 0) uniform texture2D u_gbuffer;
+layout(set = 0, binding = 1) uniform texture2D u_noise;
+layout(set = 0, binding = 2) uniform sampler u_sampler;
+
+// KERNEL_SIZE and NOISE_SIZE in ssao.rs.
+#define KERNEL_SIZE 16
+#define NOISE_SIZE 4
+
+// `SsaoUniform` in ssao.rs.
+layout(set = 1, binding = 0) uniform SsaoUniform {
+    mat4 proj_view;
+    mat4 inverse_proj_view;
+    vec3 view_pos;
+    float radius;
+    float bias;
+    float intensity;
+    // Points in the +z hemisphere, most of them close to the center.
+    vec4 kernel[KERNEL_SIZE];
+};
+
+vec4 gbuffer_at(vec2 uv) {
+    return texture(sampler2D(u_gbuffer, u_sampler), uv);
+}
+
+// The point `distance` away from the camera, seen at uv.
+vec3 world_position(vec2 uv, float distance) {
+    vec4 far = inverse_proj_view * vec4(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 1.0, 1.0);
+    return view_pos + normalize(far.xyz / far.w - view_pos) * distance;
+}
+
+void main(void) {
+    vec4 gbuffer = gbuffer_at(v_uv);
+    // Nothing occludes the sky.
+    if (gbuffer.w == 0.0) {
+        out_occlusion = vec4(1.0);
+        return;
+    }
+    vec3 normal = gbuffer.xyz;
+    vec3 position = world_position(v_uv, gbuffer.w);
+
+    // Turns the kernel around the normal by an angle from the noise, which
+    // repeats every NOISE_SIZE pixels. The blur pass averages it out.
+    vec3 random = texelFetch(sampler2D(u_noise, u_sampler), ivec2(gl_FragCoord.xy) % NOISE_SIZE, 0).xyz;
+    vec3 tangent = normalize(random - normal * dot(random, normal));
+    mat3 tbn = mat3(tangent, cross(normal, tangent), normal);
+
+    float occlusion = 0.0;
+    for (int i = 0; i < KERNEL_SIZE; i++) {
+        vec3 sample_pos = position + tbn * kernel[i].xyz * radius;
+        vec4 clip = proj_view * vec4(sample_pos, 1.0);
+        vec2 uv = vec2(clip.x, -clip.y) / clip.w * 0.5 + 0.5;
+        float scene_distance = gbuffer_at(uv).w;
+        bool behind = scene_distance > 0.0 && scene_distance < distance(view_pos, sample_pos) - bias;
+        // Surfaces far in front of the point don't occlude it.
+        float in_range = smoothstep(0.0, 1.0, radius / abs(gbuffer.w - scene_distance));
+        occlusion += behind ? in_range : 0.0;
+    }
+    out_occlusion = vec4(pow(1.0 - occlusion / float(KERNEL_SIZE), intensity));
+}
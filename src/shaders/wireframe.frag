@@ -0,0 +1,8 @@
+#version 450 core
+
+// Lines of the wireframe debug view, drawn on top of the shaded chunk.
+layout(location = 0) out vec4 outColor;
+
+void main(void) {
+    outColor = vec4(0.0, 0.0, 0.0, 1.0);
+}
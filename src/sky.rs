@@ -0,0 +1,746 @@
+//! The sky. A [`DayCycle`] moves the sun, which colors the procedural sky and
+//! lights the terrain through the [`Light`](crate::light::Light) uniform, see
+//! [`SkyLighting`]. Instead of the procedural sky a cubemap or an
+//! equirectangular image can be drawn, dimmed at night.
+//!
+//! The sky is the first thing the main pass draws, a fullscreen triangle that
+//! neither tests nor writes depth.
+
+use crate::camera::Camera;
+use crate::hot_reload;
+use crate::post_process::HDR_FORMAT;
+use crate::render_pipeline_tools::{new_render_pipeline, PipelineVariant};
+use crate::rendering::preprocessor::Preprocessor;
+use crate::rendering::reflection::PipelineInterface;
+use crate::texture::Texture;
+use anyhow::{ensure, Context, Result};
+use bytemuck::Zeroable;
+use nalgebra::{Matrix4, Vector3};
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::num::NonZeroU32;
+use std::path::Path;
+use std::time::Instant;
+use wgpu::util::DeviceExt;
+
+// The scattering model, the same as in `sky.frag`.
+const BETA_RAYLEIGH: [f32; 3] = [0.08, 0.19, 0.33];
+const BETA_MIE: f32 = 0.05;
+const MIE_G: f32 = 0.76;
+const SUN_INTENSITY: f32 = 3.;
+const NIGHT_SKY: [f32; 3] = [0.002, 0.004, 0.01];
+
+/// Color of the light at night, when the moon takes over from the sun.
+const MOON_COLOR: [f32; 3] = [0.05, 0.07, 0.12];
+const DAY_AMBIENT: f32 = 0.2;
+const NIGHT_AMBIENT: f32 = 0.03;
+/// Tilt of the sun's path towards the south, so it never stands straight
+/// overhead.
+const SUN_TILT_DEGREES: f32 = 30.;
+/// In-game hours per second, a day takes four minutes.
+const DEFAULT_SPEED: f32 = 0.1;
+
+/// Names of the cubemap faces, in the order of the layers: +X, -X, +Y, -Y,
+/// +Z and -Z.
+const CUBEMAP_FACES: [&str; 6] = ["px", "nx", "py", "ny", "pz", "nz"];
+const CUBEMAP_EXTENSIONS: [&str; 3] = ["png", "jpg", "jpeg"];
+
+/// The time of day.
+#[derive(Debug, Clone)]
+pub struct DayCycle {
+    /// From 0 to 24, noon is at 12.
+    pub hours: f32,
+    /// In-game hours per second, 0 stops the clock.
+    pub speed: f32,
+    last_tick: Option<Instant>,
+}
+
+impl DayCycle {
+    pub fn new(hours: f32) -> Self {
+        Self {
+            hours: hours.rem_euclid(24.),
+            speed: DEFAULT_SPEED,
+            last_tick: None,
+        }
+    }
+
+    /// Advances the clock by the time since the last call, call it once per
+    /// frame.
+    pub fn tick(&mut self) {
+        let now = Instant::now();
+        if let Some(last_tick) = self.last_tick.replace(now) {
+            self.advance((now - last_tick).as_secs_f32());
+        }
+    }
+
+    pub fn advance(&mut self, seconds: f32) {
+        self.set_hours(self.hours + seconds * self.speed);
+    }
+
+    pub fn set_hours(&mut self, hours: f32) {
+        self.hours = hours.rem_euclid(24.);
+    }
+
+    /// Unit vector towards the sun. It rises in the east (+x) at 6, is
+    /// highest at noon and sets in the west at 18.
+    pub fn sun_direction(&self) -> Vector3<f32> {
+        let angle = (self.hours - 6.) / 24. * std::f32::consts::TAU;
+        let tilt = SUN_TILT_DEGREES.to_radians();
+        Vector3::new(
+            angle.cos(),
+            angle.sin() * tilt.cos(),
+            -angle.sin() * tilt.sin(),
+        )
+    }
+}
+
+impl fmt::Display for DayCycle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let minutes = (self.hours * 60.) as u32;
+        write!(f, "{:02}:{:02}", minutes / 60, minutes % 60)
+    }
+}
+
+/// How the sky lights the terrain at one time of day.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SkyLighting {
+    /// Unit vector towards the sun by day and the moon by night.
+    pub light_direction: Vector3<f32>,
+    /// Fades out around sunrise and sunset, when the light changes sides.
+    pub light_color: [f32; 3],
+    /// How much of the light reaches faces that don't face it.
+    pub ambient: f32,
+    /// The sky near the horizon, away from the sun.
+    pub sky_color: [f32; 3],
+}
+
+impl SkyLighting {
+    pub fn new(sun: Vector3<f32>) -> Self {
+        let (light_direction, light_color) = if sun.y >= 0. {
+            let color = sun_light(sun) / SUN_INTENSITY * smoothstep(0., 0.1, sun.y);
+            (sun, color)
+        } else {
+            let color = Vector3::from(MOON_COLOR) * smoothstep(0., 0.1, -sun.y);
+            (-sun, color)
+        };
+        let ambient = NIGHT_AMBIENT + (DAY_AMBIENT - NIGHT_AMBIENT) * smoothstep(-0.2, 0.3, sun.y);
+        // Sideways from the sun, a little above the horizon.
+        let side = Vector3::new(-sun.z, 0., sun.x)
+            .try_normalize(1e-6)
+            .unwrap_or_else(Vector3::x);
+        let elevation = 10f32.to_radians();
+        let horizon = side * elevation.cos() + Vector3::y() * elevation.sin();
+        Self {
+            light_direction,
+            light_color: light_color.into(),
+            ambient,
+            sky_color: sky_radiance(horizon, sun).into(),
+        }
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).max(0.).min(1.);
+    t * t * (3. - 2. * t)
+}
+
+/// How much air there is along a direction with height `y`, most towards
+/// the horizon.
+fn optical_depth(y: f32) -> f32 {
+    1. / (y.max(0.) + 0.15)
+}
+
+fn extinction() -> Vector3<f32> {
+    Vector3::from(BETA_RAYLEIGH).add_scalar(BETA_MIE)
+}
+
+/// The sunlight that makes it through the air.
+fn sun_light(sun: Vector3<f32>) -> Vector3<f32> {
+    let depth = optical_depth(sun.y);
+    extinction().map(|e| (-e * depth).exp()) * SUN_INTENSITY * smoothstep(-0.1, 0.05, sun.y)
+}
+
+/// Light scattered towards the camera from direction `dir`, `sky_radiance`
+/// in `sky.frag`.
+fn sky_radiance(dir: Vector3<f32>, sun: Vector3<f32>) -> Vector3<f32> {
+    let mu = dir.dot(&sun);
+    let rayleigh = 0.75 * (1. + mu * mu);
+    let g2 = MIE_G * MIE_G;
+    let mie = (1. - g2) / (1. + g2 - 2. * MIE_G * mu).powf(1.5);
+    let extinction = extinction();
+    let depth = optical_depth(dir.y);
+    let scattered = (Vector3::from(BETA_RAYLEIGH) * rayleigh).add_scalar(BETA_MIE * mie);
+    let scattered = scattered
+        .component_div(&extinction)
+        .component_mul(&extinction.map(|e| 1. - (-e * depth).exp()));
+    let radiance = sun_light(sun).component_mul(&scattered) + Vector3::from(NIGHT_SKY);
+    radiance * (0.3 + 0.7 * smoothstep(-0.2, 0., dir.y))
+}
+
+/// What the sky shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkyKind {
+    Procedural,
+    /// Six square images, see [`Sky::load_cubemap`].
+    Cubemap,
+    /// One image wrapped around the camera, see [`Sky::load_equirect`].
+    Equirect,
+}
+
+impl SkyKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            SkyKind::Procedural => "procedural",
+            SkyKind::Cubemap => "cubemap",
+            SkyKind::Equirect => "equirect",
+        }
+    }
+
+    /// Layout entries of the image, set 1.
+    fn image_layout_entries(self) -> Option<&'static [wgpu::BindGroupLayoutEntry]> {
+        const CUBE: [wgpu::BindGroupLayoutEntry; 2] =
+            image_layout_entries(wgpu::TextureViewDimension::Cube);
+        match self {
+            SkyKind::Procedural => None,
+            SkyKind::Cubemap => Some(&CUBE),
+            SkyKind::Equirect => Some(&Texture::BIND_GROUP_LAYOUT_ENTRIES),
+        }
+    }
+}
+
+const fn image_layout_entries(
+    view_dimension: wgpu::TextureViewDimension,
+) -> [wgpu::BindGroupLayoutEntry; 2] {
+    [
+        wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        },
+        Texture::BIND_GROUP_LAYOUT_ENTRIES[1],
+    ]
+}
+
+/// `SkyUniform` in `sky.frag`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct SkyUniform {
+    inverse_proj_view: [[f32; 4]; 4],
+    view_position: [f32; 3],
+    _padding: u32,
+    sun_direction: [f32; 3],
+    /// Scales the images, so they follow the time of day.
+    brightness: f32,
+}
+
+const UNIFORM_LAYOUT_ENTRIES: [wgpu::BindGroupLayoutEntry; 1] = [wgpu::BindGroupLayoutEntry {
+    binding: 0,
+    visibility: wgpu::ShaderStage::FRAGMENT,
+    ty: wgpu::BindingType::Buffer {
+        ty: wgpu::BufferBindingType::Uniform,
+        has_dynamic_offset: false,
+        min_binding_size: None,
+    },
+    count: None,
+}];
+
+/// The image of a cubemap or equirectangular sky.
+struct SkyImage {
+    _texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+}
+
+pub struct Sky {
+    pub clock: DayCycle,
+    /// Whether the light follows the sun, and the moon at night.
+    pub drives_light: bool,
+    kind: SkyKind,
+    image: Option<SkyImage>,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    uniform_layout: wgpu::BindGroupLayout,
+    cube_layout: wgpu::BindGroupLayout,
+    equirect_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    pipeline: wgpu::RenderPipeline,
+    dev: bool,
+    sample_count: u32,
+}
+
+impl Sky {
+    /// A procedural sky at noon, which drives the light. `sample_count` is
+    /// the one of the main pass.
+    pub fn new(device: &wgpu::Device, dev: bool, sample_count: u32) -> Result<Self> {
+        let uniform_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Sky uniform layout"),
+            entries: &UNIFORM_LAYOUT_ENTRIES,
+        });
+        let image_layout = |kind: SkyKind| {
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some(&format!("Sky {} layout", kind.name())),
+                entries: kind.image_layout_entries().unwrap(),
+            })
+        };
+        let cube_layout = image_layout(SkyKind::Cubemap);
+        let equirect_layout = image_layout(SkyKind::Equirect);
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Sky uniform"),
+            contents: bytemuck::cast_slice(&[SkyUniform::zeroed()]),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Sky uniform"),
+            layout: &uniform_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Sky sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let pipeline = create_pipeline(
+            device,
+            dev,
+            SkyKind::Procedural,
+            &[&uniform_layout],
+            sample_count,
+        )?;
+        Ok(Self {
+            clock: DayCycle::new(12.),
+            drives_light: true,
+            kind: SkyKind::Procedural,
+            image: None,
+            buffer,
+            bind_group,
+            uniform_layout,
+            cube_layout,
+            equirect_layout,
+            sampler,
+            pipeline,
+            dev,
+            sample_count,
+        })
+    }
+
+    pub fn kind(&self) -> SkyKind {
+        self.kind
+    }
+
+    pub fn lighting(&self) -> SkyLighting {
+        SkyLighting::new(self.clock.sun_direction())
+    }
+
+    /// Uploads the camera and the sun, once per frame before the main pass.
+    pub fn update(&self, queue: &wgpu::Queue, camera: &Camera) {
+        let lighting = self.lighting();
+        let inverse = camera
+            .create_view_proj_model_matrix()
+            .try_inverse()
+            .unwrap_or_else(Matrix4::identity);
+        let uniform = SkyUniform {
+            inverse_proj_view: inverse.into(),
+            view_position: camera.eye.coords.into(),
+            _padding: 0,
+            sun_direction: self.clock.sun_direction().into(),
+            brightness: lighting.ambient / DAY_AMBIENT,
+        };
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        if let Some(image) = &self.image {
+            render_pass.set_bind_group(1, &image.bind_group, &[]);
+        }
+        render_pass.draw(0..3, 0..1);
+    }
+
+    pub fn set_procedural(&mut self, device: &wgpu::Device) -> Result<()> {
+        self.set_image(device, SkyKind::Procedural, None)
+    }
+
+    /// Loads the faces `px`, `nx`, `py`, `ny`, `pz` and `nz` from `dir`, as
+    /// PNG or JPEG. They have to be squares of the same size.
+    pub fn load_cubemap(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        dir: &Path,
+    ) -> Result<()> {
+        let mut faces = Vec::with_capacity(CUBEMAP_FACES.len());
+        for face in CUBEMAP_FACES.iter() {
+            let path = CUBEMAP_EXTENSIONS
+                .iter()
+                .map(|extension| dir.join(face).with_extension(extension))
+                .find(|path| path.exists())
+                .with_context(|| format!("{:?} has no {} face", dir, face))?;
+            let image = image::open(&path)
+                .with_context(|| format!("Failed to load {:?}", path))?
+                .to_rgba8();
+            faces.push((path, image));
+        }
+        let size = faces[0].1.width();
+        for (path, face) in &faces {
+            ensure!(
+                face.dimensions() == (size, size),
+                "{:?} is {}x{}, the faces have to be {}x{}",
+                path,
+                face.width(),
+                face.height(),
+                size,
+                size
+            );
+        }
+
+        let extent = wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: CUBEMAP_FACES.len() as u32,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Sky cubemap"),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+        });
+        for (layer, (_, face)) in faces.iter().enumerate() {
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer as u32,
+                    },
+                },
+                face,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(4 * size),
+                    rows_per_image: None,
+                },
+                wgpu::Extent3d {
+                    depth_or_array_layers: 1,
+                    ..extent
+                },
+            );
+        }
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        let image = self.create_image(device, SkyKind::Cubemap, texture, &view);
+        self.set_image(device, SkyKind::Cubemap, Some(image))
+    }
+
+    /// Loads an equirectangular image, a Radiance `.hdr` file or anything
+    /// else the `image` crate reads.
+    pub fn load_equirect(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: &Path,
+    ) -> Result<()> {
+        let is_hdr = path.extension().and_then(|e| e.to_str()) == Some("hdr");
+        let (width, height, format, data) = if is_hdr {
+            let file = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+            let decoder = image::codecs::hdr::HdrDecoder::new(BufReader::new(file))?;
+            let metadata = decoder.metadata();
+            let pixels = decoder.read_image_hdr()?;
+            let mut data = Vec::with_capacity(pixels.len() * 8);
+            for pixel in &pixels {
+                for &channel in pixel.0.iter().chain(&[1.]) {
+                    data.extend_from_slice(&to_half(channel).to_le_bytes());
+                }
+            }
+            let format = wgpu::TextureFormat::Rgba16Float;
+            (metadata.width, metadata.height, format, data)
+        } else {
+            let image = image::open(path)
+                .with_context(|| format!("Failed to load {:?}", path))?
+                .to_rgba8();
+            let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+            (image.width(), image.height(), format, image.into_raw())
+        };
+
+        let extent = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Sky equirect"),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+        });
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            &data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(data.len() as u32 / height),
+                rows_per_image: None,
+            },
+            extent,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let image = self.create_image(device, SkyKind::Equirect, texture, &view);
+        self.set_image(device, SkyKind::Equirect, Some(image))
+    }
+
+    /// Creates the pipeline again after the shaders or the sample count
+    /// changed. The current one stays until it's passed to
+    /// [`Sky::set_pipeline`].
+    pub fn create_pipeline(&self, device: &wgpu::Device, sample_count: u32) -> Result<SkyPipeline> {
+        let pipeline = create_pipeline(
+            device,
+            self.dev,
+            self.kind,
+            &self.layouts(self.kind),
+            sample_count,
+        )?;
+        Ok(SkyPipeline {
+            pipeline,
+            sample_count,
+        })
+    }
+
+    pub fn set_pipeline(&mut self, pipeline: SkyPipeline) {
+        self.pipeline = pipeline.pipeline;
+        self.sample_count = pipeline.sample_count;
+    }
+
+    fn layouts(&self, kind: SkyKind) -> Vec<&wgpu::BindGroupLayout> {
+        match kind {
+            SkyKind::Procedural => vec![&self.uniform_layout],
+            SkyKind::Cubemap => vec![&self.uniform_layout, &self.cube_layout],
+            SkyKind::Equirect => vec![&self.uniform_layout, &self.equirect_layout],
+        }
+    }
+
+    fn create_image(
+        &self,
+        device: &wgpu::Device,
+        kind: SkyKind,
+        texture: wgpu::Texture,
+        view: &wgpu::TextureView,
+    ) -> SkyImage {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&format!("Sky {}", kind.name())),
+            layout: self.layouts(kind)[1],
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+        SkyImage {
+            _texture: texture,
+            bind_group,
+        }
+    }
+
+    /// Switches the pipeline along with the image, the old ones stay if the
+    /// pipeline fails.
+    fn set_image(
+        &mut self,
+        device: &wgpu::Device,
+        kind: SkyKind,
+        image: Option<SkyImage>,
+    ) -> Result<()> {
+        let pipeline = create_pipeline(
+            device,
+            self.dev,
+            kind,
+            &self.layouts(kind),
+            self.sample_count,
+        )?;
+        self.pipeline = pipeline;
+        self.kind = kind;
+        self.image = image;
+        Ok(())
+    }
+}
+
+/// A pipeline made by [`Sky::create_pipeline`].
+pub struct SkyPipeline {
+    pipeline: wgpu::RenderPipeline,
+    sample_count: u32,
+}
+
+/// Shows the kind of sky and the clock.
+impl fmt::Display for Sky {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} sky, {}, {} hours per second{}",
+            self.kind.name(),
+            self.clock,
+            self.clock.speed,
+            if self.drives_light {
+                ""
+            } else {
+                ", the light doesn't follow the sun"
+            }
+        )
+    }
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    dev: bool,
+    kind: SkyKind,
+    layouts: &[&wgpu::BindGroupLayout],
+    sample_count: u32,
+) -> Result<wgpu::RenderPipeline> {
+    let vert = hot_reload::shader_code(
+        dev,
+        "sky.vert",
+        &Preprocessor::new(),
+        include_bytes!(concat!(env!("OUT_DIR"), "/shaders/sky.vert.spv")),
+    )?;
+    // `build.rs` compiles a variant of the fragment shader for each image
+    // kind.
+    let (preprocessor, bundled): (_, &'static [u8]) = match kind {
+        SkyKind::Procedural => (
+            Preprocessor::new(),
+            include_bytes!(concat!(env!("OUT_DIR"), "/shaders/sky.frag.spv")),
+        ),
+        SkyKind::Cubemap => (
+            Preprocessor::new().define("SKY_CUBEMAP", ""),
+            include_bytes!(concat!(env!("OUT_DIR"), "/shaders/sky.cubemap.frag.spv")),
+        ),
+        SkyKind::Equirect => (
+            Preprocessor::new().define("SKY_EQUIRECT", ""),
+            include_bytes!(concat!(env!("OUT_DIR"), "/shaders/sky.equirect.frag.spv")),
+        ),
+    };
+    let frag = hot_reload::shader_code(dev, "sky.frag", &preprocessor, bundled)?;
+    let mut entries: Vec<&[wgpu::BindGroupLayoutEntry]> = vec![&UNIFORM_LAYOUT_ENTRIES];
+    entries.extend(kind.image_layout_entries());
+    PipelineInterface::new(&[&vert.reflect()?, &frag.reflect()?])?
+        .validate(&entries, &[])
+        .with_context(|| {
+            format!(
+                "Layouts of the {} sky pipeline don't match its shaders",
+                kind.name()
+            )
+        })?;
+
+    Ok(new_render_pipeline(
+        &format!("{} sky", kind.name()),
+        device,
+        layouts,
+        &vert.create_module(device, "sky vertex shader"),
+        &frag.create_module(device, "sky fragment shader"),
+        HDR_FORMAT,
+        Texture::DEPTH_FORMAT,
+        sample_count,
+        &[],
+        PipelineVariant::background(),
+    ))
+}
+
+/// The bits of `value` as a half precision float. Values too large for one
+/// become the largest half, values too small for a normal half become zero.
+fn to_half(value: f32) -> u16 {
+    if value.is_nan() {
+        return 0x7e00;
+    }
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    let mantissa = ((bits >> 13) & 0x3ff) as u16;
+    if exponent >= 0x1f {
+        sign | 0x7bff
+    } else if exponent <= 0 {
+        sign
+    } else {
+        sign | ((exponent as u16) << 10) | mantissa
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sun_follows_the_clock() {
+        let sun = |hours| DayCycle::new(hours).sun_direction();
+        assert!(sun(6.).x > 0.99);
+        assert!(sun(12.).y > 0.8);
+        assert!(sun(18.).x < -0.99);
+        assert!(sun(0.).y < -0.8);
+    }
+
+    #[test]
+    fn clock_wraps_around() {
+        let mut clock = DayCycle::new(23.);
+        clock.speed = 1.;
+        clock.advance(2.);
+        assert!((clock.hours - 1.).abs() < 1e-4);
+        assert_eq!(clock.to_string(), "01:00");
+        clock.set_hours(-1.5);
+        assert_eq!(clock.to_string(), "22:30");
+    }
+
+    #[test]
+    fn lighting_changes_over_the_day() {
+        let lighting = |hours| SkyLighting::new(DayCycle::new(hours).sun_direction());
+        let noon = lighting(12.);
+        let sunset = lighting(17.7);
+        let midnight = lighting(0.);
+
+        assert!(noon.sky_color[2] > noon.sky_color[0], "a blue sky at noon");
+        assert!(
+            sunset.light_color[0] > sunset.light_color[2],
+            "a red sun at sunset"
+        );
+        assert!(midnight.ambient < noon.ambient);
+        assert!(midnight.light_color[0] < noon.light_color[0]);
+        for l in [noon, sunset, midnight].iter() {
+            assert!(l.light_direction.y >= 0., "the light comes from above");
+        }
+    }
+
+    #[test]
+    fn converts_to_half() {
+        assert_eq!(to_half(1.), 0x3c00);
+        assert_eq!(to_half(0.5), 0x3800);
+        assert_eq!(to_half(-2.), 0xc000);
+        assert_eq!(to_half(65504.), 0x7bff);
+        assert_eq!(to_half(1e6), 0x7bff);
+        assert_eq!(to_half(1e-8), 0);
+    }
+}
@@ -0,0 +1,682 @@
+//! Screen-space ambient occlusion. Before the main pass the chunk and the
+//! skinned model are drawn into a G-buffer of normals and distances to the camera. For every pixel,
+//! points in a hemisphere above its surface are checked against it, and the
+//! ones behind other surfaces darken the pixel. A blur pass hides the noise
+//! the hemisphere is turned with, and the main shader scales the ambient
+//! light with the result, and so does the model shader.
+//!
+//! The G-buffer isn't multisampled, and it has its own depth buffer, so the
+//! occlusion doesn't depend on the sample count of the main pass.
+
+use crate::camera::Camera;
+use crate::hot_reload;
+use crate::modeling::instance::InstanceRaw;
+use crate::modeling::skeleton::{JointBuffer, SkinnedVertex};
+use crate::modeling::vertex_index::{Vertex, VertexLayout};
+use crate::quad::QuadRaw;
+use crate::render_pipeline_tools::{new_render_pipeline, PipelineVariant};
+use crate::rendering::preprocessor::Preprocessor;
+use crate::rendering::reflection::{PipelineInterface, ShaderInterface};
+use crate::rendering::shader::ShaderCode;
+use crate::texture::Texture;
+use crate::uniform_matrix::MatrixUniform;
+use anyhow::{ensure, Context, Result};
+use bytemuck::Zeroable;
+use nalgebra::{Matrix4, Vector3};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::fmt;
+use std::num::NonZeroU32;
+use wgpu::util::DeviceExt;
+
+/// Normal and distance to the camera. It has to be precise far away, where
+/// the distances of neighbouring pixels barely differ.
+pub const GBUFFER_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Float;
+pub const OCCLUSION_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+/// Points in the hemisphere, the same as in `ssao.frag`.
+const KERNEL_SIZE: usize = 16;
+/// Side of the square of noise vectors, the same as in the shaders.
+const NOISE_SIZE: u32 = 4;
+/// The kernel and the noise are the same every run, so the image is too.
+const SEED: u64 = 0x55a0;
+
+/// Parameters of the occlusion, they can be changed at any time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SsaoSettings {
+    /// Radius of the hemisphere, in blocks.
+    pub radius: f32,
+    /// How far behind a surface a point has to be to count, against
+    /// surfaces occluding themselves.
+    pub bias: f32,
+    /// Exponent of the result, above 1 darkens the occlusion.
+    pub intensity: f32,
+}
+
+impl Default for SsaoSettings {
+    fn default() -> Self {
+        Self {
+            radius: 0.5,
+            bias: 0.025,
+            intensity: 1.5,
+        }
+    }
+}
+
+impl SsaoSettings {
+    pub fn set_radius(&mut self, radius: f32) -> Result<()> {
+        ensure!(radius > 0., "The radius has to be positive");
+        self.radius = radius;
+        Ok(())
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SsaoUniform {
+    proj_view: [[f32; 4]; 4],
+    inverse_proj_view: [[f32; 4]; 4],
+    view_position: [f32; 3],
+    radius: f32,
+    bias: f32,
+    intensity: f32,
+    // The kernel starts at the next 16 bytes.
+    _padding: [u32; 2],
+    kernel: [[f32; 4]; KERNEL_SIZE],
+}
+
+/// The G-buffer, the noise and their sampler.
+const OCCLUSION_LAYOUT_ENTRIES: [wgpu::BindGroupLayoutEntry; 3] = [
+    texture_entry(0, false),
+    texture_entry(1, true),
+    sampler_entry(2),
+];
+const UNIFORM_LAYOUT_ENTRIES: [wgpu::BindGroupLayoutEntry; 1] = [wgpu::BindGroupLayoutEntry {
+    binding: 0,
+    visibility: wgpu::ShaderStage::FRAGMENT,
+    ty: wgpu::BindingType::Buffer {
+        ty: wgpu::BufferBindingType::Uniform,
+        has_dynamic_offset: false,
+        min_binding_size: None,
+    },
+    count: None,
+}];
+/// The occlusion and its sampler, like `blit/blit.frag`.
+const BLUR_LAYOUT_ENTRIES: [wgpu::BindGroupLayoutEntry; 2] =
+    [texture_entry(0, true), sampler_entry(1)];
+
+const fn texture_entry(binding: u32, filterable: bool) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStage::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    }
+}
+
+const fn sampler_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStage::FRAGMENT,
+        ty: wgpu::BindingType::Sampler {
+            filtering: false,
+            comparison: false,
+        },
+        count: None,
+    }
+}
+
+struct Pipelines {
+    gbuffer: wgpu::RenderPipeline,
+    skinned_gbuffer: wgpu::RenderPipeline,
+    occlusion: wgpu::RenderPipeline,
+    blur: wgpu::RenderPipeline,
+}
+
+pub struct Ssao {
+    pub settings: SsaoSettings,
+    enabled: bool,
+    kernel: [[f32; 4]; KERNEL_SIZE],
+    pipelines: Pipelines,
+    occlusion_layout: wgpu::BindGroupLayout,
+    uniform_layout: wgpu::BindGroupLayout,
+    blur_layout: wgpu::BindGroupLayout,
+    buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    /// Nearest, the G-buffer can't be filtered.
+    sampler: wgpu::Sampler,
+    _noise: wgpu::Texture,
+    noise_view: wgpu::TextureView,
+    _unoccluded: wgpu::Texture,
+    unoccluded_view: wgpu::TextureView,
+    dev: bool,
+}
+
+impl Ssao {
+    /// Enabled to begin with. `matrix_layout` is the layout of the
+    /// [`MatrixUniform`] the chunk is drawn with, `joint_layout` the one of
+    /// the [`JointBuffer`] of skinned models.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        dev: bool,
+        matrix_layout: &wgpu::BindGroupLayout,
+        joint_layout: &wgpu::BindGroupLayout,
+    ) -> Result<Self> {
+        let layout = |label: &str, entries: &[wgpu::BindGroupLayoutEntry]| {
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some(label),
+                entries,
+            })
+        };
+        let occlusion_layout = layout("SSAO input layout", &OCCLUSION_LAYOUT_ENTRIES);
+        let uniform_layout = layout("SSAO uniform layout", &UNIFORM_LAYOUT_ENTRIES);
+        let blur_layout = layout("SSAO blur layout", &BLUR_LAYOUT_ENTRIES);
+        let pipelines = create_pipelines(
+            device,
+            dev,
+            [matrix_layout, joint_layout],
+            [&occlusion_layout, &uniform_layout, &blur_layout],
+        )?;
+
+        let mut rng = StdRng::seed_from_u64(SEED);
+        let kernel = hemisphere_kernel(&mut rng);
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("SSAO uniform"),
+            contents: bytemuck::cast_slice(&[SsaoUniform::zeroed()]),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("SSAO uniform"),
+            layout: &uniform_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("SSAO sampler"),
+            ..Default::default()
+        });
+        let (noise_texture, noise_view) = create_texture(
+            device,
+            queue,
+            "SSAO noise",
+            wgpu::TextureFormat::Rgba8Snorm,
+            NOISE_SIZE,
+            &noise(&mut rng),
+        );
+        let (unoccluded, unoccluded_view) = create_texture(
+            device,
+            queue,
+            "No ambient occlusion",
+            OCCLUSION_FORMAT,
+            1,
+            &[255],
+        );
+        Ok(Self {
+            settings: SsaoSettings::default(),
+            enabled: true,
+            kernel,
+            pipelines,
+            occlusion_layout,
+            uniform_layout,
+            blur_layout,
+            buffer,
+            uniform_bind_group,
+            sampler,
+            _noise: noise_texture,
+            noise_view,
+            _unoccluded: unoccluded,
+            unoccluded_view,
+            dev,
+        })
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns whether that changed anything, then the render graph has to
+    /// be built again.
+    pub fn set_enabled(&mut self, enabled: bool) -> bool {
+        std::mem::replace(&mut self.enabled, enabled) != enabled
+    }
+
+    /// Creates the pipelines again, after their shaders changed. The old ones
+    /// stay if that fails.
+    pub fn reload(
+        &mut self,
+        device: &wgpu::Device,
+        matrix_layout: &wgpu::BindGroupLayout,
+        joint_layout: &wgpu::BindGroupLayout,
+    ) -> Result<()> {
+        self.pipelines = create_pipelines(
+            device,
+            self.dev,
+            [matrix_layout, joint_layout],
+            [
+                &self.occlusion_layout,
+                &self.uniform_layout,
+                &self.blur_layout,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Uploads the camera and the settings, once per frame before the passes.
+    pub fn update(&self, queue: &wgpu::Queue, camera: &Camera) {
+        let proj_view = camera.create_view_proj_model_matrix();
+        let uniform = SsaoUniform {
+            proj_view: proj_view.into(),
+            inverse_proj_view: proj_view
+                .try_inverse()
+                .unwrap_or_else(Matrix4::identity)
+                .into(),
+            view_position: camera.eye.coords.into(),
+            radius: self.settings.radius,
+            bias: self.settings.bias,
+            intensity: self.settings.intensity,
+            _padding: [0; 2],
+            kernel: self.kernel,
+        };
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    /// Draws into a [`GBUFFER_FORMAT`] target, with the vertex shader and the
+    /// buffers of the chunk and the [`MatrixUniform`] in group 0.
+    pub fn gbuffer_pipeline(&self) -> &wgpu::RenderPipeline {
+        &self.pipelines.gbuffer
+    }
+
+    /// Draws skinned models into the G-buffer, with the [`MatrixUniform`] in
+    /// group 0 and the [`JointBuffer`] in group 1.
+    pub fn skinned_gbuffer_pipeline(&self) -> &wgpu::RenderPipeline {
+        &self.pipelines.skinned_gbuffer
+    }
+
+    /// Binds it instead of the occlusion, while SSAO is disabled.
+    pub fn unoccluded(&self) -> &wgpu::TextureView {
+        &self.unoccluded_view
+    }
+
+    /// The sampler the occlusion is bound with.
+    pub fn sampler(&self) -> &wgpu::Sampler {
+        &self.sampler
+    }
+
+    /// Finds the occlusion of every pixel of the G-buffer.
+    pub fn occlusion_pass(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        gbuffer: &wgpu::TextureView,
+        target: &wgpu::TextureView,
+    ) {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("SSAO input"),
+            layout: &self.occlusion_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(gbuffer),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&self.noise_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+        self.draw(
+            encoder,
+            &self.pipelines.occlusion,
+            &[&bind_group, &self.uniform_bind_group],
+            target,
+        );
+    }
+
+    /// Blurs the result of [`Ssao::occlusion_pass`] into `target`.
+    pub fn blur_pass(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        occlusion: &wgpu::TextureView,
+        target: &wgpu::TextureView,
+    ) {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("SSAO blur input"),
+            layout: &self.blur_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(occlusion),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+        self.draw(encoder, &self.pipelines.blur, &[&bind_group], target);
+    }
+
+    /// Draws a fullscreen triangle over all of `target`.
+    fn draw(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline: &wgpu::RenderPipeline,
+        bind_groups: &[&wgpu::BindGroup],
+        target: &wgpu::TextureView,
+    ) {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("SSAO pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        pass.set_pipeline(pipeline);
+        for (i, bind_group) in bind_groups.iter().enumerate() {
+            pass.set_bind_group(i as u32, bind_group, &[]);
+        }
+        pass.draw(0..3, 0..1);
+    }
+}
+
+/// Shows whether SSAO is enabled and the settings.
+impl fmt::Display for Ssao {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = &self.settings;
+        write!(
+            f,
+            "SSAO {}, radius {}, bias {}, intensity {}",
+            if self.enabled { "enabled" } else { "disabled" },
+            s.radius,
+            s.bias,
+            s.intensity
+        )
+    }
+}
+
+/// The G-buffer pipelines, of the chunk and of skinned models, and the
+/// fullscreen ones, checked against their layouts: the occlusion, its uniform
+/// and the blur.
+fn create_pipelines(
+    device: &wgpu::Device,
+    dev: bool,
+    [matrix_layout, joint_layout]: [&wgpu::BindGroupLayout; 2],
+    [occlusion_layout, uniform_layout, blur_layout]: [&wgpu::BindGroupLayout; 3],
+) -> Result<Pipelines> {
+    let shader = |name: &str, bundled: &'static [u8]| {
+        hot_reload::shader_code(dev, name, &Preprocessor::new(), bundled)
+    };
+    let chunk_vert = shader(
+        "shader.vert",
+        include_bytes!(concat!(env!("OUT_DIR"), "/shaders/shader.vert.spv")),
+    )?;
+    // The joints move to group 1, there's no material or light.
+    let skinned_vert = hot_reload::shader_code(
+        dev,
+        "skinned.vert",
+        &Preprocessor::new().define("JOINT_SET", "1"),
+        include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/shaders/skinned.gbuffer.vert.spv"
+        )),
+    )?;
+    let gbuffer_frag = shader(
+        "ssao/gbuffer.frag",
+        include_bytes!(concat!(env!("OUT_DIR"), "/shaders/ssao/gbuffer.frag.spv")),
+    )?;
+    let fullscreen_vert = shader(
+        "blit/blit.vert",
+        include_bytes!(concat!(env!("OUT_DIR"), "/shaders/blit/blit.vert.spv")),
+    )?;
+    let occlusion_frag = shader(
+        "ssao/ssao.frag",
+        include_bytes!(concat!(env!("OUT_DIR"), "/shaders/ssao/ssao.frag.spv")),
+    )?;
+    let blur_frag = shader(
+        "ssao/blur.frag",
+        include_bytes!(concat!(env!("OUT_DIR"), "/shaders/ssao/blur.frag.spv")),
+    )?;
+
+    let buffers = [Vertex::init_buffer_layout(), QuadRaw::init_buffer_layout()];
+    PipelineInterface::new(&[&chunk_vert.reflect()?, &gbuffer_frag.reflect()?])?
+        .validate(&[&MatrixUniform::BIND_GROUP_LAYOUT_ENTRIES], &buffers)
+        .context("Layouts of the G-buffer pipeline don't match its shaders")?;
+    let gbuffer = new_render_pipeline(
+        "SSAO G-buffer",
+        device,
+        &[matrix_layout],
+        &chunk_vert.create_module(device, "SSAO G-buffer vertex shader"),
+        &gbuffer_frag.create_module(device, "SSAO G-buffer fragment shader"),
+        GBUFFER_FORMAT,
+        Texture::DEPTH_FORMAT,
+        1,
+        &buffers,
+        PipelineVariant::default(),
+    );
+
+    let skinned_buffers = [
+        SkinnedVertex::init_buffer_layout(),
+        InstanceRaw::init_buffer_layout(),
+    ];
+    PipelineInterface::new(&[&skinned_vert.reflect()?, &gbuffer_frag.reflect()?])?
+        .validate(
+            &[
+                &MatrixUniform::BIND_GROUP_LAYOUT_ENTRIES,
+                &JointBuffer::BIND_GROUP_LAYOUT_ENTRIES,
+            ],
+            &skinned_buffers,
+        )
+        .context("Layouts of the skinned G-buffer pipeline don't match its shaders")?;
+    let skinned_gbuffer = new_render_pipeline(
+        "SSAO skinned G-buffer",
+        device,
+        &[matrix_layout, joint_layout],
+        &skinned_vert.create_module(device, "SSAO skinned G-buffer vertex shader"),
+        &gbuffer_frag.create_module(device, "SSAO G-buffer fragment shader"),
+        GBUFFER_FORMAT,
+        Texture::DEPTH_FORMAT,
+        1,
+        &skinned_buffers,
+        PipelineVariant::default(),
+    );
+
+    let fullscreen_interface = fullscreen_vert.reflect()?;
+    let fullscreen_vert = fullscreen_vert.create_module(device, "SSAO vertex shader");
+    let fullscreen = (&fullscreen_vert, &fullscreen_interface);
+    let occlusion = create_fullscreen_pipeline(
+        device,
+        "SSAO",
+        fullscreen,
+        &occlusion_frag,
+        &[
+            (occlusion_layout, &OCCLUSION_LAYOUT_ENTRIES),
+            (uniform_layout, &UNIFORM_LAYOUT_ENTRIES),
+        ],
+    )?;
+    let blur = create_fullscreen_pipeline(
+        device,
+        "SSAO blur",
+        fullscreen,
+        &blur_frag,
+        &[(blur_layout, &BLUR_LAYOUT_ENTRIES)],
+    )?;
+    Ok(Pipelines {
+        gbuffer,
+        skinned_gbuffer,
+        occlusion,
+        blur,
+    })
+}
+
+/// Draws `frag` over all of an [`OCCLUSION_FORMAT`] target, with the
+/// fullscreen triangle of `blit/blit.vert`.
+fn create_fullscreen_pipeline(
+    device: &wgpu::Device,
+    label: &str,
+    (vert, vert_interface): (&wgpu::ShaderModule, &ShaderInterface),
+    frag: &ShaderCode,
+    layouts: &[(&wgpu::BindGroupLayout, &[wgpu::BindGroupLayoutEntry])],
+) -> Result<wgpu::RenderPipeline> {
+    let entries = layouts.iter().map(|(_, e)| *e).collect::<Vec<_>>();
+    PipelineInterface::new(&[vert_interface, &frag.reflect()?])?
+        .validate(&entries, &[])
+        .with_context(|| format!("Layouts of the {} pipeline don't match its shaders", label))?;
+    let bind_group_layouts = layouts.iter().map(|(l, _)| *l).collect::<Vec<_>>();
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(label),
+        bind_group_layouts: &bind_group_layouts,
+        push_constant_ranges: &[],
+    });
+    Ok(
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: vert,
+                entry_point: "main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &frag.create_module(device, &format!("{} fragment shader", label)),
+                entry_point: "main",
+                targets: &[OCCLUSION_FORMAT.into()],
+            }),
+        }),
+    )
+}
+
+/// A square texture of `size` pixels with one mip level.
+fn create_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    label: &str,
+    format: wgpu::TextureFormat,
+    size: u32,
+    data: &[u8],
+) -> (wgpu::Texture, wgpu::TextureView) {
+    let extent = wgpu::Extent3d {
+        width: size,
+        height: size,
+        depth_or_array_layers: 1,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: extent,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+    });
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture: &texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+        },
+        data,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: NonZeroU32::new(data.len() as u32 / size),
+            rows_per_image: None,
+        },
+        extent,
+    );
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, view)
+}
+
+/// Points in the hemisphere of radius 1 around +z. There are more of them
+/// close to the center, where occluders matter the most.
+fn hemisphere_kernel(rng: &mut impl Rng) -> [[f32; 4]; KERNEL_SIZE] {
+    let mut kernel = [[0.; 4]; KERNEL_SIZE];
+    for (i, point) in kernel.iter_mut().enumerate() {
+        let direction = Vector3::new(
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(0.0..1.0),
+        )
+        .try_normalize(f32::EPSILON)
+        .unwrap_or_else(Vector3::z);
+        let t = i as f32 / KERNEL_SIZE as f32;
+        let scale = 0.1 + 0.9 * t * t;
+        let p = direction * scale * rng.gen_range(0.0..1.0f32).max(0.1);
+        *point = [p.x, p.y, p.z, 0.];
+    }
+    kernel
+}
+
+/// Random directions in the xy plane, as `Rgba8Snorm` texels.
+fn noise(rng: &mut impl Rng) -> Vec<u8> {
+    let snorm = |v: f32| (v * 127.).round() as i8 as u8;
+    (0..NOISE_SIZE * NOISE_SIZE)
+        .flat_map(|_| {
+            let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+            vec![snorm(angle.cos()), snorm(angle.sin()), 0, 0]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_kernel_is_in_the_hemisphere() {
+        let kernel = hemisphere_kernel(&mut StdRng::seed_from_u64(SEED));
+        for point in kernel.iter() {
+            let length = Vector3::new(point[0], point[1], point[2]).norm();
+            assert!(point[2] >= 0., "{:?} is below the surface", point);
+            assert!(length <= 1. && length > 0., "{:?} is outside", point);
+        }
+        // The last points reach further out than the first ones.
+        let reach = |points: &[[f32; 4]]| {
+            points
+                .iter()
+                .map(|p| Vector3::new(p[0], p[1], p[2]).norm())
+                .fold(0., f32::max)
+        };
+        assert!(reach(&kernel[..4]) < reach(&kernel[KERNEL_SIZE - 4..]));
+    }
+
+    #[test]
+    fn the_kernel_is_the_same_every_run() {
+        let a = hemisphere_kernel(&mut StdRng::seed_from_u64(SEED));
+        let b = hemisphere_kernel(&mut StdRng::seed_from_u64(SEED));
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn noise_is_in_the_plane() {
+        let noise = noise(&mut StdRng::seed_from_u64(SEED));
+        assert_eq!(noise.len(), (NOISE_SIZE * NOISE_SIZE * 4) as usize);
+        for texel in noise.chunks(4) {
+            let [x, y] = [texel[0] as i8 as f32, texel[1] as i8 as f32];
+            assert_eq!(texel[2..], [0, 0]);
+            assert!(((x * x + y * y).sqrt() - 127.).abs() < 2.);
+        }
+    }
+}
@@ -4,10 +4,14 @@ use std::path::Path;
 use anyhow::*;
 use image::GenericImageView;
 
+use crate::mipmap::{MipFilter, MipmapGenerator, MipmapTarget};
+use crate::texture_file::{self, TextureFile};
+
 pub struct Texture {
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
     pub sampler: wgpu::Sampler,
+    pub format: wgpu::TextureFormat,
     pub mip_level_count: u32,
 }
 
@@ -25,54 +29,29 @@ impl Texture {
 
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
 
-    pub fn create_depth_texture(
+    pub fn from_image(
         device: &wgpu::Device,
-        sc_desc: &wgpu::SwapChainDescriptor,
-        label: &str,
-    ) -> Self {
-        let size = wgpu::Extent3d {
-            width: sc_desc.width,
-            height: sc_desc.height,
-            depth_or_array_layers: 1,
-        };
-        let desc = wgpu::TextureDescriptor {
-            label: Some(label),
-            size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: Self::DEPTH_FORMAT,
-            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
-        };
-        let texture = device.create_texture(&desc);
-
-        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            compare: Some(wgpu::CompareFunction::LessEqual),
-            lod_min_clamp: -100.0,
-            lod_max_clamp: 100.0,
-            ..Default::default()
-        });
-
-        Self {
-            texture,
-            view,
-            sampler,
-            mip_level_count: 1,
-        }
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        label: Option<&str>,
+    ) -> Result<Self> {
+        Self::from_image_with_format(
+            device,
+            queue,
+            img,
+            label,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+        )
     }
 
-    pub fn from_image(
+    /// Same as [`Texture::from_image`] but lets the caller pick the format.
+    /// Normal maps and other non-color data should use `Rgba8Unorm`.
+    pub fn from_image_with_format(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         img: &image::DynamicImage,
         label: Option<&str>,
+        format: wgpu::TextureFormat,
     ) -> Result<Self> {
         let rgba = img.to_rgba8();
         let dimensions = img.dimensions();
@@ -89,7 +68,7 @@ impl Texture {
             mip_level_count,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            format,
             usage: wgpu::TextureUsage::SAMPLED
                 | wgpu::TextureUsage::COPY_DST
                 | wgpu::TextureUsage::RENDER_ATTACHMENT,
@@ -124,10 +103,82 @@ impl Texture {
             texture,
             view,
             sampler,
+            format,
             mip_level_count,
         })
     }
 
+    /// Uploads a texture with all of its stored mip levels. The mip levels
+    /// don't need to be generated afterwards.
+    pub fn from_file(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        file: &TextureFile,
+        label: Option<&str>,
+    ) -> Result<Self> {
+        let missing = file.required_features() - device.features();
+        if !missing.is_empty() {
+            bail!("{:?} textures need {:?}", file.format, missing);
+        }
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label,
+            size: wgpu::Extent3d {
+                width: file.width,
+                height: file.height,
+                depth_or_array_layers: file.layer_count,
+            },
+            mip_level_count: file.mip_level_count(),
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: file.format,
+            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+        });
+        let info = file.format.describe();
+        for (level, data) in file.levels.iter().enumerate() {
+            let (blocks_x, blocks_y) =
+                texture_file::level_blocks(file.format, file.width, file.height, level as u32);
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: level as u32,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                data,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(blocks_x * info.block_size as u32),
+                    rows_per_image: NonZeroU32::new(blocks_y * info.block_dimensions.1 as u32),
+                },
+                wgpu::Extent3d {
+                    width: (file.width >> level).max(1),
+                    height: (file.height >> level).max(1),
+                    depth_or_array_layers: file.layer_count,
+                }
+                .physical_size(file.format),
+            );
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Ok(Self {
+            texture,
+            view,
+            sampler,
+            format: file.format,
+            mip_level_count: file.mip_level_count(),
+        })
+    }
+
+    /// Loads an image, or a KTX2 or DDS file with its mip levels.
     #[allow(dead_code)]
     pub fn load<P: AsRef<Path>>(
         device: &wgpu::Device,
@@ -138,72 +189,271 @@ impl Texture {
         let path_copy = path.as_ref().to_path_buf();
         let label = path_copy.to_str();
 
+        if TextureFile::is_texture_file(path.as_ref()) {
+            let file = TextureFile::load(path.as_ref())?;
+            return Self::from_file(device, queue, &file, label);
+        }
         let img = image::open(path)?;
         Self::from_image(device, queue, &img, label)
     }
 
+    /// Describes the texture for the [`MipmapGenerator`]. Only the first
+    /// level has to be filled in.
+    pub fn mipmap_target(&self, filter: MipFilter) -> MipmapTarget {
+        MipmapTarget {
+            texture: &self.texture,
+            format: self.format,
+            mip_level_count: self.mip_level_count,
+            layer_count: 1,
+            filter,
+        }
+    }
+
+    pub const BIND_GROUP_LAYOUT_ENTRIES: [wgpu::BindGroupLayoutEntry; 2] = [
+        wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::Sampler {
+                comparison: false,
+                filtering: true,
+            },
+            count: None,
+        },
+    ];
+
     pub fn texture_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
         device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStage::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        multisampled: false,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStage::FRAGMENT,
-                    ty: wgpu::BindingType::Sampler {
-                        comparison: false,
-                        filtering: true,
-                    },
-                    count: None,
-                },
-            ],
+            entries: &Self::BIND_GROUP_LAYOUT_ENTRIES,
             label: Some("texture_bind_group_layout"),
         })
     }
 }
 
+/// How a [`TextureArray`] is bound, which depends on what the adapter
+/// supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureArrayKind {
+    /// A binding array of separate textures. Needs [`BINDING_ARRAY_FEATURES`].
+    ///
+    /// [`BINDING_ARRAY_FEATURES`]: TextureArrayKind::BINDING_ARRAY_FEATURES
+    BindingArray,
+    /// A single `D2Array` texture with one layer per texture, which every
+    /// adapter supports. All textures must have the same size.
+    Layers,
+}
+
+impl TextureArrayKind {
+    pub const BINDING_ARRAY_FEATURES: wgpu::Features = wgpu::Features::from_bits_truncate(
+        wgpu::Features::SAMPLED_TEXTURE_BINDING_ARRAY.bits()
+            | wgpu::Features::SAMPLED_TEXTURE_ARRAY_NON_UNIFORM_INDEXING.bits()
+            | wgpu::Features::UNSIZED_BINDING_ARRAY.bits(),
+    );
+
+    /// Binding arrays if the adapter supports them, layers otherwise.
+    pub fn for_features(features: wgpu::Features) -> Self {
+        if features.contains(Self::BINDING_ARRAY_FEATURES) {
+            TextureArrayKind::BindingArray
+        } else {
+            TextureArrayKind::Layers
+        }
+    }
+
+    /// Features the device has to be requested with.
+    pub fn required_features(self) -> wgpu::Features {
+        match self {
+            TextureArrayKind::BindingArray => Self::BINDING_ARRAY_FEATURES,
+            TextureArrayKind::Layers => wgpu::Features::empty(),
+        }
+    }
+
+    /// Shader define that selects the matching binding, see `shader.frag`.
+    pub fn define(self) -> Option<&'static str> {
+        match self {
+            TextureArrayKind::BindingArray => None,
+            TextureArrayKind::Layers => Some("TEXTURE_LAYERS"),
+        }
+    }
+}
+
 pub struct TextureArray {
     pub bind_group: wgpu::BindGroup,
     pub bind_group_layout: wgpu::BindGroupLayout,
+    pub len: u32,
+    pub kind: TextureArrayKind,
 }
 
 impl TextureArray {
+    pub fn layout_entries(&self) -> [wgpu::BindGroupLayoutEntry; 2] {
+        Self::entries(self.kind, self.len)
+    }
+
+    fn entries(kind: TextureArrayKind, len: u32) -> [wgpu::BindGroupLayoutEntry; 2] {
+        let (view_dimension, count) = match kind {
+            TextureArrayKind::BindingArray => {
+                (wgpu::TextureViewDimension::D2, NonZeroU32::new(len))
+            }
+            TextureArrayKind::Layers => (wgpu::TextureViewDimension::D2Array, None),
+        };
+        [
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::Sampler {
+                    filtering: true,
+                    comparison: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStage::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension,
+                    multisampled: false,
+                },
+                count,
+            },
+        ]
+    }
+
+    /// Binds `textures` as a binding array.
     pub fn create(
         device: &wgpu::Device,
         textures: Vec<&wgpu::TextureView>,
         sampler: &wgpu::Sampler,
     ) -> Self {
-        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some("Bind group texture array layout."),
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStage::FRAGMENT,
-                    ty: wgpu::BindingType::Sampler {
-                        filtering: true,
-                        comparison: false,
+        Self::bind(
+            device,
+            TextureArrayKind::BindingArray,
+            textures.len() as u32,
+            wgpu::BindingResource::TextureViewArray(textures.as_slice()),
+            sampler,
+        )
+    }
+
+    /// Uploads `images` as the layers of one `D2Array` texture and generates
+    /// the mip levels of every layer.
+    pub fn create_layers(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        mipmaps: &mut MipmapGenerator,
+        images: &[image::RgbaImage],
+        sampler: &wgpu::Sampler,
+    ) -> Result<Self> {
+        let (width, height) = images
+            .first()
+            .map(|i| i.dimensions())
+            .context("A texture array needs at least one texture")?;
+        if let Some(i) = images
+            .iter()
+            .position(|i| i.dimensions() != (width, height))
+        {
+            bail!(
+                "Texture {} is {:?} but the first one is {:?}, all layers must have the same size",
+                i,
+                images[i].dimensions(),
+                (width, height)
+            );
+        }
+
+        let len = images.len() as u32;
+        let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+        let mip_level_count = 1 + ((width.max(height) as f32).log2().floor() as u32);
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Texture array layers"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: len,
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsage::SAMPLED
+                | wgpu::TextureUsage::COPY_DST
+                | wgpu::TextureUsage::RENDER_ATTACHMENT,
+        });
+        for (layer, image) in images.iter().enumerate() {
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer as u32,
                     },
-                    count: None,
                 },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStage::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        multisampled: false,
-                    },
-                    count: NonZeroU32::new(textures.len() as u32),
+                image,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(4 * width),
+                    rows_per_image: None,
                 },
-            ],
+                wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+        mipmaps.generate_all(
+            device,
+            queue,
+            &[MipmapTarget {
+                texture: &texture,
+                format,
+                mip_level_count,
+                layer_count: len,
+                filter: MipFilter::Linear,
+            }],
+        );
+        Ok(Self::from_layers(device, &texture, len, sampler))
+    }
+
+    /// Binds the layers of an existing texture, e.g. one made with
+    /// [`Texture::from_file`].
+    pub fn from_layers(
+        device: &wgpu::Device,
+        texture: &wgpu::Texture,
+        len: u32,
+        sampler: &wgpu::Sampler,
+    ) -> Self {
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        Self::bind(
+            device,
+            TextureArrayKind::Layers,
+            len,
+            wgpu::BindingResource::TextureView(&view),
+            sampler,
+        )
+    }
+
+    fn bind(
+        device: &wgpu::Device,
+        kind: TextureArrayKind,
+        len: u32,
+        textures: wgpu::BindingResource,
+        sampler: &wgpu::Sampler,
+    ) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bind group texture array layout."),
+            entries: &Self::entries(kind, len),
         });
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("Texture array bind group"),
@@ -215,13 +465,15 @@ impl TextureArray {
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: wgpu::BindingResource::TextureViewArray(textures.as_slice()),
+                    resource: textures,
                 },
             ],
         });
         Self {
             bind_group,
             bind_group_layout,
+            len,
+            kind,
         }
     }
 }
@@ -0,0 +1,400 @@
+//! Textures that are stored with their mip levels, in KTX2 or DDS files, so
+//! nothing has to be generated when they're loaded. Block compressed formats
+//! need `TEXTURE_COMPRESSION_BC`.
+
+use crate::ktx2::Ktx2;
+use crate::mip_chain::{self, DownsampleFilter};
+use anyhow::{bail, ensure, Context, Result};
+use std::path::Path;
+
+pub struct TextureFile {
+    pub format: wgpu::TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    pub layer_count: u32,
+    /// Every mip level, largest first, with the layers of a level one after
+    /// another.
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl TextureFile {
+    /// Whether `path` is a file [`TextureFile::load`] can read.
+    pub fn is_texture_file(path: &Path) -> bool {
+        matches!(
+            path.extension().and_then(|e| e.to_str()),
+            Some("ktx2") | Some("dds")
+        )
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("ktx2") => Self::from_ktx2(Ktx2::load(path)?),
+            Some("dds") => {
+                let bytes =
+                    std::fs::read(path).with_context(|| format!("Failed to read {:?}", path))?;
+                Self::parse_dds(&bytes).with_context(|| format!("Invalid DDS file {:?}", path))
+            }
+            _ => bail!("{:?} isn't a KTX2 or DDS file", path),
+        }
+    }
+
+    /// Builds the mip levels of `image` on the CPU.
+    pub fn from_image(image: &image::RgbaImage, filter: DownsampleFilter, srgb: bool) -> Self {
+        let (width, height) = image.dimensions();
+        Self {
+            format: if srgb {
+                wgpu::TextureFormat::Rgba8UnormSrgb
+            } else {
+                wgpu::TextureFormat::Rgba8Unorm
+            },
+            width,
+            height,
+            layer_count: 1,
+            levels: mip_chain::mip_chain(image, filter, srgb)
+                .into_iter()
+                .map(|level| level.into_raw())
+                .collect(),
+        }
+    }
+
+    pub fn from_ktx2(ktx2: Ktx2) -> Result<Self> {
+        let format = vk_format(ktx2.vk_format)
+            .with_context(|| format!("VkFormat {} isn't supported", ktx2.vk_format))?;
+        let file = Self {
+            format,
+            width: ktx2.width,
+            height: ktx2.height,
+            layer_count: ktx2.layer_count,
+            levels: ktx2.levels,
+        };
+        file.check_sizes()?;
+        Ok(file)
+    }
+
+    /// Reads a DDS file. Files without the DX10 header don't say whether
+    /// they're sRGB, so they're taken to be color textures.
+    pub fn parse_dds(bytes: &[u8]) -> Result<Self> {
+        const HEADER_SIZE: usize = 4 + 124;
+        const DX10_HEADER_SIZE: usize = 20;
+        const DDPF_FOURCC: u32 = 0x4;
+        const DDPF_RGB: u32 = 0x40;
+        const DDSCAPS2_CUBEMAP: u32 = 0x200;
+        const DDSCAPS2_VOLUME: u32 = 0x20_0000;
+
+        ensure!(
+            bytes.len() >= HEADER_SIZE && &bytes[..4] == b"DDS ",
+            "not a DDS file"
+        );
+        let u32_at = |offset: usize| {
+            let mut word = [0; 4];
+            word.copy_from_slice(&bytes[offset..offset + 4]);
+            u32::from_le_bytes(word)
+        };
+        let height = u32_at(12);
+        let width = u32_at(16);
+        let mip_count = u32_at(28).max(1);
+        let pixel_flags = u32_at(80);
+        let four_cc = &bytes[84..88];
+        let caps2 = u32_at(112);
+        if caps2 & (DDSCAPS2_CUBEMAP | DDSCAPS2_VOLUME) != 0 {
+            bail!("cube maps and volume textures aren't supported");
+        }
+
+        let (format, layer_count, data_offset) = if pixel_flags & DDPF_FOURCC != 0 {
+            if four_cc == b"DX10" {
+                ensure!(
+                    bytes.len() >= HEADER_SIZE + DX10_HEADER_SIZE,
+                    "the DX10 header is cut off"
+                );
+                let dxgi = u32_at(HEADER_SIZE);
+                let format = dxgi_format(dxgi)
+                    .with_context(|| format!("DXGI format {} isn't supported", dxgi))?;
+                let array_size = u32_at(HEADER_SIZE + 12).max(1);
+                (format, array_size, HEADER_SIZE + DX10_HEADER_SIZE)
+            } else {
+                let format = match four_cc {
+                    b"DXT1" => wgpu::TextureFormat::Bc1RgbaUnormSrgb,
+                    b"DXT3" => wgpu::TextureFormat::Bc2RgbaUnormSrgb,
+                    b"DXT5" => wgpu::TextureFormat::Bc3RgbaUnormSrgb,
+                    b"ATI1" | b"BC4U" => wgpu::TextureFormat::Bc4RUnorm,
+                    b"ATI2" | b"BC5U" => wgpu::TextureFormat::Bc5RgUnorm,
+                    _ => bail!(
+                        "FourCC {:?} isn't supported",
+                        String::from_utf8_lossy(four_cc)
+                    ),
+                };
+                (format, 1, HEADER_SIZE)
+            }
+        } else if pixel_flags & DDPF_RGB != 0 && u32_at(88) == 32 {
+            let format = match (u32_at(92), u32_at(100)) {
+                (0xff, 0xff_0000) => wgpu::TextureFormat::Rgba8UnormSrgb,
+                (0xff_0000, 0xff) => wgpu::TextureFormat::Bgra8UnormSrgb,
+                masks => bail!("32 bit RGB with masks {:x?} isn't supported", masks),
+            };
+            (format, 1, HEADER_SIZE)
+        } else {
+            bail!("only block compressed and 32 bit RGBA textures are supported");
+        };
+
+        check_level_count(width, height, mip_count)?;
+        // DDS stores all levels of a layer, then the next layer. We want all
+        // layers of a level together.
+        let level_sizes = (0..mip_count)
+            .map(|level| level_size(format, width, height, level))
+            .collect::<Vec<_>>();
+        let layer_size = level_sizes.iter().sum::<usize>();
+        ensure!(
+            bytes.len() >= data_offset + layer_size * layer_count as usize,
+            "the file is too short for {} mip levels and {} layers",
+            mip_count,
+            layer_count
+        );
+        let mut levels = vec![Vec::new(); mip_count as usize];
+        for layer in 0..layer_count as usize {
+            let mut offset = data_offset + layer * layer_size;
+            for (level, size) in level_sizes.iter().enumerate() {
+                levels[level].extend_from_slice(&bytes[offset..offset + size]);
+                offset += size;
+            }
+        }
+        Ok(Self {
+            format,
+            width,
+            height,
+            layer_count,
+            levels,
+        })
+    }
+
+    pub fn mip_level_count(&self) -> u32 {
+        self.levels.len() as u32
+    }
+
+    /// Features the device needs to create a texture of this format.
+    pub fn required_features(&self) -> wgpu::Features {
+        self.format.describe().required_features
+    }
+
+    fn check_sizes(&self) -> Result<()> {
+        check_level_count(self.width, self.height, self.mip_level_count())?;
+        for (level, data) in self.levels.iter().enumerate() {
+            let expected = level_size(self.format, self.width, self.height, level as u32)
+                * self.layer_count as usize;
+            ensure!(
+                data.len() == expected,
+                "mip level {} has {} bytes, expected {}",
+                level,
+                data.len(),
+                expected
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Rejects empty textures, and more levels than halving the larger side
+/// down to 1 texel gives, before any level size is computed from them.
+fn check_level_count(width: u32, height: u32, level_count: u32) -> Result<()> {
+    ensure!(width > 0 && height > 0, "the texture is {}x{}", width, height);
+    let max_levels = 32 - width.max(height).leading_zeros();
+    ensure!(
+        (1..=max_levels).contains(&level_count),
+        "a {}x{} texture can't have {} mip levels",
+        width,
+        height,
+        level_count
+    );
+    Ok(())
+}
+
+/// Size of mip level `level` of one layer in bytes. Block compressed levels
+/// are made of whole blocks, even when the level is smaller than a block.
+pub fn level_size(format: wgpu::TextureFormat, width: u32, height: u32, level: u32) -> usize {
+    let (blocks_x, blocks_y) = level_blocks(format, width, height, level);
+    (blocks_x * blocks_y) as usize * format.describe().block_size as usize
+}
+
+/// Width and height of mip level `level` in blocks.
+pub fn level_blocks(
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+    level: u32,
+) -> (u32, u32) {
+    let info = format.describe();
+    let (block_width, block_height) = info.block_dimensions;
+    let (block_width, block_height) = (block_width as u32, block_height as u32);
+    let width = (width >> level).max(1);
+    let height = (height >> level).max(1);
+    (
+        (width + block_width - 1) / block_width,
+        (height + block_height - 1) / block_height,
+    )
+}
+
+fn vk_format(format: u32) -> Option<wgpu::TextureFormat> {
+    use wgpu::TextureFormat::*;
+    Some(match format {
+        37 => Rgba8Unorm,
+        43 => Rgba8UnormSrgb,
+        44 => Bgra8Unorm,
+        50 => Bgra8UnormSrgb,
+        133 => Bc1RgbaUnorm,
+        134 => Bc1RgbaUnormSrgb,
+        135 => Bc2RgbaUnorm,
+        136 => Bc2RgbaUnormSrgb,
+        137 => Bc3RgbaUnorm,
+        138 => Bc3RgbaUnormSrgb,
+        139 => Bc4RUnorm,
+        140 => Bc4RSnorm,
+        141 => Bc5RgUnorm,
+        142 => Bc5RgSnorm,
+        143 => Bc6hRgbUfloat,
+        144 => Bc6hRgbSfloat,
+        145 => Bc7RgbaUnorm,
+        146 => Bc7RgbaUnormSrgb,
+        _ => return None,
+    })
+}
+
+fn dxgi_format(format: u32) -> Option<wgpu::TextureFormat> {
+    use wgpu::TextureFormat::*;
+    Some(match format {
+        28 => Rgba8Unorm,
+        29 => Rgba8UnormSrgb,
+        87 => Bgra8Unorm,
+        91 => Bgra8UnormSrgb,
+        71 => Bc1RgbaUnorm,
+        72 => Bc1RgbaUnormSrgb,
+        74 => Bc2RgbaUnorm,
+        75 => Bc2RgbaUnormSrgb,
+        77 => Bc3RgbaUnorm,
+        78 => Bc3RgbaUnormSrgb,
+        80 => Bc4RUnorm,
+        81 => Bc4RSnorm,
+        83 => Bc5RgUnorm,
+        84 => Bc5RgSnorm,
+        95 => Bc6hRgbUfloat,
+        96 => Bc6hRgbSfloat,
+        98 => Bc7RgbaUnorm,
+        99 => Bc7RgbaUnormSrgb,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A DDS file with the DX10 header, where every byte of the data is the
+    /// layer times 100 plus its index within the layer.
+    fn dds(dxgi: u32, width: u32, height: u32, mip_count: u32, array_size: u32) -> Vec<u8> {
+        let mut header = [0u32; 31];
+        header[0] = 124; // dwSize
+        header[2] = height;
+        header[3] = width;
+        header[6] = mip_count;
+        header[18] = 32; // ddspf.dwSize
+        header[19] = 0x4; // DDPF_FOURCC
+        header[20] = u32::from_le_bytes(*b"DX10");
+        header[26] = 0x1000; // DDSCAPS_TEXTURE
+        let dx10 = [dxgi, 3, 0, array_size, 0];
+
+        let mut bytes = b"DDS ".to_vec();
+        for word in header.iter().chain(dx10.iter()) {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        let format = dxgi_format(dxgi).unwrap();
+        let layer_size = (0..mip_count)
+            .map(|level| level_size(format, width, height, level))
+            .sum::<usize>();
+        for layer in 0..array_size as usize {
+            bytes.extend((0..layer_size).map(|i| (layer * 100 + i) as u8));
+        }
+        bytes
+    }
+
+    #[test]
+    fn parses_dx10_dds() {
+        let file = TextureFile::parse_dds(&dds(72, 8, 4, 3, 2)).unwrap();
+        assert_eq!(file.format, wgpu::TextureFormat::Bc1RgbaUnormSrgb);
+        assert_eq!((file.width, file.height, file.layer_count), (8, 4, 2));
+        assert_eq!(file.mip_level_count(), 3);
+        // 2x1, 1x1 and 1x1 blocks of 8 bytes, with the layers of a level
+        // next to each other.
+        let layer = |layer: u8, range: std::ops::Range<u8>| range.map(move |i| layer * 100 + i);
+        let expected = |range: std::ops::Range<u8>| {
+            layer(0, range.clone())
+                .chain(layer(1, range))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(file.levels[0], expected(0..16));
+        assert_eq!(file.levels[1], expected(16..24));
+        assert_eq!(file.levels[2], expected(24..32));
+        file.check_sizes().unwrap();
+    }
+
+    #[test]
+    fn rejects_broken_dds() {
+        let bytes = dds(29, 4, 4, 3, 1);
+        let file = TextureFile::parse_dds(&bytes).unwrap();
+        assert_eq!(file.format, wgpu::TextureFormat::Rgba8UnormSrgb);
+        assert_eq!(file.levels[2].len(), 4);
+        assert!(TextureFile::parse_dds(&bytes[..bytes.len() - 1]).is_err());
+        assert!(TextureFile::parse_dds(&bytes[..4 + 124 + 10]).is_err());
+        let mut unsupported = bytes.clone();
+        unsupported[128] = 2;
+        assert!(TextureFile::parse_dds(&unsupported).is_err());
+        assert!(TextureFile::parse_dds(b"PNG ").is_err());
+    }
+
+    #[test]
+    fn rejects_impossible_level_counts() {
+        let bytes = dds(29, 4, 4, 3, 1);
+        let with_word = |offset: usize, value: u32| {
+            let mut bytes = bytes.clone();
+            bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+            TextureFile::parse_dds(&bytes)
+        };
+        // dwMipMapCount, dwHeight and dwWidth.
+        assert!(with_word(28, 4).is_err());
+        assert!(with_word(28, 40).is_err());
+        assert!(with_word(12, 0).is_err());
+        assert!(with_word(16, 0).is_err());
+
+        let ktx2 = |width: u32, level_count: usize| Ktx2 {
+            vk_format: crate::ktx2::VK_FORMAT_R8G8B8A8_SRGB,
+            width,
+            height: 1,
+            layer_count: 1,
+            levels: vec![Vec::new(); level_count],
+        };
+        assert!(TextureFile::from_ktx2(ktx2(4, 40)).is_err());
+        assert!(TextureFile::from_ktx2(ktx2(4, 0)).is_err());
+        assert!(TextureFile::from_ktx2(ktx2(0, 1)).is_err());
+    }
+
+    #[test]
+    fn block_compressed_levels_are_whole_blocks() {
+        use wgpu::TextureFormat::*;
+        assert_eq!(level_size(Bc1RgbaUnorm, 1, 1, 0), 8);
+        assert_eq!(level_size(Bc3RgbaUnorm, 2, 3, 0), 16);
+        assert_eq!(level_size(Bc7RgbaUnormSrgb, 16, 16, 3), 16);
+        assert_eq!(level_size(Bc7RgbaUnormSrgb, 16, 16, 6), 16);
+        assert_eq!(level_size(Bc4RUnorm, 5, 9, 0), 2 * 3 * 8);
+        assert_eq!(level_blocks(Bc1RgbaUnorm, 5, 9, 0), (2, 3));
+        assert_eq!(level_blocks(Bc1RgbaUnorm, 5, 9, 1), (1, 1));
+        assert_eq!(level_size(Rgba8Unorm, 5, 3, 1), 2 * 4);
+        assert_eq!(level_size(Rgba8Unorm, 5, 3, 4), 4);
+    }
+
+    #[test]
+    fn from_image_matches_the_expected_sizes() {
+        let image = image::RgbaImage::new(6, 3);
+        let file = TextureFile::from_image(&image, DownsampleFilter::Box, true);
+        assert_eq!(file.format, wgpu::TextureFormat::Rgba8UnormSrgb);
+        assert_eq!(file.mip_level_count(), 3);
+        file.check_sizes().unwrap();
+    }
+}
@@ -1,11 +1,17 @@
 use crate::camera::Camera;
+use crate::debug_view::DebugView;
+use crate::rendering::reflection::{PipelineInterface, ShaderInterface};
+use anyhow::Result;
 use wgpu::util::DeviceExt;
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Data {
     pub proj_view_model_matrix: [[f32; 4]; 4],
-    pub view_position: [f32; 4],
+    pub view_position: [f32; 3],
+    /// [`DebugView::shader_mode`](crate::debug_view::DebugView::shader_mode)
+    /// of the current view, it takes the padding after `view_position`.
+    pub debug_view: u32,
 }
 
 #[repr(C)]
@@ -18,13 +24,41 @@ pub struct MatrixUniform {
 }
 
 impl MatrixUniform {
-    pub fn new(device: &wgpu::Device, camera: &Camera) -> Self {
+    pub const BIND_GROUP_LAYOUT_ENTRIES: [wgpu::BindGroupLayoutEntry; 1] =
+        [wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStage::VERTEX_FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }];
+
+    /// Generates the layout from the main shaders, which read the uniform in
+    /// both stages. The other pipelines are checked against
+    /// `BIND_GROUP_LAYOUT_ENTRIES`, so a test makes sure the two agree.
+    pub fn layout_entries() -> Result<Vec<wgpu::BindGroupLayoutEntry>> {
+        let vert = ShaderInterface::from_spirv(include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/shaders/shader.vert.spv"
+        )))?;
+        let frag = ShaderInterface::from_spirv(include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/shaders/shader.frag.spv"
+        )))?;
+        Ok(PipelineInterface::new(&[&vert, &frag])?.bind_group_layout_entries(0)?)
+    }
+
+    pub fn new(device: &wgpu::Device, camera: &Camera) -> Result<Self> {
         let proj_view_model_matrix: [[f32; 4]; 4] = camera.create_view_proj_model_matrix().into();
-        let view_position: [f32; 4] = [0., 0., 0., 0.];
+        let view_position: [f32; 3] = [0., 0., 0.];
 
         let data = Data {
             proj_view_model_matrix,
             view_position,
+            debug_view: DebugView::Shaded.shader_mode(),
         };
 
         let matrix_uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -35,16 +69,7 @@ impl MatrixUniform {
         let uniform_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: Some("uniform_bind_group_layout"),
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
+                entries: &Self::layout_entries()?,
             });
         let matrix_uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("uniform_bind_group"),
@@ -54,16 +79,29 @@ impl MatrixUniform {
                 resource: matrix_uniform_buffer.as_entire_binding(),
             }],
         });
-        MatrixUniform {
+        Ok(MatrixUniform {
             data,
             bind_group: matrix_uniform_bind_group,
             bind_group_layout: uniform_bind_group_layout,
             buffer: matrix_uniform_buffer,
-        }
+        })
     }
 
     pub fn update_uniform(&mut self, camera: &mut Camera) {
-        self.data.view_position = camera.eye.to_homogeneous().into();
+        self.data.view_position = camera.eye.coords.into();
         self.data.proj_view_model_matrix = camera.create_view_proj_model_matrix().into();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_layout_matches_the_hand_written_one() {
+        assert_eq!(
+            MatrixUniform::layout_entries().unwrap(),
+            MatrixUniform::BIND_GROUP_LAYOUT_ENTRIES
+        );
+    }
+}
@@ -0,0 +1,2 @@
+#include "c.glsl"
+float b;
@@ -0,0 +1,3 @@
+#include "a.glsl"
+#include "b.glsl"
+void main() {}
@@ -0,0 +1,187 @@
+//! Vertex buffer layouts that are derived from the struct they describe.
+//!
+//! ```ignore
+//! #[repr(C)]
+//! #[derive(Clone, Copy, VertexLayout)]
+//! #[vertex(step_mode = "instance", location = 3)]
+//! struct InstanceRaw {
+//!     matrix: [[f32; 4]; 4],   // locations 3 to 6, one per column
+//!     #[vertex(format = "Uint32")]
+//!     texture_offset: u32,     // location 7
+//! }
+//! ```
+//!
+//! Every field takes up the next free shader locations and gets the format of
+//! its type through [`VertexAttribute`]. On the struct, `step_mode` is
+//! `"vertex"` (the default) or `"instance"` and `location` is the first shader
+//! location. On a field, `format` overrides the format and makes the field a
+//! single attribute, `location` moves the field (and the ones after it) to
+//! another location and `skip` leaves it out of the layout.
+//!
+//! Offsets follow the `#[repr(C)]` rules, which the derive requires.
+
+pub use vertex_layout_derive::VertexLayout;
+
+#[doc(hidden)]
+pub use wgpu;
+
+pub trait VertexLayout {
+    fn init_buffer_layout<'a>() -> wgpu::VertexBufferLayout<'a>;
+}
+
+/// Types that can be read by a vertex shader. Types that take up several
+/// locations, like matrices, have one format per location.
+pub trait VertexAttribute {
+    const FORMATS: &'static [wgpu::VertexFormat];
+}
+
+macro_rules! impl_vertex_attribute {
+    ($($ty:ty => $format:ident,)*) => {
+        $(
+            impl VertexAttribute for $ty {
+                const FORMATS: &'static [wgpu::VertexFormat] = &[wgpu::VertexFormat::$format];
+            }
+        )*
+    };
+}
+
+impl_vertex_attribute! {
+    f32 => Float32,
+    [f32; 1] => Float32,
+    [f32; 2] => Float32x2,
+    [f32; 3] => Float32x3,
+    [f32; 4] => Float32x4,
+    u32 => Uint32,
+    [u32; 1] => Uint32,
+    [u32; 2] => Uint32x2,
+    [u32; 3] => Uint32x3,
+    [u32; 4] => Uint32x4,
+    i32 => Sint32,
+    [i32; 1] => Sint32,
+    [i32; 2] => Sint32x2,
+    [i32; 3] => Sint32x3,
+    [i32; 4] => Sint32x4,
+    f64 => Float64,
+    [f64; 2] => Float64x2,
+    [f64; 3] => Float64x3,
+    [f64; 4] => Float64x4,
+    [u8; 2] => Uint8x2,
+    [u8; 4] => Uint8x4,
+    [i8; 2] => Sint8x2,
+    [i8; 4] => Sint8x4,
+    [u16; 2] => Uint16x2,
+    [u16; 4] => Uint16x4,
+    [i16; 2] => Sint16x2,
+    [i16; 4] => Sint16x4,
+}
+
+// Matrices are read as one vector per column.
+macro_rules! impl_matrix {
+    ($($ty:ty => [$format:ident; $columns:literal],)*) => {
+        $(
+            impl VertexAttribute for $ty {
+                const FORMATS: &'static [wgpu::VertexFormat] =
+                    &[wgpu::VertexFormat::$format; $columns];
+            }
+        )*
+    };
+}
+
+impl_matrix! {
+    [[f32; 2]; 2] => [Float32x2; 2],
+    [[f32; 3]; 3] => [Float32x3; 3],
+    [[f32; 4]; 4] => [Float32x4; 4],
+}
+
+/// Size of a vertex format in bytes. `VertexFormat::size` can't be used in
+/// constants.
+pub const fn format_size(format: wgpu::VertexFormat) -> u64 {
+    use wgpu::VertexFormat::*;
+    match format {
+        Uint8x2 | Sint8x2 | Unorm8x2 | Snorm8x2 => 2,
+        Uint8x4 | Sint8x4 | Unorm8x4 | Snorm8x4 | Uint16x2 | Sint16x2 | Unorm16x2 | Snorm16x2
+        | Float16x2 | Float32 | Uint32 | Sint32 => 4,
+        Uint16x4 | Sint16x4 | Unorm16x4 | Snorm16x4 | Float16x4 | Float32x2 | Uint32x2
+        | Sint32x2 | Float64 => 8,
+        Float32x3 | Uint32x3 | Sint32x3 => 12,
+        Float32x4 | Uint32x4 | Sint32x4 | Float64x2 => 16,
+        Float64x3 => 24,
+        Float64x4 => 32,
+    }
+}
+
+/// Used by the derive to build the attributes in a constant.
+#[doc(hidden)]
+pub struct Attributes<const N: usize> {
+    attributes: [wgpu::VertexAttribute; N],
+    len: usize,
+    offset: u64,
+    location: u32,
+}
+
+impl<const N: usize> Attributes<N> {
+    pub const fn new(location: u32) -> Self {
+        Self {
+            attributes: [wgpu::VertexAttribute {
+                format: wgpu::VertexFormat::Float32,
+                offset: 0,
+                shader_location: 0,
+            }; N],
+            len: 0,
+            offset: 0,
+            location,
+        }
+    }
+
+    /// Adds a field of `size` bytes, which is read as `formats`, one location
+    /// each, spread evenly over the field.
+    pub const fn field(
+        mut self,
+        formats: &[wgpu::VertexFormat],
+        size: usize,
+        align: usize,
+        location: Option<u32>,
+    ) -> Self {
+        self = self.align(align);
+        if let Some(location) = location {
+            self.location = location;
+        }
+        let stride = size as u64 / formats.len() as u64;
+        let mut i = 0;
+        while i < formats.len() {
+            if format_size(formats[i]) > stride {
+                panic!("vertex format is bigger than the field");
+            }
+            self.attributes[self.len] = wgpu::VertexAttribute {
+                format: formats[i],
+                offset: self.offset + i as u64 * stride,
+                shader_location: self.location,
+            };
+            self.len += 1;
+            self.location += 1;
+            i += 1;
+        }
+        self.offset += size as u64;
+        self
+    }
+
+    /// Moves past a field that isn't part of the layout.
+    pub const fn skip(mut self, size: usize, align: usize) -> Self {
+        self = self.align(align);
+        self.offset += size as u64;
+        self
+    }
+
+    pub const fn finish(self) -> [wgpu::VertexAttribute; N] {
+        if self.len != N {
+            panic!("wrong number of vertex attributes");
+        }
+        self.attributes
+    }
+
+    const fn align(mut self, align: usize) -> Self {
+        let align = align as u64;
+        self.offset += (align - self.offset % align) % align;
+        self
+    }
+}
@@ -0,0 +1,12 @@
+use vertex_layout::VertexLayout;
+
+#[repr(C)]
+#[derive(Clone, Copy, VertexLayout)]
+struct Vertex {
+    #[vertex(format = "Float32x4")]
+    position: [f32; 3],
+}
+
+fn main() {
+    let _ = Vertex::init_buffer_layout();
+}
@@ -0,0 +1,17 @@
+use vertex_layout::VertexLayout;
+
+#[repr(C)]
+#[derive(Clone, Copy, VertexLayout)]
+#[vertex(step_mode = "per_instance")]
+struct Instance {
+    offset: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, VertexLayout)]
+struct Vertex {
+    #[vertex(stride = 4)]
+    position: [f32; 3],
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use vertex_layout::VertexLayout;
+
+#[derive(Clone, Copy, VertexLayout)]
+struct Vertex {
+    position: [f32; 3],
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use vertex_layout::VertexLayout;
+
+#[repr(C)]
+#[derive(Clone, Copy, VertexLayout)]
+struct Vertex {
+    position: [f32; 3],
+    flags: u64,
+}
+
+fn main() {}
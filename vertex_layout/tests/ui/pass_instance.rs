@@ -0,0 +1,41 @@
+use vertex_layout::VertexLayout;
+use wgpu::{InputStepMode, VertexFormat};
+
+#[repr(C)]
+#[derive(Clone, Copy, VertexLayout)]
+#[vertex(step_mode = "instance", location = 3)]
+struct InstanceRaw {
+    matrix: [[f32; 4]; 4],
+    n_matrix: [[f32; 3]; 3],
+    #[vertex(skip)]
+    _unused: u8,
+    #[vertex(format = "Uint32", location = 12)]
+    texture_offset: u32,
+    scale: f32,
+}
+
+fn main() {
+    let layout = InstanceRaw::init_buffer_layout();
+    assert_eq!(layout.array_stride, 112);
+    assert_eq!(layout.step_mode, InputStepMode::Instance);
+    let attributes = layout
+        .attributes
+        .iter()
+        .map(|a| (a.format, a.offset, a.shader_location))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        attributes,
+        [
+            (VertexFormat::Float32x4, 0, 3),
+            (VertexFormat::Float32x4, 16, 4),
+            (VertexFormat::Float32x4, 32, 5),
+            (VertexFormat::Float32x4, 48, 6),
+            (VertexFormat::Float32x3, 64, 7),
+            (VertexFormat::Float32x3, 76, 8),
+            (VertexFormat::Float32x3, 88, 9),
+            // The skipped byte is padded to the alignment of `u32`.
+            (VertexFormat::Uint32, 104, 12),
+            (VertexFormat::Float32, 108, 13),
+        ]
+    );
+}
@@ -0,0 +1,29 @@
+use vertex_layout::VertexLayout;
+use wgpu::{InputStepMode, VertexFormat};
+
+#[repr(C)]
+#[derive(Clone, Copy, VertexLayout)]
+struct Vertex {
+    position: [f32; 3],
+    tex_cords: [f32; 2],
+    normal: [f32; 3],
+}
+
+fn main() {
+    let layout = Vertex::init_buffer_layout();
+    assert_eq!(layout.array_stride, 32);
+    assert_eq!(layout.step_mode, InputStepMode::Vertex);
+    let attributes = layout
+        .attributes
+        .iter()
+        .map(|a| (a.format, a.offset, a.shader_location))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        attributes,
+        [
+            (VertexFormat::Float32x3, 0, 0),
+            (VertexFormat::Float32x2, 12, 1),
+            (VertexFormat::Float32x3, 20, 2),
+        ]
+    );
+}
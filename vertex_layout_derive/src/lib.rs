@@ -0,0 +1,182 @@
+//! `#[derive(VertexLayout)]`, see the `vertex_layout` crate.
+
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Error, Lit, Meta, NestedMeta, Result};
+
+#[proc_macro_derive(VertexLayout, attributes(vertex))]
+pub fn derive_vertex_layout(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+#[derive(Default)]
+struct Options {
+    step_mode: Option<syn::Ident>,
+    location: Option<u32>,
+    format: Option<syn::Ident>,
+    skip: bool,
+}
+
+fn expand(input: &DeriveInput) -> Result<TokenStream> {
+    let name = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "VertexLayout can't be derived for generic structs",
+        ));
+    }
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return Err(Error::new_spanned(
+                name,
+                "VertexLayout can only be derived for structs",
+            ))
+        }
+    };
+    if !has_repr_c(&input.attrs) {
+        return Err(Error::new_spanned(
+            name,
+            "VertexLayout needs #[repr(C)] to know the field offsets",
+        ));
+    }
+
+    let options = parse_options(&input.attrs)?;
+    if options.format.is_some() || options.skip {
+        return Err(Error::new_spanned(
+            name,
+            "`format` and `skip` can only be used on fields",
+        ));
+    }
+    let step_mode = options
+        .step_mode
+        .unwrap_or_else(|| syn::Ident::new("Vertex", Span::call_site()));
+    let start = options.location.unwrap_or(0);
+
+    let mut lens = Vec::new();
+    let mut calls = Vec::new();
+    for field in fields.iter() {
+        let options = parse_options(&field.attrs)?;
+        if options.step_mode.is_some() {
+            return Err(Error::new_spanned(
+                field,
+                "`step_mode` can only be used on the struct",
+            ));
+        }
+        let ty = &field.ty;
+        let size = quote!(::std::mem::size_of::<#ty>());
+        let align = quote!(::std::mem::align_of::<#ty>());
+        if options.skip {
+            calls.push(quote!(.skip(#size, #align)));
+            continue;
+        }
+        let formats = match &options.format {
+            Some(format) => {
+                lens.push(quote!(1));
+                quote!(&[wgpu::VertexFormat::#format])
+            }
+            None => {
+                let formats = quote!(<#ty as ::vertex_layout::VertexAttribute>::FORMATS);
+                lens.push(quote!(#formats.len()));
+                formats
+            }
+        };
+        let location = match options.location {
+            Some(location) => quote!(Some(#location)),
+            None => quote!(None),
+        };
+        calls.push(quote!(.field(#formats, #size, #align, #location)));
+    }
+
+    Ok(quote! {
+        impl ::vertex_layout::VertexLayout for #name {
+            fn init_buffer_layout<'a>() -> ::vertex_layout::wgpu::VertexBufferLayout<'a> {
+                use ::vertex_layout::wgpu;
+                const LEN: usize = 0 #(+ #lens)*;
+                const ATTRIBUTES: [wgpu::VertexAttribute; LEN] =
+                    ::vertex_layout::Attributes::<LEN>::new(#start)
+                        #(#calls)*
+                        .finish();
+                wgpu::VertexBufferLayout {
+                    array_stride: ::std::mem::size_of::<#name>() as wgpu::BufferAddress,
+                    step_mode: wgpu::InputStepMode::#step_mode,
+                    attributes: &ATTRIBUTES,
+                }
+            }
+        }
+    })
+}
+
+fn has_repr_c(attrs: &[Attribute]) -> bool {
+    attrs
+        .iter()
+        .filter(|a| a.path.is_ident("repr"))
+        .filter_map(|a| a.parse_meta().ok())
+        .any(|meta| match meta {
+            Meta::List(list) => list.nested.iter().any(|n| match n {
+                NestedMeta::Meta(m) => m.path().is_ident("C"),
+                _ => false,
+            }),
+            _ => false,
+        })
+}
+
+/// Collects the options of every `#[vertex(...)]` attribute.
+fn parse_options(attrs: &[Attribute]) -> Result<Options> {
+    let mut options = Options::default();
+    for attr in attrs.iter().filter(|a| a.path.is_ident("vertex")) {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            meta => return Err(Error::new_spanned(meta, "expected #[vertex(...)]")),
+        };
+        for nested in list.nested.iter() {
+            match nested {
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("skip") => options.skip = true,
+                NestedMeta::Meta(Meta::NameValue(pair)) if pair.path.is_ident("step_mode") => {
+                    let mode = string(&pair.lit)?;
+                    let variant = match mode.value().as_str() {
+                        "vertex" => "Vertex",
+                        "instance" => "Instance",
+                        _ => {
+                            return Err(Error::new_spanned(
+                                mode,
+                                "expected \"vertex\" or \"instance\"",
+                            ))
+                        }
+                    };
+                    options.step_mode = Some(syn::Ident::new(variant, mode.span()));
+                }
+                NestedMeta::Meta(Meta::NameValue(pair)) if pair.path.is_ident("location") => {
+                    options.location = match &pair.lit {
+                        Lit::Int(int) => Some(int.base10_parse()?),
+                        lit => return Err(Error::new_spanned(lit, "expected a shader location")),
+                    };
+                }
+                NestedMeta::Meta(Meta::NameValue(pair)) if pair.path.is_ident("format") => {
+                    let format = string(&pair.lit)?;
+                    let ident = syn::parse_str::<syn::Ident>(&format.value())
+                        .map_err(|_| Error::new(format.span(), "expected a `VertexFormat` name"))?;
+                    options.format = Some(syn::Ident::new(&ident.to_string(), format.span()));
+                }
+                _ => {
+                    return Err(Error::new(
+                        nested.span(),
+                        "unknown option, expected `step_mode`, `location`, `format` or `skip`",
+                    ))
+                }
+            }
+        }
+    }
+    Ok(options)
+}
+
+fn string(lit: &Lit) -> Result<&syn::LitStr> {
+    match lit {
+        Lit::Str(s) => Ok(s),
+        lit => Err(Error::new_spanned(lit, "expected a string")),
+    }
+}